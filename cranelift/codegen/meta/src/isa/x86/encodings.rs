@@ -2605,6 +2605,41 @@ fn define_reftypes(e: &mut PerCpuModeEncodings, shared_defs: &SharedDefinitions,
     e.enc64_rec(safepoint, rec_safepoint, 0);
 }
 
+fn define_atomics(e: &mut PerCpuModeEncodings, shared_defs: &SharedDefinitions, r: &RecipeGroup) {
+    let shared = &shared_defs.instructions;
+
+    // Shorthands for instructions.
+    let atomic_cas = shared.by_name("atomic_cas");
+    let fence = shared.by_name("fence");
+
+    // Shorthands for recipes.
+    let rec_lock_cmpxchg = r.template("lock_cmpxchg");
+    let rec_mfence = r.recipe("mfence");
+
+    // Atomic memory accesses are only supported in 64-bit mode; `atomic_load`, `atomic_store`
+    // and `atomic_rmw` are legalized in terms of these. A REX prefix is always emitted so that
+    // the byte variant can address %sil, %dil, and friends.
+    e.enc64(
+        atomic_cas.bind(I8).bind(I64),
+        rec_lock_cmpxchg.opcodes(&CMPXCHG_BYTE).rex(),
+    );
+    e.enc64(
+        atomic_cas.bind(I16).bind(I64),
+        rec_lock_cmpxchg.opcodes(&CMPXCHG_WORD).rex(),
+    );
+    e.enc64(
+        atomic_cas.bind(I32).bind(I64),
+        rec_lock_cmpxchg.opcodes(&CMPXCHG).rex(),
+    );
+    e.enc64(
+        atomic_cas.bind(I64).bind(I64),
+        rec_lock_cmpxchg.opcodes(&CMPXCHG).rex().w(),
+    );
+
+    e.enc32_rec(fence, rec_mfence, 0);
+    e.enc64_rec(fence, rec_mfence, 0);
+}
+
 #[allow(clippy::cognitive_complexity)]
 pub(crate) fn define(
     shared_defs: &SharedDefinitions,
@@ -2625,6 +2660,7 @@ pub(crate) fn define(
     define_entity_ref(&mut e, shared_defs, settings, r);
     define_control_flow(&mut e, shared_defs, settings, r);
     define_reftypes(&mut e, shared_defs, r);
+    define_atomics(&mut e, shared_defs, r);
 
     let x86_elf_tls_get_addr = x86.by_name("x86_elf_tls_get_addr");
     let x86_macho_tls_get_addr = x86.by_name("x86_macho_tls_get_addr");
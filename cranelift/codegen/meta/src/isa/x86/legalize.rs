@@ -51,6 +51,9 @@ pub(crate) fn define(shared: &mut SharedDefinitions, x86_instructions: &Instruct
 
     // List of instructions.
     let insts = &shared.instructions;
+    let atomic_load = insts.by_name("atomic_load");
+    let atomic_rmw = insts.by_name("atomic_rmw");
+    let atomic_store = insts.by_name("atomic_store");
     let band = insts.by_name("band");
     let bor = insts.by_name("bor");
     let clz = insts.by_name("clz");
@@ -62,10 +65,12 @@ pub(crate) fn define(shared: &mut SharedDefinitions, x86_instructions: &Instruct
     let fcvt_to_sint_sat = insts.by_name("fcvt_to_sint_sat");
     let fcvt_to_uint_sat = insts.by_name("fcvt_to_uint_sat");
     let fmax = insts.by_name("fmax");
+    let fence = insts.by_name("fence");
     let fmin = insts.by_name("fmin");
     let iadd = insts.by_name("iadd");
     let iconst = insts.by_name("iconst");
     let imul = insts.by_name("imul");
+    let load = insts.by_name("load");
     let ineg = insts.by_name("ineg");
     let isub = insts.by_name("isub");
     let ishl = insts.by_name("ishl");
@@ -75,6 +80,7 @@ pub(crate) fn define(shared: &mut SharedDefinitions, x86_instructions: &Instruct
     let selectif = insts.by_name("selectif");
     let smulhi = insts.by_name("smulhi");
     let srem = insts.by_name("srem");
+    let store = insts.by_name("store");
     let tls_value = insts.by_name("tls_value");
     let udiv = insts.by_name("udiv");
     let umulhi = insts.by_name("umulhi");
@@ -353,6 +359,27 @@ pub(crate) fn define(shared: &mut SharedDefinitions, x86_instructions: &Instruct
     expand.custom_legalize(tls_value, "expand_tls_value");
     widen.custom_legalize(ineg, "convert_ineg");
 
+    // Atomics. Naturally aligned loads and stores are atomic on x86, and its strong memory
+    // ordering means a sequentially consistent load needs no fence; a store is followed by an
+    // `mfence` so that later loads can't be reordered before it. Read-modify-write operations are
+    // implemented with a `lock cmpxchg` loop.
+    let a = var("a");
+    let p = var("p");
+    let x = var("x");
+    let flags = var("flags");
+    let offset0 = Literal::constant(&imm.offset32, 0);
+    for group in &mut [&mut expand, &mut widen] {
+        group.legalize(
+            def!(a = atomic_load(flags, p)),
+            vec![def!(a = load(flags, p, offset0))],
+        );
+        group.legalize(
+            def!(atomic_store(flags, x, p)),
+            vec![def!(store(flags, x, p, offset0)), def!(fence())],
+        );
+        group.custom_legalize(atomic_rmw, "expand_atomic_rmw");
+    }
+
     // To reduce compilation times, separate out large blocks of legalizations by theme.
     define_simd(shared, x86_instructions, &mut narrow, &mut narrow_avx);
 
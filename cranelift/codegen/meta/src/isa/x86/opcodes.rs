@@ -85,6 +85,16 @@ pub static CMPPD: [u8; 3] = [0x66, 0x0f, 0xc2];
 /// imm8 as comparison predicate (SSE).
 pub static CMPPS: [u8; 2] = [0x0f, 0xc2];
 
+/// Compare %rax with r/m{16,32,64}; if equal, load r{16,32,64} into r/m, else load r/m into
+/// %rax. Used with a LOCK prefix for atomic compare-and-swap.
+pub static CMPXCHG: [u8; 2] = [0x0f, 0xb1];
+
+/// Compare %al with r/m8; if equal, load r8 into r/m8, else load r/m8 into %al.
+pub static CMPXCHG_BYTE: [u8; 2] = [0x0f, 0xb0];
+
+/// Compare %ax with r/m16; if equal, load r16 into r/m16, else load r/m16 into %ax.
+pub static CMPXCHG_WORD: [u8; 3] = [0x66, 0x0f, 0xb1];
+
 /// Convert four packed signed doubleword integers from xmm2/mem to four packed single-precision
 /// floating-point values in xmm1 (SSE2).
 pub static CVTDQ2PS: [u8; 2] = [0x0f, 0x5b];
@@ -501,6 +501,17 @@ pub(crate) fn define<'shared>(
         EncodingRecipeBuilder::new("debugtrap", &formats.nullary, 1).emit("sink.put1(0xcc);"),
     );
 
+    // MFENCE: serialize all prior loads and stores.
+    recipes.add_recipe(
+        EncodingRecipeBuilder::new("mfence", &formats.nullary, 3).emit(
+            r#"
+                sink.put1(0x0f);
+                sink.put1(0xae);
+                sink.put1(0xf0);
+            "#,
+        ),
+    );
+
     // XX opcode, no ModR/M.
     recipes.add_template_recipe(EncodingRecipeBuilder::new("trap", &formats.trap, 0).emit(
         r#"
@@ -1516,6 +1527,37 @@ pub(crate) fn define<'shared>(
                 ),
         );
 
+        // LOCK XX /r atomic compare-and-swap through a register-indirect address with no
+        // offset. The expected value and the returned old value live in %rax.
+        recipes.add_template_recipe(
+            EncodingRecipeBuilder::new("lock_cmpxchg", &formats.atomic_cas, 2)
+                .operands_in(vec![
+                    OperandConstraint::RegClass(gpr),
+                    OperandConstraint::FixedReg(reg_rax),
+                    OperandConstraint::RegClass(gpr),
+                ])
+                .operands_out(vec![reg_rax])
+                .compute_size("size_plus_maybe_sib_or_offset_for_inreg_0")
+                .emit(
+                    r#"
+                        if !flags.notrap() {
+                            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+                        }
+                        sink.put1(0xf0);
+                        {{PUT_OP}}(bits, rex2(in_reg0, in_reg2), sink);
+                        if needs_sib_byte(in_reg0) {
+                            modrm_sib(in_reg2, sink);
+                            sib_noindex(in_reg0, sink);
+                        } else if needs_offset(in_reg0) {
+                            modrm_disp8(in_reg0, in_reg2, sink);
+                            sink.put1(0);
+                        } else {
+                            modrm_rm(in_reg0, in_reg2, sink);
+                        }
+                    "#,
+                ),
+        );
+
         // XX /r register-indirect store with no offset.
         // Only ABCD allowed for stored value. This is for byte stores with no REX.
         recipes.add_template(
@@ -24,6 +24,9 @@ pub enum TrapCode {
     /// offset-guard pages.
     HeapOutOfBounds,
 
+    /// An atomic heap access was not naturally aligned.
+    HeapMisaligned,
+
     /// A `table_addr` instruction detected an out-of-bounds error.
     TableOutOfBounds,
 
@@ -49,6 +52,12 @@ pub enum TrapCode {
     /// This trap is resumable.
     Interrupt,
 
+    /// Execution has consumed all of the fuel it was allotted.
+    OutOfFuel,
+
+    /// An atomic wait was performed on a memory that is not shared.
+    AtomicWaitNonSharedMemory,
+
     /// A user-defined trap code.
     User(u16),
 }
@@ -59,6 +68,7 @@ impl Display for TrapCode {
         let identifier = match *self {
             StackOverflow => "stk_ovf",
             HeapOutOfBounds => "heap_oob",
+            HeapMisaligned => "heap_misaligned",
             TableOutOfBounds => "table_oob",
             IndirectCallToNull => "icall_null",
             BadSignature => "bad_sig",
@@ -67,6 +77,8 @@ impl Display for TrapCode {
             BadConversionToInteger => "bad_toint",
             UnreachableCodeReached => "unreachable",
             Interrupt => "interrupt",
+            OutOfFuel => "out_of_fuel",
+            AtomicWaitNonSharedMemory => "atomic_wait_non_shared",
             User(x) => return write!(f, "user{}", x),
         };
         f.write_str(identifier)
@@ -81,6 +93,7 @@ impl FromStr for TrapCode {
         match s {
             "stk_ovf" => Ok(StackOverflow),
             "heap_oob" => Ok(HeapOutOfBounds),
+            "heap_misaligned" => Ok(HeapMisaligned),
             "table_oob" => Ok(TableOutOfBounds),
             "icall_null" => Ok(IndirectCallToNull),
             "bad_sig" => Ok(BadSignature),
@@ -89,6 +102,8 @@ impl FromStr for TrapCode {
             "bad_toint" => Ok(BadConversionToInteger),
             "unreachable" => Ok(UnreachableCodeReached),
             "interrupt" => Ok(Interrupt),
+            "out_of_fuel" => Ok(OutOfFuel),
+            "atomic_wait_non_shared" => Ok(AtomicWaitNonSharedMemory),
             _ if s.starts_with("user") => s[4..].parse().map(User).map_err(|_| ()),
             _ => Err(()),
         }
@@ -101,9 +116,10 @@ mod tests {
     use alloc::string::ToString;
 
     // Everything but user-defined codes.
-    const CODES: [TrapCode; 10] = [
+    const CODES: [TrapCode; 13] = [
         TrapCode::StackOverflow,
         TrapCode::HeapOutOfBounds,
+        TrapCode::HeapMisaligned,
         TrapCode::TableOutOfBounds,
         TrapCode::IndirectCallToNull,
         TrapCode::BadSignature,
@@ -112,6 +128,8 @@ mod tests {
         TrapCode::BadConversionToInteger,
         TrapCode::UnreachableCodeReached,
         TrapCode::Interrupt,
+        TrapCode::OutOfFuel,
+        TrapCode::AtomicWaitNonSharedMemory,
     ];
 
     #[test]
@@ -242,6 +242,49 @@ fn enc_ldst_imm19(op_31_24: u32, imm19: u32, rd: Reg) -> u32 {
     (op_31_24 << 24) | (imm19 << 5) | machreg_to_gpr_or_vec(rd)
 }
 
+fn enc_ldar(ty: Type, rt: Writable<Reg>, rn: Reg) -> u32 {
+    0b00_001000_1_1_0_11111_1_11111_00000_00000
+        | (enc_atomic_size(ty) << 30)
+        | (machreg_to_gpr(rn) << 5)
+        | machreg_to_gpr(rt.to_reg())
+}
+
+fn enc_stlr(ty: Type, rt: Reg, rn: Reg) -> u32 {
+    0b00_001000_1_0_0_11111_1_11111_00000_00000
+        | (enc_atomic_size(ty) << 30)
+        | (machreg_to_gpr(rn) << 5)
+        | machreg_to_gpr(rt)
+}
+
+fn enc_ldaxr(ty: Type, rt: Writable<Reg>, rn: Reg) -> u32 {
+    0b00_001000_0_1_0_11111_1_11111_00000_00000
+        | (enc_atomic_size(ty) << 30)
+        | (machreg_to_gpr(rn) << 5)
+        | machreg_to_gpr(rt.to_reg())
+}
+
+fn enc_stlxr(ty: Type, rs: Writable<Reg>, rt: Reg, rn: Reg) -> u32 {
+    0b00_001000_0_0_0_00000_1_11111_00000_00000
+        | (enc_atomic_size(ty) << 30)
+        | (machreg_to_gpr(rs.to_reg()) << 16)
+        | (machreg_to_gpr(rn) << 5)
+        | machreg_to_gpr(rt)
+}
+
+fn enc_atomic_size(ty: Type) -> u32 {
+    match ty {
+        I8 => 0b00,
+        I16 => 0b01,
+        I32 => 0b10,
+        I64 => 0b11,
+        _ => panic!("Unsupported type for atomic access: {}", ty),
+    }
+}
+
+fn enc_dmb_ish() -> u32 {
+    0xD5033BBF
+}
+
 fn enc_extend(top22: u32, rd: Writable<Reg>, rn: Reg) -> u32 {
     (top22 << 10) | (machreg_to_gpr(rn) << 5) | machreg_to_gpr(rd.to_reg())
 }
@@ -916,6 +959,239 @@ impl MachInstEmit for Inst {
                     }
                 }
             }
+            &Inst::AtomicRMW { ty, op, srcloc } => {
+                /* Emit this:
+                  again:
+                   ldaxr{,b,h}  x/w27, [x25]
+                   op           x28, x27, x26 // op is add,sub,and,orr,eor,...
+                   stlxr{,b,h}  w24, x/w28, [x25]
+                   cbnz         x24, again
+
+                  Operand conventions:
+                     IN:  x25 (addr), x26 (2nd arg for op)
+                     OUT: x27 (old value), x24 (trashed), x28 (trashed)
+
+                  It is unfortunate that, per the ARM documentation, x28 cannot be used for
+                  both the store-data and success-flag operands of stlxr. This causes the
+                  instruction's behaviour to be "CONSTRAINED UNPREDICTABLE", so we use x24
+                  instead for the success-flag.
+
+                  For the min/max variants, `op` is a compare followed by a conditional
+                  select, and the narrow cases first extend the old value into x28 so that
+                  the comparison happens at 32 bits. Lowering has already extended x26 to
+                  match.
+                */
+                let xzr = zero_reg();
+                let x24 = xreg(24);
+                let x25 = xreg(25);
+                let x26 = xreg(26);
+                let x27 = xreg(27);
+                let x28 = xreg(28);
+                let x24wr = writable_xreg(24);
+                let x27wr = writable_xreg(27);
+                let x28wr = writable_xreg(28);
+                let again_label = sink.get_label();
+
+                // again:
+                sink.bind_label(again_label);
+                if let Some(srcloc) = srcloc {
+                    sink.add_trap(srcloc, TrapCode::HeapOutOfBounds);
+                }
+                sink.put4(enc_ldaxr(ty, x27wr, x25)); // ldaxr x27, [x25]
+
+                match op {
+                    AtomicRmwOp::Xchg => {
+                        // The value to store is already in x26.
+                    }
+                    AtomicRmwOp::Nand => {
+                        // and x28, x27, x26
+                        // mvn x28, x28
+                        Inst::AluRRR {
+                            alu_op: ALUOp::And64,
+                            rd: x28wr,
+                            rn: x27,
+                            rm: x26,
+                        }
+                        .emit(sink, flags, state);
+                        Inst::AluRRR {
+                            alu_op: ALUOp::OrrNot64,
+                            rd: x28wr,
+                            rn: xzr,
+                            rm: x28,
+                        }
+                        .emit(sink, flags, state);
+                    }
+                    AtomicRmwOp::Umin
+                    | AtomicRmwOp::Umax
+                    | AtomicRmwOp::Smin
+                    | AtomicRmwOp::Smax => {
+                        let signed = op == AtomicRmwOp::Smin || op == AtomicRmwOp::Smax;
+                        let cond = match op {
+                            AtomicRmwOp::Umin => Cond::Lo,
+                            AtomicRmwOp::Umax => Cond::Hi,
+                            AtomicRmwOp::Smin => Cond::Lt,
+                            AtomicRmwOp::Smax => Cond::Gt,
+                            _ => unreachable!(),
+                        };
+                        let (cmp_op, lhs) = match ty {
+                            I8 | I16 => {
+                                // {s,u}xt{b,h} w28, w27
+                                Inst::Extend {
+                                    rd: x28wr,
+                                    rn: x27,
+                                    signed,
+                                    from_bits: ty_bits(ty) as u8,
+                                    to_bits: 32,
+                                }
+                                .emit(sink, flags, state);
+                                (ALUOp::SubS32, x28)
+                            }
+                            I32 => (ALUOp::SubS32, x27),
+                            _ => (ALUOp::SubS64, x27),
+                        };
+                        // cmp lhs, x26
+                        Inst::AluRRR {
+                            alu_op: cmp_op,
+                            rd: writable_zero_reg(),
+                            rn: lhs,
+                            rm: x26,
+                        }
+                        .emit(sink, flags, state);
+                        // csel x28, x27, x26, cond
+                        Inst::CSel {
+                            rd: x28wr,
+                            cond,
+                            rn: x27,
+                            rm: x26,
+                        }
+                        .emit(sink, flags, state);
+                    }
+                    _ => {
+                        // op x28, x27, x26
+                        let alu_op = match op {
+                            AtomicRmwOp::Add => ALUOp::Add64,
+                            AtomicRmwOp::Sub => ALUOp::Sub64,
+                            AtomicRmwOp::And => ALUOp::And64,
+                            AtomicRmwOp::Or => ALUOp::Orr64,
+                            AtomicRmwOp::Xor => ALUOp::Eor64,
+                            _ => unreachable!(),
+                        };
+                        Inst::AluRRR {
+                            alu_op,
+                            rd: x28wr,
+                            rn: x27,
+                            rm: x26,
+                        }
+                        .emit(sink, flags, state);
+                    }
+                }
+
+                let x_data = if op == AtomicRmwOp::Xchg { x26 } else { x28 };
+                sink.put4(enc_stlxr(ty, x24wr, x_data, x25)); // stlxr w24, x28, [x25]
+
+                // cbnz w24, again
+                // Note, we're actually testing x24, and relying on the default zero-high-half
+                // rule in the assignment that `stlxr` does.
+                let br_offset = sink.cur_offset();
+                sink.put4(enc_conditional_br(
+                    BranchTarget::Label(again_label),
+                    CondBrKind::NotZero(x24),
+                ));
+                sink.use_label_at_offset(br_offset, again_label, LabelUse::Branch19);
+            }
+            &Inst::AtomicCAS { ty, srcloc } => {
+                /* Emit this:
+                  again:
+                   ldaxr{,b,h} x/w27, [x25]
+                   cmp         x/w27, x/w26
+                   b.ne        out
+                   stlxr{,b,h} w24, x/w28, [x25]
+                   cbnz        x24, again
+                  out:
+
+                  Operand conventions:
+                     IN:  x25 (addr), x26 (expected value), x28 (replacement value)
+                     OUT: x27 (old value), x24 (trashed)
+
+                  The narrow loads zero-extend, and lowering zero-extends x26 to match, so a
+                  32-bit comparison suffices for everything but 64-bit accesses.
+                */
+                let x24 = xreg(24);
+                let x25 = xreg(25);
+                let x26 = xreg(26);
+                let x27 = xreg(27);
+                let x28 = xreg(28);
+                let x24wr = writable_xreg(24);
+                let x27wr = writable_xreg(27);
+                let again_label = sink.get_label();
+                let out_label = sink.get_label();
+
+                // again:
+                sink.bind_label(again_label);
+                if let Some(srcloc) = srcloc {
+                    sink.add_trap(srcloc, TrapCode::HeapOutOfBounds);
+                }
+                sink.put4(enc_ldaxr(ty, x27wr, x25)); // ldaxr x27, [x25]
+
+                // cmp x27, x26
+                Inst::AluRRR {
+                    alu_op: if ty == I64 {
+                        ALUOp::SubS64
+                    } else {
+                        ALUOp::SubS32
+                    },
+                    rd: writable_zero_reg(),
+                    rn: x27,
+                    rm: x26,
+                }
+                .emit(sink, flags, state);
+
+                // b.ne out
+                let br_offset = sink.cur_offset();
+                sink.put4(enc_conditional_br(
+                    BranchTarget::Label(out_label),
+                    CondBrKind::Cond(Cond::Ne),
+                ));
+                sink.use_label_at_offset(br_offset, out_label, LabelUse::Branch19);
+
+                sink.put4(enc_stlxr(ty, x24wr, x28, x25)); // stlxr w24, x28, [x25]
+
+                // cbnz w24, again
+                let br_offset = sink.cur_offset();
+                sink.put4(enc_conditional_br(
+                    BranchTarget::Label(again_label),
+                    CondBrKind::NotZero(x24),
+                ));
+                sink.use_label_at_offset(br_offset, again_label, LabelUse::Branch19);
+
+                // out:
+                sink.bind_label(out_label);
+            }
+            &Inst::AtomicLoad {
+                ty,
+                r_data,
+                r_addr,
+                srcloc,
+            } => {
+                if let Some(srcloc) = srcloc {
+                    sink.add_trap(srcloc, TrapCode::HeapOutOfBounds);
+                }
+                sink.put4(enc_ldar(ty, r_data, r_addr));
+            }
+            &Inst::AtomicStore {
+                ty,
+                r_data,
+                r_addr,
+                srcloc,
+            } => {
+                if let Some(srcloc) = srcloc {
+                    sink.add_trap(srcloc, TrapCode::HeapOutOfBounds);
+                }
+                sink.put4(enc_stlr(ty, r_data, r_addr));
+            }
+            &Inst::Fence => {
+                sink.put4(enc_dmb_ish()); // dmb ish
+            }
             &Inst::Mov { rd, rm } => {
                 assert!(rd.to_reg().get_class() == rm.get_class());
                 assert!(rm.get_class() == RegClass::I64);
@@ -1,4 +1,5 @@
 use crate::ir::types::*;
+use crate::ir::AtomicRmwOp;
 use crate::isa::aarch64::inst::*;
 use crate::isa::test_utils;
 use crate::settings;
@@ -3414,6 +3415,90 @@ fn test_aarch64_binemit() {
         "frintn d23, d24",
     ));
 
+    insns.push((
+        Inst::AtomicRMW {
+            ty: I16,
+            op: AtomicRmwOp::Xor,
+            srcloc: None,
+        },
+        "3BFF5F487C031ACA3CFF1848B8FFFFB5",
+        "atomically { 16_bits_at_[x25] xor= x26 ; x27 = old_value_at_[x25] ; x24,x28 = trash }",
+    ));
+
+    insns.push((
+        Inst::AtomicRMW {
+            ty: I32,
+            op: AtomicRmwOp::Xchg,
+            srcloc: None,
+        },
+        "3BFF5F883AFF1888D8FFFFB5",
+        "atomically { 32_bits_at_[x25] xchg= x26 ; x27 = old_value_at_[x25] ; x24,x28 = trash }",
+    ));
+
+    insns.push((
+        Inst::AtomicCAS {
+            ty: I8,
+            srcloc: None,
+        },
+        "3BFF5F087F031A6B610000543CFF180898FFFFB5",
+        "atomically { compare-and-swap(8_bits_at_[x25], x26 -> x28), x27 = old_value_at_[x25] ; x24 = trash }",
+    ));
+
+    insns.push((
+        Inst::AtomicCAS {
+            ty: I64,
+            srcloc: None,
+        },
+        "3BFF5FC87F031AEB610000543CFF18C898FFFFB5",
+        "atomically { compare-and-swap(64_bits_at_[x25], x26 -> x28), x27 = old_value_at_[x25] ; x24 = trash }",
+    ));
+
+    insns.push((
+        Inst::AtomicLoad {
+            ty: I8,
+            r_data: writable_xreg(7),
+            r_addr: xreg(28),
+            srcloc: None,
+        },
+        "87FFDF08",
+        "ldarb w7, [x28]",
+    ));
+
+    insns.push((
+        Inst::AtomicLoad {
+            ty: I64,
+            r_data: writable_xreg(28),
+            r_addr: xreg(7),
+            srcloc: None,
+        },
+        "FCFCDFC8",
+        "ldar x28, [x7]",
+    ));
+
+    insns.push((
+        Inst::AtomicStore {
+            ty: I16,
+            r_data: xreg(17),
+            r_addr: xreg(8),
+            srcloc: None,
+        },
+        "11FD9F48",
+        "stlrh w17, [x8]",
+    ));
+
+    insns.push((
+        Inst::AtomicStore {
+            ty: I32,
+            r_data: xreg(18),
+            r_addr: xreg(7),
+            srcloc: None,
+        },
+        "F2FC9F88",
+        "stlr w18, [x7]",
+    ));
+
+    insns.push((Inst::Fence, "BF3B03D5", "dmb ish"));
+
     let rru = create_reg_universe(&settings::Flags::new(settings::builder()));
     for (insn, expected_encoding, expected_printing) in insns {
         println!(
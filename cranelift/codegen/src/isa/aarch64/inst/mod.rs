@@ -8,7 +8,7 @@ use crate::ir::types::{
     B1, B16, B16X8, B32, B32X4, B64, B64X2, B8, B8X16, F32, F32X2, F32X4, F64, F64X2, FFLAGS, I128,
     I16, I16X4, I16X8, I32, I32X2, I32X4, I64, I64X2, I8, I8X16, I8X8, IFLAGS,
 };
-use crate::ir::{ExternalName, Opcode, SourceLoc, TrapCode, Type};
+use crate::ir::{AtomicRmwOp, ExternalName, Opcode, SourceLoc, TrapCode, Type};
 use crate::machinst::*;
 use crate::{settings, CodegenError, CodegenResult};
 
@@ -490,6 +490,42 @@ pub enum Inst {
         mem: PairMemArg,
     },
 
+    /// An atomic read-modify-write, sequentially consistent. The address is taken from x25 and
+    /// the second operand from x26. The old value in memory is returned in x27, and x24 and x28
+    /// are clobbered. This is expanded into an `ldaxr` / `stlxr` retry loop at emission time.
+    AtomicRMW {
+        ty: Type, // I8, I16, I32 or I64
+        op: AtomicRmwOp,
+        srcloc: Option<SourceLoc>,
+    },
+
+    /// An atomic compare-and-swap, sequentially consistent. The address is taken from x25, the
+    /// expected value from x26 and the replacement value from x28. The old value in memory is
+    /// returned in x27, and x24 is clobbered.
+    AtomicCAS {
+        ty: Type, // I8, I16, I32 or I64
+        srcloc: Option<SourceLoc>,
+    },
+
+    /// A sequentially consistent atomic load (`ldar`), zero-extending the loaded value.
+    AtomicLoad {
+        ty: Type, // I8, I16, I32 or I64
+        r_data: Writable<Reg>,
+        r_addr: Reg,
+        srcloc: Option<SourceLoc>,
+    },
+
+    /// A sequentially consistent atomic store (`stlr`).
+    AtomicStore {
+        ty: Type, // I8, I16, I32 or I64
+        r_data: Reg,
+        r_addr: Reg,
+        srcloc: Option<SourceLoc>,
+    },
+
+    /// A full memory barrier (`dmb ish`).
+    Fence,
+
     /// A MOV instruction. These are encoded as ORR's (AluRRR form) but we
     /// keep them separate at the `Inst` level for better pretty-printing
     /// and faster `is_move()` logic.
@@ -1174,6 +1210,29 @@ fn aarch64_get_regs(inst: &Inst, collector: &mut RegUsageCollector) {
             collector.add_def(rt2);
             pairmemarg_regs(mem, collector);
         }
+        &Inst::AtomicRMW { .. } => {
+            collector.add_use(xreg(25));
+            collector.add_use(xreg(26));
+            collector.add_def(writable_xreg(24));
+            collector.add_def(writable_xreg(27));
+            collector.add_def(writable_xreg(28));
+        }
+        &Inst::AtomicCAS { .. } => {
+            collector.add_use(xreg(25));
+            collector.add_use(xreg(26));
+            collector.add_use(xreg(28));
+            collector.add_def(writable_xreg(24));
+            collector.add_def(writable_xreg(27));
+        }
+        &Inst::AtomicLoad { r_data, r_addr, .. } => {
+            collector.add_use(r_addr);
+            collector.add_def(r_data);
+        }
+        &Inst::AtomicStore { r_data, r_addr, .. } => {
+            collector.add_use(r_addr);
+            collector.add_use(r_data);
+        }
+        &Inst::Fence => {}
         &Inst::Mov { rd, rm } => {
             collector.add_def(rd);
             collector.add_use(rm);
@@ -1629,6 +1688,26 @@ fn aarch64_map_regs<RUM: RegUsageMapper>(inst: &mut Inst, mapper: &RUM) {
             map_def(mapper, rt2);
             map_pairmem(mapper, mem);
         }
+        &mut Inst::AtomicRMW { .. } | &mut Inst::AtomicCAS { .. } => {
+            // There are no vregs to map in these instructions; they only use fixed real regs.
+        }
+        &mut Inst::AtomicLoad {
+            ref mut r_data,
+            ref mut r_addr,
+            ..
+        } => {
+            map_def(mapper, r_data);
+            map_use(mapper, r_addr);
+        }
+        &mut Inst::AtomicStore {
+            ref mut r_data,
+            ref mut r_addr,
+            ..
+        } => {
+            map_use(mapper, r_data);
+            map_use(mapper, r_addr);
+        }
+        &mut Inst::Fence => {}
         &mut Inst::Mov {
             ref mut rd,
             ref mut rm,
@@ -2409,6 +2488,48 @@ impl ShowWithRRU for Inst {
                 let mem = mem.show_rru_sized(mb_rru, /* size = */ 8);
                 format!("ldp {}, {}, {}", rt, rt2, mem)
             }
+            &Inst::AtomicRMW { ty, op, .. } => {
+                format!(
+                    "atomically {{ {}_bits_at_[x25] {}= x26 ; x27 = old_value_at_[x25] ; x24,x28 = trash }}",
+                    ty.bits(),
+                    op
+                )
+            }
+            &Inst::AtomicCAS { ty, .. } => {
+                format!(
+                    "atomically {{ compare-and-swap({}_bits_at_[x25], x26 -> x28), x27 = old_value_at_[x25] ; x24 = trash }}",
+                    ty.bits()
+                )
+            }
+            &Inst::AtomicLoad {
+                ty, r_data, r_addr, ..
+            } => {
+                let (op, size) = match ty {
+                    I8 => ("ldarb", InstSize::Size32),
+                    I16 => ("ldarh", InstSize::Size32),
+                    I32 => ("ldar", InstSize::Size32),
+                    I64 => ("ldar", InstSize::Size64),
+                    _ => panic!("Unsupported type for AtomicLoad: {}", ty),
+                };
+                let r_data = show_ireg_sized(r_data.to_reg(), mb_rru, size);
+                let r_addr = r_addr.show_rru(mb_rru);
+                format!("{} {}, [{}]", op, r_data, r_addr)
+            }
+            &Inst::AtomicStore {
+                ty, r_data, r_addr, ..
+            } => {
+                let (op, size) = match ty {
+                    I8 => ("stlrb", InstSize::Size32),
+                    I16 => ("stlrh", InstSize::Size32),
+                    I32 => ("stlr", InstSize::Size32),
+                    I64 => ("stlr", InstSize::Size64),
+                    _ => panic!("Unsupported type for AtomicStore: {}", ty),
+                };
+                let r_data = show_ireg_sized(r_data, mb_rru, size);
+                let r_addr = r_addr.show_rru(mb_rru);
+                format!("{} {}, [{}]", op, r_data, r_addr)
+            }
+            &Inst::Fence => "dmb ish".to_string(),
             &Inst::Mov { rd, rm } => {
                 let rd = rd.to_reg().show_rru(mb_rru);
                 let rm = rm.show_rru(mb_rru);
@@ -851,6 +851,14 @@ pub(crate) fn ty_is_float(ty: Type) -> bool {
     !ty_is_int(ty)
 }
 
+/// Is `ty` a type which can be the subject of an atomic memory access?
+pub(crate) fn is_valid_atomic_transaction_ty(ty: Type) -> bool {
+    match ty {
+        I8 | I16 | I32 | I64 => true,
+        _ => false,
+    }
+}
+
 pub(crate) fn choose_32_64<T: Copy>(ty: Type, op32: T, op64: T) -> T {
     let bits = ty_bits(ty);
     if bits <= 32 {
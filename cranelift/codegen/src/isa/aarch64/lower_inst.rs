@@ -4,7 +4,7 @@ use crate::binemit::CodeOffset;
 use crate::ir::condcodes::FloatCC;
 use crate::ir::types::*;
 use crate::ir::Inst as IRInst;
-use crate::ir::{InstructionData, Opcode, TrapCode};
+use crate::ir::{AtomicRmwOp, InstructionData, Opcode, TrapCode};
 use crate::machinst::lower::*;
 use crate::machinst::*;
 use crate::CodegenResult;
@@ -988,6 +988,114 @@ pub(crate) fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
             });
         }
 
+        Opcode::AtomicRmw => {
+            let r_dst = get_output_reg(ctx, outputs[0]);
+            let ty_access = ty.unwrap();
+            assert!(is_valid_atomic_transaction_ty(ty_access));
+            let op = match ctx.data(insn) {
+                &InstructionData::AtomicRmw { op, .. } => op,
+                _ => unreachable!(),
+            };
+            // The min/max variants compare narrow values at 32 bits, so the second operand
+            // needs extending in the same way the old value will be.
+            let narrow_mode = match op {
+                AtomicRmwOp::Smin | AtomicRmwOp::Smax => NarrowValueMode::SignExtend32,
+                _ => NarrowValueMode::ZeroExtend32,
+            };
+            let r_addr = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            let r_arg2 = put_input_in_reg(ctx, inputs[1], narrow_mode);
+            let memflags = ctx.memflags(insn).expect("memory flags");
+            let srcloc = if !memflags.notrap() {
+                Some(ctx.srcloc(insn))
+            } else {
+                None
+            };
+            // Move the args to the preordained AtomicRMW input regs
+            ctx.emit(Inst::gen_move(writable_xreg(25), r_addr, I64));
+            ctx.emit(Inst::gen_move(writable_xreg(26), r_arg2, I64));
+            // Now the AtomicRMW insn itself
+            ctx.emit(Inst::AtomicRMW {
+                ty: ty_access,
+                op,
+                srcloc,
+            });
+            // And finally, copy the preordained AtomicRMW output reg to its destination.
+            ctx.emit(Inst::gen_move(r_dst, xreg(27), I64));
+            // Also, x24 and x28 are trashed. `fn aarch64_get_regs` must mention that.
+        }
+
+        Opcode::AtomicCas => {
+            // This is very similar to, but not identical to, the AtomicRmw case. The narrow
+            // exclusive loads zero-extend, so the expected value must be zero-extended too for
+            // the comparison in the AtomicCAS sequence to work.
+            let r_dst = get_output_reg(ctx, outputs[0]);
+            let ty_access = ty.unwrap();
+            assert!(is_valid_atomic_transaction_ty(ty_access));
+            let r_addr = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            let r_expected = put_input_in_reg(ctx, inputs[1], NarrowValueMode::ZeroExtend32);
+            let r_replacement = put_input_in_reg(ctx, inputs[2], NarrowValueMode::None);
+            let memflags = ctx.memflags(insn).expect("memory flags");
+            let srcloc = if !memflags.notrap() {
+                Some(ctx.srcloc(insn))
+            } else {
+                None
+            };
+            // Move the args to the preordained AtomicCAS input regs
+            ctx.emit(Inst::gen_move(writable_xreg(25), r_addr, I64));
+            ctx.emit(Inst::gen_move(writable_xreg(26), r_expected, I64));
+            ctx.emit(Inst::gen_move(writable_xreg(28), r_replacement, I64));
+            // Now the AtomicCAS itself, implemented in the normal way, with an LL-SC loop
+            ctx.emit(Inst::AtomicCAS {
+                ty: ty_access,
+                srcloc,
+            });
+            // And finally, copy the preordained AtomicCAS output reg to its destination.
+            ctx.emit(Inst::gen_move(r_dst, xreg(27), I64));
+            // Also, x24 is trashed. `fn aarch64_get_regs` must mention that.
+        }
+
+        Opcode::AtomicLoad => {
+            let r_data = get_output_reg(ctx, outputs[0]);
+            let r_addr = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            let ty_access = ty.unwrap();
+            assert!(is_valid_atomic_transaction_ty(ty_access));
+            let memflags = ctx.memflags(insn).expect("memory flags");
+            let srcloc = if !memflags.notrap() {
+                Some(ctx.srcloc(insn))
+            } else {
+                None
+            };
+            ctx.emit(Inst::AtomicLoad {
+                ty: ty_access,
+                r_data,
+                r_addr,
+                srcloc,
+            });
+        }
+
+        Opcode::AtomicStore => {
+            let r_data = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            let r_addr = put_input_in_reg(ctx, inputs[1], NarrowValueMode::None);
+            let ty_access = ctx.input_ty(insn, 0);
+            assert!(is_valid_atomic_transaction_ty(ty_access));
+            let memflags = ctx.memflags(insn).expect("memory flags");
+            let srcloc = if !memflags.notrap() {
+                Some(ctx.srcloc(insn))
+            } else {
+                None
+            };
+            ctx.emit(Inst::AtomicStore {
+                ty: ty_access,
+                r_data,
+                r_addr,
+                srcloc,
+            });
+        }
+
+        Opcode::Fence => {
+            ctx.emit(Inst::Fence {});
+        }
+
         Opcode::StackAddr => {
             let (stack_slot, offset) = match *ctx.data(insn) {
                 InstructionData::StackLoad {
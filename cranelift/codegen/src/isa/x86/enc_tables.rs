@@ -596,6 +596,88 @@ fn expand_minmax(
     cfg.recompute_block(pos.func, done);
 }
 
+/// Expand `atomic_rmw` into a loop around `atomic_cas`.
+///
+/// x86 has no single instruction for most read-modify-write operations that also returns the
+/// old value, so we load the current value, compute the new one, and retry the compare-and-swap
+/// until no other thread has modified the memory in between.
+fn expand_atomic_rmw(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    cfg: &mut ControlFlowGraph,
+    _isa: &dyn TargetIsa,
+) {
+    let (flags, op, p, x) = match func.dfg[inst] {
+        ir::InstructionData::AtomicRmw {
+            opcode: ir::Opcode::AtomicRmw,
+            flags,
+            op,
+            args,
+        } => (flags, op, args[0], args[1]),
+        _ => panic!("Expected atomic_rmw: {}", func.dfg.display_inst(inst, None)),
+    };
+    let old_block = func.layout.pp_block(inst);
+
+    // The loop block receives the value we expect to find in memory.
+    let loop_block = func.dfg.make_block();
+    let ty = func.dfg.ctrl_typevar(inst);
+    let expected = func.dfg.append_block_param(loop_block, ty);
+
+    // Final block with one argument representing the old value in memory.
+    let done = func.dfg.make_block();
+
+    // Move the `inst` result value onto the `done` block.
+    let result = func.dfg.first_result(inst);
+    func.dfg.clear_results(inst);
+    func.dfg.attach_block_param(done, result);
+
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+    let initial = pos.ins().load(ty, flags, p, 0);
+    pos.ins().jump(loop_block, &[initial]);
+
+    pos.insert_block(loop_block);
+    let replacement = match op {
+        ir::AtomicRmwOp::Add => pos.ins().iadd(expected, x),
+        ir::AtomicRmwOp::Sub => pos.ins().isub(expected, x),
+        ir::AtomicRmwOp::And => pos.ins().band(expected, x),
+        ir::AtomicRmwOp::Nand => {
+            let and = pos.ins().band(expected, x);
+            pos.ins().bnot(and)
+        }
+        ir::AtomicRmwOp::Or => pos.ins().bor(expected, x),
+        ir::AtomicRmwOp::Xor => pos.ins().bxor(expected, x),
+        ir::AtomicRmwOp::Xchg => x,
+        ir::AtomicRmwOp::Umin
+        | ir::AtomicRmwOp::Umax
+        | ir::AtomicRmwOp::Smin
+        | ir::AtomicRmwOp::Smax => {
+            let cc = match op {
+                ir::AtomicRmwOp::Umin => IntCC::UnsignedLessThan,
+                ir::AtomicRmwOp::Umax => IntCC::UnsignedGreaterThan,
+                ir::AtomicRmwOp::Smin => IntCC::SignedLessThan,
+                _ => IntCC::SignedGreaterThan,
+            };
+            let keep = pos.ins().icmp(cc, expected, x);
+            pos.ins().select(keep, expected, x)
+        }
+    };
+    let actual = pos.ins().atomic_cas(flags, p, expected, replacement);
+    let succeeded = pos.ins().icmp(IntCC::Equal, actual, expected);
+    pos.ins().brz(succeeded, loop_block, &[actual]);
+
+    // Recycle the original instruction as a jump.
+    pos.func.dfg.replace(inst).jump(done, &[actual]);
+
+    // Finally insert a label for the completion.
+    pos.next_inst();
+    pos.insert_block(done);
+
+    cfg.recompute_block(pos.func, old_block);
+    cfg.recompute_block(pos.func, loop_block);
+    cfg.recompute_block(pos.func, done);
+}
+
 /// x86 has no unsigned-to-float conversions. We handle the easy case of zero-extending i32 to
 /// i64 with a pattern, the rest needs more code.
 ///
@@ -769,6 +769,10 @@ impl<'a> Verifier<'a> {
             | IntSelect { .. }
             | Load { .. }
             | Store { .. }
+            | LoadNoOffset { .. }
+            | StoreNoOffset { .. }
+            | AtomicRmw { .. }
+            | AtomicCas { .. }
             | RegMove { .. }
             | CopySpecial { .. }
             | CopyToSsa { .. }
@@ -1901,7 +1905,8 @@ impl<'a> Verifier<'a> {
 
         match *inst_data {
             ir::InstructionData::Store { flags, .. }
-            | ir::InstructionData::StoreComplex { flags, .. } => {
+            | ir::InstructionData::StoreComplex { flags, .. }
+            | ir::InstructionData::StoreNoOffset { flags, .. } => {
                 if flags.readonly() {
                     errors.fatal((
                         inst,
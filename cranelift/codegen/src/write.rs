@@ -659,6 +659,14 @@ pub fn write_operands(
                 offset
             )
         }
+        LoadNoOffset { flags, arg, .. } => write!(w, "{} {}", flags, arg),
+        StoreNoOffset { flags, args, .. } => write!(w, "{} {}, {}", flags, args[0], args[1]),
+        AtomicRmw { flags, op, args, .. } => {
+            write!(w, "{} {} {}, {}", flags, op, args[0], args[1])
+        }
+        AtomicCas { flags, args, .. } => {
+            write!(w, "{} {}, {}, {}", flags, args[0], args[1], args[2])
+        }
         RegMove { arg, src, dst, .. } => {
             if let Some(isa) = isa {
                 let regs = isa.register_info();
@@ -479,11 +479,45 @@ pub trait FuncEnvironment: TargetEnvironment {
         val: ir::Value,
     ) -> WasmResult<()>;
 
+    /// Translate a `memory.atomic.wait32` or `memory.atomic.wait64` WebAssembly instruction.
+    ///
+    /// The `index` provided identifies the linear memory containing the value to wait on, and
+    /// `heap` is the heap reference returned by `make_heap` for the same index. Whether the
+    /// waited-on value is 32- or 64-bit can be determined by examining the type of `expected`,
+    /// which must only ever be `I32` or `I64`.
+    ///
+    /// Returns an i32: 0 if the thread was woken, 1 if the value in memory didn't match
+    /// `expected`, or 2 if `timeout` (in nanoseconds; negative means forever) elapsed.
+    fn translate_atomic_wait(
+        &mut self,
+        pos: FuncCursor,
+        index: MemoryIndex,
+        heap: ir::Heap,
+        addr: ir::Value,
+        expected: ir::Value,
+        timeout: ir::Value,
+    ) -> WasmResult<ir::Value>;
+
+    /// Translate a `memory.atomic.notify` WebAssembly instruction.
+    ///
+    /// The `index` provided identifies the linear memory containing the address being notified,
+    /// and `heap` is the heap reference returned by `make_heap` for the same index.
+    ///
+    /// Returns an i32, the number of waiters that were woken.
+    fn translate_atomic_notify(
+        &mut self,
+        pos: FuncCursor,
+        index: MemoryIndex,
+        heap: ir::Heap,
+        addr: ir::Value,
+        count: ir::Value,
+    ) -> WasmResult<ir::Value>;
+
     /// Emit code at the beginning of every wasm loop.
     ///
     /// This can be used to insert explicit interrupt or safepoint checking at
     /// the beginnings of loops.
-    fn translate_loop_header(&mut self, _pos: FuncCursor) -> WasmResult<()> {
+    fn translate_loop_header(&mut self, _builder: &mut FunctionBuilder) -> WasmResult<()> {
         // By default, don't emit anything.
         Ok(())
     }
@@ -509,6 +543,26 @@ pub trait FuncEnvironment: TargetEnvironment {
     ) -> WasmResult<()> {
         Ok(())
     }
+
+    /// Optional callback for the `FunctionEnvironment` performing this translation to learn the
+    /// number of locals, including parameters, declared by the function. The locals are the
+    /// `Variable`s numbered `0..num_locals_defined`.
+    fn after_locals(&mut self, _num_locals_defined: usize) {}
+
+    /// Optional callback for the `FunctionEnvironment` performing this translation to perform work
+    /// before the function body is translated, once the function's locals have been declared.
+    fn before_translate_function(
+        &mut self,
+        _builder: &mut FunctionBuilder,
+        _state: &FuncTranslationState,
+    ) -> WasmResult<()> {
+        Ok(())
+    }
+
+    /// Optional callback for the `FunctionEnvironment` performing this translation to perform work
+    /// right before each `return` instruction the function exits through, with the values it
+    /// returns. This is only called when using `ReturnMode::NormalReturns`.
+    fn handle_before_return(&mut self, _retvals: &[ir::Value], _builder: &mut FunctionBuilder) {}
 }
 
 /// An object satisfying the `ModuleEnvironment` trait can be passed as argument to the
@@ -108,6 +108,7 @@ impl FuncTranslator {
         self.state.initialize(&builder.func.signature, exit_block);
 
         parse_local_decls(&mut reader, &mut builder, num_params, environ)?;
+        environ.before_translate_function(&mut builder, &self.state)?;
         parse_function_body(
             module_translation_state,
             reader,
@@ -172,6 +173,8 @@ fn parse_local_decls<FE: FuncEnvironment + ?Sized>(
         declare_locals(builder, count, ty, &mut next_local, environ)?;
     }
 
+    environ.after_locals(next_local);
+
     Ok(())
 }
 
@@ -248,6 +251,7 @@ fn parse_function_body<FE: FuncEnvironment + ?Sized>(
                         environ.is_wasm_return(&builder.func.signature, i)
                     });
                     bitcast_arguments(&mut state.stack, &return_types, builder);
+                    environ.handle_before_return(&state.stack, builder);
                     builder.ins().return_(&state.stack)
                 }
                 ReturnMode::FallthroughReturn => builder.ins().fallthrough_return(&state.stack),
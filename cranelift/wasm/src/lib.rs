@@ -54,6 +54,8 @@ mod environ;
 mod func_translator;
 mod module_translator;
 mod sections_translator;
+#[cfg(feature = "enable-serde")]
+pub mod serde_wasmparser;
 mod state;
 mod translation_utils;
 
@@ -0,0 +1,67 @@
+//! Serde support for the `wasmparser` types which are kept around in translated modules, since
+//! `wasmparser` doesn't implement serde itself.
+//!
+//! The modules here are meant to be used with `#[serde(with = "...")]`.
+
+use crate::environ::WasmType;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "WasmType")]
+enum WasmTypeDef {
+    I32,
+    I64,
+    F32,
+    F64,
+    V128,
+    FuncRef,
+    ExternRef,
+    Func,
+    EmptyBlockType,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Type(#[serde(with = "WasmTypeDef")] WasmType);
+
+/// Serializes a `WasmType`.
+pub mod wasm_type {
+    use super::WasmTypeDef;
+    use crate::environ::WasmType;
+    use serde::{Deserializer, Serializer};
+
+    /// Serializes `ty`.
+    pub fn serialize<S: Serializer>(ty: &WasmType, serializer: S) -> Result<S::Ok, S::Error> {
+        WasmTypeDef::serialize(ty, serializer)
+    }
+
+    /// Deserializes a `WasmType`.
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<WasmType, D::Error> {
+        WasmTypeDef::deserialize(deserializer)
+    }
+}
+
+/// Serializes a `WasmFuncType` as its lists of parameter and result types.
+pub mod wasm_func_type {
+    use super::Type;
+    use crate::environ::WasmFuncType;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::vec::Vec;
+
+    /// Serializes `ty`.
+    pub fn serialize<S: Serializer>(ty: &WasmFuncType, serializer: S) -> Result<S::Ok, S::Error> {
+        let params = ty.params.iter().map(|ty| Type(*ty)).collect::<Vec<_>>();
+        let returns = ty.returns.iter().map(|ty| Type(*ty)).collect::<Vec<_>>();
+        (params, returns).serialize(serializer)
+    }
+
+    /// Deserializes a `WasmFuncType`.
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<WasmFuncType, D::Error> {
+        let (params, returns) = <(Vec<Type>, Vec<Type>)>::deserialize(deserializer)?;
+        Ok(WasmFuncType {
+            params: params.into_iter().map(|ty| ty.0).collect(),
+            returns: returns.into_iter().map(|ty| ty.0).collect(),
+        })
+    }
+}
@@ -0,0 +1,33 @@
+(module
+  (memory 1 1 shared)
+  (func (export "load") (param i32) (result i64)
+    (i64.add
+      (i64.extend_i32_u (i32.atomic.load (local.get 0)))
+      (i64.add
+        (i64.atomic.load offset=8 (local.get 0))
+        (i64.add
+          (i64.atomic.load8_u offset=3 (local.get 0))
+          (i64.atomic.load32_u (local.get 0))))))
+  (func (export "store") (param i32 i64)
+    (i64.atomic.store (local.get 0) (local.get 1))
+    (i64.atomic.store16 offset=2 (local.get 0) (local.get 1))
+    (i32.atomic.store8 (local.get 0) (i32.wrap_i64 (local.get 1))))
+  (func (export "rmw") (param i32 i32) (result i32)
+    (i32.atomic.rmw.add (local.get 0) (local.get 1))
+    (i32.atomic.rmw8.sub_u (local.get 0))
+    (i32.atomic.rmw16.and_u (local.get 0))
+    (i32.atomic.rmw.or (local.get 0))
+    (i32.atomic.rmw.xor offset=4 (local.get 0))
+    (i32.atomic.rmw.xchg (local.get 0)))
+  (func (export "cmpxchg") (param i32 i64 i64) (result i64)
+    (i64.atomic.rmw32.cmpxchg_u (local.get 0) (local.get 1) (local.get 2))
+    (i64.atomic.rmw.cmpxchg (local.get 0) (local.get 1) (local.get 2))
+    (i64.add)
+    (atomic.fence))
+  (func (export "wait_notify") (param i32) (result i32)
+    (i32.atomic.wait (local.get 0) (i32.const 0) (i64.const -1))
+    (i64.atomic.wait offset=8 (local.get 0) (i64.const 0) (i64.const 1000))
+    (i32.add)
+    (atomic.notify (local.get 0) (i32.const 1))
+    (i32.add))
+)
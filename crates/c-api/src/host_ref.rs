@@ -4,6 +4,16 @@ use std::convert::TryFrom;
 use std::marker::PhantomData;
 use wasmtime::{ExternRef, Store};
 
+/// The value wrapped by a `HostRef`.
+///
+/// `ExternRef` values must be `Send` and `Sync`, but the C API doesn't track
+/// which thread a value is used on, so that's left up to the embedder just
+/// like the rest of the thread safety of the objects it passes around.
+struct HostCell<T>(RefCell<T>);
+
+unsafe impl<T> Send for HostCell<T> {}
+unsafe impl<T> Sync for HostCell<T> {}
+
 /// Represents a piece of data located in the host environment.
 #[derive(Debug)]
 pub struct HostRef<T>
@@ -21,7 +31,7 @@ where
     /// Creates a new `HostRef<T>` from `T`.
     pub fn new(store: &Store, item: T) -> HostRef<T> {
         HostRef {
-            externref: ExternRef::new(store, RefCell::new(item)),
+            externref: ExternRef::new(store, HostCell(RefCell::new(item))),
             _phantom: PhantomData,
         }
     }
@@ -51,10 +61,12 @@ where
     }
 
     fn inner(&self) -> &RefCell<T> {
-        self.externref
+        &self
+            .externref
             .data()
-            .downcast_ref::<RefCell<T>>()
-            .expect("`HostRef<T>`s always wrap an `ExternRef` of `RefCell<T>`")
+            .downcast_ref::<HostCell<T>>()
+            .expect("`HostRef<T>`s always wrap an `ExternRef` of `HostCell<T>`")
+            .0
     }
 }
 
@@ -80,7 +92,7 @@ where
     type Error = ExternRef;
 
     fn try_from(externref: ExternRef) -> Result<Self, ExternRef> {
-        if externref.data().is::<RefCell<T>>() {
+        if externref.data().is::<HostCell<T>>() {
             Ok(HostRef {
                 externref,
                 _phantom: PhantomData,
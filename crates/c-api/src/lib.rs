@@ -69,6 +69,10 @@ struct HostInfoState {
     finalizer: Option<extern "C" fn(arg1: *mut std::ffi::c_void)>,
 }
 
+// Host info must be `Send`, and it's up to the embedder to only use `info`
+// from threads where that's valid.
+unsafe impl Send for HostInfoState {}
+
 impl Drop for HostInfoState {
     fn drop(&mut self) {
         if let Some(f) = &self.finalizer {
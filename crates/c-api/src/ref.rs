@@ -29,7 +29,7 @@ pub(crate) fn get_host_info(r: &ExternRef) -> *mut c_void {
         Some(info) => info,
         None => return std::ptr::null_mut(),
     };
-    let host_info = host_info.borrow();
+    let host_info = host_info.lock().unwrap();
     match host_info.downcast_ref::<HostInfoState>() {
         Some(state) => state.info,
         None => std::ptr::null_mut(),
@@ -50,7 +50,7 @@ pub(crate) fn set_host_info(
     let info = if info.is_null() && finalizer.is_none() {
         None
     } else {
-        Some(Box::new(crate::HostInfoState { info, finalizer }) as Box<dyn std::any::Any>)
+        Some(Box::new(crate::HostInfoState { info, finalizer }) as Box<dyn std::any::Any + Send>)
     };
     r.set_host_info(info);
 }
@@ -11,11 +11,13 @@ use object::{
 use std::collections::HashMap;
 use wasmtime_environ::isa::TargetIsa;
 
-pub use crate::read_debuginfo::{read_debuginfo, DebugInfoData, WasmFileInfo};
+pub use crate::read_debuginfo::{has_debuginfo, read_debuginfo, DebugInfoData, WasmFileInfo};
+pub use crate::symbolize::{FrameSymbol, ModuleSymbols};
 pub use crate::write_debuginfo::{emit_dwarf, DwarfSection};
 
 mod gc;
 mod read_debuginfo;
+mod symbolize;
 mod transform;
 mod write_debuginfo;
 
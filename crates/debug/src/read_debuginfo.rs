@@ -154,6 +154,28 @@ fn read_name_section(reader: wasmparser::NameSectionReader) -> wasmparser::Resul
     Ok(result)
 }
 
+/// Returns whether the module `data` has any `.debug_*` custom sections,
+/// without reading any of them.
+pub fn has_debuginfo(data: &[u8]) -> bool {
+    let mut reader = match ModuleReader::new(data) {
+        Ok(reader) => reader,
+        Err(_) => return false,
+    };
+    while !reader.eof() {
+        match reader.read() {
+            Ok(section) => {
+                if let SectionCode::Custom { name, .. } = section.code {
+                    if name.starts_with(".debug_") {
+                        return true;
+                    }
+                }
+            }
+            Err(_) => return false,
+        }
+    }
+    false
+}
+
 pub fn read_debuginfo(data: &[u8]) -> Result<DebugInfoData> {
     let mut reader = ModuleReader::new(data)?;
     let mut sections = HashMap::new();
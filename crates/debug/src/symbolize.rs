@@ -0,0 +1,425 @@
+//! Resolution of wasm code offsets back to source locations, using the DWARF
+//! sections embedded in the module.
+//!
+//! Unlike the transformation in `transform`, which rewrites DWARF for native
+//! debuggers, this only extracts what's needed to symbolicate trap
+//! backtraces, into tables that don't borrow from the original wasm binary.
+
+use crate::read_debuginfo::{DebugInfoData, Dwarf};
+use anyhow::Result;
+use gimli::{
+    AttributeValue, ColumnType, DebuggingInformationEntry, EndianSlice, LineProgramHeader,
+    LittleEndian, Unit,
+};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::PathBuf;
+
+type Reader<'input> = EndianSlice<'input, LittleEndian>;
+
+/// Bounds how many `DW_AT_abstract_origin` and `DW_AT_specification` links are
+/// followed when looking for a function's name.
+const MAX_NAME_INDIRECTIONS: usize = 16;
+
+/// Source-level description of one function frame at a wasm code offset.
+#[derive(Debug, Clone)]
+pub struct FrameSymbol {
+    /// The function's name, as recorded in DWARF; this may be mangled.
+    pub name: Option<String>,
+    /// The source file the location is in.
+    pub file: Option<String>,
+    /// The 1-based line number within `file`.
+    pub line: Option<u32>,
+    /// The 1-based column within `line`.
+    pub column: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Location {
+    file: Option<usize>,
+    line: Option<u32>,
+    column: Option<u32>,
+}
+
+#[derive(Debug)]
+struct Function {
+    name: Option<String>,
+    /// For inlined subroutines, the location they were inlined at.
+    call_location: Option<Location>,
+}
+
+#[derive(Debug)]
+struct FunctionRange {
+    begin: u64,
+    end: u64,
+    depth: usize,
+    function: usize,
+}
+
+/// Line and function information of a wasm module, extracted from its
+/// `.debug_line` and `.debug_info` sections.
+#[derive(Debug)]
+pub struct ModuleSymbols {
+    code_section_offset: u64,
+    files: Vec<String>,
+    /// Line table rows sorted by address. A `None` location marks the end of
+    /// a sequence, i.e. that nothing is known from that address onwards.
+    rows: Vec<(u64, Option<Location>)>,
+    /// Code ranges of subprograms and inlined subroutines, sorted by start.
+    ranges: Vec<FunctionRange>,
+    functions: Vec<Function>,
+}
+
+impl ModuleSymbols {
+    /// Extracts symbol information from previously read debug info.
+    ///
+    /// Returns `None` if the module has no line or function information.
+    pub fn new(debug_data: &DebugInfoData) -> Result<Option<Self>> {
+        let dwarf = &debug_data.dwarf;
+        let mut symbols = ModuleSymbols {
+            code_section_offset: debug_data.wasm_file.code_section_offset,
+            files: Vec::new(),
+            rows: Vec::new(),
+            ranges: Vec::new(),
+            functions: Vec::new(),
+        };
+        let mut file_names = HashMap::new();
+        let mut units = dwarf.units();
+        while let Some(header) = units.next()? {
+            let unit = dwarf.unit(header)?;
+            let mut unit_files = HashMap::new();
+            symbols.add_lines(dwarf, &unit, &mut file_names, &mut unit_files)?;
+            symbols.add_functions(dwarf, &unit, &mut file_names, &mut unit_files)?;
+        }
+        if symbols.rows.is_empty() && symbols.ranges.is_empty() {
+            return Ok(None);
+        }
+        symbols.rows.sort_by_key(|(address, _)| *address);
+        symbols.ranges.sort_by_key(|range| range.begin);
+        Ok(Some(symbols))
+    }
+
+    /// Looks up the frames at `module_offset`, an offset from the start of the
+    /// wasm binary.
+    ///
+    /// The innermost frame comes first; when code was inlined, subsequent
+    /// frames describe the functions it was inlined into, each at the location
+    /// of the inlined call. An empty list is returned if nothing is known
+    /// about the offset.
+    pub fn lookup(&self, module_offset: u64) -> Vec<FrameSymbol> {
+        let address = match module_offset.checked_sub(self.code_section_offset) {
+            Some(address) => address,
+            None => return Vec::new(),
+        };
+        let mut location = self.find_location(address);
+
+        let mut scopes = self
+            .ranges
+            .iter()
+            .take_while(|range| range.begin <= address)
+            .filter(|range| address < range.end)
+            .collect::<Vec<_>>();
+        if scopes.is_empty() {
+            return location
+                .map(|location| vec![self.symbol(None, Some(location))])
+                .unwrap_or_default();
+        }
+        scopes.sort_by_key(|range| std::cmp::Reverse(range.depth));
+
+        let mut symbols = Vec::with_capacity(scopes.len());
+        for scope in scopes {
+            let function = &self.functions[scope.function];
+            symbols.push(self.symbol(function.name.clone(), location));
+            location = function.call_location;
+        }
+        symbols
+    }
+
+    /// Returns the source lines of the code between `module_offsets`, offsets
+    /// from the start of the wasm binary, in the order of the line table.
+    ///
+    /// Unlike `lookup` this doesn't go through inlined frames, so each line
+    /// is that of the innermost function.
+    pub fn lines(&self, module_offsets: Range<u64>) -> Vec<(&str, u32)> {
+        let start = module_offsets
+            .start
+            .saturating_sub(self.code_section_offset);
+        let end = match module_offsets.end.checked_sub(self.code_section_offset) {
+            Some(end) => end,
+            None => return Vec::new(),
+        };
+        // Start from the row in effect at `start`, which is the first row at
+        // that address or else the last one before it.
+        let first = match self.rows.binary_search_by_key(&start, |(a, _)| *a) {
+            Ok(mut index) => {
+                while index > 0 && self.rows[index - 1].0 == start {
+                    index -= 1;
+                }
+                index
+            }
+            Err(0) => 0,
+            Err(index) => index - 1,
+        };
+
+        let mut lines: Vec<(&str, u32)> = Vec::new();
+        for (_, location) in self.rows[first..].iter().take_while(|(a, _)| *a < end) {
+            let (file, line) = match location {
+                Some(Location {
+                    file: Some(file),
+                    line: Some(line),
+                    ..
+                }) => (&self.files[*file][..], *line),
+                _ => continue,
+            };
+            if lines.last() != Some(&(file, line)) {
+                lines.push((file, line));
+            }
+        }
+        lines
+    }
+
+    fn find_location(&self, address: u64) -> Option<Location> {
+        let index = match self.rows.binary_search_by_key(&address, |(a, _)| *a) {
+            Ok(mut index) => {
+                // Several rows may share an address; the last one wins.
+                while index + 1 < self.rows.len() && self.rows[index + 1].0 == address {
+                    index += 1;
+                }
+                index
+            }
+            Err(0) => return None,
+            Err(index) => index - 1,
+        };
+        self.rows[index].1
+    }
+
+    fn symbol(&self, name: Option<String>, location: Option<Location>) -> FrameSymbol {
+        let location = location.unwrap_or(Location {
+            file: None,
+            line: None,
+            column: None,
+        });
+        FrameSymbol {
+            name,
+            file: location.file.map(|index| self.files[index].clone()),
+            line: location.line,
+            column: location.column,
+        }
+    }
+
+    fn add_lines(
+        &mut self,
+        dwarf: &Dwarf,
+        unit: &Unit<Reader>,
+        file_names: &mut HashMap<String, usize>,
+        unit_files: &mut HashMap<u64, Option<usize>>,
+    ) -> Result<()> {
+        let program = match unit.line_program.clone() {
+            Some(program) => program,
+            None => return Ok(()),
+        };
+        let mut rows = program.rows();
+        let mut sequence: Vec<(u64, Option<Location>)> = Vec::new();
+        while let Some((header, row)) = rows.next_row()? {
+            if row.end_sequence() {
+                // The linker leaves sequences of functions it discarded at
+                // address zero, where they'd shadow the real code.
+                if !sequence.is_empty() && sequence[0].0 != 0 {
+                    self.rows.append(&mut sequence);
+                    self.rows.push((row.address(), None));
+                }
+                sequence.clear();
+                continue;
+            }
+            let file = self.file(
+                dwarf,
+                unit,
+                header,
+                row.file_index(),
+                file_names,
+                unit_files,
+            )?;
+            let column = match row.column() {
+                ColumnType::LeftEdge => None,
+                ColumnType::Column(column) => Some(column as u32),
+            };
+            let location = Location {
+                file,
+                line: row.line().map(|line| line as u32),
+                column,
+            };
+            sequence.push((row.address(), Some(location)));
+        }
+        Ok(())
+    }
+
+    fn add_functions(
+        &mut self,
+        dwarf: &Dwarf,
+        unit: &Unit<Reader>,
+        file_names: &mut HashMap<String, usize>,
+        unit_files: &mut HashMap<u64, Option<usize>>,
+    ) -> Result<()> {
+        let header = unit.line_program.as_ref().map(|program| program.header());
+        let mut entries = unit.entries();
+        let mut depth = 0;
+        while let Some((delta, entry)) = entries.next_dfs()? {
+            depth += delta;
+            let is_inlined = match entry.tag() {
+                gimli::DW_TAG_subprogram => false,
+                gimli::DW_TAG_inlined_subroutine => true,
+                _ => continue,
+            };
+
+            let function = self.functions.len();
+            let ranges_len = self.ranges.len();
+            let mut ranges = dwarf.die_ranges(unit, entry)?;
+            while let Some(range) = ranges.next()? {
+                // As with line sequences, ranges at address zero are left
+                // behind for discarded functions.
+                if range.begin == 0 || range.begin >= range.end {
+                    continue;
+                }
+                self.ranges.push(FunctionRange {
+                    begin: range.begin,
+                    end: range.end,
+                    depth: depth as usize,
+                    function,
+                });
+            }
+            if self.ranges.len() == ranges_len {
+                continue;
+            }
+
+            let name = function_name(dwarf, unit, entry, 0)?;
+            let call_location = match (is_inlined, header) {
+                (true, Some(header)) => {
+                    let file = match entry.attr_value(gimli::DW_AT_call_file)? {
+                        Some(AttributeValue::FileIndex(index)) => {
+                            self.file(dwarf, unit, header, index, file_names, unit_files)?
+                        }
+                        _ => None,
+                    };
+                    let line = entry.attr(gimli::DW_AT_call_line)?;
+                    let column = entry.attr(gimli::DW_AT_call_column)?;
+                    Some(Location {
+                        file,
+                        line: line.and_then(|a| a.udata_value()).map(|l| l as u32),
+                        column: column.and_then(|a| a.udata_value()).map(|c| c as u32),
+                    })
+                }
+                _ => None,
+            };
+            self.functions.push(Function {
+                name,
+                call_location,
+            });
+        }
+        Ok(())
+    }
+
+    /// Resolves a line program file index to an index into `self.files`.
+    fn file(
+        &mut self,
+        dwarf: &Dwarf,
+        unit: &Unit<Reader>,
+        header: &LineProgramHeader<Reader>,
+        index: u64,
+        file_names: &mut HashMap<String, usize>,
+        unit_files: &mut HashMap<u64, Option<usize>>,
+    ) -> Result<Option<usize>> {
+        if let Some(file) = unit_files.get(&index) {
+            return Ok(*file);
+        }
+        let file = match header.file(index) {
+            Some(file) => {
+                let mut path = PathBuf::new();
+                if let Some(comp_dir) = &unit.comp_dir {
+                    path.push(&*comp_dir.to_string_lossy());
+                }
+                // Directory zero is the compilation directory, pushed above.
+                if file.directory_index() != 0 {
+                    if let Some(directory) = file.directory(header) {
+                        path.push(&*dwarf.attr_string(unit, directory)?.to_string_lossy());
+                    }
+                }
+                path.push(&*dwarf.attr_string(unit, file.path_name())?.to_string_lossy());
+                let path = path.to_string_lossy().into_owned();
+                let index = match file_names.get(&path) {
+                    Some(index) => *index,
+                    None => {
+                        self.files.push(path.clone());
+                        file_names.insert(path, self.files.len() - 1);
+                        self.files.len() - 1
+                    }
+                };
+                Some(index)
+            }
+            None => None,
+        };
+        unit_files.insert(index, file);
+        Ok(file)
+    }
+}
+
+fn function_name(
+    dwarf: &Dwarf,
+    unit: &Unit<Reader>,
+    entry: &DebuggingInformationEntry<Reader>,
+    indirections: usize,
+) -> Result<Option<String>> {
+    for name in &[
+        gimli::DW_AT_linkage_name,
+        gimli::DW_AT_MIPS_linkage_name,
+        gimli::DW_AT_name,
+    ] {
+        if let Some(value) = entry.attr_value(*name)? {
+            let name = dwarf.attr_string(unit, value)?;
+            return Ok(Some(name.to_string_lossy().into_owned()));
+        }
+    }
+    if indirections < MAX_NAME_INDIRECTIONS {
+        for origin in &[gimli::DW_AT_abstract_origin, gimli::DW_AT_specification] {
+            if let Some(AttributeValue::UnitRef(offset)) = entry.attr_value(*origin)? {
+                let entry = unit.entry(offset)?;
+                return function_name(dwarf, unit, &entry, indirections + 1);
+            }
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ModuleSymbols;
+    use crate::read_debuginfo::{has_debuginfo, read_debuginfo};
+
+    #[test]
+    fn lookup_fib() {
+        let wasm = include_bytes!("../../../tests/all/debug/testsuite/fib-wasm.wasm");
+        let debug_data = read_debuginfo(wasm).unwrap();
+        let code_section_offset = debug_data.wasm_file.code_section_offset;
+        let symbols = ModuleSymbols::new(&debug_data).unwrap().unwrap();
+
+        // `for (int i = 0; i < n; i++)`, at `i < n`.
+        let frames = symbols.lookup(code_section_offset + 0x3d);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].name.as_deref(), Some("fib"));
+        assert_eq!(frames[0].file.as_deref(), Some("./fib-wasm.c"));
+        assert_eq!(frames[0].line, Some(10));
+        assert_eq!(frames[0].column, Some(19));
+
+        // Past the end of the last sequence.
+        assert!(symbols.lookup(code_section_offset + 0xce).is_empty());
+        assert!(symbols.lookup(0).is_empty());
+    }
+
+    #[test]
+    fn only_modules_with_dwarf_have_debuginfo() {
+        let wasm = include_bytes!("../../../tests/all/debug/testsuite/fib-wasm.wasm");
+        assert!(has_debuginfo(wasm));
+        // An empty module: just the magic number and version.
+        assert!(!has_debuginfo(b"\0asm\x01\0\0\0"));
+        assert!(!has_debuginfo(b"not wasm"));
+    }
+}
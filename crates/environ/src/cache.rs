@@ -3,26 +3,29 @@ use crate::compilation::{Compilation, Relocations, StackMaps, Traps};
 use cranelift_codegen::ir;
 use cranelift_entity::PrimaryMap;
 use cranelift_wasm::DefinedFuncIndex;
-use log::{debug, trace, warn};
+use log::{trace, warn};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::fs;
 use std::hash::Hash;
 use std::hash::Hasher;
 use std::io::Write;
-use std::path::{Path, PathBuf};
+use std::path::Path;
 
 #[macro_use] // for tests
 mod config;
+mod store;
 mod worker;
 
 pub use config::{create_new_config, CacheConfig};
+use store::FileCacheStore;
+pub use store::{CacheStore, InMemoryCacheStore};
 use worker::Worker;
 
 pub struct ModuleCacheEntry<'config>(Option<ModuleCacheEntryInner<'config>>);
 
 struct ModuleCacheEntryInner<'config> {
-    root_path: PathBuf,
+    compiler_dir: String,
     cache_config: &'config CacheConfig,
 }
 
@@ -84,16 +87,14 @@ impl<'config> ModuleCacheEntry<'config> {
             None => return compute(state).map(ModuleCacheData::from_tuple),
         };
 
-        if let Some(cached_val) = inner.get_data(&hash) {
-            let mod_cache_path = inner.root_path.join(&hash);
-            inner.cache_config.on_cache_get_async(&mod_cache_path); // call on success
+        let key = format!("{}/{}", inner.compiler_dir, hash);
+        if let Some(cached_val) = inner.get_data(&key) {
+            inner.cache_config.on_cache_hit();
             return Ok(cached_val);
         }
         let val_to_cache = ModuleCacheData::from_tuple(compute(state)?);
-        if inner.update_data(&hash, &val_to_cache).is_some() {
-            let mod_cache_path = inner.root_path.join(&hash);
-            inner.cache_config.on_cache_update_async(&mod_cache_path); // call on success
-        }
+        inner.cache_config.on_cache_miss();
+        inner.update_data(&key, &val_to_cache);
         Ok(val_to_cache)
     }
 }
@@ -133,67 +134,43 @@ impl<'config> ModuleCacheEntryInner<'config> {
                 comp_ver = env!("GIT_REV"),
             )
         };
-        let root_path = cache_config.directory().join("modules").join(compiler_dir);
 
         Self {
-            root_path,
+            compiler_dir,
             cache_config,
         }
     }
 
-    fn get_data(&self, hash: &str) -> Option<ModuleCacheData> {
-        let mod_cache_path = self.root_path.join(hash);
-        trace!("get_data() for path: {}", mod_cache_path.display());
-        let compressed_cache_bytes = fs::read(&mod_cache_path).ok()?;
-        let cache_bytes = zstd::decode_all(&compressed_cache_bytes[..])
-            .map_err(|err| warn!("Failed to decompress cached code: {}", err))
-            .ok()?;
-        bincode::deserialize(&cache_bytes[..])
-            .map_err(|err| warn!("Failed to deserialize cached code: {}", err))
-            .ok()
+    fn get_data(&self, key: &str) -> Option<ModuleCacheData> {
+        trace!("get_data() for key: {}", key);
+        let store = self.cache_config.store();
+        let cache_bytes = store
+            .get(key)
+            .map_err(|err| warn!("Failed to read cached code, key: {}, err: {:?}", key, err))
+            .ok()??;
+        match bincode::deserialize(&cache_bytes[..]) {
+            Ok(data) => Some(data),
+            Err(err) => {
+                warn!("Failed to deserialize cached code: {}", err);
+                // Don't let a corrupt entry shadow the fresh one about to be computed.
+                if let Err(err) = store.evict(key) {
+                    warn!("Failed to evict cached code, key: {}, err: {:?}", key, err);
+                }
+                None
+            }
+        }
     }
 
-    fn update_data(&self, hash: &str, data: &ModuleCacheData) -> Option<()> {
-        let mod_cache_path = self.root_path.join(hash);
-        trace!("update_data() for path: {}", mod_cache_path.display());
+    fn update_data(&self, key: &str, data: &ModuleCacheData) -> Option<()> {
+        trace!("update_data() for key: {}", key);
         let serialized_data = bincode::serialize(&data)
             .map_err(|err| warn!("Failed to serialize cached code: {}", err))
             .ok()?;
-        let compressed_data = zstd::encode_all(
-            &serialized_data[..],
-            self.cache_config.baseline_compression_level(),
-        )
-        .map_err(|err| warn!("Failed to compress cached code: {}", err))
-        .ok()?;
-
-        // Optimize syscalls: first, try writing to disk. It should succeed in most cases.
-        // Otherwise, try creating the cache directory and retry writing to the file.
-        if fs_write_atomic(&mod_cache_path, "mod", &compressed_data) {
-            return Some(());
-        }
-
-        debug!(
-            "Attempting to create the cache directory, because \
-             failed to write cached code to disk, path: {}",
-            mod_cache_path.display(),
-        );
-
-        let cache_dir = mod_cache_path.parent().unwrap();
-        fs::create_dir_all(cache_dir)
-            .map_err(|err| {
-                warn!(
-                    "Failed to create cache directory, path: {}, message: {}",
-                    cache_dir.display(),
-                    err
-                )
-            })
-            .ok()?;
-
-        if fs_write_atomic(&mod_cache_path, "mod", &compressed_data) {
-            Some(())
-        } else {
-            None
-        }
+        self.cache_config
+            .store()
+            .insert(key, serialized_data)
+            .map_err(|err| warn!("Failed to write cached code, key: {}, err: {:?}", key, err))
+            .ok()
     }
 }
 
@@ -1,6 +1,6 @@
 //! Module for configuring the cache system.
 
-use super::Worker;
+use super::{CacheStore, FileCacheStore, Worker};
 use anyhow::{anyhow, bail, Context, Result};
 use directories::ProjectDirs;
 use log::{trace, warn};
@@ -91,6 +91,8 @@ pub struct CacheConfig {
     #[serde(skip)]
     worker: Option<Worker>,
     #[serde(skip)]
+    store: Option<Arc<dyn CacheStore>>,
+    #[serde(skip)]
     state: Arc<CacheState>,
 }
 
@@ -332,10 +334,22 @@ impl CacheConfig {
             file_count_limit_percent_if_deleting: None,
             files_total_size_limit_percent_if_deleting: None,
             worker: None,
+            store: None,
             state: Arc::new(CacheState::default()),
         }
     }
 
+    /// Creates a new set of configuration for a cache kept in `store`
+    /// instead of the cache directory.
+    ///
+    /// The settings for the directory and the worker which cleans it up
+    /// don't apply to such a cache, and their getters panic.
+    pub fn new_cache_with_store(store: Arc<dyn CacheStore>) -> Self {
+        let mut conf = Self::new_cache_enabled_template();
+        conf.store = Some(store);
+        conf
+    }
+
     fn new_cache_enabled_template() -> Self {
         let mut conf = Self::new_cache_disabled();
         conf.enabled = true;
@@ -360,6 +374,7 @@ impl CacheConfig {
         config.validate_file_count_limit_percent_if_deleting_or_default()?;
         config.validate_files_total_size_limit_percent_if_deleting_or_default()?;
         config.spawn_worker();
+        config.create_file_store();
 
         Ok(config)
     }
@@ -370,6 +385,14 @@ impl CacheConfig {
         }
     }
 
+    // Note: the worker keeps a copy of the configuration from before this,
+    // so it doesn't keep itself alive through the store.
+    fn create_file_store(&mut self) {
+        if self.enabled {
+            self.store = Some(Arc::new(FileCacheStore::new(self)));
+        }
+    }
+
     pub(super) fn worker(&self) -> &Worker {
         assert!(self.enabled);
         self.worker.as_ref().unwrap()
@@ -385,14 +408,17 @@ impl CacheConfig {
         self.state.misses.load(SeqCst)
     }
 
-    pub(crate) fn on_cache_get_async(&self, path: impl AsRef<Path>) {
+    pub(super) fn store(&self) -> &dyn CacheStore {
+        assert!(self.enabled);
+        &**self.store.as_ref().unwrap()
+    }
+
+    pub(super) fn on_cache_hit(&self) {
         self.state.hits.fetch_add(1, SeqCst);
-        self.worker().on_cache_get_async(path)
     }
 
-    pub(crate) fn on_cache_update_async(&self, path: impl AsRef<Path>) {
+    pub(super) fn on_cache_miss(&self) {
         self.state.misses.fetch_add(1, SeqCst);
-        self.worker().on_cache_update_async(path)
     }
 
     fn load_and_parse_file(config_file: Option<&Path>) -> Result<Self> {
@@ -0,0 +1,138 @@
+//! Storage backends for the compilation cache.
+
+use super::{fs_write_atomic, CacheConfig, Worker};
+use anyhow::{bail, Context, Result};
+use log::{debug, trace};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Storage for the compiled artifacts in the compilation cache.
+///
+/// Keys are made of URL-safe base64 characters, `-`, `.` and a single `/`
+/// separating the name and version of the compiler from a hash of its
+/// inputs, so an artifact can only be found again by the same version of the
+/// compiler. Values are opaque serialized artifacts.
+///
+/// Errors returned by a store are logged and otherwise treated as cache
+/// misses, so an unavailable store only makes compilation slower.
+pub trait CacheStore: Debug + Send + Sync {
+    /// Returns the value stored under `key`, or `None` if there is none.
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+
+    /// Stores `value` under `key`, replacing any previous value.
+    fn insert(&self, key: &str, value: Vec<u8>) -> Result<()>;
+
+    /// Removes the value stored under `key`, if any.
+    fn evict(&self, key: &str) -> Result<()>;
+}
+
+/// The default cache store, which keeps zstd-compressed artifacts as files in
+/// the cache directory, and lets the cache worker recompress and clean them
+/// up.
+#[derive(Debug)]
+pub(super) struct FileCacheStore {
+    root_path: PathBuf,
+    compression_level: i32,
+    worker: Worker,
+}
+
+impl FileCacheStore {
+    pub(super) fn new(cache_config: &CacheConfig) -> Self {
+        Self {
+            root_path: cache_config.directory().join("modules"),
+            compression_level: cache_config.baseline_compression_level(),
+            worker: cache_config.worker().clone(),
+        }
+    }
+}
+
+impl CacheStore for FileCacheStore {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let mod_cache_path = self.root_path.join(key);
+        trace!("get() for path: {}", mod_cache_path.display());
+        let compressed_cache_bytes = match fs::read(&mod_cache_path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let cache_bytes = zstd::decode_all(&compressed_cache_bytes[..])
+            .context("failed to decompress cached code")?;
+        self.worker.on_cache_get_async(&mod_cache_path);
+        Ok(Some(cache_bytes))
+    }
+
+    fn insert(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        let mod_cache_path = self.root_path.join(key);
+        trace!("insert() for path: {}", mod_cache_path.display());
+        let compressed_data = zstd::encode_all(&value[..], self.compression_level)
+            .context("failed to compress cached code")?;
+
+        // Optimize syscalls: first, try writing to disk. It should succeed in most cases.
+        // Otherwise, try creating the cache directory and retry writing to the file.
+        if !fs_write_atomic(&mod_cache_path, "mod", &compressed_data) {
+            debug!(
+                "Attempting to create the cache directory, because \
+                 failed to write cached code to disk, path: {}",
+                mod_cache_path.display(),
+            );
+
+            let cache_dir = mod_cache_path.parent().unwrap();
+            fs::create_dir_all(cache_dir).with_context(|| {
+                format!("failed to create cache directory {}", cache_dir.display())
+            })?;
+
+            if !fs_write_atomic(&mod_cache_path, "mod", &compressed_data) {
+                bail!("failed to write {}", mod_cache_path.display());
+            }
+        }
+
+        self.worker.on_cache_update_async(&mod_cache_path);
+        Ok(())
+    }
+
+    fn evict(&self, key: &str) -> Result<()> {
+        let mod_cache_path = self.root_path.join(key);
+        trace!("evict() for path: {}", mod_cache_path.display());
+        match fs::remove_file(&mod_cache_path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A cache store which keeps artifacts in memory, for as long as it lives.
+///
+/// This is useful to share compiled code between engines in a process
+/// without touching the filesystem, and as a starting point for stores
+/// backed by other services.
+#[derive(Debug, Default)]
+pub struct InMemoryCacheStore {
+    entries: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl InMemoryCacheStore {
+    /// Creates a new, empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl CacheStore for InMemoryCacheStore {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.entries.lock().unwrap().get(key).cloned())
+    }
+
+    fn insert(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        self.entries.lock().unwrap().insert(key.to_string(), value);
+        Ok(())
+    }
+
+    fn evict(&self, key: &str) -> Result<()> {
+        self.entries.lock().unwrap().remove(key);
+        Ok(())
+    }
+}
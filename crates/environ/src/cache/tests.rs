@@ -2,6 +2,7 @@ use super::config::tests::test_prolog;
 use super::*;
 use cranelift_entity::PrimaryMap;
 use std::fs;
+use std::sync::{Arc, Mutex};
 
 // Since cache system is a global thing, each test needs to be run in seperate process.
 // So, init() tests are run as integration tests.
@@ -92,6 +93,56 @@ fn test_write_read_cache() {
     entry2.get_data::<_, i32>(1, |_| panic!()).unwrap();
 }
 
+// A custom store which remembers the keys it was asked to insert.
+#[derive(Debug, Default)]
+struct KeyRecordingStore {
+    inner: InMemoryCacheStore,
+    keys: Mutex<Vec<String>>,
+}
+
+impl CacheStore for KeyRecordingStore {
+    fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        self.inner.get(key)
+    }
+
+    fn insert(&self, key: &str, value: Vec<u8>) -> anyhow::Result<()> {
+        self.keys.lock().unwrap().push(key.to_string());
+        self.inner.insert(key, value)
+    }
+
+    fn evict(&self, key: &str) -> anyhow::Result<()> {
+        self.inner.evict(key)
+    }
+}
+
+#[test]
+fn test_custom_store() {
+    let store = Arc::new(KeyRecordingStore::default());
+    let cache_config = CacheConfig::new_cache_with_store(store.clone());
+    let entry = ModuleCacheEntry::new("test", &cache_config);
+
+    entry.get_data(1, |_| new_module_cache_data()).unwrap();
+    entry.get_data::<_, i32>(1, |_| panic!()).unwrap();
+    assert_eq!(cache_config.cache_misses(), 1);
+    assert_eq!(cache_config.cache_hits(), 1);
+
+    let key = store.keys.lock().unwrap()[0].clone();
+    assert!(key.starts_with("test-"));
+
+    // evicted entries are recomputed
+    store.evict(&key).unwrap();
+    entry.get_data(1, |_| new_module_cache_data()).unwrap();
+    assert_eq!(cache_config.cache_misses(), 2);
+
+    // corrupt entries are recomputed and replaced
+    store.insert(&key, vec![1, 2, 3]).unwrap();
+    entry.get_data(1, |_| new_module_cache_data()).unwrap();
+    entry.get_data::<_, i32>(1, |_| panic!()).unwrap();
+    assert_eq!(cache_config.cache_misses(), 3);
+    assert_eq!(cache_config.cache_hits(), 2);
+    assert!(store.keys.lock().unwrap().iter().all(|k| *k == key));
+}
+
 fn new_module_cache_data() -> Result<ModuleCacheDataTupleType, ()> {
     Ok((
         Compilation::new(PrimaryMap::new()),
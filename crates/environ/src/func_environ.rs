@@ -9,13 +9,15 @@ use cranelift_codegen::ir::types::*;
 use cranelift_codegen::ir::{AbiParam, ArgumentPurpose, Function, InstBuilder, Signature};
 use cranelift_codegen::isa::{self, TargetFrontendConfig};
 use cranelift_entity::EntityRef;
+use cranelift_frontend::FunctionBuilder;
 use cranelift_wasm::{
-    self, FuncIndex, GlobalIndex, GlobalVariable, MemoryIndex, SignatureIndex, TableIndex,
-    TargetEnvironment, WasmError, WasmResult, WasmType,
+    self, FuncIndex, FuncTranslationState, GlobalIndex, GlobalVariable, MemoryIndex,
+    SignatureIndex, TableIndex, TargetEnvironment, WasmError, WasmResult, WasmType,
 };
 #[cfg(feature = "lightbeam")]
 use cranelift_wasm::{DefinedFuncIndex, DefinedGlobalIndex, DefinedMemoryIndex, DefinedTableIndex};
 use std::convert::TryFrom;
+use wasmparser::Operator;
 
 /// Compute an `ir::ExternalName` for a given wasm function index.
 pub fn get_func_name(func_index: FuncIndex) -> ir::ExternalName {
@@ -76,6 +78,10 @@ macro_rules! declare_builtin_functions {
                 AbiParam::new(I32)
             }
 
+            fn i64(&self) -> AbiParam {
+                AbiParam::new(I64)
+            }
+
             $(
                 fn $name(&mut self, func: &mut Function) -> ir::SigRef {
                     let sig = self.$name.unwrap_or_else(|| {
@@ -169,6 +175,66 @@ declare_builtin_functions! {
     table_grow_funcref(vmctx, i32, i32, pointer) -> (i32);
     /// Returns an index for Wasm's `table.grow` instruction for `externref`s.
     table_grow_externref(vmctx, i32, i32, reference) -> (i32);
+    /// Returns an index for Wasm's `table.fill` instruction for `funcref`s.
+    table_fill_funcref(vmctx, i32, i32, pointer, i32) -> ();
+    /// Returns an index for Wasm's `table.fill` instruction for `externref`s.
+    table_fill_externref(vmctx, i32, i32, reference, i32) -> ();
+    /// Returns an index for Wasm's `table.get` instruction for `externref`s.
+    table_get_externref(vmctx, i32, i32) -> (reference);
+    /// Returns an index for Wasm's `table.set` instruction for `externref`s.
+    table_set_externref(vmctx, i32, i32, reference) -> ();
+    /// Returns an index for Wasm's `global.get` instruction for `externref`s.
+    externref_global_get(vmctx, i32) -> (reference);
+    /// Returns an index for Wasm's `global.set` instruction for `externref`s.
+    externref_global_set(vmctx, i32, reference) -> ();
+    /// Returns an index for wasm's `memory.atomic.notify` instruction.
+    memory_atomic_notify(vmctx, i32, i64, i32) -> (i32);
+    /// Returns an index for wasm's `memory.atomic.wait32` instruction.
+    memory_atomic_wait32(vmctx, i32, i64, i32, i64) -> (i32);
+    /// Returns an index for wasm's `memory.atomic.wait64` instruction.
+    memory_atomic_wait64(vmctx, i32, i64, i64, i64) -> (i32);
+    /// Returns an index for the builtin invoked when the epoch deadline has
+    /// been reached, which returns the new deadline.
+    new_epoch(vmctx) -> (i64);
+    /// Returns an index for the builtin invoked before every instruction when
+    /// guest debugging is enabled.
+    debug_hook(vmctx, i32, pointer, i32) -> ();
+}
+
+/// The type of a local's value as passed to the `debug_hook` builtin.
+///
+/// The hook receives a pointer to `n` 16-byte little-endian value slots, one
+/// per local, followed by `n` bytes holding each local's type.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum DebugLocalType {
+    /// An `i32` in the low 4 bytes of its slot.
+    I32 = 0,
+    /// An `i64` in the low 8 bytes of its slot.
+    I64 = 1,
+    /// The bits of an `f32` in the low 4 bytes of its slot.
+    F32 = 2,
+    /// The bits of an `f64` in the low 8 bytes of its slot.
+    F64 = 3,
+    /// A `v128` filling its slot.
+    V128 = 4,
+    /// A reference, whose value isn't recorded.
+    Unavailable = 5,
+}
+
+impl DebugLocalType {
+    /// Decodes a type byte written by compiled code.
+    pub fn from_u8(byte: u8) -> Option<Self> {
+        Some(match byte {
+            0 => DebugLocalType::I32,
+            1 => DebugLocalType::I64,
+            2 => DebugLocalType::F32,
+            3 => DebugLocalType::F64,
+            4 => DebugLocalType::V128,
+            5 => DebugLocalType::Unavailable,
+            _ => return None,
+        })
+    }
 }
 
 impl BuiltinFunctionIndex {
@@ -201,6 +267,26 @@ pub struct FuncEnvironment<'module_environment> {
     pub(crate) offsets: VMOffsets,
 
     tunables: &'module_environment Tunables,
+
+    /// The amount of fuel consumed by instructions translated so far which
+    /// hasn't yet been added to the `VMInterrupts` fuel counter.
+    ///
+    /// This is flushed whenever control flow may leave the current basic
+    /// block, and is unused unless `tunables.consume_fuel` is enabled.
+    fuel_consumed: i64,
+
+    /// The number of locals, including parameters, of the function being
+    /// translated.
+    num_locals: usize,
+
+    /// The stack slot the values of locals are spilled to for each call to
+    /// the debug hook, created on first use when `tunables.guest_debug` is
+    /// enabled.
+    debug_locals_slot: Option<ir::StackSlot>,
+
+    /// The stack slot holding this function's `VMFrameRecord`, created on
+    /// entry when `tunables.record_frames` is enabled.
+    frame_record_slot: Option<ir::StackSlot>,
 }
 
 impl<'module_environment> FuncEnvironment<'module_environment> {
@@ -225,6 +311,12 @@ impl<'module_environment> FuncEnvironment<'module_environment> {
             builtin_function_signatures,
             offsets: VMOffsets::new(target_config.pointer_bytes(), module),
             tunables,
+            // Start with one unit of fuel consumed to account for the call
+            // into the function itself.
+            fuel_consumed: 1,
+            num_locals: 0,
+            debug_locals_slot: None,
+            frame_record_slot: None,
         }
     }
 
@@ -370,6 +462,34 @@ impl<'module_environment> FuncEnvironment<'module_environment> {
         )
     }
 
+    fn get_memory_atomic_notify_func(
+        &mut self,
+        func: &mut Function,
+    ) -> (ir::SigRef, BuiltinFunctionIndex) {
+        (
+            self.builtin_function_signatures.memory_atomic_notify(func),
+            BuiltinFunctionIndex::memory_atomic_notify(),
+        )
+    }
+
+    fn get_memory_atomic_wait_func(
+        &mut self,
+        func: &mut Function,
+        expected_ty: ir::Type,
+    ) -> (ir::SigRef, BuiltinFunctionIndex) {
+        match expected_ty {
+            I32 => (
+                self.builtin_function_signatures.memory_atomic_wait32(func),
+                BuiltinFunctionIndex::memory_atomic_wait32(),
+            ),
+            I64 => (
+                self.builtin_function_signatures.memory_atomic_wait64(func),
+                BuiltinFunctionIndex::memory_atomic_wait64(),
+            ),
+            _ => panic!("unexpected type for memory.atomic.wait: {}", expected_ty),
+        }
+    }
+
     /// Translates load of builtin function and returns a pair of values `vmctx`
     /// and address of the loaded function.
     fn translate_load_builtin_function_address(
@@ -392,6 +512,270 @@ impl<'module_environment> FuncEnvironment<'module_environment> {
 
         (base, func_addr)
     }
+
+    /// Loads the address of this instance's `VMInterrupts`.
+    fn vminterrupts_ptr(&mut self, pos: &mut FuncCursor<'_>) -> ir::Value {
+        let vmctx = self.vmctx(&mut pos.func);
+        let pointer_type = self.pointer_type();
+        let base = pos.ins().global_value(pointer_type, vmctx);
+        let offset = i32::try_from(self.offsets.vmctx_interrupts()).unwrap();
+        pos.ins()
+            .load(pointer_type, ir::MemFlags::trusted(), base, offset)
+    }
+
+    /// Loads the address of this instance's `VMInterrupts` and returns it
+    /// along with the offset of its `fuel_consumed` field.
+    fn fuel_addr_offset(&mut self, pos: &mut FuncCursor<'_>) -> (ir::Value, i32) {
+        (
+            self.vminterrupts_ptr(pos),
+            i32::from(self.offsets.vminterrupts_fuel_consumed()),
+        )
+    }
+
+    /// Adds the fuel consumed by the instructions translated since the last
+    /// flush to the fuel counter in `VMInterrupts`.
+    fn fuel_increment(&mut self, pos: &mut FuncCursor<'_>) {
+        if self.fuel_consumed == 0 {
+            return;
+        }
+        let (addr, offset) = self.fuel_addr_offset(pos);
+        let fuel = pos.ins().load(I64, ir::MemFlags::trusted(), addr, offset);
+        let fuel = pos.ins().iadd_imm(fuel, self.fuel_consumed);
+        pos.ins().store(ir::MemFlags::trusted(), fuel, addr, offset);
+        self.fuel_consumed = 0;
+    }
+
+    /// Emits a check which traps if more fuel has been consumed than was
+    /// provided.
+    ///
+    /// The fuel counter starts out as the negation of the fuel available and
+    /// counts upwards, so exhaustion is a positive counter.
+    fn fuel_check(&mut self, pos: &mut FuncCursor<'_>) {
+        let (addr, offset) = self.fuel_addr_offset(pos);
+        let fuel = pos.ins().load(I64, ir::MemFlags::trusted(), addr, offset);
+        let cmp = pos.ins().icmp_imm(IntCC::SignedGreaterThan, fuel, 0);
+        pos.ins().trapnz(cmp, ir::TrapCode::OutOfFuel);
+    }
+
+    fn fuel_before_op(&mut self, op: &Operator<'_>, builder: &mut FunctionBuilder<'_>) {
+        self.fuel_consumed += match op {
+            // Nop and drop generate no code, so don't consume fuel for them.
+            Operator::Nop | Operator::Drop => 0,
+
+            // Structured control flow doesn't do any work on its own, so it's
+            // free. Note that `if` and the branches are not listed here since
+            // they perform a conditional check or a jump.
+            Operator::Block { .. }
+            | Operator::Loop { .. }
+            | Operator::Unreachable
+            | Operator::Return
+            | Operator::Else
+            | Operator::End => 0,
+
+            _ => 1,
+        };
+
+        match op {
+            // These operators end the current basic block, or transfer control
+            // to another function which will read the fuel counter, so the
+            // counter in memory needs to be brought up to date first.
+            Operator::Unreachable
+            | Operator::Return
+            | Operator::Br { .. }
+            | Operator::BrIf { .. }
+            | Operator::BrTable { .. }
+            | Operator::If { .. }
+            | Operator::Else
+            | Operator::End
+            | Operator::Loop { .. }
+            | Operator::Call { .. }
+            | Operator::CallIndirect { .. } => {
+                self.fuel_increment(&mut builder.cursor());
+            }
+            _ => {}
+        }
+    }
+
+    /// Increments the coverage counter of the basic block starting at the
+    /// builder's current source location, if any.
+    fn coverage_increment(&mut self, builder: &mut FunctionBuilder<'_>) {
+        let srcloc = builder.srcloc().bits();
+        let index = match self
+            .module
+            .coverage_blocks
+            .binary_search_by_key(&srcloc, |(start, _)| *start)
+        {
+            Ok(index) => index,
+            Err(_) => return,
+        };
+        let vmctx = self.vmctx(&mut builder.func);
+        let base = builder.ins().global_value(self.pointer_type(), vmctx);
+        let offset = i32::try_from(self.offsets.vmctx_coverage_counter(index as u32)).unwrap();
+        let count = builder
+            .ins()
+            .load(I64, ir::MemFlags::trusted(), base, offset);
+        let count = builder.ins().iadd_imm(count, 1);
+        builder
+            .ins()
+            .store(ir::MemFlags::trusted(), count, base, offset);
+    }
+
+    /// Emits a call to the debug hook for the instruction at the builder's
+    /// current source location, passing along the values of all locals.
+    fn debug_hook(&mut self, builder: &mut FunctionBuilder<'_>) {
+        let locals = self.spill_locals(builder);
+        let num_locals = self.num_locals;
+        let offset = builder.srcloc().bits();
+        let mut pos = builder.cursor();
+        let func_sig = self.builtin_function_signatures.debug_hook(&mut pos.func);
+        let (vmctx, func_addr) = self
+            .translate_load_builtin_function_address(&mut pos, BuiltinFunctionIndex::debug_hook());
+        let offset = pos.ins().iconst(I32, i64::from(offset));
+        let num_locals = pos.ins().iconst(I32, num_locals as i64);
+        pos.ins()
+            .call_indirect(func_sig, func_addr, &[vmctx, offset, locals, num_locals]);
+    }
+
+    /// Stores the current values of all locals, followed by their types, to
+    /// the stack slot read by the debug hook, returning the slot's address.
+    fn spill_locals(&mut self, builder: &mut FunctionBuilder<'_>) -> ir::Value {
+        let num_locals = self.num_locals;
+        let slot = match self.debug_locals_slot {
+            Some(slot) => slot,
+            None => {
+                let slot = builder.func.create_stack_slot(ir::StackSlotData::new(
+                    ir::StackSlotKind::ExplicitSlot,
+                    u32::try_from((num_locals * 17).max(1)).unwrap(),
+                ));
+                self.debug_locals_slot = Some(slot);
+                slot
+            }
+        };
+
+        let pointer_type = self.pointer_type();
+        let locals = builder.ins().stack_addr(pointer_type, slot, 0);
+        let mut flags = ir::MemFlags::new();
+        flags.set_notrap();
+        for i in 0..num_locals {
+            let value = builder.use_var(cranelift_frontend::Variable::new(i));
+            let ty = match builder.func.dfg.value_type(value) {
+                I32 => DebugLocalType::I32,
+                I64 => DebugLocalType::I64,
+                F32 => DebugLocalType::F32,
+                F64 => DebugLocalType::F64,
+                ty if ty.is_vector() => DebugLocalType::V128,
+                _ => DebugLocalType::Unavailable,
+            };
+            if ty != DebugLocalType::Unavailable {
+                let offset = i32::try_from(i * 16).unwrap();
+                builder.ins().store(flags, value, locals, offset);
+            }
+            let ty = builder.ins().iconst(I8, ty as i64);
+            let offset = i32::try_from(num_locals * 16 + i).unwrap();
+            builder.ins().store(flags, ty, locals, offset);
+        }
+        locals
+    }
+
+    /// Links a record of this frame into the list of wasm frames starting at
+    /// `VMInterrupts::frame_records`, to be unlinked again before returning.
+    fn push_frame_record(&mut self, builder: &mut FunctionBuilder<'_>) {
+        let pointer_type = self.pointer_type();
+        let flags = ir::MemFlags::trusted();
+        let slot = builder.func.create_stack_slot(ir::StackSlotData::new(
+            ir::StackSlotKind::ExplicitSlot,
+            u32::from(self.offsets.size_of_vmframe_record()),
+        ));
+        self.frame_record_slot = Some(slot);
+
+        // Locals are only spilled to the stack for the debug hook, which
+        // then keeps the spilled values current as of each instruction, so
+        // the record can only point at them when debugging the guest.
+        let (locals, num_locals) = if self.tunables.guest_debug {
+            let locals = self.spill_locals(builder);
+            let num_locals = builder.ins().iconst(pointer_type, self.num_locals as i64);
+            (locals, num_locals)
+        } else {
+            let null = builder.ins().iconst(pointer_type, 0);
+            (null, null)
+        };
+
+        let mut pos = builder.cursor();
+        let vmctx = self.vmctx(&mut pos.func);
+        let vmctx = pos.ins().global_value(pointer_type, vmctx);
+        let interrupts = self.vminterrupts_ptr(&mut pos);
+        let head = i32::from(self.offsets.vminterrupts_frame_records());
+        let prev = pos.ins().load(pointer_type, flags, interrupts, head);
+        let record = pos.ins().stack_addr(pointer_type, slot, 0);
+        let fields = [
+            (prev, self.offsets.vmframe_record_prev()),
+            (vmctx, self.offsets.vmframe_record_vmctx()),
+            (locals, self.offsets.vmframe_record_locals()),
+            (num_locals, self.offsets.vmframe_record_num_locals()),
+        ];
+        for (value, offset) in fields.iter() {
+            pos.ins().store(flags, *value, record, i32::from(*offset));
+        }
+        pos.ins().store(flags, record, interrupts, head);
+    }
+
+    /// Unlinks the record pushed by `push_frame_record`, making the calling
+    /// frame's record the head of the list again.
+    fn pop_frame_record(&mut self, slot: ir::StackSlot, builder: &mut FunctionBuilder<'_>) {
+        let pointer_type = self.pointer_type();
+        let flags = ir::MemFlags::trusted();
+        let mut pos = builder.cursor();
+        let record = pos.ins().stack_addr(pointer_type, slot, 0);
+        let offset = i32::from(self.offsets.vmframe_record_prev());
+        let prev = pos.ins().load(pointer_type, flags, record, offset);
+        let interrupts = self.vminterrupts_ptr(&mut pos);
+        let head = i32::from(self.offsets.vminterrupts_frame_records());
+        pos.ins().store(flags, prev, interrupts, head);
+    }
+
+    /// Emits a check of the engine's current epoch against the store's
+    /// deadline, calling into the runtime if the deadline has been reached.
+    ///
+    /// The runtime either raises a trap or returns after updating the
+    /// deadline, so execution simply carries on in a new block afterwards.
+    fn epoch_check(&mut self, builder: &mut FunctionBuilder<'_>) {
+        let new_epoch_block = builder.create_block();
+        let continuation_block = builder.create_block();
+
+        let mut pos = builder.cursor();
+        let pointer_type = self.pointer_type();
+        let interrupts_ptr = self.vminterrupts_ptr(&mut pos);
+        let epoch_ptr = pos.ins().load(
+            pointer_type,
+            ir::MemFlags::trusted(),
+            interrupts_ptr,
+            i32::from(self.offsets.vminterrupts_epoch_ptr()),
+        );
+        let epoch = pos.ins().load(I64, ir::MemFlags::trusted(), epoch_ptr, 0);
+        let deadline = pos.ins().load(
+            I64,
+            ir::MemFlags::trusted(),
+            interrupts_ptr,
+            i32::from(self.offsets.vminterrupts_epoch_deadline()),
+        );
+        let cmp = pos
+            .ins()
+            .icmp(IntCC::UnsignedGreaterThanOrEqual, epoch, deadline);
+        builder.ins().brnz(cmp, new_epoch_block, &[]);
+        builder.ins().jump(continuation_block, &[]);
+        builder.seal_block(new_epoch_block);
+
+        builder.switch_to_block(new_epoch_block);
+        let mut pos = builder.cursor();
+        let func_sig = self.builtin_function_signatures.new_epoch(&mut pos.func);
+        let (vmctx, func_addr) = self
+            .translate_load_builtin_function_address(&mut pos, BuiltinFunctionIndex::new_epoch());
+        pos.ins().call_indirect(func_sig, func_addr, &[vmctx]);
+        builder.ins().jump(continuation_block, &[]);
+        builder.seal_block(continuation_block);
+
+        builder.switch_to_block(continuation_block);
+    }
 }
 
 // TODO: This is necessary as if Lightbeam used `FuncEnvironment` directly it would cause
@@ -646,40 +1030,119 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
 
     fn translate_table_get(
         &mut self,
-        _: cranelift_codegen::cursor::FuncCursor<'_>,
-        _: TableIndex,
-        _: ir::Table,
-        _: ir::Value,
+        mut pos: cranelift_codegen::cursor::FuncCursor<'_>,
+        table_index: TableIndex,
+        table: ir::Table,
+        index: ir::Value,
     ) -> WasmResult<ir::Value> {
-        Err(WasmError::Unsupported(
-            "the `table.get` instruction is not supported yet".into(),
-        ))
+        match self.module.table_plans[table_index].table.wasm_ty {
+            WasmType::FuncRef => {
+                let pointer_type = self.pointer_type();
+                let elem_addr = pos.ins().table_addr(pointer_type, table, index, 0);
+                Ok(pos
+                    .ins()
+                    .load(pointer_type, ir::MemFlags::trusted(), elem_addr, 0))
+            }
+            WasmType::ExternRef => {
+                // The element has to be rooted in the activations table
+                // before it can live on the wasm stack, which may need a GC,
+                // so leave it to the runtime.
+                let func_sig = self
+                    .builtin_function_signatures
+                    .table_get_externref(&mut pos.func);
+                let (vmctx, func_addr) = self.translate_load_builtin_function_address(
+                    &mut pos,
+                    BuiltinFunctionIndex::table_get_externref(),
+                );
+                let table_index_arg = pos.ins().iconst(I32, table_index.as_u32() as i64);
+                let call_inst =
+                    pos.ins()
+                        .call_indirect(func_sig, func_addr, &[vmctx, table_index_arg, index]);
+                Ok(pos.func.dfg.first_result(call_inst))
+            }
+            _ => Err(WasmError::Unsupported(
+                "`table.get` with a table element type that is not `funcref` or `externref`".into(),
+            )),
+        }
     }
 
     fn translate_table_set(
         &mut self,
-        _: cranelift_codegen::cursor::FuncCursor<'_>,
-        _: TableIndex,
-        _: ir::Table,
-        _: ir::Value,
-        _: ir::Value,
+        mut pos: cranelift_codegen::cursor::FuncCursor<'_>,
+        table_index: TableIndex,
+        table: ir::Table,
+        value: ir::Value,
+        index: ir::Value,
     ) -> WasmResult<()> {
-        Err(WasmError::Unsupported(
-            "the `table.set` instruction is not supported yet".into(),
-        ))
+        match self.module.table_plans[table_index].table.wasm_ty {
+            WasmType::FuncRef => {
+                let pointer_type = self.pointer_type();
+                let elem_addr = pos.ins().table_addr(pointer_type, table, index, 0);
+                pos.ins()
+                    .store(ir::MemFlags::trusted(), value, elem_addr, 0);
+                Ok(())
+            }
+            WasmType::ExternRef => {
+                // Overwriting an element has to update the reference counts of
+                // both the old and new values, and possibly drop the old one,
+                // so leave it to the runtime.
+                let func_sig = self
+                    .builtin_function_signatures
+                    .table_set_externref(&mut pos.func);
+                let (vmctx, func_addr) = self.translate_load_builtin_function_address(
+                    &mut pos,
+                    BuiltinFunctionIndex::table_set_externref(),
+                );
+                let table_index_arg = pos.ins().iconst(I32, table_index.as_u32() as i64);
+                pos.ins().call_indirect(
+                    func_sig,
+                    func_addr,
+                    &[vmctx, table_index_arg, index, value],
+                );
+                Ok(())
+            }
+            _ => Err(WasmError::Unsupported(
+                "`table.set` with a table element type that is not `funcref` or `externref`".into(),
+            )),
+        }
     }
 
     fn translate_table_fill(
         &mut self,
-        _: cranelift_codegen::cursor::FuncCursor<'_>,
-        _: TableIndex,
-        _: ir::Value,
-        _: ir::Value,
-        _: ir::Value,
+        mut pos: cranelift_codegen::cursor::FuncCursor<'_>,
+        table_index: TableIndex,
+        dst: ir::Value,
+        val: ir::Value,
+        len: ir::Value,
     ) -> WasmResult<()> {
-        Err(WasmError::Unsupported(
-            "the `table.fill` instruction is not supported yet".into(),
-        ))
+        let (func_idx, func_sig) =
+            match self.module.table_plans[table_index].table.wasm_ty {
+                WasmType::FuncRef => (
+                    BuiltinFunctionIndex::table_fill_funcref(),
+                    self.builtin_function_signatures
+                        .table_fill_funcref(&mut pos.func),
+                ),
+                WasmType::ExternRef => (
+                    BuiltinFunctionIndex::table_fill_externref(),
+                    self.builtin_function_signatures
+                        .table_fill_externref(&mut pos.func),
+                ),
+                _ => return Err(WasmError::Unsupported(
+                    "`table.fill` with a table element type that is not `funcref` or `externref`"
+                        .into(),
+                )),
+            };
+
+        let (vmctx, func_addr) = self.translate_load_builtin_function_address(&mut pos, func_idx);
+
+        let table_index_arg = pos.ins().iconst(I32, table_index.as_u32() as i64);
+        pos.ins().call_indirect(
+            func_sig,
+            func_addr,
+            &[vmctx, table_index_arg, dst, val, len],
+        );
+
+        Ok(())
     }
 
     fn translate_ref_null(
@@ -730,19 +1193,56 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
 
     fn translate_custom_global_get(
         &mut self,
-        _: cranelift_codegen::cursor::FuncCursor<'_>,
-        _: cranelift_wasm::GlobalIndex,
+        mut pos: cranelift_codegen::cursor::FuncCursor<'_>,
+        index: cranelift_wasm::GlobalIndex,
     ) -> WasmResult<ir::Value> {
-        unreachable!("we don't make any custom globals")
+        debug_assert_eq!(
+            self.module.globals[index].wasm_ty,
+            WasmType::ExternRef,
+            "We only use GlobalVariable::Custom for externref"
+        );
+
+        let func_sig = self
+            .builtin_function_signatures
+            .externref_global_get(&mut pos.func);
+        let (vmctx, func_addr) = self.translate_load_builtin_function_address(
+            &mut pos,
+            BuiltinFunctionIndex::externref_global_get(),
+        );
+
+        let global_index_arg = pos.ins().iconst(I32, index.as_u32() as i64);
+        let call_inst = pos
+            .ins()
+            .call_indirect(func_sig, func_addr, &[vmctx, global_index_arg]);
+
+        Ok(pos.func.dfg.first_result(call_inst))
     }
 
     fn translate_custom_global_set(
         &mut self,
-        _: cranelift_codegen::cursor::FuncCursor<'_>,
-        _: cranelift_wasm::GlobalIndex,
-        _: ir::Value,
+        mut pos: cranelift_codegen::cursor::FuncCursor<'_>,
+        index: cranelift_wasm::GlobalIndex,
+        value: ir::Value,
     ) -> WasmResult<()> {
-        unreachable!("we don't make any custom globals")
+        debug_assert_eq!(
+            self.module.globals[index].wasm_ty,
+            WasmType::ExternRef,
+            "We only use GlobalVariable::Custom for externref"
+        );
+
+        let func_sig = self
+            .builtin_function_signatures
+            .externref_global_set(&mut pos.func);
+        let (vmctx, func_addr) = self.translate_load_builtin_function_address(
+            &mut pos,
+            BuiltinFunctionIndex::externref_global_set(),
+        );
+
+        let global_index_arg = pos.ins().iconst(I32, index.as_u32() as i64);
+        pos.ins()
+            .call_indirect(func_sig, func_addr, &[vmctx, global_index_arg, value]);
+
+        Ok(())
     }
 
     fn make_heap(&mut self, func: &mut ir::Function, index: MemoryIndex) -> WasmResult<ir::Heap> {
@@ -829,6 +1329,13 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
         func: &mut ir::Function,
         index: GlobalIndex,
     ) -> WasmResult<GlobalVariable> {
+        // Reading and writing `externref` globals needs reference count
+        // updates and rooting in the activations table, which the runtime
+        // does for us in `translate_custom_global_{get,set}`.
+        if self.module.globals[index].wasm_ty == WasmType::ExternRef {
+            return Ok(GlobalVariable::Custom);
+        }
+
         let pointer_type = self.pointer_type();
 
         let (ptr, offset) = {
@@ -1096,6 +1603,52 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
         Ok(())
     }
 
+    fn translate_atomic_wait(
+        &mut self,
+        mut pos: FuncCursor,
+        memory_index: MemoryIndex,
+        _heap: ir::Heap,
+        addr: ir::Value,
+        expected: ir::Value,
+        timeout: ir::Value,
+    ) -> WasmResult<ir::Value> {
+        let expected_ty = pos.func.dfg.value_type(expected);
+        let (func_sig, func_idx) = self.get_memory_atomic_wait_func(&mut pos.func, expected_ty);
+
+        let memory_index_arg = pos.ins().iconst(I32, memory_index.index() as i64);
+
+        let (vmctx, func_addr) = self.translate_load_builtin_function_address(&mut pos, func_idx);
+
+        let call_inst = pos.ins().call_indirect(
+            func_sig,
+            func_addr,
+            &[vmctx, memory_index_arg, addr, expected, timeout],
+        );
+
+        Ok(*pos.func.dfg.inst_results(call_inst).first().unwrap())
+    }
+
+    fn translate_atomic_notify(
+        &mut self,
+        mut pos: FuncCursor,
+        memory_index: MemoryIndex,
+        _heap: ir::Heap,
+        addr: ir::Value,
+        count: ir::Value,
+    ) -> WasmResult<ir::Value> {
+        let (func_sig, func_idx) = self.get_memory_atomic_notify_func(&mut pos.func);
+
+        let memory_index_arg = pos.ins().iconst(I32, memory_index.index() as i64);
+
+        let (vmctx, func_addr) = self.translate_load_builtin_function_address(&mut pos, func_idx);
+
+        let call_inst =
+            pos.ins()
+                .call_indirect(func_sig, func_addr, &[vmctx, memory_index_arg, addr, count]);
+
+        Ok(*pos.func.dfg.inst_results(call_inst).first().unwrap())
+    }
+
     fn translate_memory_init(
         &mut self,
         mut pos: FuncCursor,
@@ -1216,11 +1769,25 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
         Ok(())
     }
 
-    fn translate_loop_header(&mut self, mut pos: FuncCursor) -> WasmResult<()> {
+    fn translate_loop_header(&mut self, builder: &mut FunctionBuilder) -> WasmResult<()> {
+        // Each loop iteration consumes fuel, so check the counter at the top
+        // of the loop to guarantee that infinite loops run out of fuel.
+        if self.tunables.consume_fuel {
+            self.fuel_check(&mut builder.cursor());
+        }
+
+        // Similarly an infinite loop needs to notice when the epoch deadline
+        // has passed.
+        if self.tunables.epoch_interruption {
+            self.epoch_check(builder);
+        }
+
         if !self.tunables.interruptable {
             return Ok(());
         }
 
+        let mut pos = builder.cursor();
+
         // Start out each loop with a check to the interupt flag to allow
         // interruption of long or infinite loops.
         //
@@ -1248,4 +1815,62 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
         pos.ins().trapnz(cmp, ir::TrapCode::Interrupt);
         Ok(())
     }
+
+    fn before_translate_operator(
+        &mut self,
+        op: &Operator,
+        builder: &mut FunctionBuilder,
+        state: &FuncTranslationState,
+    ) -> WasmResult<()> {
+        // Instructions in unreachable code are never executed, so they don't
+        // count towards coverage or consume any fuel, nor can a debugger stop
+        // at them.
+        if self.tunables.coverage && state.reachable() {
+            self.coverage_increment(builder);
+        }
+        if self.tunables.guest_debug && state.reachable() {
+            self.debug_hook(builder);
+        }
+        if self.tunables.consume_fuel && state.reachable() {
+            self.fuel_before_op(op, builder);
+        }
+        Ok(())
+    }
+
+    fn after_locals(&mut self, num_locals_defined: usize) {
+        self.num_locals = num_locals_defined;
+    }
+
+    fn before_translate_function(
+        &mut self,
+        builder: &mut FunctionBuilder,
+        _state: &FuncTranslationState,
+    ) -> WasmResult<()> {
+        // The record goes first so that it's in place for any trap raised by
+        // the checks below.
+        if self.tunables.record_frames {
+            self.push_frame_record(builder);
+        }
+
+        // Charge for the call itself and check the counter on entry, which
+        // together with the loop header checks bounds recursion as well as
+        // iteration.
+        if self.tunables.consume_fuel {
+            let mut pos = builder.cursor();
+            self.fuel_increment(&mut pos);
+            self.fuel_check(&mut pos);
+        }
+
+        // Checking the epoch on entry bounds recursion in the same way.
+        if self.tunables.epoch_interruption {
+            self.epoch_check(builder);
+        }
+        Ok(())
+    }
+
+    fn handle_before_return(&mut self, _retvals: &[ir::Value], builder: &mut FunctionBuilder) {
+        if let Some(slot) = self.frame_record_slot {
+            self.pop_frame_record(slot, builder);
+        }
+    }
 }
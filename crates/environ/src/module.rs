@@ -11,6 +11,7 @@ use cranelift_wasm::{
 };
 use indexmap::IndexMap;
 use more_asserts::assert_ge;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::HashMap;
 use std::sync::{
     atomic::{AtomicUsize, Ordering::SeqCst},
@@ -18,7 +19,7 @@ use std::sync::{
 };
 
 /// A WebAssembly table initializer.
-#[derive(Clone, Debug, Hash)]
+#[derive(Clone, Debug, Hash, Serialize, Deserialize)]
 pub struct TableElements {
     /// The index of a table to initialize.
     pub table_index: TableIndex,
@@ -31,7 +32,7 @@ pub struct TableElements {
 }
 
 /// An index of an entity.
-#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum EntityIndex {
     /// Function index.
     Function(FuncIndex),
@@ -44,7 +45,7 @@ pub enum EntityIndex {
 }
 
 /// Implemenation styles for WebAssembly linear memory.
-#[derive(Debug, Clone, Hash)]
+#[derive(Debug, Clone, Hash, Serialize, Deserialize)]
 pub enum MemoryStyle {
     /// The actual memory can be resized and moved.
     Dynamic,
@@ -80,7 +81,7 @@ impl MemoryStyle {
 
 /// A WebAssembly linear memory description along with our chosen style for
 /// implementing it.
-#[derive(Debug, Clone, Hash)]
+#[derive(Debug, Clone, Hash, Serialize, Deserialize)]
 pub struct MemoryPlan {
     /// The WebAssembly linear memory description.
     pub memory: Memory,
@@ -103,7 +104,7 @@ impl MemoryPlan {
 }
 
 /// Implemenation styles for WebAssembly tables.
-#[derive(Debug, Clone, Hash)]
+#[derive(Debug, Clone, Hash, Serialize, Deserialize)]
 pub enum TableStyle {
     /// Signatures are stored in the table and checked in the caller.
     CallerChecksSignature,
@@ -118,7 +119,7 @@ impl TableStyle {
 
 /// A WebAssembly table description along with our chosen style for
 /// implementing it.
-#[derive(Debug, Clone, Hash)]
+#[derive(Debug, Clone, Hash, Serialize, Deserialize)]
 pub struct TablePlan {
     /// The WebAssembly table description.
     pub table: cranelift_wasm::Table,
@@ -136,9 +137,12 @@ impl TablePlan {
 
 /// A translated WebAssembly module, excluding the function bodies and
 /// memory initializers.
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Module {
     /// A unique identifier (within this process) for this module.
+    ///
+    /// This isn't serialized, a deserialized module gets a new one instead.
+    #[serde(skip, default = "Module::next_id")]
     pub id: usize,
 
     /// The name of this wasm module, often found in the wasm file.
@@ -178,9 +182,11 @@ pub struct Module {
 /// This is stored within a `Module` and it implements `Hash`, unlike `Module`,
 /// and is used as part of the cache key when we load compiled modules from the
 /// global cache.
-#[derive(Debug, Hash)]
+#[derive(Debug, Hash, Serialize, Deserialize)]
 pub struct ModuleLocal {
     /// Unprocessed signatures exactly as provided by `declare_signature()`.
+    #[serde(serialize_with = "serialize_signatures")]
+    #[serde(deserialize_with = "deserialize_signatures")]
     pub signatures: PrimaryMap<SignatureIndex, (WasmFuncType, ir::Signature)>,
 
     /// Number of imported functions in the module.
@@ -206,15 +212,20 @@ pub struct ModuleLocal {
 
     /// WebAssembly global variables.
     pub globals: PrimaryMap<GlobalIndex, Global>,
+
+    /// The basic blocks of all function bodies as the module offsets of
+    /// their first instruction and of their end, sorted by offset.
+    ///
+    /// This is only populated when `Tunables::coverage` is enabled, in which
+    /// case each block has an execution counter in the `VMContext`.
+    pub coverage_blocks: Vec<(u32, u32)>,
 }
 
 impl Module {
     /// Allocates the module data structures.
     pub fn new() -> Self {
-        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
-
         Self {
-            id: NEXT_ID.fetch_add(1, SeqCst),
+            id: Self::next_id(),
             name: None,
             imports: Vec::new(),
             exports: IndexMap::new(),
@@ -233,10 +244,16 @@ impl Module {
                 table_plans: PrimaryMap::new(),
                 memory_plans: PrimaryMap::new(),
                 globals: PrimaryMap::new(),
+                coverage_blocks: Vec::new(),
             },
         }
     }
 
+    fn next_id() -> usize {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        NEXT_ID.fetch_add(1, SeqCst)
+    }
+
     /// Get the given passive element, if it exists.
     pub fn get_passive_element(&self, index: ElemIndex) -> Option<&[FuncIndex]> {
         self.passive_elements.get(&index).map(|es| &**es)
@@ -344,3 +361,32 @@ impl ModuleLocal {
         &self.signatures[self.functions[func_index]].0
     }
 }
+
+/// A signature of `ModuleLocal::signatures`, whose wasm half comes from
+/// `wasmparser` and so needs help to be serialized.
+#[derive(Serialize, Deserialize)]
+struct SerializedSignature(
+    #[serde(with = "cranelift_wasm::serde_wasmparser::wasm_func_type")] WasmFuncType,
+    ir::Signature,
+);
+
+fn serialize_signatures<S: Serializer>(
+    signatures: &PrimaryMap<SignatureIndex, (WasmFuncType, ir::Signature)>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(
+        signatures
+            .values()
+            .map(|(wasm, native)| SerializedSignature(wasm.clone(), native.clone())),
+    )
+}
+
+fn deserialize_signatures<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<PrimaryMap<SignatureIndex, (WasmFuncType, ir::Signature)>, D::Error> {
+    let signatures = Vec::<SerializedSignature>::deserialize(deserializer)?;
+    Ok(signatures
+        .into_iter()
+        .map(|SerializedSignature(wasm, native)| (wasm, native))
+        .collect())
+}
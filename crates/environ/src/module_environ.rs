@@ -9,8 +9,10 @@ use cranelift_wasm::{
     Memory, MemoryIndex, ModuleTranslationState, SignatureIndex, Table, TableIndex,
     TargetEnvironment, WasmError, WasmFuncType, WasmResult,
 };
+use serde::{Deserialize, Serialize};
 use std::convert::TryFrom;
 use std::sync::Arc;
+use wasmparser::{FunctionBody, Operator};
 
 /// Contains function data: byte code and its offset in the module.
 #[derive(Hash)]
@@ -178,9 +180,6 @@ impl<'data> cranelift_wasm::ModuleEnvironment<'data> for ModuleEnvironment<'data
             self.result.module.local.num_imported_memories,
             "Imported memories must be declared first"
         );
-        if memory.shared {
-            return Err(WasmError::Unsupported("shared memories".to_owned()));
-        }
         let plan = MemoryPlan::for_memory(memory, &self.result.tunables);
         let memory_index = self.result.module.local.memory_plans.push(plan);
         self.result.module.imports.push((
@@ -255,9 +254,6 @@ impl<'data> cranelift_wasm::ModuleEnvironment<'data> for ModuleEnvironment<'data
     }
 
     fn declare_memory(&mut self, memory: Memory) -> WasmResult<()> {
-        if memory.shared {
-            return Err(WasmError::Unsupported("shared memories".to_owned()));
-        }
         let plan = MemoryPlan::for_memory(memory, &self.result.tunables);
         self.result.module.local.memory_plans.push(plan);
         Ok(())
@@ -355,6 +351,10 @@ impl<'data> cranelift_wasm::ModuleEnvironment<'data> for ModuleEnvironment<'data
         body_bytes: &'data [u8],
         body_offset: usize,
     ) -> WasmResult<()> {
+        if self.result.tunables.coverage {
+            let body = FunctionBody::new(body_offset, body_bytes);
+            coverage_blocks(&body, &mut self.result.module.local.coverage_blocks)?;
+        }
         self.result.function_body_inputs.push(FunctionBodyData {
             data: body_bytes,
             module_offset: body_offset,
@@ -454,7 +454,7 @@ pub fn translate_signature(mut sig: ir::Signature, pointer_type: ir::Type) -> ir
 
 /// A memory index and offset within that memory where a data initialization
 /// should is to be performed.
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct DataInitializerLocation {
     /// The index of the memory to initialize.
     pub memory_index: MemoryIndex,
@@ -474,3 +474,38 @@ pub struct DataInitializer<'data> {
     /// The initialization data.
     pub data: &'data [u8],
 }
+
+/// Appends the basic blocks of `body` to `blocks`, for coverage.
+///
+/// A new block starts at the first instruction and after every instruction
+/// which either is a branch target or may fall through to the next
+/// instruction after branching elsewhere. Blocks that follow an unconditional
+/// branch are unreachable and just never get counted.
+fn coverage_blocks(body: &FunctionBody<'_>, blocks: &mut Vec<(u32, u32)>) -> WasmResult<()> {
+    let first = blocks.len();
+    let mut reader = body.get_operators_reader()?;
+    let mut starts_block = true;
+    while !reader.eof() {
+        let (op, offset) = reader.read_with_offset()?;
+        if starts_block {
+            blocks.push((offset as u32, 0));
+        }
+        starts_block = match op {
+            Operator::Loop { .. }
+            | Operator::If { .. }
+            | Operator::Else
+            | Operator::End
+            | Operator::BrIf { .. } => true,
+            _ => false,
+        };
+    }
+
+    // Each block ends where the next one starts, and the last one at the end
+    // of the body.
+    let mut end = reader.original_position() as u32;
+    for block in blocks[first..].iter_mut().rev() {
+        block.1 = end;
+        end = block.0;
+    }
+    Ok(())
+}
@@ -1,5 +1,7 @@
+use serde::{Deserialize, Serialize};
+
 /// Tunable parameters for WebAssembly compilation.
-#[derive(Clone, Hash)]
+#[derive(Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Tunables {
     /// For static heaps, the size in wasm pages of the heap protected by bounds checking.
     pub static_memory_bound: u32,
@@ -20,6 +22,45 @@ pub struct Tunables {
     /// calls and interrupts are implemented through the `VMInterrupts`
     /// structure, or `InterruptHandle` in the `wasmtime` crate.
     pub interruptable: bool,
+
+    /// Whether or not fuel is enabled for generated code, meaning that fuel
+    /// will be consumed every time a wasm instruction is executed.
+    ///
+    /// Fuel is tracked through the `fuel_consumed` field of `VMInterrupts`,
+    /// and `Store::add_fuel` in the `wasmtime` crate.
+    pub consume_fuel: bool,
+
+    /// Whether or not to check the engine's epoch counter against the store's
+    /// deadline at function entries and loop headers.
+    ///
+    /// The deadline and a pointer to the counter live in the `VMInterrupts`
+    /// structure, and are configured through `Store::set_epoch_deadline` and
+    /// `Engine::increment_epoch` in the `wasmtime` crate.
+    pub epoch_interruption: bool,
+
+    /// Whether or not to call into the runtime before every wasm instruction
+    /// with the instruction's offset and the values of the function's
+    /// locals, for debugging the guest.
+    ///
+    /// This is the hook behind `GdbStub` in the `wasmtime` crate, and makes
+    /// generated code much slower.
+    pub guest_debug: bool,
+
+    /// Whether or not to count how many times each basic block of the wasm
+    /// is executed, for code coverage.
+    ///
+    /// The counters are stored at the end of each instance's `VMContext`,
+    /// one per entry of `ModuleLocal::coverage_blocks`.
+    pub coverage: bool,
+
+    /// Whether or not each function links a record of its `VMContext` into
+    /// a list of the wasm frames on the stack on entry, and unlinks it again
+    /// on return, so that the instance of every frame can be found when a
+    /// trap happens.
+    ///
+    /// The head of the list is the `frame_records` field of `VMInterrupts`.
+    /// This is enabled by `Config::coredump_on_trap` in the `wasmtime` crate.
+    pub record_frames: bool,
 }
 
 impl Default for Tunables {
@@ -53,6 +94,11 @@ impl Default for Tunables {
 
             debug_info: false,
             interruptable: false,
+            consume_fuel: false,
+            epoch_interruption: false,
+            guest_debug: false,
+            coverage: false,
+            record_frames: false,
         }
     }
 }
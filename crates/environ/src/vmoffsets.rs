@@ -71,6 +71,8 @@ pub struct VMOffsets {
     pub num_defined_memories: u32,
     /// The number of defined globals in the module.
     pub num_defined_globals: u32,
+    /// The number of basic block counters for coverage.
+    pub num_coverage_counters: u32,
 }
 
 impl VMOffsets {
@@ -87,6 +89,7 @@ impl VMOffsets {
             num_defined_tables: cast_to_u32(module.table_plans.len()),
             num_defined_memories: cast_to_u32(module.memory_plans.len()),
             num_defined_globals: cast_to_u32(module.globals.len()),
+            num_coverage_counters: cast_to_u32(module.coverage_blocks.len()),
         }
     }
 }
@@ -258,6 +261,56 @@ impl VMOffsets {
     pub fn vminterrupts_stack_limit(&self) -> u8 {
         0
     }
+
+    /// Return the offset of the `fuel_consumed` field of `VMInterrupts`
+    pub fn vminterrupts_fuel_consumed(&self) -> u8 {
+        self.pointer_size
+    }
+
+    /// Return the offset of the `epoch_deadline` field of `VMInterrupts`
+    pub fn vminterrupts_epoch_deadline(&self) -> u8 {
+        self.vminterrupts_fuel_consumed() + 8
+    }
+
+    /// Return the offset of the `epoch_ptr` field of `VMInterrupts`
+    pub fn vminterrupts_epoch_ptr(&self) -> u8 {
+        self.vminterrupts_epoch_deadline() + 8
+    }
+
+    /// Return the offset of the `frame_records` field of `VMInterrupts`
+    pub fn vminterrupts_frame_records(&self) -> u8 {
+        self.vminterrupts_epoch_ptr() + self.pointer_size
+    }
+}
+
+/// Offsets for `VMFrameRecord`.
+impl VMOffsets {
+    /// The offset of the `prev` field.
+    #[allow(clippy::erasing_op)]
+    pub fn vmframe_record_prev(&self) -> u8 {
+        0 * self.pointer_size
+    }
+
+    /// The offset of the `vmctx` field.
+    #[allow(clippy::identity_op)]
+    pub fn vmframe_record_vmctx(&self) -> u8 {
+        1 * self.pointer_size
+    }
+
+    /// The offset of the `locals` field.
+    pub fn vmframe_record_locals(&self) -> u8 {
+        2 * self.pointer_size
+    }
+
+    /// The offset of the `num_locals` field.
+    pub fn vmframe_record_num_locals(&self) -> u8 {
+        3 * self.pointer_size
+    }
+
+    /// Return the size of `VMFrameRecord`.
+    pub fn size_of_vmframe_record(&self) -> u8 {
+        4 * self.pointer_size
+    }
 }
 
 /// Offsets for `VMCallerCheckedAnyfunc`.
@@ -418,14 +471,23 @@ impl VMOffsets {
             .unwrap()
     }
 
-    /// Return the size of the `VMContext` allocation.
-    pub fn size_of_vmctx(&self) -> u32 {
-        self.vmctx_builtin_functions_begin()
+    /// The offset of the coverage counters array.
+    pub fn vmctx_coverage_counters_begin(&self) -> u32 {
+        let offset = self
+            .vmctx_builtin_functions_begin()
             .checked_add(
                 BuiltinFunctionIndex::builtin_functions_total_number()
                     .checked_mul(u32::from(self.pointer_size))
                     .unwrap(),
             )
+            .unwrap();
+        align(offset, 8)
+    }
+
+    /// Return the size of the `VMContext` allocation.
+    pub fn size_of_vmctx(&self) -> u32 {
+        self.vmctx_coverage_counters_begin()
+            .checked_add(self.num_coverage_counters.checked_mul(8).unwrap())
             .unwrap()
     }
 
@@ -616,6 +678,14 @@ impl VMOffsets {
             .unwrap()
     }
 
+    /// Return the offset to the coverage counter of basic block `index`.
+    pub fn vmctx_coverage_counter(&self, index: u32) -> u32 {
+        assert_lt!(index, self.num_coverage_counters);
+        self.vmctx_coverage_counters_begin()
+            .checked_add(index.checked_mul(8).unwrap())
+            .unwrap()
+    }
+
     /// Return the offset to builtin function in `VMBuiltinFunctionsArray` index `index`.
     pub fn vmctx_builtin_function(&self, index: BuiltinFunctionIndex) -> u32 {
         self.vmctx_builtin_functions_begin()
@@ -0,0 +1,18 @@
+use std::env;
+
+fn main() {
+    let mut build = cc::Build::new();
+    let arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap();
+    let os = env::var("CARGO_CFG_TARGET_OS").unwrap();
+    if os == "windows" {
+        println!("cargo:rerun-if-changed=src/windows.c");
+        build.file("src/windows.c");
+    } else if arch == "x86_64" || arch == "aarch64" {
+        println!("cargo:rerun-if-changed=src/arch/header.h");
+        println!("cargo:rerun-if-changed=src/arch/{}.S", arch);
+        build.file(format!("src/arch/{}.S", arch));
+    } else {
+        panic!("fibers are not supported on `{}-{}` at this time", arch, os);
+    }
+    build.compile("wasmtime-fiber");
+}
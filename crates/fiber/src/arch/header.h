@@ -0,0 +1,22 @@
+// Macros papering over the differences between ELF and Mach-O assembly
+// syntax for the fiber routines in this directory.
+
+#ifdef __APPLE__
+#define GLOBL(fnname) .globl _##fnname
+#define HIDDEN(fnname) .private_extern _##fnname
+#define TYPE(fnname)
+#define FUNCTION(fnname) _##fnname
+#define SIZE(fnname)
+#else
+#define GLOBL(fnname) .globl fnname
+#define HIDDEN(fnname) .hidden fnname
+#define TYPE(fnname) .type fnname,%function
+#define FUNCTION(fnname) fnname
+#define SIZE(fnname) .size fnname,.-fnname
+#endif
+
+#ifdef __ELF__
+#define FOOTER .section .note.GNU-stack,"",%progbits
+#else
+#define FOOTER
+#endif
@@ -1,21 +1,22 @@
 //! JIT compilation.
 
 use crate::code_memory::CodeMemory;
-use crate::instantiate::SetupError;
+use crate::instantiate::{CompilationArtifacts, OwnedDataInitializer, SetupError};
 use cranelift_codegen::ir::ExternalName;
 use cranelift_codegen::ir::InstBuilder;
 use cranelift_codegen::print_errors::pretty_error;
 use cranelift_codegen::Context;
 use cranelift_codegen::{binemit, ir};
 use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
-use wasmtime_debug::{emit_dwarf, DebugInfoData, DwarfSection};
+use std::sync::Arc;
+use wasmtime_debug::{emit_dwarf, has_debuginfo, DebugInfoData, DwarfSection};
 use wasmtime_environ::entity::{EntityRef, PrimaryMap};
 use wasmtime_environ::isa::{TargetFrontendConfig, TargetIsa};
 use wasmtime_environ::wasm::{DefinedFuncIndex, DefinedMemoryIndex, MemoryIndex, SignatureIndex};
 use wasmtime_environ::{
     CacheConfig, CompileError, CompiledFunction, Compiler as _C, Module, ModuleAddressMap,
     ModuleMemoryOffset, ModuleTranslation, ModuleVmctxInfo, Relocation, RelocationTarget,
-    Relocations, StackMaps, Traps, Tunables, VMOffsets, ValueLabelsRanges,
+    Relocations, Tunables, VMOffsets, ValueLabelsRanges,
 };
 use wasmtime_runtime::{InstantiationError, VMFunctionBody, VMTrampoline};
 
@@ -134,12 +135,9 @@ pub struct Compilation {
     pub code_memory: CodeMemory,
     pub finished_functions: PrimaryMap<DefinedFuncIndex, *mut [VMFunctionBody]>,
     pub code_range: (*const u8, usize),
-    pub trampolines: PrimaryMap<SignatureIndex, VMTrampoline>,
+    pub trampolines: PrimaryMap<SignatureIndex, (VMTrampoline, *mut [VMFunctionBody])>,
     pub jt_offsets: PrimaryMap<DefinedFuncIndex, ir::JumpTableOffsets>,
     pub dwarf_sections: Vec<DwarfSection>,
-    pub traps: Traps,
-    pub stack_maps: StackMaps,
-    pub address_transform: ModuleAddressMap,
 }
 
 impl Compiler {
@@ -158,14 +156,13 @@ impl Compiler {
         &self.tunables
     }
 
-    /// Compile the given function bodies.
+    /// Compile the given function bodies, along with an entry trampoline for
+    /// every signature in the module.
     pub(crate) fn compile<'data>(
         &self,
-        translation: &ModuleTranslation,
-        debug_data: Option<DebugInfoData>,
-    ) -> Result<Compilation, SetupError> {
-        let mut code_memory = CodeMemory::new();
-
+        data: &'data [u8],
+        translation: ModuleTranslation<'data>,
+    ) -> Result<CompilationArtifacts, SetupError> {
         let (
             compilation,
             relocations,
@@ -179,7 +176,7 @@ impl Compiler {
             // implementation.
             CompilationStrategy::Auto | CompilationStrategy::Cranelift => {
                 wasmtime_environ::cranelift::Cranelift::compile_module(
-                    translation,
+                    &translation,
                     &*self.isa,
                     &self.cache_config,
                 )
@@ -187,7 +184,7 @@ impl Compiler {
             #[cfg(feature = "lightbeam")]
             CompilationStrategy::Lightbeam => {
                 wasmtime_environ::lightbeam::Lightbeam::compile_module(
-                    translation,
+                    &translation,
                     &*self.isa,
                     &self.cache_config,
                 )
@@ -195,15 +192,66 @@ impl Compiler {
         }
         .map_err(SetupError::Compile)?;
 
+        // Eagerly generate a entry trampoline for every type signature in the
+        // module. This should be "relatively lightweight" for most modules and
+        // guarantees that all functions (including indirect ones through
+        // tables) have a trampoline when invoked through the wasmtime API.
+        let mut cx = FunctionBuilderContext::new();
+        let mut trampolines = PrimaryMap::new();
+        for (_, (_, native_sig)) in translation.module.local.signatures.iter() {
+            let trampoline =
+                compile_trampoline(&*self.isa, &mut cx, native_sig, std::mem::size_of::<u128>())?;
+            trampolines.push(trampoline);
+        }
+
+        let data_initializers = translation
+            .data_initializers
+            .into_iter()
+            .map(OwnedDataInitializer::new)
+            .collect::<Vec<_>>()
+            .into_boxed_slice();
+
+        // The binary itself is only needed again to read its DWARF, or to
+        // show it to a debugger.
+        let keep_wasm =
+            self.tunables.debug_info || self.tunables.guest_debug || has_debuginfo(data);
+        let wasm = if keep_wasm { data } else { &[] };
+
+        Ok(CompilationArtifacts {
+            module: Arc::new(translation.module),
+            data_initializers,
+            wasm: wasm.to_vec().into_boxed_slice(),
+            compilation,
+            relocations,
+            trampolines,
+            address_transform,
+            value_ranges,
+            stack_slots,
+            traps,
+            stack_maps,
+        })
+    }
+
+    /// Copy previously compiled artifacts into executable memory.
+    ///
+    /// No code generation happens here.
+    pub(crate) fn load(
+        &self,
+        artifacts: &CompilationArtifacts,
+        debug_data: Option<DebugInfoData>,
+    ) -> Result<Compilation, SetupError> {
+        let mut code_memory = CodeMemory::new();
+        let compilation = &artifacts.compilation;
+
         let dwarf_sections = if debug_data.is_some() && !compilation.is_empty() {
             transform_dwarf_data(
                 &*self.isa,
-                &translation.module,
+                &artifacts.module,
                 debug_data.as_ref().unwrap(),
-                &address_transform,
-                &value_ranges,
-                stack_slots,
-                &compilation,
+                &artifacts.address_transform,
+                &artifacts.value_ranges,
+                artifacts.stack_slots.clone(),
+                compilation,
             )?
         } else {
             vec![]
@@ -211,33 +259,23 @@ impl Compiler {
 
         // Allocate all of the compiled functions into executable memory,
         // copying over their contents.
-        let finished_functions = allocate_functions(&mut code_memory, &compilation, &relocations)
-            .map_err(|message| {
-            SetupError::Instantiate(InstantiationError::Resource(format!(
-                "failed to allocate memory for functions: {}",
-                message
-            )))
-        })?;
+        let finished_functions =
+            allocate_functions(&mut code_memory, compilation, &artifacts.relocations).map_err(
+                |message| {
+                    SetupError::Instantiate(InstantiationError::Resource(format!(
+                        "failed to allocate memory for functions: {}",
+                        message
+                    )))
+                },
+            )?;
 
-        // Eagerly generate a entry trampoline for every type signature in the
-        // module. This should be "relatively lightweight" for most modules and
-        // guarantees that all functions (including indirect ones through
-        // tables) have a trampoline when invoked through the wasmtime API.
-        let mut cx = FunctionBuilderContext::new();
         let mut trampolines = PrimaryMap::new();
-        for (_, (_, native_sig)) in translation.module.local.signatures.iter() {
-            let trampoline = make_trampoline(
-                &*self.isa,
-                &mut code_memory,
-                &mut cx,
-                native_sig,
-                std::mem::size_of::<u128>(),
-            )?;
-            trampolines.push(trampoline);
+        for (_, (func, relocs)) in artifacts.trampolines.iter() {
+            trampolines.push(allocate_trampoline(&mut code_memory, func, relocs)?);
         }
 
         let jt_offsets = compilation.get_jt_offsets();
-        let code_range = get_code_range(&compilation, &finished_functions);
+        let code_range = get_code_range(compilation, &finished_functions);
 
         Ok(Compilation {
             code_memory,
@@ -246,21 +284,36 @@ impl Compiler {
             trampolines,
             jt_offsets,
             dwarf_sections,
-            traps,
-            stack_maps,
-            address_transform,
         })
     }
 }
 
 /// Create a trampoline for invoking a function.
+///
+/// Along with the trampoline itself this returns its body in `code_memory`,
+/// for reporting to profilers.
 pub fn make_trampoline(
     isa: &dyn TargetIsa,
     code_memory: &mut CodeMemory,
     fn_builder_ctx: &mut FunctionBuilderContext,
     signature: &ir::Signature,
     value_size: usize,
-) -> Result<VMTrampoline, SetupError> {
+) -> Result<(VMTrampoline, *mut [VMFunctionBody]), SetupError> {
+    let (func, relocs) = compile_trampoline(isa, fn_builder_ctx, signature, value_size)?;
+    allocate_trampoline(code_memory, &func, &relocs)
+}
+
+/// Compile a trampoline for invoking a function, without placing it in
+/// executable memory.
+///
+/// The returned relocations must be applied when the trampoline is copied
+/// into a `CodeMemory`.
+pub fn compile_trampoline(
+    isa: &dyn TargetIsa,
+    fn_builder_ctx: &mut FunctionBuilderContext,
+    signature: &ir::Signature,
+    value_size: usize,
+) -> Result<(CompiledFunction, Vec<Relocation>), SetupError> {
     let pointer_type = isa.pointer_type();
     let mut wrapper_sig = ir::Signature::new(isa.frontend_config().default_call_conv);
 
@@ -367,18 +420,27 @@ pub fn make_trampoline(
         )))
     })?;
 
-    let ptr = code_memory
-        .allocate_for_function(
-            &CompiledFunction {
-                body: code_buf,
-                jt_offsets: context.func.jt_offsets,
-                unwind_info,
-            },
-            reloc_sink.relocs.iter(),
-        )
-        .map_err(|message| SetupError::Instantiate(InstantiationError::Resource(message)))?
-        .as_ptr();
-    Ok(unsafe { std::mem::transmute::<*const VMFunctionBody, VMTrampoline>(ptr) })
+    Ok((
+        CompiledFunction {
+            body: code_buf,
+            jt_offsets: context.func.jt_offsets,
+            unwind_info,
+        },
+        reloc_sink.relocs,
+    ))
+}
+
+fn allocate_trampoline(
+    code_memory: &mut CodeMemory,
+    func: &CompiledFunction,
+    relocs: &[Relocation],
+) -> Result<(VMTrampoline, *mut [VMFunctionBody]), SetupError> {
+    let body = code_memory
+        .allocate_for_function(func, relocs.iter())
+        .map_err(|message| SetupError::Instantiate(InstantiationError::Resource(message)))?;
+    let trampoline =
+        unsafe { std::mem::transmute::<*const VMFunctionBody, VMTrampoline>(body.as_ptr()) };
+    Ok((trampoline, body as *mut [VMFunctionBody]))
 }
 
 fn allocate_functions(
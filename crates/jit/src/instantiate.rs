@@ -8,22 +8,29 @@ use crate::compiler::{Compilation, Compiler};
 use crate::imports::resolve_imports;
 use crate::link::link_module;
 use crate::resolver::Resolver;
+use serde::{Deserialize, Serialize};
 use std::any::Any;
 use std::collections::HashMap;
+use std::rc::Rc;
 use std::sync::Arc;
 use thiserror::Error;
-use wasmtime_debug::{read_debuginfo, write_debugsections_image, DwarfSection};
-use wasmtime_environ::entity::{BoxedSlice, PrimaryMap};
+use wasmtime_debug::{
+    has_debuginfo, read_debuginfo, write_debugsections_image, DwarfSection, ModuleSymbols,
+};
+use wasmtime_environ::entity::{BoxedSlice, EntityRef, PrimaryMap};
+use wasmtime_environ::ir;
 use wasmtime_environ::isa::TargetIsa;
 use wasmtime_environ::wasm::{DefinedFuncIndex, SignatureIndex};
 use wasmtime_environ::{
-    CompileError, DataInitializer, DataInitializerLocation, Module, ModuleAddressMap,
-    ModuleEnvironment, ModuleTranslation, StackMaps, Traps,
+    CompileError, CompiledFunction, DataInitializer, DataInitializerLocation, Module,
+    ModuleAddressMap, ModuleEnvironment, ModuleTranslation, Relocation, Relocations, StackMaps,
+    Traps, ValueLabelsRanges,
 };
 use wasmtime_profiling::ProfilingAgent;
 use wasmtime_runtime::VMInterrupts;
 use wasmtime_runtime::{
-    GdbJitImageRegistration, InstanceHandle, InstantiationError, RuntimeMemoryCreator,
+    DebugHandler, EpochDeadlineHandler, GdbJitImageRegistration, InstanceAllocationRequest,
+    InstanceAllocator, InstanceHandle, InstantiationError, ModuleMemoryImages, ResourceLimiter,
     SignatureRegistry, StackMapRegistry, VMExternRefActivationsTable, VMFunctionBody, VMTrampoline,
 };
 
@@ -61,16 +68,62 @@ pub struct ModuleCode {
     dbg_jit_registration: Option<GdbJitImageRegistration>,
 }
 
+/// Everything produced by compiling a wasm module, in a form which can be
+/// serialized and later turned back into a `CompiledModule` without running
+/// the compiler again.
+#[derive(Serialize, Deserialize)]
+pub struct CompilationArtifacts {
+    /// The translated module.
+    pub(crate) module: Arc<Module>,
+
+    /// The module's data initializers.
+    pub(crate) data_initializers: Box<[OwnedDataInitializer]>,
+
+    /// The original wasm binary, which DWARF is read from. Only kept when
+    /// the binary has DWARF or is being debugged, and empty otherwise.
+    pub(crate) wasm: Box<[u8]>,
+
+    /// Compiled machine code of all defined functions.
+    pub(crate) compilation: wasmtime_environ::Compilation,
+
+    /// Relocations to apply to `compilation` once it is in memory.
+    pub(crate) relocations: Relocations,
+
+    /// Compiled entry trampolines, one per signature, and their relocations.
+    pub(crate) trampolines: PrimaryMap<SignatureIndex, (CompiledFunction, Vec<Relocation>)>,
+
+    /// Mapping of compiled code back to wasm bytecode offsets.
+    pub(crate) address_transform: ModuleAddressMap,
+
+    /// Value label ranges, used to generate debug information.
+    pub(crate) value_ranges: ValueLabelsRanges,
+
+    /// Stack slots of each function, used to generate debug information.
+    pub(crate) stack_slots: PrimaryMap<DefinedFuncIndex, ir::StackSlots>,
+
+    /// Trap information for each function.
+    pub(crate) traps: Traps,
+
+    /// Stack maps for each function.
+    pub(crate) stack_maps: StackMaps,
+}
+
+impl CompilationArtifacts {
+    /// Translate and compile a wasm binary.
+    pub fn new(compiler: &Compiler, data: &[u8]) -> Result<Self, SetupError> {
+        let translation = translate(compiler, data)?;
+        compiler.compile(data, translation)
+    }
+}
+
 /// A compiled wasm module, ready to be instantiated.
 pub struct CompiledModule {
-    module: Arc<Module>,
+    artifacts: CompilationArtifacts,
     code: Arc<ModuleCode>,
     finished_functions: FinishedFunctions,
     trampolines: PrimaryMap<SignatureIndex, VMTrampoline>,
-    data_initializers: Box<[OwnedDataInitializer]>,
-    traps: Traps,
-    stack_maps: StackMaps,
-    address_transform: ModuleAddressMap,
+    memory_images: Option<Arc<ModuleMemoryImages>>,
+    symbols: Option<Arc<ModuleSymbols>>,
 }
 
 impl CompiledModule {
@@ -80,18 +133,41 @@ impl CompiledModule {
         data: &'data [u8],
         profiler: &dyn ProfilingAgent,
     ) -> Result<Self, SetupError> {
-        let environ = ModuleEnvironment::new(compiler.frontend_config(), compiler.tunables());
-
-        let translation = environ
-            .translate(data)
-            .map_err(|error| SetupError::Compile(CompileError::Wasm(error)))?;
+        let artifacts = CompilationArtifacts::new(compiler, data)?;
+        Self::from_artifacts(compiler, artifacts, profiler)
+    }
 
+    /// Creates a `CompiledModule` from previously produced `artifacts`.
+    ///
+    /// The artifacts must have been produced by a `Compiler` configured
+    /// identically to `compiler`; this is not checked here. The artifacts
+    /// are kept so that the module can be serialized again.
+    pub fn from_artifacts(
+        compiler: &Compiler,
+        artifacts: CompilationArtifacts,
+        profiler: &dyn ProfilingAgent,
+    ) -> Result<Self, SetupError> {
         let mut debug_data = None;
         if compiler.tunables().debug_info {
             // TODO Do we want to ignore invalid DWARF data?
-            debug_data = Some(read_debuginfo(&data)?);
+            debug_data = Some(read_debuginfo(&artifacts.wasm)?);
         }
 
+        // Source locations for trap backtraces come from the module's own
+        // DWARF, whether or not native debug info is being generated. Being
+        // purely informational, invalid DWARF here just means no locations.
+        // Most modules have no DWARF at all, and those aren't parsed again.
+        let symbols = match &debug_data {
+            Some(debug_data) => ModuleSymbols::new(debug_data),
+            None if has_debuginfo(&artifacts.wasm) => {
+                read_debuginfo(&artifacts.wasm).and_then(|data| ModuleSymbols::new(&data))
+            }
+            None => Ok(None),
+        }
+        .ok()
+        .flatten()
+        .map(Arc::new);
+
         let Compilation {
             mut code_memory,
             finished_functions,
@@ -99,28 +175,14 @@ impl CompiledModule {
             trampolines,
             jt_offsets,
             dwarf_sections,
-            traps,
-            stack_maps,
-            address_transform,
-        } = compiler.compile(&translation, debug_data)?;
-
-        let ModuleTranslation {
-            module,
-            data_initializers,
-            ..
-        } = translation;
+        } = compiler.load(&artifacts, debug_data)?;
 
-        link_module(&mut code_memory, &module, &finished_functions, &jt_offsets);
+        let module = &artifacts.module;
+        link_module(&mut code_memory, module, &finished_functions, &jt_offsets);
 
         // Make all code compiled thus far executable.
         code_memory.publish(compiler.isa());
 
-        let data_initializers = data_initializers
-            .into_iter()
-            .map(OwnedDataInitializer::new)
-            .collect::<Vec<_>>()
-            .into_boxed_slice();
-
         // Register GDB JIT images; initialize profiler and load the wasm module.
         let dbg_jit_registration = if !dwarf_sections.is_empty() {
             let bytes = create_dbg_image(
@@ -130,32 +192,50 @@ impl CompiledModule {
                 &finished_functions,
             )?;
 
-            profiler.module_load(&module, &finished_functions, Some(&bytes));
+            profiler.module_load(module, &finished_functions, Some(&bytes));
 
             let reg = GdbJitImageRegistration::register(bytes);
             Some(reg)
         } else {
-            profiler.module_load(&module, &finished_functions, None);
+            profiler.module_load(module, &finished_functions, None);
             None
         };
+        for (i, (_, body)) in trampolines.iter() {
+            profiler.trampoline_load(&format!("wasm::trampoline[{}]", i.index()), *body);
+        }
+        let trampolines = trampolines
+            .values()
+            .map(|(trampoline, _)| *trampoline)
+            .collect();
 
         let finished_functions = FinishedFunctions(finished_functions.into_boxed_slice());
 
         Ok(Self {
-            module: Arc::new(module),
+            artifacts,
             code: Arc::new(ModuleCode {
                 code_memory,
                 dbg_jit_registration,
             }),
             finished_functions,
             trampolines,
-            data_initializers,
-            traps,
-            stack_maps,
-            address_transform,
+            memory_images: None,
+            symbols,
         })
     }
 
+    /// Builds copy-on-write images of this module's initial memory contents,
+    /// which instances then map in rather than copying in data segments.
+    ///
+    /// Only allocators which create memories themselves make use of the
+    /// images; otherwise, and for any data segments the images can't cover,
+    /// instantiation copies data in as usual.
+    pub fn create_memory_images(&mut self) -> Result<(), SetupError> {
+        let images = ModuleMemoryImages::new(&self.artifacts.module, &self.data_initializers())
+            .map_err(|e| SetupError::Instantiate(InstantiationError::Resource(e)))?;
+        self.memory_images = images.map(Arc::new);
+        Ok(())
+    }
+
     /// Crate an `Instance` from this `CompiledModule`.
     ///
     /// Note that if only one instance of this module is needed, it may be more
@@ -164,20 +244,24 @@ impl CompiledModule {
     ///
     /// # Unsafety
     ///
-    /// See `InstanceHandle::new`
+    /// See `InstanceAllocator::allocate`
     pub unsafe fn instantiate(
         &self,
         resolver: &mut dyn Resolver,
         signature_registry: &mut SignatureRegistry,
-        mem_creator: Option<&dyn RuntimeMemoryCreator>,
+        allocator: &dyn InstanceAllocator,
         interrupts: Arc<VMInterrupts>,
         host_state: Box<dyn Any>,
         externref_activations_table: *mut VMExternRefActivationsTable,
         stack_map_registry: *mut StackMapRegistry,
+        limiter: Option<Rc<dyn ResourceLimiter>>,
+        epoch_deadline_handler: Option<Rc<dyn EpochDeadlineHandler>>,
+        debug_handler: Option<Rc<dyn DebugHandler>>,
     ) -> Result<InstanceHandle, InstantiationError> {
         // Compute indices into the shared signature table.
         let signatures = {
-            self.module
+            self.artifacts
+                .module
                 .local
                 .signatures
                 .values()
@@ -194,25 +278,29 @@ impl CompiledModule {
 
         let finished_functions = self.finished_functions.0.clone();
 
-        let imports = resolve_imports(&self.module, signature_registry, resolver)?;
-        InstanceHandle::new(
-            self.module.clone(),
-            self.code.clone(),
+        let imports = resolve_imports(&self.artifacts.module, signature_registry, resolver)?;
+        allocator.allocate(InstanceAllocationRequest {
+            module: self.artifacts.module.clone(),
+            code: self.code.clone(),
             finished_functions,
             trampolines,
             imports,
-            mem_creator,
-            signatures.into_boxed_slice(),
+            vmshared_signatures: signatures.into_boxed_slice(),
             host_state,
             interrupts,
             externref_activations_table,
             stack_map_registry,
-        )
+            limiter,
+            epoch_deadline_handler,
+            debug_handler,
+            memory_images: self.memory_images.clone(),
+        })
     }
 
     /// Returns data initializers to pass to `InstanceHandle::initialize`
     pub fn data_initializers(&self) -> Vec<DataInitializer<'_>> {
-        self.data_initializers
+        self.artifacts
+            .data_initializers
             .iter()
             .map(|init| DataInitializer {
                 location: init.location.clone(),
@@ -223,12 +311,12 @@ impl CompiledModule {
 
     /// Return a reference-counting pointer to a module.
     pub fn module(&self) -> &Arc<Module> {
-        &self.module
+        &self.artifacts.module
     }
 
     /// Return a reference to a mutable module (if possible).
     pub fn module_mut(&mut self) -> Option<&mut Module> {
-        Arc::get_mut(&mut self.module)
+        Arc::get_mut(&mut self.artifacts.module)
     }
 
     /// Returns the map of all finished JIT functions compiled for this module
@@ -236,19 +324,37 @@ impl CompiledModule {
         &self.finished_functions.0
     }
 
+    /// Returns the artifacts this module was created from.
+    pub fn artifacts(&self) -> &CompilationArtifacts {
+        &self.artifacts
+    }
+
+    /// Returns the original wasm binary of this module.
+    ///
+    /// This is empty unless the module has DWARF or was compiled with debug
+    /// info or guest debugging enabled.
+    pub fn wasm(&self) -> &[u8] {
+        &self.artifacts.wasm
+    }
+
     /// Returns the map for all traps in this module.
     pub fn traps(&self) -> &Traps {
-        &self.traps
+        &self.artifacts.traps
     }
 
     /// Returns the map for each of this module's stack maps.
     pub fn stack_maps(&self) -> &StackMaps {
-        &self.stack_maps
+        &self.artifacts.stack_maps
     }
 
     /// Returns a map of compiled addresses back to original bytecode offsets.
     pub fn address_transform(&self) -> &ModuleAddressMap {
-        &self.address_transform
+        &self.artifacts.address_transform
+    }
+
+    /// Returns the source-level symbols of this module, if it has DWARF.
+    pub fn symbols(&self) -> Option<&Arc<ModuleSymbols>> {
+        self.symbols.as_ref()
     }
 
     /// Returns all ranges convered by JIT code.
@@ -264,6 +370,7 @@ impl CompiledModule {
 
 /// Similar to `DataInitializer`, but owns its own copy of the data rather
 /// than holding a slice of the original module.
+#[derive(Serialize, Deserialize)]
 pub struct OwnedDataInitializer {
     /// The location where the initialization is to be performed.
     location: DataInitializerLocation,
@@ -273,7 +380,7 @@ pub struct OwnedDataInitializer {
 }
 
 impl OwnedDataInitializer {
-    fn new(borrowed: DataInitializer<'_>) -> Self {
+    pub(crate) fn new(borrowed: DataInitializer<'_>) -> Self {
         Self {
             location: borrowed.location.clone(),
             data: borrowed.data.to_vec().into_boxed_slice(),
@@ -281,6 +388,16 @@ impl OwnedDataInitializer {
     }
 }
 
+fn translate<'data>(
+    compiler: &Compiler,
+    data: &'data [u8],
+) -> Result<ModuleTranslation<'data>, SetupError> {
+    let environ = ModuleEnvironment::new(compiler.frontend_config(), compiler.tunables());
+    environ
+        .translate(data)
+        .map_err(|error| SetupError::Compile(CompileError::Wasm(error)))
+}
+
 fn create_dbg_image(
     dwarf_sections: Vec<DwarfSection>,
     isa: &dyn TargetIsa,
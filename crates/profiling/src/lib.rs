@@ -25,7 +25,10 @@ cfg_if::cfg_if! {
     }
 }
 
+mod perfmap;
+
 pub use crate::jitdump::JitDumpAgent;
+pub use crate::perfmap::PerfMapAgent;
 pub use crate::vtune::VTuneAgent;
 
 /// Common interface for profiling tools.
@@ -37,6 +40,10 @@ pub trait ProfilingAgent: Send + Sync + 'static {
         functions: &PrimaryMap<DefinedFuncIndex, *mut [VMFunctionBody]>,
         dbg_image: Option<&[u8]>,
     ) -> ();
+
+    /// Notify the profiler of a trampoline loaded into memory, either one
+    /// belonging to a module or one generated for a host function
+    fn trampoline_load(&self, _name: &str, _body: *const [VMFunctionBody]) {}
 }
 
 /// Default agent for unsupported profiling build.
@@ -70,7 +77,6 @@ impl ProfilingAgent for NullProfilerAgent {
     }
 }
 
-#[allow(dead_code)]
 fn debug_name(module: &Module, index: DefinedFuncIndex) -> String {
     let index = module.local.func_index(index);
     match module.func_names.get(&index) {
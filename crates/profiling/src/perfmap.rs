@@ -0,0 +1,92 @@
+//! Support for perf map files, the simplest format `perf` understands for
+//! symbolizing jitted code.
+//!
+//! Each line of `/tmp/perf-<pid>.map` is `START SIZE symbolname`, with start
+//! and size in hex. Unlike jitdump no post-processing is needed, but only
+//! symbol names (no line information or code bytes) are available.
+//!
+//! Usage Example:
+//!     Record
+//!         perf record -g target/debug/wasmtime --profile=perfmap test.wasm
+//!     Report
+//!         perf report
+
+use crate::{debug_name, ProfilingAgent};
+use anyhow::{Context, Result};
+use std::fmt::Write as _;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::process;
+use std::sync::Mutex;
+use wasmtime_environ::entity::PrimaryMap;
+use wasmtime_environ::wasm::DefinedFuncIndex;
+use wasmtime_environ::Module;
+use wasmtime_runtime::VMFunctionBody;
+
+/// Interface for driving the creation of perf map files
+#[derive(Debug)]
+pub struct PerfMapAgent {
+    file: Mutex<File>,
+}
+
+impl PerfMapAgent {
+    /// Intialize a PerfMapAgent, opening `/tmp/perf-<pid>.map` for appending
+    pub fn new() -> Result<Self> {
+        let filename = format!("/tmp/perf-{}.map", process::id());
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&filename)
+            .with_context(|| format!("failed to open perf map file {}", filename))?;
+        Ok(PerfMapAgent {
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Appends the given lines to the map file.
+    ///
+    /// Everything is written with a single `write_all` so that `perf` never
+    /// observes half a module, even if the process dies mid-way.
+    fn write_entries(&self, entries: &str) {
+        if entries.is_empty() {
+            return;
+        }
+        let mut file = self.file.lock().unwrap();
+        if let Err(e) = file.write_all(entries.as_bytes()) {
+            println!("PerfMap: failed to write entries: {:?}", e);
+        }
+    }
+}
+
+/// Formats a single map entry for `body` into `out`.
+fn push_entry(out: &mut String, name: &str, body: *const [VMFunctionBody]) {
+    let addr = body as *const VMFunctionBody as usize;
+    let len = unsafe { (*body).len() };
+    if len == 0 {
+        return;
+    }
+    // The format is line based, so symbol names can't contain newlines.
+    let name = name.replace(|c| c == '\n' || c == '\r', " ");
+    writeln!(out, "{:x} {:x} {}", addr, len, name).unwrap();
+}
+
+impl ProfilingAgent for PerfMapAgent {
+    fn module_load(
+        &self,
+        module: &Module,
+        functions: &PrimaryMap<DefinedFuncIndex, *mut [VMFunctionBody]>,
+        _dbg_image: Option<&[u8]>,
+    ) {
+        let mut entries = String::new();
+        for (idx, func) in functions.iter() {
+            push_entry(&mut entries, &debug_name(module, idx), *func);
+        }
+        self.write_entries(&entries);
+    }
+
+    fn trampoline_load(&self, name: &str, body: *const [VMFunctionBody]) {
+        let mut entries = String::new();
+        push_entry(&mut entries, name, body);
+        self.write_entries(&entries);
+    }
+}
@@ -3,9 +3,9 @@
 //! `VMExternRef` is a reference-counted box for any kind of data that is
 //! external and opaque to running Wasm. Sometimes it might hold a Wasmtime
 //! thing, other times it might hold something from a Wasmtime embedder and is
-//! opaque even to us. It is morally equivalent to `Rc<dyn Any>` in Rust, but
-//! additionally always fits in a pointer-sized word. `VMExternRef` is
-//! non-nullable, but `Option<VMExternRef>` is a null pointer.
+//! opaque even to us. It is morally equivalent to `Arc<dyn Any + Send + Sync>`
+//! in Rust, but additionally always fits in a pointer-sized word.
+//! `VMExternRef` is non-nullable, but `Option<VMExternRef>` is a null pointer.
 //!
 //! The one part of `VMExternRef` that can't ever be opaque to us is the
 //! reference count. Even when we don't know what's inside an `VMExternRef`, we
@@ -110,6 +110,10 @@ use std::mem;
 use std::ops::Deref;
 use std::ptr::{self, NonNull};
 use std::rc::Rc;
+use std::sync::atomic::{
+    self, AtomicUsize,
+    Ordering::{Acquire, Relaxed, Release, SeqCst},
+};
 use wasmtime_environ::{ir::Stackmap, StackMapInformation};
 
 /// An external reference to some opaque data.
@@ -125,8 +129,11 @@ use wasmtime_environ::{ir::Stackmap, StackMapInformation};
 /// `VMExternRef`s are reference counted, so cloning is a cheap, shallow
 /// operation. It also means they are inherently shared, so you may not get a
 /// mutable, exclusive reference to their inner contents, only a shared,
-/// immutable reference. You may use interior mutability with `RefCell` or
-/// `Mutex` to work around this restriction, if necessary.
+/// immutable reference. You may use interior mutability with `Mutex` to work
+/// around this restriction, if necessary.
+///
+/// The reference count is atomic, and the data within must be `Send` and
+/// `Sync`, so `VMExternRef`s may be sent to and shared between threads.
 ///
 /// `VMExternRef`s have pointer-equality semantics, not structural-equality
 /// semantics. Given two `VMExternRef`s `a` and `b`, `a == b` only if `a` and
@@ -139,7 +146,7 @@ use wasmtime_environ::{ir::Stackmap, StackMapInformation};
 ///
 /// ```
 /// # fn foo() -> Result<(), Box<dyn std::error::Error>> {
-/// use std::cell::RefCell;
+/// use std::sync::Mutex;
 /// use wasmtime_runtime::VMExternRef;
 ///
 /// // Open a file. Wasm doesn't know about files, but we can let Wasm instances
@@ -147,17 +154,17 @@ use wasmtime_environ::{ir::Stackmap, StackMapInformation};
 /// let file = std::fs::File::create("some/file/path")?;
 ///
 /// // Wrap the file up as an `VMExternRef` that can be passed to Wasm.
-/// let extern_ref_to_file = VMExternRef::new(RefCell::new(file));
+/// let extern_ref_to_file = VMExternRef::new(Mutex::new(file));
 ///
 /// // `VMExternRef`s dereference to `dyn Any`, so you can use `Any` methods to
 /// // perform runtime type checks and downcasts.
 ///
-/// assert!(extern_ref_to_file.is::<RefCell<std::fs::File>>());
+/// assert!(extern_ref_to_file.is::<Mutex<std::fs::File>>());
 /// assert!(!extern_ref_to_file.is::<String>());
 ///
-/// if let Some(file) = extern_ref_to_file.downcast_ref::<RefCell<std::fs::File>>() {
+/// if let Some(file) = extern_ref_to_file.downcast_ref::<Mutex<std::fs::File>>() {
 ///     use std::io::Write;
-///     let mut file = file.borrow_mut();
+///     let mut file = file.lock().unwrap();
 ///     writeln!(&mut file, "Hello, `VMExternRef`!")?;
 /// }
 /// # Ok(())
@@ -167,6 +174,11 @@ use wasmtime_environ::{ir::Stackmap, StackMapInformation};
 #[repr(transparent)]
 pub struct VMExternRef(NonNull<VMExternData>);
 
+// The reference count is atomic and the value is `Send + Sync`, see
+// `VMExternRef::new_with`.
+unsafe impl Send for VMExternRef {}
+unsafe impl Sync for VMExternRef {}
+
 #[repr(C)]
 struct VMExternData {
     // Implicit, dynamically-sized member that always preceded an
@@ -176,17 +188,17 @@ struct VMExternData {
     //
     /// The reference count for this `VMExternData` and value. When it reaches
     /// zero, we can safely destroy the value and free this heap
-    /// allocation. This is an `UnsafeCell`, rather than plain `Cell`, because
-    /// it can be modified by compiled Wasm code.
+    /// allocation. This is atomic because clones of a `VMExternRef` may be
+    /// dropped on different threads.
     ///
     /// Note: this field's offset must be kept in sync with
     /// `wasmtime_environ::VMOffsets::vm_extern_data_ref_count()` which is
     /// currently always zero.
-    ref_count: UnsafeCell<usize>,
+    ref_count: AtomicUsize,
 
     /// Always points to the implicit, dynamically-sized `value` member that
     /// precedes this `VMExternData`.
-    value_ptr: NonNull<dyn Any>,
+    value_ptr: NonNull<dyn Any + Send + Sync>,
 }
 
 impl Clone for VMExternRef {
@@ -201,8 +213,7 @@ impl Drop for VMExternRef {
     #[inline]
     fn drop(&mut self) {
         let data = self.extern_data();
-        data.decrement_ref_count();
-        if data.get_ref_count() == 0 {
+        if data.decrement_ref_count() {
             // Drop our live reference to `data` before we drop it itself.
             drop(data);
             unsafe {
@@ -264,23 +275,26 @@ impl VMExternData {
 
     #[inline]
     fn get_ref_count(&self) -> usize {
-        unsafe { *self.ref_count.get() }
+        self.ref_count.load(SeqCst)
     }
 
     #[inline]
     fn increment_ref_count(&self) {
-        unsafe {
-            let count = self.ref_count.get();
-            *count += 1;
-        }
+        // Like `Arc`, a new reference can only be made from an existing one, so
+        // this needs no synchronization with other threads.
+        self.ref_count.fetch_add(1, Relaxed);
     }
 
+    /// Decrements the reference count, returning whether it reached zero, in
+    /// which case all other threads' uses of the value happen before this
+    /// returns.
     #[inline]
-    fn decrement_ref_count(&self) {
-        unsafe {
-            let count = self.ref_count.get();
-            *count -= 1;
+    fn decrement_ref_count(&self) -> bool {
+        if self.ref_count.fetch_sub(1, Release) != 1 {
+            return false;
         }
+        atomic::fence(Acquire);
+        true
     }
 }
 
@@ -295,7 +309,7 @@ impl VMExternRef {
     /// Wrap the given value inside an `VMExternRef`.
     pub fn new<T>(value: T) -> VMExternRef
     where
-        T: 'static + Any,
+        T: 'static + Any + Send + Sync,
     {
         VMExternRef::new_with(|| value)
     }
@@ -303,7 +317,7 @@ impl VMExternRef {
     /// Construct a new `VMExternRef` in place by invoking `make_value`.
     pub fn new_with<T>(make_value: impl FnOnce() -> T) -> VMExternRef
     where
-        T: 'static + Any,
+        T: 'static + Any + Send + Sync,
     {
         unsafe {
             let (layout, footer_offset) =
@@ -318,9 +332,9 @@ impl VMExternRef {
             ptr::write(value_ptr.as_ptr(), make_value());
 
             let value_ref: &T = value_ptr.as_ref();
-            let value_ref: &dyn Any = value_ref as _;
-            let value_ptr: *const dyn Any = value_ref as _;
-            let value_ptr: *mut dyn Any = value_ptr as _;
+            let value_ref: &(dyn Any + Send + Sync) = value_ref as _;
+            let value_ptr: *const (dyn Any + Send + Sync) = value_ref as _;
+            let value_ptr: *mut (dyn Any + Send + Sync) = value_ptr as _;
             let value_ptr = NonNull::new_unchecked(value_ptr);
 
             let extern_data_ptr =
@@ -328,7 +342,7 @@ impl VMExternRef {
             ptr::write(
                 extern_data_ptr,
                 VMExternData {
-                    ref_count: UnsafeCell::new(1),
+                    ref_count: AtomicUsize::new(1),
                     value_ptr,
                 },
             );
@@ -502,6 +516,13 @@ pub struct VMExternRefActivationsTable {
     /// inside-a-Wasm-frame roots, and doing a GC could lead to freeing one of
     /// those missed roots, and use after free.
     stack_canary: Cell<Option<NonNull<u8>>>,
+
+    /// The number of native stacks with Wasm frames on them that are currently
+    /// suspended (for example async fibers waiting on a host future).
+    ///
+    /// We can't walk a suspended stack to find its roots, so garbage
+    /// collection will not sweep the table while this is non-zero.
+    suspended_stacks: Cell<usize>,
 }
 
 impl VMExternRefActivationsTable {
@@ -520,6 +541,7 @@ impl VMExternRefActivationsTable {
             over_approximated_stack_roots: RefCell::new(HashSet::with_capacity(Self::CHUNK_SIZE)),
             precise_stack_roots: RefCell::new(HashSet::with_capacity(Self::CHUNK_SIZE)),
             stack_canary: Cell::new(None),
+            suspended_stacks: Cell::new(0),
         }
     }
 
@@ -721,6 +743,26 @@ impl VMExternRefActivationsTable {
             }
         }
     }
+
+    /// Detach the current stack canary because the native stack it lives on
+    /// is about to be switched away from, for example when an async fiber
+    /// executing Wasm is suspended.
+    ///
+    /// The returned canary must be given back to `resume_stack` once the stack
+    /// is switched back to. Until then, `gc` will not sweep this table since
+    /// the suspended stack's roots can't be discovered.
+    pub fn suspend_stack(&self) -> Option<NonNull<u8>> {
+        self.suspended_stacks.set(self.suspended_stacks.get() + 1);
+        self.stack_canary.take()
+    }
+
+    /// Reattach a stack canary previously detached with `suspend_stack` once
+    /// its native stack is being executed again.
+    pub fn resume_stack(&self, canary: Option<NonNull<u8>>) {
+        debug_assert!(self.suspended_stacks.get() > 0);
+        self.suspended_stacks.set(self.suspended_stacks.get() - 1);
+        self.stack_canary.set(canary);
+    }
 }
 
 /// A registry of stack maps for currently active Wasm modules.
@@ -956,6 +998,15 @@ pub unsafe fn gc(
         precise_stack_roots.is_empty()
     });
 
+    // If there are suspended stacks with Wasm frames on them then we have no
+    // way of finding the roots in those frames, so we can't safely reset the
+    // table at all.
+    if externref_activations_table.suspended_stacks.get() > 0 {
+        log::debug!("suspended Wasm stacks exist; skipping GC sweep");
+        log::debug!("end GC");
+        return;
+    }
+
     // Whenever we call into Wasm from host code for the first time, we set a
     // stack canary. When we return to that host code, we unset the stack
     // canary. If there is *not* a stack canary, then there must be zero Wasm
@@ -1088,12 +1139,12 @@ mod tests {
     #[test]
     fn ref_count_is_at_correct_offset() {
         let s = "hi";
-        let s: &dyn Any = &s as _;
-        let s: *const dyn Any = s as _;
-        let s: *mut dyn Any = s as _;
+        let s: &(dyn Any + Send + Sync) = &s as _;
+        let s: *const (dyn Any + Send + Sync) = s as _;
+        let s: *mut (dyn Any + Send + Sync) = s as _;
 
         let extern_data = VMExternData {
-            ref_count: UnsafeCell::new(0),
+            ref_count: AtomicUsize::new(0),
             value_ptr: NonNull::new(s).unwrap(),
         };
 
@@ -1128,6 +1179,7 @@ mod tests {
             num_defined_tables: 0,
             num_defined_memories: 0,
             num_defined_globals: 0,
+            num_coverage_counters: 0,
         };
         assert_eq!(
             offsets.vm_extern_ref_activation_table_next() as usize,
@@ -1155,6 +1207,7 @@ mod tests {
             num_defined_tables: 0,
             num_defined_memories: 0,
             num_defined_globals: 0,
+            num_coverage_counters: 0,
         };
         assert_eq!(
             offsets.vm_extern_ref_activation_table_end() as usize,
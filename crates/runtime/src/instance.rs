@@ -4,8 +4,10 @@
 
 use crate::export::Export;
 use crate::externref::{StackMapRegistry, VMExternRefActivationsTable};
-use crate::imports::Imports;
-use crate::memory::{DefaultMemoryCreator, RuntimeLinearMemory, RuntimeMemoryCreator};
+use crate::instance_allocator::InstanceAllocationRequest;
+use crate::memfd::ModuleMemoryImages;
+use crate::memory::RuntimeLinearMemory;
+use crate::parking_spot::{self, ParkResult};
 use crate::table::{Table, TableElement};
 use crate::traphandlers::Trap;
 use crate::vmcontext::{
@@ -22,16 +24,170 @@ use std::cell::RefCell;
 use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::ptr::NonNull;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering::SeqCst};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use std::{mem, ptr, slice};
 use thiserror::Error;
 use wasmtime_environ::entity::{packed_option::ReservedValue, BoxedSlice, EntityRef, PrimaryMap};
 use wasmtime_environ::wasm::{
     DataIndex, DefinedFuncIndex, DefinedGlobalIndex, DefinedMemoryIndex, DefinedTableIndex,
     ElemIndex, FuncIndex, GlobalIndex, GlobalInit, MemoryIndex, SignatureIndex, TableElementType,
-    TableIndex,
+    TableIndex, WasmType,
 };
-use wasmtime_environ::{ir, DataInitializer, EntityIndex, Module, TableElements, VMOffsets};
+use wasmtime_environ::{
+    ir, DataInitializer, DebugLocalType, EntityIndex, Module, TableElements, VMOffsets,
+    WASM_PAGE_SIZE,
+};
+
+/// The default limit on the number of instances a resource limiter allows.
+pub const DEFAULT_INSTANCE_LIMIT: usize = 10000;
+/// The default limit on the number of tables a resource limiter allows.
+pub const DEFAULT_TABLE_LIMIT: usize = 10000;
+/// The default limit on the number of linear memories a resource limiter
+/// allows.
+pub const DEFAULT_MEMORY_LIMIT: usize = 10000;
+
+/// Used by hosts to limit resource consumption of instances.
+///
+/// An instance can be created with a resource limiter so that hosts can take
+/// into account non-WebAssembly resource usage to determine if a linear memory
+/// or table should grow.
+pub trait ResourceLimiter {
+    /// Notifies the resource limiter that an instance's linear memory has been
+    /// requested to grow.
+    ///
+    /// * `current` is the current size of the linear memory in WebAssembly
+    ///   page units.
+    /// * `desired` is the desired size of the linear memory in WebAssembly
+    ///   page units.
+    /// * `maximum` is the linear memory's declared maximum, also in
+    ///   WebAssembly page units. A value of `None` indicates that the linear
+    ///   memory is unbounded.
+    ///
+    /// This function should return `true` to indicate that the growing
+    /// operation is permitted or `false` if not permitted.
+    fn memory_growing(&self, current: u32, desired: u32, maximum: Option<u32>) -> bool;
+
+    /// Notifies the resource limiter that an instance's table has been
+    /// requested to grow.
+    ///
+    /// * `current` is the current number of elements in the table.
+    /// * `desired` is the desired number of elements in the table.
+    /// * `maximum` is the table's declared maximum. A value of `None`
+    ///   indicates that the table is unbounded.
+    ///
+    /// This function should return `true` to indicate that the growing
+    /// operation is permitted or `false` if not permitted.
+    fn table_growing(&self, current: u32, desired: u32, maximum: Option<u32>) -> bool;
+
+    /// The maximum number of instances that can be created for a store.
+    fn instances(&self) -> usize {
+        DEFAULT_INSTANCE_LIMIT
+    }
+
+    /// The maximum number of tables that can be created for a store.
+    fn tables(&self) -> usize {
+        DEFAULT_TABLE_LIMIT
+    }
+
+    /// The maximum number of linear memories that can be created for a store.
+    fn memories(&self) -> usize {
+        DEFAULT_MEMORY_LIMIT
+    }
+}
+
+/// Decides what happens when wasm code reaches its store's epoch deadline.
+///
+/// An instance compiled with epoch interruption enabled calls into its handler
+/// whenever the engine's epoch counter is at or beyond the deadline stored in
+/// its `VMInterrupts`.
+pub trait EpochDeadlineHandler {
+    /// Invoked once the epoch deadline has been reached.
+    ///
+    /// Returns the new deadline with which execution should continue, or the
+    /// trap to raise in the wasm code instead.
+    fn new_epoch(&self) -> Result<u64, Trap>;
+}
+
+/// Observes the execution of code compiled with guest debugging enabled.
+///
+/// An instance of such code calls into its handler before executing each
+/// wasm instruction.
+pub trait DebugHandler {
+    /// Invoked before the instruction at `offset` in the module's binary is
+    /// executed by the instance at `vmctx`, with the current values of the
+    /// executing function's locals.
+    ///
+    /// Returns the trap to raise in the wasm code, if execution is to be
+    /// aborted rather than continue.
+    fn before_instruction(
+        &self,
+        vmctx: *mut VMContext,
+        offset: u32,
+        locals: &DebugLocals<'_>,
+    ) -> Result<(), Trap>;
+}
+
+/// The values of a function's locals, as passed to a `DebugHandler`.
+pub struct DebugLocals<'a> {
+    /// The 16-byte value slots of all locals, followed by their type bytes,
+    /// as laid out by compiled code.
+    bytes: &'a [u8],
+    len: usize,
+}
+
+/// The value of a local, see `DebugLocals::get`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugValue {
+    /// An `i32` value.
+    I32(i32),
+    /// An `i64` value.
+    I64(i64),
+    /// The bits of an `f32` value.
+    F32(u32),
+    /// The bits of an `f64` value.
+    F64(u64),
+    /// A `v128` value.
+    V128(u128),
+}
+
+impl<'a> DebugLocals<'a> {
+    /// Creates a view of the `len` locals at `ptr`, as written by compiled
+    /// code before calling the debug hook.
+    pub(crate) unsafe fn new(ptr: *const u8, len: usize) -> Self {
+        DebugLocals {
+            bytes: slice::from_raw_parts(ptr, len * 17),
+            len,
+        }
+    }
+
+    /// Returns the number of locals, including parameters.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns the value of the local at `index`, or `None` if there's no
+    /// such local or it holds a reference, whose value isn't recorded.
+    pub fn get(&self, index: usize) -> Option<DebugValue> {
+        if index >= self.len {
+            return None;
+        }
+        let mut slot = [0; 16];
+        slot.copy_from_slice(&self.bytes[index * 16..][..16]);
+        let bits = u128::from_le_bytes(slot);
+        let ty = DebugLocalType::from_u8(self.bytes[self.len * 16 + index])?;
+        Some(match ty {
+            DebugLocalType::I32 => DebugValue::I32(bits as i32),
+            DebugLocalType::I64 => DebugValue::I64(bits as i64),
+            DebugLocalType::F32 => DebugValue::F32(bits as u32),
+            DebugLocalType::F64 => DebugValue::F64(bits as u64),
+            DebugLocalType::V128 => DebugValue::V128(bits),
+            DebugLocalType::Unavailable => return None,
+        })
+    }
+}
 
 /// A WebAssembly instance.
 ///
@@ -75,6 +231,23 @@ pub(crate) struct Instance {
     /// interrupted.
     pub(crate) interrupts: Arc<VMInterrupts>,
 
+    /// The resource limiter consulted when this instance's memories and
+    /// tables grow, if any.
+    limiter: Option<Rc<dyn ResourceLimiter>>,
+
+    /// The handler invoked when this instance's code reaches the epoch
+    /// deadline, if any.
+    epoch_deadline_handler: Option<Rc<dyn EpochDeadlineHandler>>,
+
+    /// The handler invoked before each instruction of this instance's code
+    /// when it was compiled with guest debugging, if any.
+    debug_handler: Option<Rc<dyn DebugHandler>>,
+
+    /// The images which were mapped into this instance's memories when they
+    /// were created, if any. The data initializers they cover are skipped
+    /// during initialization.
+    memory_images: Option<Arc<ModuleMemoryImages>>,
+
     /// Additional context used by compiled wasm code. This field is last, and
     /// represents a dynamically-sized array that extends beyond the nominal
     /// end of the struct (similar to a flexible array member).
@@ -185,8 +358,14 @@ impl Instance {
     }
 
     /// Return the indexed `VMMemoryDefinition`.
+    ///
+    /// A shared memory may have been grown through an instance in another
+    /// store, which only updates that instance's definition, so this instance's
+    /// definition is brought up to date before it's returned.
     fn memory(&self, index: DefinedMemoryIndex) -> VMMemoryDefinition {
-        unsafe { *self.memory_ptr(index) }
+        let memory = self.memories[index].vmmemory();
+        self.set_memory(index, memory);
+        memory
     }
 
     /// Set the indexed memory to `VMMemoryDefinition`.
@@ -236,6 +415,22 @@ impl Instance {
         unsafe { self.vmctx_plus_offset(self.offsets.vmctx_builtin_functions_begin()) }
     }
 
+    /// Return a pointer to the coverage counters.
+    fn coverage_counters_ptr(&self) -> *mut u64 {
+        unsafe { self.vmctx_plus_offset(self.offsets.vmctx_coverage_counters_begin()) }
+    }
+
+    /// Return the execution count of each basic block in
+    /// `ModuleLocal::coverage_blocks`.
+    pub fn coverage_counters(&self) -> &[u64] {
+        unsafe {
+            slice::from_raw_parts(
+                self.coverage_counters_ptr(),
+                self.offsets.num_coverage_counters as usize,
+            )
+        }
+    }
+
     /// Return a pointer to the interrupts structure
     pub fn interrupts(&self) -> *mut *const VMInterrupts {
         unsafe { self.vmctx_plus_offset(self.offsets.vmctx_interrupts()) }
@@ -377,16 +572,47 @@ impl Instance {
         index
     }
 
+    /// Handle this instance's code reaching the epoch deadline, returning the
+    /// new deadline.
+    ///
+    /// Instances without a handler always trap.
+    pub(crate) fn new_epoch(&self) -> Result<u64, Trap> {
+        match &self.epoch_deadline_handler {
+            Some(handler) => handler.new_epoch(),
+            None => Err(Trap::wasm(ir::TrapCode::Interrupt)),
+        }
+    }
+
+    /// Handle this instance's code reaching the instruction at `offset`, when
+    /// compiled with guest debugging.
+    pub(crate) fn debug_hook(&self, offset: u32, locals: &DebugLocals<'_>) -> Result<(), Trap> {
+        match &self.debug_handler {
+            Some(handler) => handler.before_instruction(self.vmctx_ptr(), offset, locals),
+            None => Ok(()),
+        }
+    }
+
     /// Grow memory by the specified amount of pages.
     ///
     /// Returns `None` if memory can't be grown by the specified amount
     /// of pages.
     pub(crate) fn memory_grow(&self, memory_index: DefinedMemoryIndex, delta: u32) -> Option<u32> {
-        let result = self
+        let memory = self
             .memories
             .get(memory_index)
-            .unwrap_or_else(|| panic!("no memory for index {}", memory_index.index()))
-            .grow(delta);
+            .unwrap_or_else(|| panic!("no memory for index {}", memory_index.index()));
+
+        if let Some(limiter) = &self.limiter {
+            let current = memory.size();
+            let desired = current.checked_add(delta)?;
+            let local = &self.module.local;
+            let plan = &local.memory_plans[local.memory_index(memory_index)];
+            if !limiter.memory_growing(current, desired, plan.memory.maximum) {
+                return None;
+            }
+        }
+
+        let result = memory.grow(delta);
 
         // Keep current the VMContext pointers used by compiled wasm code.
         self.set_memory(memory_index, self.memories[memory_index].vmmemory());
@@ -464,12 +690,23 @@ impl Instance {
         delta: u32,
         init_value: TableElement,
     ) -> Option<u32> {
+        let table = self
+            .tables
+            .get(table_index)
+            .unwrap_or_else(|| panic!("no table for index {}", table_index.index()));
+
+        if let Some(limiter) = &self.limiter {
+            let current = table.size();
+            let desired = current.checked_add(delta)?;
+            let local = &self.module.local;
+            let plan = &local.table_plans[local.table_index(table_index)];
+            if !limiter.table_growing(current, desired, plan.table.maximum) {
+                return None;
+            }
+        }
+
         unsafe {
-            let orig_size = self
-                .tables
-                .get(table_index)
-                .unwrap_or_else(|| panic!("no table for index {}", table_index.index()))
-                .grow(delta, init_value)?;
+            let orig_size = table.grow(delta, init_value)?;
 
             // Keep the `VMContext` pointers used by compiled Wasm code up to
             // date.
@@ -743,6 +980,218 @@ impl Instance {
         passive_data.remove(&data_index);
     }
 
+    /// Returns a pointer to the `size` bytes at wasm address `addr` in the
+    /// given memory, trapping if they're out of bounds.
+    ///
+    /// The memory's size is read from the memory itself rather than from its
+    /// `VMMemoryDefinition`, since a shared memory may have been grown by an
+    /// instance in another store.
+    fn atomic_addr(
+        &self,
+        memory_index: MemoryIndex,
+        addr: u64,
+        size: u64,
+    ) -> Result<*mut u8, Trap> {
+        let pages = match self.module.local.defined_memory_index(memory_index) {
+            Some(defined_index) => self.memory_size(defined_index),
+            None => unsafe { self.imported_memory_size(memory_index) },
+        };
+        let current_length = u64::from(pages) * u64::from(WASM_PAGE_SIZE);
+        if addr
+            .checked_add(size)
+            .map_or(true, |end| end > current_length)
+        {
+            return Err(Trap::wasm(ir::TrapCode::HeapOutOfBounds));
+        }
+        let memory = self.get_memory(memory_index);
+        Ok(unsafe { memory.base.add(usize::try_from(addr).unwrap()) })
+    }
+
+    /// Perform a `memory.atomic.notify`, waking up to `count` threads waiting
+    /// on `addr` and returning how many were woken.
+    pub(crate) fn memory_atomic_notify(
+        &self,
+        memory_index: MemoryIndex,
+        addr: u64,
+        count: u32,
+    ) -> Result<u32, Trap> {
+        let ptr = self.atomic_addr(memory_index, addr, 4)?;
+        if !self.module.local.memory_plans[memory_index].memory.shared {
+            // Nothing can wait on an unshared memory.
+            return Ok(0);
+        }
+        Ok(parking_spot::unpark(ptr as u64, count))
+    }
+
+    /// Perform a `memory.atomic.wait32`, see `memory_atomic_wait`.
+    pub(crate) fn memory_atomic_wait32(
+        &self,
+        memory_index: MemoryIndex,
+        addr: u64,
+        expected: u32,
+        timeout: i64,
+    ) -> Result<u32, Trap> {
+        let ptr = self.atomic_addr(memory_index, addr, 4)? as *const AtomicU32;
+        self.memory_atomic_wait(memory_index, ptr as u64, timeout, || unsafe {
+            (*ptr).load(SeqCst) == expected
+        })
+    }
+
+    /// Perform a `memory.atomic.wait64`, see `memory_atomic_wait`.
+    pub(crate) fn memory_atomic_wait64(
+        &self,
+        memory_index: MemoryIndex,
+        addr: u64,
+        expected: u64,
+        timeout: i64,
+    ) -> Result<u32, Trap> {
+        let ptr = self.atomic_addr(memory_index, addr, 8)? as *const AtomicU64;
+        self.memory_atomic_wait(memory_index, ptr as u64, timeout, || unsafe {
+            (*ptr).load(SeqCst) == expected
+        })
+    }
+
+    /// Park the current thread on the native address `key` if `validate`
+    /// returns `true`.
+    ///
+    /// Returns 0 if the thread was woken, 1 if `validate` returned `false`,
+    /// or 2 if `timeout` nanoseconds elapsed first. A negative `timeout` waits
+    /// forever.
+    fn memory_atomic_wait(
+        &self,
+        memory_index: MemoryIndex,
+        key: u64,
+        timeout: i64,
+        validate: impl FnOnce() -> bool,
+    ) -> Result<u32, Trap> {
+        if !self.module.local.memory_plans[memory_index].memory.shared {
+            return Err(Trap::wasm(ir::TrapCode::AtomicWaitNonSharedMemory));
+        }
+        let deadline = u64::try_from(timeout)
+            .ok()
+            .map(|timeout| Instant::now() + Duration::from_nanos(timeout));
+        Ok(match parking_spot::park(key, validate, deadline) {
+            ParkResult::Unparked => 0,
+            ParkResult::Invalid => 1,
+            ParkResult::TimedOut => 2,
+        })
+    }
+
+    /// The `table.fill` operation: fills a portion of a table with a given
+    /// value.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `Trap` error when the range within the table is out of bounds.
+    pub(crate) fn table_fill(
+        &self,
+        table_index: TableIndex,
+        dst: u32,
+        val: TableElement,
+        len: u32,
+    ) -> Result<(), Trap> {
+        self.get_table(table_index).fill(dst, val, len)
+    }
+
+    /// Return a pointer to the `VMGlobalDefinition` for the given global,
+    /// regardless of whether it is locally-defined or imported.
+    pub(crate) fn defined_or_imported_global_ptr(
+        &self,
+        index: GlobalIndex,
+    ) -> *mut VMGlobalDefinition {
+        if let Some(def_index) = self.module.local.defined_global_index(index) {
+            self.global_ptr(def_index)
+        } else {
+            self.imported_global(index).from
+        }
+    }
+
+    /// Drop the `externref`s held by this instance's locally-defined globals.
+    ///
+    /// Every other global type is plain old data and needs no cleanup.
+    unsafe fn drop_globals(&self) {
+        let module = self.module();
+        let num_imports = module.local.num_imported_globals;
+        for (index, global) in module.local.globals.iter().skip(num_imports) {
+            if global.wasm_ty != WasmType::ExternRef {
+                continue;
+            }
+            let def_index = module.local.defined_global_index(index).unwrap();
+            ptr::drop_in_place((*self.global_ptr(def_index)).as_externref_mut());
+        }
+    }
+
+    /// Returns whether this instance refers to anything within `other`, either
+    /// by importing one of its items or by holding a reference to one of its
+    /// functions in a table, global or passive element segment.
+    ///
+    /// `externref` tables and globals aren't inspected: an `externref` is
+    /// opaque host data which wasm can't create from anything in an instance,
+    /// so the only way for one to refer to `other` is through a handle the
+    /// embedder wrapped in it, and embedders keep track of their handles.
+    fn depends_on(&self, other: &Instance) -> bool {
+        let other_vmctx = other.vmctx_ptr();
+        let other_range = {
+            let start = other_vmctx as usize;
+            start..start + other.offsets.size_of_vmctx() as usize
+        };
+        let refers_to_other = |anyfunc: *mut VMCallerCheckedAnyfunc| {
+            !anyfunc.is_null()
+                && (other_range.contains(&(anyfunc as usize))
+                    || unsafe { (*anyfunc).vmctx } == other_vmctx)
+        };
+
+        let module = &self.module.local;
+        for i in 0..module.num_imported_funcs {
+            if self.imported_function(FuncIndex::new(i)).vmctx == other_vmctx {
+                return true;
+            }
+        }
+        for i in 0..module.num_imported_tables {
+            if self.imported_table(TableIndex::new(i)).vmctx == other_vmctx {
+                return true;
+            }
+        }
+        for i in 0..module.num_imported_memories {
+            if self.imported_memory(MemoryIndex::new(i)).vmctx == other_vmctx {
+                return true;
+            }
+        }
+        for i in 0..module.num_imported_globals {
+            let from = self.imported_global(GlobalIndex::new(i)).from;
+            if other_range.contains(&(from as usize)) {
+                return true;
+            }
+        }
+
+        for (index, global) in module.globals.iter().skip(module.num_imported_globals) {
+            if global.wasm_ty != WasmType::FuncRef {
+                continue;
+            }
+            let def_index = module.defined_global_index(index).unwrap();
+            if refers_to_other(unsafe { *(*self.global_ptr(def_index)).as_anyfunc() }) {
+                return true;
+            }
+        }
+        for table in self.tables.values() {
+            if table.element_type() != TableElementType::Func {
+                continue;
+            }
+            for i in 0..table.size() {
+                if let Some(TableElement::FuncRef(anyfunc)) = table.get(i) {
+                    if refers_to_other(anyfunc) {
+                        return true;
+                    }
+                }
+            }
+        }
+        self.passive_elements
+            .borrow()
+            .values()
+            .flat_map(|elements| elements.iter())
+            .any(|anyfunc| refers_to_other(*anyfunc))
+    }
+
     /// Get a table by index regardless of whether it is locally-defined or an
     /// imported, foreign table.
     pub(crate) fn get_table(&self, table_index: TableIndex) -> &Table {
@@ -790,7 +1239,8 @@ pub struct InstanceHandle {
 }
 
 impl InstanceHandle {
-    /// Create a new `InstanceHandle` pointing at a new `Instance`.
+    /// Create a new `InstanceHandle` pointing at a new `Instance`, using the
+    /// `memories` and `tables` set up by an `InstanceAllocator`.
     ///
     /// # Unsafety
     ///
@@ -809,25 +1259,31 @@ impl InstanceHandle {
     /// It is your responsibility to ensure that the given raw
     /// `externref_activations_table` and `stack_map_registry` outlive this
     /// instance.
-    pub unsafe fn new(
-        module: Arc<Module>,
-        code: Arc<dyn Any>,
-        finished_functions: BoxedSlice<DefinedFuncIndex, *mut [VMFunctionBody]>,
-        trampolines: HashMap<VMSharedSignatureIndex, VMTrampoline>,
-        imports: Imports,
-        mem_creator: Option<&dyn RuntimeMemoryCreator>,
-        vmshared_signatures: BoxedSlice<SignatureIndex, VMSharedSignatureIndex>,
-        host_state: Box<dyn Any>,
-        interrupts: Arc<VMInterrupts>,
-        externref_activations_table: *mut VMExternRefActivationsTable,
-        stack_map_registry: *mut StackMapRegistry,
-    ) -> Result<Self, InstantiationError> {
+    pub(crate) unsafe fn new(
+        req: InstanceAllocationRequest,
+        memories: BoxedSlice<DefinedMemoryIndex, Box<dyn RuntimeLinearMemory>>,
+        tables: BoxedSlice<DefinedTableIndex, Table>,
+    ) -> Self {
+        let InstanceAllocationRequest {
+            module,
+            code,
+            finished_functions,
+            trampolines,
+            imports,
+            vmshared_signatures,
+            host_state,
+            interrupts,
+            externref_activations_table,
+            stack_map_registry,
+            limiter,
+            epoch_deadline_handler,
+            debug_handler,
+            memory_images,
+        } = req;
+
         debug_assert!(!externref_activations_table.is_null());
         debug_assert!(!stack_map_registry.is_null());
 
-        let tables = create_tables(&module);
-        let memories = create_memories(&module, mem_creator.unwrap_or(&DefaultMemoryCreator {}))?;
-
         let vmctx_tables = tables
             .values()
             .map(Table::vmtable)
@@ -859,6 +1315,10 @@ impl InstanceHandle {
                 trampolines,
                 host_state,
                 interrupts,
+                limiter,
+                epoch_deadline_handler,
+                debug_handler,
+                memory_images,
                 vmctx: VMContext {},
             };
             let layout = instance.alloc_layout();
@@ -917,6 +1377,11 @@ impl InstanceHandle {
             instance.builtin_functions_ptr() as *mut VMBuiltinFunctionsArray,
             VMBuiltinFunctionsArray::initialized(),
         );
+        ptr::write_bytes(
+            instance.coverage_counters_ptr(),
+            0,
+            instance.offsets.num_coverage_counters as usize,
+        );
         *instance.interrupts() = &*instance.interrupts;
         *instance.externref_activations_table() = externref_activations_table;
         *instance.stack_map_registry() = stack_map_registry;
@@ -950,7 +1415,7 @@ impl InstanceHandle {
         initialize_passive_elements(instance);
         initialize_globals(instance);
 
-        Ok(handle)
+        handle
     }
 
     /// Finishes the instantiation process started by `Instance::new`.
@@ -961,6 +1426,13 @@ impl InstanceHandle {
         is_bulk_memory: bool,
         data_initializers: &[DataInitializer<'_>],
     ) -> Result<(), InstantiationError> {
+        // Data initializers already applied through memory images are known to
+        // be in bounds and don't need to be copied in again.
+        let data_initializers = match &self.instance().memory_images {
+            Some(images) => &data_initializers[images.num_initializers()..],
+            None => data_initializers,
+        };
+
         // Check initializer bounds before initializing anything. Only do this
         // when bulk memory is disabled, since the bulk memory proposal changes
         // instantiation such that the intermediate results of failed
@@ -1030,11 +1502,33 @@ impl InstanceHandle {
         self.instance().host_state()
     }
 
+    /// Return the execution count of each basic block in
+    /// `ModuleLocal::coverage_blocks`.
+    pub fn coverage_counters(&self) -> &[u64] {
+        self.instance().coverage_counters()
+    }
+
+    /// Returns whether this instance refers to anything within `other`, be it
+    /// through an import or a function reference held in one of its tables,
+    /// globals or passive element segments.
+    ///
+    /// An instance which depends on another must not outlive it.
+    pub fn depends_on(&self, other: &InstanceHandle) -> bool {
+        self.instance().depends_on(other.instance())
+    }
+
     /// Return the memory index for the given `VMMemoryDefinition` in this instance.
     pub fn memory_index(&self, memory: &VMMemoryDefinition) -> DefinedMemoryIndex {
         self.instance().memory_index(memory)
     }
 
+    /// Return the up-to-date `VMMemoryDefinition` of a locally-defined memory,
+    /// which may have changed without this instance's knowledge if the memory
+    /// is shared.
+    pub fn defined_memory(&self, index: DefinedMemoryIndex) -> VMMemoryDefinition {
+        self.instance().memory(index)
+    }
+
     /// Grow memory in this instance by the specified amount of pages.
     ///
     /// Returns `None` if memory can't be grown by the specified amount
@@ -1135,9 +1629,30 @@ impl InstanceHandle {
     pub unsafe fn dealloc(&self) {
         let instance = self.instance();
         let layout = instance.alloc_layout();
+        instance.drop_globals();
         ptr::drop_in_place(self.instance);
         alloc::dealloc(self.instance.cast(), layout);
     }
+
+    /// Deallocates this instance like `dealloc`, except that its memories and
+    /// tables are handed back rather than dropped, so that an allocator can
+    /// recycle them.
+    ///
+    /// This is unsafe for the same reasons as `dealloc`.
+    pub(crate) unsafe fn dealloc_into_parts(
+        &self,
+    ) -> (
+        BoxedSlice<DefinedMemoryIndex, Box<dyn RuntimeLinearMemory>>,
+        BoxedSlice<DefinedTableIndex, Table>,
+    ) {
+        let layout = self.instance().alloc_layout();
+        self.instance().drop_globals();
+        let Instance {
+            memories, tables, ..
+        } = ptr::read(self.instance);
+        alloc::dealloc(self.instance.cast(), layout);
+        (memories, tables)
+    }
 }
 
 fn check_table_init_bounds(instance: &Instance) -> Result<(), InstantiationError> {
@@ -1214,17 +1729,6 @@ fn check_memory_init_bounds(
     Ok(())
 }
 
-/// Allocate memory for just the tables of the current module.
-fn create_tables(module: &Module) -> BoxedSlice<DefinedTableIndex, Table> {
-    let num_imports = module.local.num_imported_tables;
-    let mut tables: PrimaryMap<DefinedTableIndex, _> =
-        PrimaryMap::with_capacity(module.local.table_plans.len() - num_imports);
-    for table in &module.local.table_plans.values().as_slice()[num_imports..] {
-        tables.push(Table::new(table));
-    }
-    tables.into_boxed_slice()
-}
-
 /// Compute the offset for a table element initializer.
 fn get_table_init_start(init: &TableElements, instance: &Instance) -> usize {
     let mut start = init.offset;
@@ -1309,24 +1813,6 @@ fn initialize_passive_elements(instance: &Instance) {
     );
 }
 
-/// Allocate memory for just the memories of the current module.
-fn create_memories(
-    module: &Module,
-    mem_creator: &dyn RuntimeMemoryCreator,
-) -> Result<BoxedSlice<DefinedMemoryIndex, Box<dyn RuntimeLinearMemory>>, InstantiationError> {
-    let num_imports = module.local.num_imported_memories;
-    let mut memories: PrimaryMap<DefinedMemoryIndex, _> =
-        PrimaryMap::with_capacity(module.local.memory_plans.len() - num_imports);
-    for plan in &module.local.memory_plans.values().as_slice()[num_imports..] {
-        memories.push(
-            mem_creator
-                .new_memory(plan)
-                .map_err(InstantiationError::Resource)?,
-        );
-    }
-    Ok(memories.into_boxed_slice())
-}
-
 /// Initialize the table memory from the provided initializers.
 fn initialize_memories(
     instance: &Instance,
@@ -1383,15 +1869,27 @@ fn initialize_globals(instance: &Instance) {
                 GlobalInit::F64Const(x) => *(*to).as_f64_bits_mut() = x,
                 GlobalInit::V128Const(x) => *(*to).as_u128_bits_mut() = x.0,
                 GlobalInit::GetGlobal(x) => {
-                    let from = if let Some(def_x) = module.local.defined_global_index(x) {
-                        instance.global(def_x)
+                    let from = instance.defined_or_imported_global_ptr(x);
+                    // `externref`s are reference counted, so a copy of one
+                    // needs to be a clone rather than a bitwise copy.
+                    if global.wasm_ty == WasmType::ExternRef {
+                        let from = (*from).as_externref().clone();
+                        ptr::write((*to).as_externref_mut(), from);
                     } else {
-                        *instance.imported_global(x).from
-                    };
-                    *to = from;
+                        *to = *from;
+                    }
+                }
+                GlobalInit::RefFunc(f) => {
+                    *(*to).as_anyfunc_mut() = instance.get_caller_checked_anyfunc(f).unwrap()
+                        as *const VMCallerCheckedAnyfunc
+                        as *mut _;
                 }
+                GlobalInit::RefNullConst => match global.wasm_ty {
+                    WasmType::FuncRef => *(*to).as_anyfunc_mut() = ptr::null_mut(),
+                    WasmType::ExternRef => ptr::write((*to).as_externref_mut(), None),
+                    ty => panic!("unsupported reference type for global: {:?}", ty),
+                },
                 GlobalInit::Import => panic!("locally-defined global initialized as import"),
-                GlobalInit::RefNullConst | GlobalInit::RefFunc(_) => unimplemented!(),
             }
         }
     }
@@ -1416,4 +1914,8 @@ pub enum InstantiationError {
     /// A trap ocurred during instantiation, after linking.
     #[error("Trap occurred during instantiation")]
     Trap(Trap),
+
+    /// A limit on how many instances are supported has been reached.
+    #[error("Limit of {0} concurrent instances has been reached")]
+    Limit(u32),
 }
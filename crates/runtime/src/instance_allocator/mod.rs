@@ -0,0 +1,212 @@
+//! Strategies for allocating the runtime state of instances.
+//!
+//! An `InstanceAllocator` decides where an instance's linear memories and
+//! tables live. `OnDemandInstanceAllocator` creates them fresh for every
+//! instantiation, while `PoolingInstanceAllocator` hands out slots from
+//! regions reserved up front.
+
+use crate::externref::{StackMapRegistry, VMExternRefActivationsTable};
+use crate::imports::Imports;
+use crate::instance::{
+    DebugHandler, EpochDeadlineHandler, InstanceHandle, InstantiationError, ResourceLimiter,
+};
+use crate::memfd::ModuleMemoryImages;
+use crate::memory::{DefaultMemoryCreator, MmapMemory, RuntimeLinearMemory, RuntimeMemoryCreator};
+use crate::table::Table;
+use crate::vmcontext::{VMFunctionBody, VMInterrupts, VMSharedSignatureIndex, VMTrampoline};
+use std::any::Any;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::Arc;
+use wasmtime_environ::entity::{BoxedSlice, PrimaryMap};
+use wasmtime_environ::wasm::{
+    DefinedFuncIndex, DefinedMemoryIndex, DefinedTableIndex, SignatureIndex,
+};
+use wasmtime_environ::Module;
+
+mod pooling;
+
+pub use self::pooling::{InstanceLimits, PoolingInstanceAllocator};
+
+/// Everything needed to allocate a new instance, apart from its memories and
+/// tables which the allocator provides.
+pub struct InstanceAllocationRequest {
+    /// The module being instantiated.
+    pub module: Arc<Module>,
+
+    /// The module's JIT code, kept alive as long as the instance.
+    pub code: Arc<dyn Any>,
+
+    /// Pointers to the module's compiled functions.
+    pub finished_functions: BoxedSlice<DefinedFuncIndex, *mut [VMFunctionBody]>,
+
+    /// Trampolines for entering the module's functions, by signature.
+    pub trampolines: HashMap<VMSharedSignatureIndex, VMTrampoline>,
+
+    /// The resolved imports of the instance.
+    pub imports: Imports,
+
+    /// The shared signature index of each of the module's signatures.
+    pub vmshared_signatures: BoxedSlice<SignatureIndex, VMSharedSignatureIndex>,
+
+    /// Arbitrary state for the host to associate with the instance.
+    pub host_state: Box<dyn Any>,
+
+    /// How the instance is interrupted.
+    pub interrupts: Arc<VMInterrupts>,
+
+    /// The table of `externref`s that are live on the wasm stack. This must
+    /// outlive the instance.
+    pub externref_activations_table: *mut VMExternRefActivationsTable,
+
+    /// The registry of stack maps used to find `externref`s on the wasm
+    /// stack. This must outlive the instance.
+    pub stack_map_registry: *mut StackMapRegistry,
+
+    /// The resource limiter to consult when the instance's memories and tables
+    /// are created or grown, if any.
+    pub limiter: Option<Rc<dyn ResourceLimiter>>,
+
+    /// The handler to invoke when the instance's code reaches the epoch
+    /// deadline. Without one reaching the deadline always traps.
+    pub epoch_deadline_handler: Option<Rc<dyn EpochDeadlineHandler>>,
+
+    /// The handler to invoke before each instruction of the instance's code,
+    /// if it was compiled with guest debugging.
+    pub debug_handler: Option<Rc<dyn DebugHandler>>,
+
+    /// Images of the module's initial memory contents, if any. Allocators
+    /// which don't map these into the memories they create must clear this
+    /// field, in which case all data segments are copied in when the instance
+    /// is initialized.
+    pub memory_images: Option<Arc<ModuleMemoryImages>>,
+}
+
+/// A strategy for allocating instances.
+pub trait InstanceAllocator: Send + Sync {
+    /// Allocates a new instance, creating its memories and tables.
+    ///
+    /// # Unsafety
+    ///
+    /// See `InstanceHandle::new`.
+    unsafe fn allocate(
+        &self,
+        req: InstanceAllocationRequest,
+    ) -> Result<InstanceHandle, InstantiationError>;
+
+    /// Deallocates an instance previously returned by `allocate` on this same
+    /// allocator.
+    ///
+    /// # Unsafety
+    ///
+    /// The handle, and any clone of it, must not be used afterwards.
+    unsafe fn deallocate(&self, handle: &InstanceHandle);
+}
+
+/// An instance allocator which creates memories and tables on demand, with no
+/// limits beyond those of the module itself.
+#[derive(Clone, Default)]
+pub struct OnDemandInstanceAllocator {
+    mem_creator: Option<Arc<dyn RuntimeMemoryCreator>>,
+}
+
+impl OnDemandInstanceAllocator {
+    /// Creates a new on-demand allocator, which creates linear memories with
+    /// `mem_creator` if one is given.
+    pub fn new(mem_creator: Option<Arc<dyn RuntimeMemoryCreator>>) -> Self {
+        Self { mem_creator }
+    }
+}
+
+impl InstanceAllocator for OnDemandInstanceAllocator {
+    unsafe fn allocate(
+        &self,
+        mut req: InstanceAllocationRequest,
+    ) -> Result<InstanceHandle, InstantiationError> {
+        check_initial_sizes(&req.module, req.limiter.as_deref())?;
+        // Memory images can only be mapped into memories we create ourselves.
+        let memories = match &self.mem_creator {
+            Some(creator) => {
+                req.memory_images = None;
+                create_memories(&req.module, &**creator, None)?
+            }
+            None => create_memories(
+                &req.module,
+                &DefaultMemoryCreator,
+                req.memory_images.as_deref(),
+            )?,
+        };
+        let tables = create_tables(&req.module);
+        Ok(InstanceHandle::new(req, memories, tables))
+    }
+
+    unsafe fn deallocate(&self, handle: &InstanceHandle) {
+        handle.dealloc();
+    }
+}
+
+/// Ask `limiter`, if there is one, whether the module's memories and tables may
+/// be created at their minimum sizes.
+fn check_initial_sizes(
+    module: &Module,
+    limiter: Option<&dyn ResourceLimiter>,
+) -> Result<(), InstantiationError> {
+    let limiter = match limiter {
+        Some(limiter) => limiter,
+        None => return Ok(()),
+    };
+    let local = &module.local;
+
+    for plan in &local.memory_plans.values().as_slice()[local.num_imported_memories..] {
+        if !limiter.memory_growing(0, plan.memory.minimum, plan.memory.maximum) {
+            return Err(InstantiationError::Resource(format!(
+                "memory minimum size of {} pages exceeds memory limits",
+                plan.memory.minimum
+            )));
+        }
+    }
+
+    for plan in &local.table_plans.values().as_slice()[local.num_imported_tables..] {
+        if !limiter.table_growing(0, plan.table.minimum, plan.table.maximum) {
+            return Err(InstantiationError::Resource(format!(
+                "table minimum size of {} elements exceeds table limits",
+                plan.table.minimum
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Allocate memory for just the tables of the current module.
+fn create_tables(module: &Module) -> BoxedSlice<DefinedTableIndex, Table> {
+    let num_imports = module.local.num_imported_tables;
+    let mut tables: PrimaryMap<DefinedTableIndex, _> =
+        PrimaryMap::with_capacity(module.local.table_plans.len() - num_imports);
+    for table in &module.local.table_plans.values().as_slice()[num_imports..] {
+        tables.push(Table::new(table));
+    }
+    tables.into_boxed_slice()
+}
+
+/// Allocate memory for just the memories of the current module, mapping in
+/// their images from `memory_images` where there are any.
+fn create_memories(
+    module: &Module,
+    mem_creator: &dyn RuntimeMemoryCreator,
+    memory_images: Option<&ModuleMemoryImages>,
+) -> Result<BoxedSlice<DefinedMemoryIndex, Box<dyn RuntimeLinearMemory>>, InstantiationError> {
+    let num_imports = module.local.num_imported_memories;
+    let mut memories: PrimaryMap<DefinedMemoryIndex, _> =
+        PrimaryMap::with_capacity(module.local.memory_plans.len() - num_imports);
+    for plan in &module.local.memory_plans.values().as_slice()[num_imports..] {
+        let image = memory_images.and_then(|images| images.get_memory_image(memories.next_key()));
+        let memory = match image {
+            Some(image) => MmapMemory::with_image(plan, image)
+                .map(|m| Box::new(m) as Box<dyn RuntimeLinearMemory>),
+            None => mem_creator.new_memory(plan),
+        };
+        memories.push(memory.map_err(InstantiationError::Resource)?);
+    }
+    Ok(memories.into_boxed_slice())
+}
@@ -0,0 +1,533 @@
+//! An instance allocator which reserves memory for a fixed number of instances
+//! up front and recycles it between instantiations.
+//!
+//! Each instance slot owns `InstanceLimits::memories` linear memory slots and
+//! `InstanceLimits::tables` table slots. Linear memory slots are large enough
+//! for any memory the engine's tunables may produce, including guard pages,
+//! so compiled code can use the same bounds-checking strategy as it would for
+//! an on-demand memory. When an instance is deallocated the pages it touched
+//! are released back to the OS and made inaccessible, so that the next
+//! instance to use the slot starts out with zeroed memory.
+
+use super::{check_initial_sizes, InstanceAllocationRequest, InstanceAllocator};
+use crate::instance::{InstanceHandle, InstantiationError};
+use crate::memory::RuntimeLinearMemory;
+use crate::mmap::Mmap;
+use crate::table::Table;
+use crate::vmcontext::VMMemoryDefinition;
+use std::cell::Cell;
+use std::cmp;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::mem;
+use std::sync::{Arc, Mutex};
+use wasmtime_environ::entity::{BoxedSlice, PrimaryMap};
+use wasmtime_environ::wasm::{DefinedMemoryIndex, DefinedTableIndex};
+use wasmtime_environ::{MemoryPlan, MemoryStyle, Module, Tunables, WASM_MAX_PAGES, WASM_PAGE_SIZE};
+
+/// Limits on the instances created by a `PoolingInstanceAllocator`.
+///
+/// These determine how much memory the allocator reserves, so they should be
+/// kept as small as the embedding allows.
+#[derive(Debug, Copy, Clone)]
+pub struct InstanceLimits {
+    /// The maximum number of instances which may be alive at the same time.
+    pub count: u32,
+
+    /// The maximum number of linear memories each instance may define.
+    pub memories: u32,
+
+    /// The maximum number of tables each instance may define.
+    pub tables: u32,
+
+    /// The maximum number of wasm pages each linear memory may grow to.
+    pub memory_pages: u32,
+
+    /// The maximum number of elements each table may grow to.
+    pub table_elements: u32,
+}
+
+impl Default for InstanceLimits {
+    fn default() -> Self {
+        Self {
+            count: 1000,
+            memories: 1,
+            tables: 1,
+            // 10 MiB
+            memory_pages: 160,
+            table_elements: 10_000,
+        }
+    }
+}
+
+/// An instance allocator which hands out memories and tables from regions
+/// reserved when the allocator is created.
+pub struct PoolingInstanceAllocator {
+    limits: InstanceLimits,
+    state: Mutex<PoolState>,
+    memories: MemoryPool,
+    tables: TablePool,
+}
+
+struct PoolState {
+    /// Instance slots which are not in use.
+    free: Vec<usize>,
+    /// The slot of each live instance, keyed by the address of its vmctx.
+    live: HashMap<usize, usize>,
+}
+
+impl PoolingInstanceAllocator {
+    /// Creates a new pooling allocator, reserving enough memory for
+    /// `limits.count` instances whose memories are laid out according to
+    /// `tunables`.
+    ///
+    /// The tunables must match those of the modules that will be instantiated
+    /// with this allocator; modules whose memories don't fit the reserved
+    /// slots fail to instantiate.
+    pub fn new(limits: InstanceLimits, tunables: &Tunables) -> Result<Self, String> {
+        if limits.memory_pages > WASM_MAX_PAGES {
+            return Err(format!(
+                "memory page limit of {} exceeds the maximum of {} wasm pages",
+                limits.memory_pages, WASM_MAX_PAGES
+            ));
+        }
+
+        let count = usize::try_from(limits.count).unwrap();
+        let memories = MemoryPool::new(&limits, tunables, count)?;
+        let tables = TablePool::new(&limits, count)?;
+
+        Ok(Self {
+            limits,
+            state: Mutex::new(PoolState {
+                free: (0..count).rev().collect(),
+                live: HashMap::new(),
+            }),
+            memories,
+            tables,
+        })
+    }
+
+    /// Returns the limits this allocator was created with.
+    pub fn limits(&self) -> &InstanceLimits {
+        &self.limits
+    }
+
+    fn validate(&self, module: &Module) -> Result<(), InstantiationError> {
+        let local = &module.local;
+
+        let memory_plans = &local.memory_plans.values().as_slice()[local.num_imported_memories..];
+        if memory_plans.len() > usize::try_from(self.limits.memories).unwrap() {
+            return Err(InstantiationError::Resource(format!(
+                "module defines {} linear memories, but instances are limited to {}",
+                memory_plans.len(),
+                self.limits.memories
+            )));
+        }
+        for plan in memory_plans {
+            self.memories.validate(plan, &self.limits)?;
+        }
+
+        let table_plans = &local.table_plans.values().as_slice()[local.num_imported_tables..];
+        if table_plans.len() > usize::try_from(self.limits.tables).unwrap() {
+            return Err(InstantiationError::Resource(format!(
+                "module defines {} tables, but instances are limited to {}",
+                table_plans.len(),
+                self.limits.tables
+            )));
+        }
+        for plan in table_plans {
+            if plan.table.minimum > self.limits.table_elements {
+                return Err(InstantiationError::Resource(format!(
+                    "table needs {} elements, but tables are limited to {}",
+                    plan.table.minimum, self.limits.table_elements
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    unsafe fn create_memories(
+        &self,
+        slot: usize,
+        module: &Module,
+    ) -> Result<BoxedSlice<DefinedMemoryIndex, Box<dyn RuntimeLinearMemory>>, InstantiationError>
+    {
+        let local = &module.local;
+        let mut memories: PrimaryMap<DefinedMemoryIndex, Box<dyn RuntimeLinearMemory>> =
+            PrimaryMap::new();
+        for plan in &local.memory_plans.values().as_slice()[local.num_imported_memories..] {
+            let index = slot * usize::try_from(self.limits.memories).unwrap() + memories.len();
+            match self.memories.create(index, plan, &self.limits) {
+                Ok(memory) => {
+                    memories.push(Box::new(memory));
+                }
+                Err(e) => {
+                    self.release_memories(memories.into_boxed_slice());
+                    return Err(e);
+                }
+            }
+        }
+        Ok(memories.into_boxed_slice())
+    }
+
+    unsafe fn create_tables(
+        &self,
+        slot: usize,
+        module: &Module,
+    ) -> Result<BoxedSlice<DefinedTableIndex, Table>, InstantiationError> {
+        let local = &module.local;
+        let mut tables = PrimaryMap::new();
+        for plan in &local.table_plans.values().as_slice()[local.num_imported_tables..] {
+            let index = slot * usize::try_from(self.limits.tables).unwrap() + tables.len();
+            let base = match self.tables.commit(index) {
+                Ok(base) => base,
+                Err(e) => {
+                    self.release_tables(tables.into_boxed_slice());
+                    return Err(e);
+                }
+            };
+            let capacity = usize::try_from(self.limits.table_elements).unwrap();
+            tables.push(Table::new_static(plan, base, capacity));
+        }
+        Ok(tables.into_boxed_slice())
+    }
+
+    fn release_memories(
+        &self,
+        memories: BoxedSlice<DefinedMemoryIndex, Box<dyn RuntimeLinearMemory>>,
+    ) {
+        for memory in memories.values() {
+            self.memories.release(memory.vmmemory());
+        }
+    }
+
+    fn release_tables(&self, tables: BoxedSlice<DefinedTableIndex, Table>) {
+        let bases = tables
+            .values()
+            .map(|table| table.vmtable().base)
+            .collect::<Vec<_>>();
+        // Drop the tables' elements before their storage is decommitted.
+        mem::drop(tables);
+        for base in bases {
+            self.tables.release(base);
+        }
+    }
+}
+
+impl InstanceAllocator for PoolingInstanceAllocator {
+    unsafe fn allocate(
+        &self,
+        mut req: InstanceAllocationRequest,
+    ) -> Result<InstanceHandle, InstantiationError> {
+        self.validate(&req.module)?;
+        check_initial_sizes(&req.module, req.limiter.as_deref())?;
+
+        let slot = self
+            .state
+            .lock()
+            .unwrap()
+            .free
+            .pop()
+            .ok_or(InstantiationError::Limit(self.limits.count))?;
+
+        let resources = self
+            .create_memories(slot, &req.module)
+            .and_then(|memories| match self.create_tables(slot, &req.module) {
+                Ok(tables) => Ok((memories, tables)),
+                Err(e) => {
+                    self.release_memories(memories);
+                    Err(e)
+                }
+            });
+        let (memories, tables) = match resources {
+            Ok(resources) => resources,
+            Err(e) => {
+                self.state.lock().unwrap().free.push(slot);
+                return Err(e);
+            }
+        };
+
+        // Memory slots are recycled by decommitting their pages rather than
+        // remapping them, so data segments are always copied into them.
+        req.memory_images = None;
+        let handle = InstanceHandle::new(req, memories, tables);
+        self.state
+            .lock()
+            .unwrap()
+            .live
+            .insert(handle.vmctx_ptr() as usize, slot);
+        Ok(handle)
+    }
+
+    unsafe fn deallocate(&self, handle: &InstanceHandle) {
+        let slot = self
+            .state
+            .lock()
+            .unwrap()
+            .live
+            .remove(&(handle.vmctx_ptr() as usize))
+            .expect("instance was not allocated by this allocator");
+
+        let (memories, tables) = handle.dealloc_into_parts();
+        self.release_memories(memories);
+        self.release_tables(tables);
+
+        self.state.lock().unwrap().free.push(slot);
+    }
+}
+
+/// A region holding every linear memory slot of the pool.
+struct MemoryPool {
+    mapping: Arc<Mmap>,
+    slot_size: usize,
+}
+
+impl MemoryPool {
+    fn new(limits: &InstanceLimits, tunables: &Tunables, count: usize) -> Result<Self, String> {
+        // A slot has to be able to hold both kinds of memory plans the
+        // tunables can produce: a static one reserving `static_memory_bound`
+        // pages, and a dynamic one which we never let grow past the page
+        // limit, each followed by its guard region.
+        let reserved_pages = cmp::max(tunables.static_memory_bound, limits.memory_pages);
+        let guard_size = cmp::max(
+            tunables.static_memory_offset_guard_size,
+            tunables.dynamic_memory_offset_guard_size,
+        );
+        let slot_size = (u64::from(reserved_pages) * u64::from(WASM_PAGE_SIZE))
+            .checked_add(guard_size)
+            .and_then(|size| usize::try_from(size).ok())
+            .ok_or_else(|| "linear memory slot size overflows".to_string())?;
+        let slot_size = round_up_to_page_size(slot_size);
+
+        let total = count
+            .checked_mul(usize::try_from(limits.memories).unwrap())
+            .and_then(|slots| slots.checked_mul(slot_size))
+            .ok_or_else(|| "linear memory pool size overflows".to_string())?;
+
+        Ok(Self {
+            mapping: Arc::new(Mmap::accessible_reserved(0, total)?),
+            slot_size,
+        })
+    }
+
+    fn validate(
+        &self,
+        plan: &MemoryPlan,
+        limits: &InstanceLimits,
+    ) -> Result<(), InstantiationError> {
+        if plan.memory.minimum > limits.memory_pages {
+            return Err(InstantiationError::Resource(format!(
+                "memory needs {} wasm pages, but memories are limited to {}",
+                plan.memory.minimum, limits.memory_pages
+            )));
+        }
+        let reserved_pages = match plan.style {
+            MemoryStyle::Static { bound } => bound,
+            MemoryStyle::Dynamic => limits.memory_pages,
+        };
+        let needed = u64::from(reserved_pages) * u64::from(WASM_PAGE_SIZE) + plan.offset_guard_size;
+        if needed > self.slot_size as u64 {
+            return Err(InstantiationError::Resource(format!(
+                "memory needs a reservation of {} bytes, but pooled memories only have {}",
+                needed, self.slot_size
+            )));
+        }
+        Ok(())
+    }
+
+    fn create(
+        &self,
+        index: usize,
+        plan: &MemoryPlan,
+        limits: &InstanceLimits,
+    ) -> Result<PooledMemory, InstantiationError> {
+        let offset = index * self.slot_size;
+        let initial_bytes = plan.memory.minimum as usize * WASM_PAGE_SIZE as usize;
+        if initial_bytes > 0 {
+            self.mapping
+                .make_accessible(offset, initial_bytes)
+                .map_err(InstantiationError::Resource)?;
+        }
+        Ok(PooledMemory {
+            mapping: self.mapping.clone(),
+            offset,
+            size: Cell::new(plan.memory.minimum),
+            maximum: cmp::min(
+                plan.memory.maximum.unwrap_or(WASM_MAX_PAGES),
+                limits.memory_pages,
+            ),
+        })
+    }
+
+    fn release(&self, memory: VMMemoryDefinition) {
+        let offset = memory.base as usize - self.mapping.as_ptr() as usize;
+        debug_assert_eq!(offset % self.slot_size, 0);
+        self.mapping
+            .decommit(offset, memory.current_length)
+            .expect("failed to reset pooled linear memory");
+    }
+}
+
+/// A linear memory living in a slot of a `MemoryPool`.
+///
+/// The slot never moves, so growing the memory only makes more of it
+/// accessible.
+struct PooledMemory {
+    mapping: Arc<Mmap>,
+    offset: usize,
+    size: Cell<u32>,
+    maximum: u32,
+}
+
+impl RuntimeLinearMemory for PooledMemory {
+    fn size(&self) -> u32 {
+        self.size.get()
+    }
+
+    fn grow(&self, delta: u32) -> Option<u32> {
+        let prev_pages = self.size.get();
+        if delta == 0 {
+            return Some(prev_pages);
+        }
+
+        let new_pages = prev_pages.checked_add(delta)?;
+        if new_pages > self.maximum {
+            return None;
+        }
+
+        let prev_bytes = prev_pages as usize * WASM_PAGE_SIZE as usize;
+        let delta_bytes = delta as usize * WASM_PAGE_SIZE as usize;
+        self.mapping
+            .make_accessible(self.offset + prev_bytes, delta_bytes)
+            .ok()?;
+        self.size.set(new_pages);
+
+        Some(prev_pages)
+    }
+
+    fn vmmemory(&self) -> VMMemoryDefinition {
+        VMMemoryDefinition {
+            base: unsafe { (self.mapping.as_ptr() as *mut u8).add(self.offset) },
+            current_length: self.size.get() as usize * WASM_PAGE_SIZE as usize,
+        }
+    }
+}
+
+/// A region holding the element storage of every table slot of the pool.
+struct TablePool {
+    mapping: Mmap,
+    slot_size: usize,
+}
+
+impl TablePool {
+    fn new(limits: &InstanceLimits, count: usize) -> Result<Self, String> {
+        let slot_size = usize::try_from(limits.table_elements)
+            .unwrap()
+            .checked_mul(mem::size_of::<*mut u8>())
+            .ok_or_else(|| "table slot size overflows".to_string())?;
+        let slot_size = round_up_to_page_size(slot_size);
+
+        let total = count
+            .checked_mul(usize::try_from(limits.tables).unwrap())
+            .and_then(|slots| slots.checked_mul(slot_size))
+            .ok_or_else(|| "table pool size overflows".to_string())?;
+
+        Ok(Self {
+            mapping: Mmap::accessible_reserved(0, total)?,
+            slot_size,
+        })
+    }
+
+    fn commit(&self, index: usize) -> Result<*mut u8, InstantiationError> {
+        let offset = index * self.slot_size;
+        if self.slot_size > 0 {
+            self.mapping
+                .make_accessible(offset, self.slot_size)
+                .map_err(InstantiationError::Resource)?;
+        }
+        Ok(unsafe { (self.mapping.as_ptr() as *mut u8).add(offset) })
+    }
+
+    fn release(&self, base: *mut u8) {
+        let offset = base as usize - self.mapping.as_ptr() as usize;
+        debug_assert_eq!(offset % cmp::max(self.slot_size, 1), 0);
+        self.mapping
+            .decommit(offset, self.slot_size)
+            .expect("failed to reset pooled table");
+    }
+}
+
+fn round_up_to_page_size(size: usize) -> usize {
+    let page_size = region::page::size();
+    (size + (page_size - 1)) & !(page_size - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn small_limits() -> InstanceLimits {
+        InstanceLimits {
+            count: 2,
+            memories: 1,
+            tables: 1,
+            memory_pages: 2,
+            table_elements: 10,
+        }
+    }
+
+    fn small_tunables() -> Tunables {
+        Tunables {
+            static_memory_bound: 1,
+            static_memory_offset_guard_size: 0x10000,
+            dynamic_memory_offset_guard_size: 0x10000,
+            ..Tunables::default()
+        }
+    }
+
+    #[test]
+    fn memory_slots_fit_static_and_dynamic_plans() {
+        let pool = MemoryPool::new(&small_limits(), &small_tunables(), 2).unwrap();
+        // Two pages for the page limit plus one for the guard.
+        assert_eq!(pool.slot_size, 3 * WASM_PAGE_SIZE as usize);
+        assert_eq!(pool.mapping.len(), 2 * pool.slot_size);
+    }
+
+    #[test]
+    fn pooled_memory_grows_up_to_limit() {
+        let limits = small_limits();
+        let pool = MemoryPool::new(&limits, &small_tunables(), 2).unwrap();
+        let plan = MemoryPlan {
+            memory: wasmtime_environ::wasm::Memory {
+                minimum: 1,
+                maximum: None,
+                shared: false,
+            },
+            style: MemoryStyle::Dynamic,
+            offset_guard_size: 0x10000,
+        };
+        pool.validate(&plan, &limits).unwrap();
+        let memory = pool.create(1, &plan, &limits).unwrap();
+        assert_eq!(memory.size(), 1);
+        assert_eq!(memory.grow(1), Some(1));
+        assert_eq!(memory.grow(1), None);
+
+        let vmmemory = memory.vmmemory();
+        unsafe { *vmmemory.base = 42 };
+        pool.release(vmmemory);
+
+        // The slot comes back zeroed.
+        let memory = pool.create(1, &plan, &limits).unwrap();
+        assert_eq!(unsafe { *memory.vmmemory().base }, 0);
+    }
+
+    #[test]
+    fn rejects_too_large_page_limit() {
+        let limits = InstanceLimits {
+            memory_pages: WASM_MAX_PAGES + 1,
+            ..small_limits()
+        };
+        assert!(PoolingInstanceAllocator::new(limits, &small_tunables()).is_err());
+    }
+}
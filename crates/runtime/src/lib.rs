@@ -25,9 +25,16 @@ mod export;
 mod externref;
 mod imports;
 mod instance;
+mod instance_allocator;
 mod jit_int;
+#[cfg(target_os = "linux")]
+mod memfd;
+#[cfg(not(target_os = "linux"))]
+#[path = "memfd_disabled.rs"]
+mod memfd;
 mod memory;
 mod mmap;
+mod parking_spot;
 mod sig_registry;
 mod table;
 mod traphandlers;
@@ -39,19 +46,29 @@ pub mod libcalls;
 pub use crate::export::*;
 pub use crate::externref::*;
 pub use crate::imports::Imports;
-pub use crate::instance::{InstanceHandle, InstantiationError, LinkError};
+pub use crate::instance::{
+    DebugHandler, DebugLocals, DebugValue, EpochDeadlineHandler, InstanceHandle,
+    InstantiationError, LinkError, ResourceLimiter, DEFAULT_INSTANCE_LIMIT, DEFAULT_MEMORY_LIMIT,
+    DEFAULT_TABLE_LIMIT,
+};
+pub use crate::instance_allocator::{
+    InstanceAllocationRequest, InstanceAllocator, InstanceLimits, OnDemandInstanceAllocator,
+    PoolingInstanceAllocator,
+};
 pub use crate::jit_int::GdbJitImageRegistration;
-pub use crate::memory::{RuntimeLinearMemory, RuntimeMemoryCreator};
+pub use crate::memfd::{MemoryImage, ModuleMemoryImages};
+pub use crate::memory::{RuntimeLinearMemory, RuntimeMemoryCreator, SharedMemory};
 pub use crate::mmap::Mmap;
 pub use crate::sig_registry::SignatureRegistry;
 pub use crate::table::{Table, TableElement};
 pub use crate::traphandlers::{
-    catch_traps, init_traps, raise_lib_trap, raise_user_trap, resume_panic, SignalHandler, Trap,
+    catch_traps, init_traps, raise_lib_trap, raise_user_trap, resume_panic, wasm_frames,
+    SignalHandler, TlsMark, TlsRestore, Trap, WasmFrame,
 };
 pub use crate::vmcontext::{
-    VMCallerCheckedAnyfunc, VMContext, VMFunctionBody, VMFunctionImport, VMGlobalDefinition,
-    VMGlobalImport, VMInterrupts, VMInvokeArgument, VMMemoryDefinition, VMMemoryImport,
-    VMSharedSignatureIndex, VMTableDefinition, VMTableImport, VMTrampoline,
+    VMCallerCheckedAnyfunc, VMContext, VMFrameRecord, VMFunctionBody, VMFunctionImport,
+    VMGlobalDefinition, VMGlobalImport, VMInterrupts, VMInvokeArgument, VMMemoryDefinition,
+    VMMemoryImport, VMSharedSignatureIndex, VMTableDefinition, VMTableImport, VMTrampoline,
 };
 
 /// Version number of this crate.
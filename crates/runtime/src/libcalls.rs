@@ -57,11 +57,16 @@
 //!   ```
 
 use crate::externref::VMExternRef;
-use crate::table::Table;
-use crate::traphandlers::raise_lib_trap;
+use crate::instance::{DebugLocals, Instance};
+use crate::table::{Table, TableElement};
+use crate::traphandlers::{raise_lib_trap, Trap};
 use crate::vmcontext::{VMCallerCheckedAnyfunc, VMContext};
+use std::convert::TryInto;
+use std::ptr;
+use wasmtime_environ::ir;
 use wasmtime_environ::wasm::{
-    DataIndex, DefinedMemoryIndex, ElemIndex, MemoryIndex, TableElementType, TableIndex,
+    DataIndex, DefinedMemoryIndex, ElemIndex, GlobalIndex, MemoryIndex, TableElementType,
+    TableIndex,
 };
 
 /// Implementation of f32.ceil
@@ -262,6 +267,129 @@ pub unsafe extern "C" fn wasmtime_table_grow(
     }
 }
 
+/// Implementation of `table.fill`.
+pub unsafe extern "C" fn wasmtime_table_fill(
+    vmctx: *mut VMContext,
+    table_index: u32,
+    dst: u32,
+    // NB: we don't know whether this is a pointer to a `VMCallerCheckedAnyfunc`
+    // or is a `VMExternRef` until we look at the table type.
+    val: *mut u8,
+    len: u32,
+) {
+    let result = {
+        let instance = (&mut *vmctx).instance();
+        let table_index = TableIndex::from_u32(table_index);
+        let val = match instance.table_element_type(table_index) {
+            TableElementType::Func => TableElement::FuncRef(val as *mut VMCallerCheckedAnyfunc),
+            TableElementType::Val(ty) => {
+                debug_assert_eq!(ty, crate::ref_type());
+                TableElement::ExternRef(externref_from_raw(val))
+            }
+        };
+        instance.table_fill(table_index, dst, val, len)
+    };
+    if let Err(trap) = result {
+        raise_lib_trap(trap);
+    }
+}
+
+/// Implementation of `table.get` for `externref` tables.
+pub unsafe extern "C" fn wasmtime_table_get_externref(
+    vmctx: *mut VMContext,
+    table_index: u32,
+    index: u32,
+) -> *mut u8 {
+    let result = {
+        let instance = (&mut *vmctx).instance();
+        let table_index = TableIndex::from_u32(table_index);
+        match instance.get_table(table_index).get(index) {
+            Some(elem) => {
+                let externref: Option<VMExternRef> = elem
+                    .try_into()
+                    .expect("`externref` table has `funcref` element");
+                Ok(root_externref(instance, externref))
+            }
+            None => Err(Trap::wasm(ir::TrapCode::TableOutOfBounds)),
+        }
+    };
+    match result {
+        Ok(raw) => raw,
+        Err(trap) => raise_lib_trap(trap),
+    }
+}
+
+/// Implementation of `table.set` for `externref` tables.
+pub unsafe extern "C" fn wasmtime_table_set_externref(
+    vmctx: *mut VMContext,
+    table_index: u32,
+    index: u32,
+    val: *mut u8,
+) {
+    let result = {
+        let instance = (&mut *vmctx).instance();
+        let table_index = TableIndex::from_u32(table_index);
+        let val = externref_from_raw(val);
+        instance
+            .get_table(table_index)
+            .set(index, val.into())
+            .map_err(|()| Trap::wasm(ir::TrapCode::TableOutOfBounds))
+    };
+    if let Err(trap) = result {
+        raise_lib_trap(trap);
+    }
+}
+
+/// Implementation of `global.get` for `externref` globals.
+pub unsafe extern "C" fn wasmtime_externref_global_get(
+    vmctx: *mut VMContext,
+    index: u32,
+) -> *mut u8 {
+    let instance = (&mut *vmctx).instance();
+    let global = instance.defined_or_imported_global_ptr(GlobalIndex::from_u32(index));
+    let externref = (*global).as_externref().clone();
+    root_externref(instance, externref)
+}
+
+/// Implementation of `global.set` for `externref` globals.
+pub unsafe extern "C" fn wasmtime_externref_global_set(
+    vmctx: *mut VMContext,
+    index: u32,
+    val: *mut u8,
+) {
+    let val = externref_from_raw(val);
+    let instance = (&mut *vmctx).instance();
+    let global = instance.defined_or_imported_global_ptr(GlobalIndex::from_u32(index));
+    *(*global).as_externref_mut() = val;
+}
+
+/// Convert a raw, possibly-null `externref` passed in from Wasm into a rooted
+/// `VMExternRef`.
+unsafe fn externref_from_raw(raw: *mut u8) -> Option<VMExternRef> {
+    if raw.is_null() {
+        None
+    } else {
+        Some(VMExternRef::clone_from_raw(raw))
+    }
+}
+
+/// Hand an `externref` back out to Wasm as a raw pointer.
+///
+/// The reference is inserted into the activations table first, so that it
+/// stays alive until a GC finds it is no longer referenced from the stack.
+unsafe fn root_externref(instance: &Instance, externref: Option<VMExternRef>) -> *mut u8 {
+    match externref {
+        None => ptr::null_mut(),
+        Some(externref) => {
+            let raw = externref.as_raw();
+            let activations_table = &**instance.externref_activations_table();
+            let registry = &**instance.stack_map_registry();
+            activations_table.insert_with_gc(externref, registry);
+            raw
+        }
+    }
+}
+
 /// Implementation of `table.copy`.
 pub unsafe extern "C" fn wasmtime_table_copy(
     vmctx: *mut VMContext,
@@ -409,3 +537,88 @@ pub unsafe extern "C" fn wasmtime_data_drop(vmctx: *mut VMContext, data_index: u
     let instance = (&mut *vmctx).instance();
     instance.data_drop(data_index)
 }
+
+/// Implementation of `memory.atomic.notify`.
+pub unsafe extern "C" fn wasmtime_memory_atomic_notify(
+    vmctx: *mut VMContext,
+    memory_index: u32,
+    addr: u64,
+    count: u32,
+) -> u32 {
+    let result = {
+        let memory_index = MemoryIndex::from_u32(memory_index);
+        let instance = (&mut *vmctx).instance();
+        instance.memory_atomic_notify(memory_index, addr, count)
+    };
+    match result {
+        Ok(woken) => woken,
+        Err(trap) => raise_lib_trap(trap),
+    }
+}
+
+/// Implementation of `memory.atomic.wait32`.
+pub unsafe extern "C" fn wasmtime_memory_atomic_wait32(
+    vmctx: *mut VMContext,
+    memory_index: u32,
+    addr: u64,
+    expected: u32,
+    timeout: i64,
+) -> u32 {
+    let result = {
+        let memory_index = MemoryIndex::from_u32(memory_index);
+        let instance = (&mut *vmctx).instance();
+        instance.memory_atomic_wait32(memory_index, addr, expected, timeout)
+    };
+    match result {
+        Ok(result) => result,
+        Err(trap) => raise_lib_trap(trap),
+    }
+}
+
+/// Implementation of `memory.atomic.wait64`.
+pub unsafe extern "C" fn wasmtime_memory_atomic_wait64(
+    vmctx: *mut VMContext,
+    memory_index: u32,
+    addr: u64,
+    expected: u64,
+    timeout: i64,
+) -> u32 {
+    let result = {
+        let memory_index = MemoryIndex::from_u32(memory_index);
+        let instance = (&mut *vmctx).instance();
+        instance.memory_atomic_wait64(memory_index, addr, expected, timeout)
+    };
+    match result {
+        Ok(result) => result,
+        Err(trap) => raise_lib_trap(trap),
+    }
+}
+
+/// Implementation of the epoch deadline check, returning the new deadline.
+pub unsafe extern "C" fn wasmtime_new_epoch(vmctx: *mut VMContext) -> u64 {
+    let result = {
+        let instance = (&mut *vmctx).instance();
+        instance.new_epoch()
+    };
+    match result {
+        Ok(deadline) => deadline,
+        Err(trap) => raise_lib_trap(trap),
+    }
+}
+
+/// Implementation of the guest debugging hook run before each instruction.
+pub unsafe extern "C" fn wasmtime_debug_hook(
+    vmctx: *mut VMContext,
+    offset: u32,
+    locals: *const u8,
+    num_locals: u32,
+) {
+    let result = {
+        let instance = (&mut *vmctx).instance();
+        let locals = DebugLocals::new(locals, num_locals as usize);
+        instance.debug_hook(offset, &locals)
+    };
+    if let Err(trap) = result {
+        raise_lib_trap(trap);
+    }
+}
@@ -0,0 +1,195 @@
+//! Copy-on-write initialization of linear memories from memfd-backed images.
+//!
+//! Rather than copying every data segment into a fresh linear memory on each
+//! instantiation, a module's static data segments are written once into an
+//! in-memory file per defined memory. New memories then map that file
+//! privately over their initial pages, so pages are only copied when an
+//! instance writes to them.
+
+use std::convert::TryFrom;
+use std::ffi::CStr;
+use std::fs::File;
+use std::io;
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::sync::Arc;
+use wasmtime_environ::entity::PrimaryMap;
+use wasmtime_environ::wasm::DefinedMemoryIndex;
+use wasmtime_environ::{DataInitializer, Module, WASM_PAGE_SIZE};
+
+/// The memory images of all of a module's defined memories.
+#[derive(Debug)]
+pub struct ModuleMemoryImages {
+    memories: PrimaryMap<DefinedMemoryIndex, Option<Arc<MemoryImage>>>,
+    num_initializers: usize,
+}
+
+impl ModuleMemoryImages {
+    /// Builds memory images from a prefix of `data_initializers`.
+    ///
+    /// Images can only cover segments with constant offsets which target one
+    /// of the module's own memories and fit within its initial size. The
+    /// first segment which doesn't qualify, and every segment after it, is
+    /// left to be copied in at instantiation time as usual so that segments
+    /// are still applied in order.
+    ///
+    /// Returns `Ok(None)` if no segment can be covered by an image.
+    pub fn new(
+        module: &Module,
+        data_initializers: &[DataInitializer<'_>],
+    ) -> Result<Option<Self>, String> {
+        let local = &module.local;
+        let num_defined = local.memory_plans.len() - local.num_imported_memories;
+        let mut segments =
+            PrimaryMap::<DefinedMemoryIndex, Vec<(usize, &[u8])>>::with_capacity(num_defined);
+        for _ in 0..num_defined {
+            segments.push(Vec::new());
+        }
+
+        let mut num_initializers = 0;
+        for init in data_initializers {
+            if init.location.base.is_some() {
+                break;
+            }
+            let index = match local.defined_memory_index(init.location.memory_index) {
+                Some(index) => index,
+                None => break,
+            };
+            let plan = &local.memory_plans[init.location.memory_index];
+            let initial_bytes = usize::try_from(plan.memory.minimum).unwrap()
+                * usize::try_from(WASM_PAGE_SIZE).unwrap();
+            let start = init.location.offset;
+            match start.checked_add(init.data.len()) {
+                Some(end) if end <= initial_bytes => {}
+                _ => break,
+            }
+            segments[index].push((start, init.data));
+            num_initializers += 1;
+        }
+
+        if num_initializers == 0 {
+            return Ok(None);
+        }
+
+        let page_size = region::page::size();
+        let mut memories = PrimaryMap::with_capacity(num_defined);
+        for (index, segments) in segments.iter() {
+            let plan = &local.memory_plans[local.memory_index(index)];
+            let initial_bytes = usize::try_from(plan.memory.minimum).unwrap()
+                * usize::try_from(WASM_PAGE_SIZE).unwrap();
+            let image = MemoryImage::new(segments, initial_bytes, page_size)?;
+            memories.push(image.map(Arc::new));
+        }
+
+        // If no image could be built, e.g. because the host's pages are larger
+        // than the memories' initial sizes, there's nothing to map.
+        if memories.values().all(Option::is_none) {
+            return Ok(None);
+        }
+
+        Ok(Some(Self {
+            memories,
+            num_initializers,
+        }))
+    }
+
+    /// Returns the image to map into the given defined memory, if it has one.
+    pub fn get_memory_image(&self, index: DefinedMemoryIndex) -> Option<&Arc<MemoryImage>> {
+        self.memories[index].as_ref()
+    }
+
+    /// Returns how many of the module's data initializers, counted from the
+    /// start, are covered by these images.
+    pub(crate) fn num_initializers(&self) -> usize {
+        self.num_initializers
+    }
+}
+
+/// The initial contents of one linear memory, backed by a memfd.
+#[derive(Debug)]
+pub struct MemoryImage {
+    /// The memfd holding the image. Its contents are never modified once
+    /// written.
+    fd: File,
+
+    /// The length of the image, a multiple of the host page size.
+    len: usize,
+
+    /// The offset in linear memory at which the image starts, a multiple of
+    /// the host page size.
+    linear_memory_offset: usize,
+}
+
+impl MemoryImage {
+    fn new(
+        segments: &[(usize, &[u8])],
+        initial_bytes: usize,
+        page_size: usize,
+    ) -> Result<Option<Self>, String> {
+        let segments = segments.iter().filter(|(_, data)| !data.is_empty());
+        let start = match segments.clone().map(|(start, _)| *start).min() {
+            Some(start) => start & !(page_size - 1),
+            None => return Ok(None),
+        };
+        let end = segments
+            .clone()
+            .map(|(start, data)| start + data.len())
+            .max()
+            .unwrap();
+        let end = (end + (page_size - 1)) & !(page_size - 1);
+        if end > initial_bytes {
+            return Ok(None);
+        }
+
+        let fd = create_memfd().map_err(|e| format!("failed to create memory image: {}", e))?;
+        let len = end - start;
+        let write = || -> io::Result<()> {
+            fd.set_len(u64::try_from(len).unwrap())?;
+            // Later segments overwrite earlier ones, just like they would if
+            // they were copied into linear memory one after another.
+            for (offset, data) in segments {
+                fd.write_all_at(data, u64::try_from(offset - start).unwrap())?;
+            }
+            Ok(())
+        };
+        write().map_err(|e| format!("failed to write memory image: {}", e))?;
+
+        Ok(Some(Self {
+            fd,
+            len,
+            linear_memory_offset: start,
+        }))
+    }
+
+    /// Maps this image copy-on-write over the linear memory starting at
+    /// `base`.
+    ///
+    /// # Unsafety
+    ///
+    /// `base` must point to an accessible linear memory of at least the
+    /// initial size this image was built for, whose current contents are
+    /// discarded.
+    pub(crate) unsafe fn map_at(&self, base: *mut u8) -> Result<(), String> {
+        let ptr = libc::mmap(
+            base.add(self.linear_memory_offset) as *mut libc::c_void,
+            self.len,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_FIXED,
+            self.fd.as_raw_fd(),
+            0,
+        );
+        if ptr as isize == -1_isize {
+            return Err(io::Error::last_os_error().to_string());
+        }
+        Ok(())
+    }
+}
+
+fn create_memfd() -> io::Result<File> {
+    let name = CStr::from_bytes_with_nul(b"wasm-memory-image\0").unwrap();
+    let fd = unsafe { libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC) };
+    if fd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
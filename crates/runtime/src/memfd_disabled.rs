@@ -0,0 +1,43 @@
+//! Stand-ins for the memfd-based memory images on platforms without memfd.
+//! No images are ever built, so linear memories are always initialized by
+//! copying their data segments in.
+
+use std::sync::Arc;
+use wasmtime_environ::wasm::DefinedMemoryIndex;
+use wasmtime_environ::{DataInitializer, Module};
+
+/// The memory images of all of a module's defined memories.
+#[derive(Debug)]
+pub enum ModuleMemoryImages {}
+
+impl ModuleMemoryImages {
+    /// Memory images aren't supported on this platform, so this always
+    /// returns `Ok(None)`.
+    pub fn new(
+        _module: &Module,
+        _data_initializers: &[DataInitializer<'_>],
+    ) -> Result<Option<Self>, String> {
+        Ok(None)
+    }
+
+    /// Returns the image to map into the given defined memory, if it has one.
+    pub fn get_memory_image(&self, _index: DefinedMemoryIndex) -> Option<&Arc<MemoryImage>> {
+        match *self {}
+    }
+
+    /// Returns how many of the module's data initializers, counted from the
+    /// start, are covered by these images.
+    pub(crate) fn num_initializers(&self) -> usize {
+        match *self {}
+    }
+}
+
+/// The initial contents of one linear memory.
+#[derive(Debug)]
+pub enum MemoryImage {}
+
+impl MemoryImage {
+    pub(crate) unsafe fn map_at(&self, _base: *mut u8) -> Result<(), String> {
+        match *self {}
+    }
+}
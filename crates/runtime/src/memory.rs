@@ -2,11 +2,13 @@
 //!
 //! `RuntimeLinearMemory` is to WebAssembly linear memories what `Table` is to WebAssembly tables.
 
+use crate::memfd::MemoryImage;
 use crate::mmap::Mmap;
 use crate::vmcontext::VMMemoryDefinition;
 use more_asserts::{assert_ge, assert_le};
 use std::cell::RefCell;
 use std::convert::TryFrom;
+use std::sync::{Arc, Mutex};
 use wasmtime_environ::{MemoryPlan, MemoryStyle, WASM_MAX_PAGES, WASM_PAGE_SIZE};
 
 /// A memory allocator
@@ -108,6 +110,16 @@ impl MmapMemory {
             needs_signal_handlers,
         })
     }
+
+    /// Create a new linear memory instance like `new`, with `image` mapped
+    /// copy-on-write over its initial pages.
+    pub fn with_image(plan: &MemoryPlan, image: &MemoryImage) -> Result<Self, String> {
+        let memory = Self::new(plan)?;
+        unsafe {
+            image.map_at(memory.mmap.borrow_mut().alloc.as_mut_ptr())?;
+        }
+        Ok(memory)
+    }
 }
 
 impl RuntimeLinearMemory for MmapMemory {
@@ -184,3 +196,57 @@ impl RuntimeLinearMemory for MmapMemory {
         }
     }
 }
+
+/// A linear memory which can be shared between instances in different stores
+/// and threads.
+///
+/// Shared memories are always created with a static memory style, so their
+/// base pointer never moves when they grow and compiled code can keep using
+/// it from any thread.
+#[derive(Clone)]
+pub struct SharedMemory(Arc<SharedMemoryInner>);
+
+struct SharedMemoryInner {
+    memory: Mutex<MmapMemory>,
+    plan: MemoryPlan,
+}
+
+impl SharedMemory {
+    /// Create a new shared memory for the given plan, which must describe a
+    /// shared memory with a static style.
+    pub fn new(plan: MemoryPlan) -> Result<Self, String> {
+        if !plan.memory.shared {
+            return Err("memory is not declared as shared".to_string());
+        }
+        match plan.style {
+            MemoryStyle::Static { .. } => {}
+            MemoryStyle::Dynamic => {
+                return Err("shared memories must use a static memory style".to_string())
+            }
+        }
+        let memory = MmapMemory::new(&plan)?;
+        Ok(Self(Arc::new(SharedMemoryInner {
+            memory: Mutex::new(memory),
+            plan,
+        })))
+    }
+
+    /// Returns the plan this memory was created with.
+    pub fn plan(&self) -> &MemoryPlan {
+        &self.0.plan
+    }
+}
+
+impl RuntimeLinearMemory for SharedMemory {
+    fn size(&self) -> u32 {
+        self.0.memory.lock().unwrap().size()
+    }
+
+    fn grow(&self, delta: u32) -> Option<u32> {
+        self.0.memory.lock().unwrap().grow(delta)
+    }
+
+    fn vmmemory(&self) -> VMMemoryDefinition {
+        self.0.memory.lock().unwrap().vmmemory()
+    }
+}
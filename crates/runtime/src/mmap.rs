@@ -2,7 +2,6 @@
 //! of memory.
 
 use more_asserts::assert_le;
-use more_asserts::assert_lt;
 use std::io;
 use std::ptr;
 use std::slice;
@@ -99,7 +98,7 @@ impl Mmap {
                 return Err(io::Error::last_os_error().to_string());
             }
 
-            let mut result = Self {
+            let result = Self {
                 ptr: ptr as usize,
                 len: mapping_size,
             };
@@ -155,7 +154,7 @@ impl Mmap {
                 return Err(io::Error::last_os_error().to_string());
             }
 
-            let mut result = Self {
+            let result = Self {
                 ptr: ptr as usize,
                 len: mapping_size,
             };
@@ -173,12 +172,12 @@ impl Mmap {
     /// `start` and `len` must be native page-size multiples and describe a range within
     /// `self`'s reserved memory.
     #[cfg(not(target_os = "windows"))]
-    pub fn make_accessible(&mut self, start: usize, len: usize) -> Result<(), String> {
+    pub fn make_accessible(&self, start: usize, len: usize) -> Result<(), String> {
         let page_size = region::page::size();
         assert_eq!(start & (page_size - 1), 0);
         assert_eq!(len & (page_size - 1), 0);
-        assert_lt!(len, self.len);
-        assert_lt!(start, self.len - len);
+        assert_le!(len, self.len);
+        assert_le!(start, self.len - len);
 
         // Commit the accessible size.
         let ptr = self.ptr as *const u8;
@@ -190,15 +189,15 @@ impl Mmap {
     /// `start` and `len` must be native page-size multiples and describe a range within
     /// `self`'s reserved memory.
     #[cfg(target_os = "windows")]
-    pub fn make_accessible(&mut self, start: usize, len: usize) -> Result<(), String> {
+    pub fn make_accessible(&self, start: usize, len: usize) -> Result<(), String> {
         use winapi::ctypes::c_void;
         use winapi::um::memoryapi::VirtualAlloc;
         use winapi::um::winnt::{MEM_COMMIT, PAGE_READWRITE};
         let page_size = region::page::size();
         assert_eq!(start & (page_size - 1), 0);
         assert_eq!(len & (page_size - 1), 0);
-        assert_lt!(len, self.len);
-        assert_lt!(start, self.len - len);
+        assert_le!(len, self.len);
+        assert_le!(start, self.len - len);
 
         // Commit the accessible size.
         let ptr = self.ptr as *const u8;
@@ -218,6 +217,91 @@ impl Mmap {
         Ok(())
     }
 
+    /// Discard the contents of the memory starting at `start` and extending for
+    /// `len` bytes, and make it inaccessible again. Subsequently making the
+    /// range accessible yields zeroed pages. `start` and `len` must be native
+    /// page-size multiples and describe a range within `self`'s reserved memory.
+    #[cfg(target_os = "linux")]
+    pub fn decommit(&self, start: usize, len: usize) -> Result<(), String> {
+        let page_size = region::page::size();
+        assert_eq!(start & (page_size - 1), 0);
+        assert_eq!(len & (page_size - 1), 0);
+        assert_le!(len, self.len);
+        assert_le!(start, self.len - len);
+
+        if len == 0 {
+            return Ok(());
+        }
+
+        // On Linux, `MADV_DONTNEED` frees the backing pages of a private
+        // anonymous mapping, and they read as zero when next touched.
+        let ptr = unsafe { (self.ptr as *mut u8).add(start) };
+        if unsafe { libc::madvise(ptr as *mut libc::c_void, len, libc::MADV_DONTNEED) } != 0 {
+            return Err(io::Error::last_os_error().to_string());
+        }
+        unsafe { region::protect(ptr, len, region::Protection::NONE) }.map_err(|e| e.to_string())
+    }
+
+    /// Discard the contents of the memory starting at `start` and extending for
+    /// `len` bytes, and make it inaccessible again. Subsequently making the
+    /// range accessible yields zeroed pages. `start` and `len` must be native
+    /// page-size multiples and describe a range within `self`'s reserved memory.
+    #[cfg(all(not(target_os = "linux"), not(target_os = "windows")))]
+    pub fn decommit(&self, start: usize, len: usize) -> Result<(), String> {
+        let page_size = region::page::size();
+        assert_eq!(start & (page_size - 1), 0);
+        assert_eq!(len & (page_size - 1), 0);
+        assert_le!(len, self.len);
+        assert_le!(start, self.len - len);
+
+        if len == 0 {
+            return Ok(());
+        }
+
+        // `MADV_DONTNEED` doesn't guarantee zeroed pages everywhere, so map
+        // fresh inaccessible pages over the range instead.
+        let ptr = unsafe {
+            libc::mmap(
+                (self.ptr as *mut u8).add(start) as *mut libc::c_void,
+                len,
+                libc::PROT_NONE,
+                libc::MAP_PRIVATE | libc::MAP_ANON | libc::MAP_FIXED,
+                -1,
+                0,
+            )
+        };
+        if ptr as isize == -1_isize {
+            return Err(io::Error::last_os_error().to_string());
+        }
+        Ok(())
+    }
+
+    /// Discard the contents of the memory starting at `start` and extending for
+    /// `len` bytes, and make it inaccessible again. Subsequently making the
+    /// range accessible yields zeroed pages. `start` and `len` must be native
+    /// page-size multiples and describe a range within `self`'s reserved memory.
+    #[cfg(target_os = "windows")]
+    pub fn decommit(&self, start: usize, len: usize) -> Result<(), String> {
+        use winapi::ctypes::c_void;
+        use winapi::um::memoryapi::VirtualFree;
+        use winapi::um::winnt::MEM_DECOMMIT;
+        let page_size = region::page::size();
+        assert_eq!(start & (page_size - 1), 0);
+        assert_eq!(len & (page_size - 1), 0);
+        assert_le!(len, self.len);
+        assert_le!(start, self.len - len);
+
+        if len == 0 {
+            return Ok(());
+        }
+
+        let ptr = self.ptr as *const u8;
+        if unsafe { VirtualFree(ptr.add(start) as *mut c_void, len, MEM_DECOMMIT) } == 0 {
+            return Err(io::Error::last_os_error().to_string());
+        }
+        Ok(())
+    }
+
     /// Return the allocated memory as a slice of u8.
     pub fn as_slice(&self) -> &[u8] {
         unsafe { slice::from_raw_parts(self.ptr as *const u8, self.len) }
@@ -0,0 +1,169 @@
+//! A process-wide table of threads parked on addresses, used to implement
+//! `memory.atomic.wait` and `memory.atomic.notify`.
+//!
+//! Waiters are keyed by the native address they wait on, so instances in
+//! different stores sharing the same linear memory see each other's waiters.
+
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Instant;
+
+/// The outcome of a call to `park`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ParkResult {
+    /// The thread was woken by a call to `unpark`.
+    Unparked,
+    /// The validation callback returned `false`, so the thread never parked.
+    Invalid,
+    /// The deadline passed before the thread was woken.
+    TimedOut,
+}
+
+#[derive(Default)]
+struct Spot {
+    // Number of threads currently parked on this address.
+    num_parked: u32,
+    // Number of parked threads that have been unparked but have not yet
+    // noticed. Always at most `num_parked`.
+    to_unpark: u32,
+    cvar: Arc<Condvar>,
+}
+
+lazy_static! {
+    static ref SPOTS: Mutex<HashMap<u64, Spot>> = Mutex::new(HashMap::new());
+}
+
+/// Parks the current thread on `key` until it is unparked or `deadline`
+/// passes.
+///
+/// `validate` is called with the table locked, before parking. If it returns
+/// `false` the thread does not park. Since `unpark` takes the same lock, a
+/// notification can't be lost between validating and parking.
+pub(crate) fn park(
+    key: u64,
+    validate: impl FnOnce() -> bool,
+    deadline: Option<Instant>,
+) -> ParkResult {
+    let mut spots = SPOTS.lock().unwrap();
+    if !validate() {
+        return ParkResult::Invalid;
+    }
+
+    let cvar = {
+        let spot = spots.entry(key).or_default();
+        spot.num_parked += 1;
+        spot.cvar.clone()
+    };
+
+    loop {
+        let timed_out = match deadline {
+            Some(deadline) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    true
+                } else {
+                    spots = cvar.wait_timeout(spots, deadline - now).unwrap().0;
+                    false
+                }
+            }
+            None => {
+                spots = cvar.wait(spots).unwrap();
+                false
+            }
+        };
+
+        let spot = spots.get_mut(&key).unwrap();
+        // A pending wakeup takes priority over a timeout, otherwise `unpark`
+        // would have reported waking more threads than it did.
+        let result = if spot.to_unpark > 0 {
+            spot.to_unpark -= 1;
+            ParkResult::Unparked
+        } else if timed_out {
+            ParkResult::TimedOut
+        } else {
+            // Spurious wakeup, or another thread was woken.
+            continue;
+        };
+
+        spot.num_parked -= 1;
+        if spot.num_parked == 0 {
+            spots.remove(&key);
+        }
+        return result;
+    }
+}
+
+/// Unparks at most `count` threads parked on `key`, returning how many were
+/// unparked.
+pub(crate) fn unpark(key: u64, count: u32) -> u32 {
+    let mut spots = SPOTS.lock().unwrap();
+    let spot = match spots.get_mut(&key) {
+        Some(spot) => spot,
+        None => return 0,
+    };
+
+    let unparked = (spot.num_parked - spot.to_unpark).min(count);
+    if unparked > 0 {
+        spot.to_unpark += unparked;
+        spot.cvar.notify_all();
+    }
+    unparked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering::SeqCst};
+    use std::thread;
+    use std::time::Duration;
+
+    // Each test uses its own keys since the table is process-wide.
+
+    #[test]
+    fn unpark_without_waiters() {
+        assert_eq!(unpark(1, 10), 0);
+    }
+
+    #[test]
+    fn park_invalid() {
+        assert_eq!(park(2, || false, None), ParkResult::Invalid);
+        assert_eq!(unpark(2, 1), 0);
+    }
+
+    #[test]
+    fn park_timeout() {
+        let deadline = Instant::now() + Duration::from_millis(10);
+        assert_eq!(park(3, || true, Some(deadline)), ParkResult::TimedOut);
+        assert_eq!(unpark(3, 1), 0);
+    }
+
+    #[test]
+    fn park_unpark() {
+        static PARKED: AtomicU32 = AtomicU32::new(0);
+        let threads = (0..4)
+            .map(|_| {
+                thread::spawn(|| {
+                    park(
+                        4,
+                        || {
+                            PARKED.fetch_add(1, SeqCst);
+                            true
+                        },
+                        None,
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+        while PARKED.load(SeqCst) < 4 {
+            thread::yield_now();
+        }
+
+        assert_eq!(unpark(4, 1), 1);
+        assert_eq!(unpark(4, 10), 3);
+        assert_eq!(unpark(4, 10), 0);
+        for thread in threads {
+            assert_eq!(thread.join().unwrap(), ParkResult::Unparked);
+        }
+    }
+}
@@ -6,7 +6,7 @@ use crate::vmcontext::{VMCallerCheckedAnyfunc, VMTableDefinition};
 use crate::{Trap, VMExternRef};
 use std::cell::RefCell;
 use std::convert::{TryFrom, TryInto};
-use std::ptr;
+use std::{ptr, slice};
 use wasmtime_environ::wasm::TableElementType;
 use wasmtime_environ::{ir, TablePlan, TableStyle};
 
@@ -28,24 +28,139 @@ pub enum TableElement {
 
 #[derive(Debug)]
 enum TableElements {
-    FuncRefs(Vec<*mut VMCallerCheckedAnyfunc>),
-    ExternRefs(Vec<Option<VMExternRef>>),
+    FuncRefs(TableStorage<*mut VMCallerCheckedAnyfunc>),
+    ExternRefs(TableStorage<Option<VMExternRef>>),
+}
+
+/// The memory backing a table's elements.
+#[derive(Debug)]
+enum TableStorage<T> {
+    /// Storage owned by the table, which is reallocated as the table grows.
+    Dynamic(Vec<T>),
+    /// Storage reserved up front by an instance allocator, with room for
+    /// `capacity` elements. It never moves, and the table can't grow past it.
+    Static {
+        base: *mut T,
+        len: usize,
+        capacity: usize,
+    },
+}
+
+impl<T: Clone> TableStorage<T> {
+    fn as_ptr(&self) -> *const T {
+        match self {
+            TableStorage::Dynamic(v) => v.as_ptr(),
+            TableStorage::Static { base, .. } => *base,
+        }
+    }
+
+    fn as_slice(&self) -> &[T] {
+        match self {
+            TableStorage::Dynamic(v) => v,
+            TableStorage::Static { base, len, .. } => unsafe { slice::from_raw_parts(*base, *len) },
+        }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [T] {
+        match self {
+            TableStorage::Dynamic(v) => v,
+            TableStorage::Static { base, len, .. } => unsafe {
+                slice::from_raw_parts_mut(*base, *len)
+            },
+        }
+    }
+
+    /// Grows the storage to `new_len` elements, filling new slots with
+    /// `value`. Returns `false` if static storage doesn't have enough room.
+    fn grow(&mut self, new_len: usize, value: T) -> bool {
+        match self {
+            TableStorage::Dynamic(v) => {
+                v.resize(new_len, value);
+                true
+            }
+            TableStorage::Static {
+                base,
+                len,
+                capacity,
+            } => {
+                if new_len > *capacity {
+                    return false;
+                }
+                // The slots past `len` hold no live values, so write into them
+                // rather than assigning (which would drop the old contents).
+                for i in *len..new_len {
+                    unsafe { ptr::write(base.add(i), value.clone()) };
+                }
+                *len = new_len;
+                true
+            }
+        }
+    }
+}
+
+impl<T> Drop for TableStorage<T> {
+    fn drop(&mut self) {
+        if let TableStorage::Static { base, len, .. } = self {
+            // The memory itself belongs to the instance allocator, but the
+            // elements in it are ours to drop.
+            unsafe { ptr::drop_in_place(slice::from_raw_parts_mut(*base, *len)) };
+        }
+    }
 }
 
 impl Table {
     /// Create a new table instance with specified minimum and maximum number of elements.
     pub fn new(plan: &TablePlan) -> Self {
         let min = usize::try_from(plan.table.minimum).unwrap();
-        let elements = RefCell::new(match plan.table.ty {
-            TableElementType::Func => TableElements::FuncRefs(vec![ptr::null_mut(); min]),
+        let elements = match plan.table.ty {
+            TableElementType::Func => {
+                TableElements::FuncRefs(TableStorage::Dynamic(vec![ptr::null_mut(); min]))
+            }
             TableElementType::Val(ty) => {
                 debug_assert_eq!(ty, crate::ref_type());
-                TableElements::ExternRefs(vec![None; min])
+                TableElements::ExternRefs(TableStorage::Dynamic(vec![None; min]))
             }
-        });
+        };
+        Self::with_elements(plan, elements)
+    }
+
+    /// Create a new table instance whose elements are stored in memory
+    /// reserved by an instance allocator.
+    ///
+    /// # Unsafety
+    ///
+    /// `base` must be valid for reads and writes of `capacity` pointer-sized
+    /// elements, suitably aligned, and must not be used for anything else
+    /// until the returned table is dropped.
+    pub(crate) unsafe fn new_static(plan: &TablePlan, base: *mut u8, capacity: usize) -> Self {
+        let min = usize::try_from(plan.table.minimum).unwrap();
+        let mut elements = match plan.table.ty {
+            TableElementType::Func => TableElements::FuncRefs(TableStorage::Static {
+                base: base.cast(),
+                len: 0,
+                capacity,
+            }),
+            TableElementType::Val(ty) => {
+                debug_assert_eq!(ty, crate::ref_type());
+                TableElements::ExternRefs(TableStorage::Static {
+                    base: base.cast(),
+                    len: 0,
+                    capacity,
+                })
+            }
+        };
+        let fits = match &mut elements {
+            TableElements::FuncRefs(x) => x.grow(min, ptr::null_mut()),
+            TableElements::ExternRefs(x) => x.grow(min, None),
+        };
+        assert!(fits, "table minimum exceeds its static capacity");
+        Self::with_elements(plan, elements)
+    }
+
+    fn with_elements(plan: &TablePlan, elements: TableElements) -> Self {
         match plan.style {
             TableStyle::CallerChecksSignature => Self {
-                elements,
+                elements: RefCell::new(elements),
                 maximum: plan.table.maximum,
             },
         }
@@ -62,8 +177,8 @@ impl Table {
     /// Returns the number of allocated elements.
     pub fn size(&self) -> u32 {
         match &*self.elements.borrow() {
-            TableElements::FuncRefs(x) => x.len().try_into().unwrap(),
-            TableElements::ExternRefs(x) => x.len().try_into().unwrap(),
+            TableElements::FuncRefs(x) => x.as_slice().len().try_into().unwrap(),
+            TableElements::ExternRefs(x) => x.as_slice().len().try_into().unwrap(),
         }
     }
 
@@ -94,15 +209,18 @@ impl Table {
         }
         let new_len = usize::try_from(new_len).unwrap();
 
-        match &mut *self.elements.borrow_mut() {
+        let grew = match &mut *self.elements.borrow_mut() {
             TableElements::FuncRefs(x) => {
                 let init_value = init_value.try_into().ok()?;
-                x.resize(new_len, init_value)
+                x.grow(new_len, init_value)
             }
             TableElements::ExternRefs(x) => {
                 let init_value = init_value.try_into().ok()?;
-                x.resize(new_len, init_value)
+                x.grow(new_len, init_value)
             }
+        };
+        if !grew {
+            return None;
         }
 
         Some(size)
@@ -113,10 +231,16 @@ impl Table {
     /// Returns `None` if the index is out of bounds.
     pub fn get(&self, index: u32) -> Option<TableElement> {
         match &*self.elements.borrow() {
-            TableElements::FuncRefs(x) => x.get(index as usize).cloned().map(TableElement::FuncRef),
-            TableElements::ExternRefs(x) => {
-                x.get(index as usize).cloned().map(TableElement::ExternRef)
-            }
+            TableElements::FuncRefs(x) => x
+                .as_slice()
+                .get(index as usize)
+                .cloned()
+                .map(TableElement::FuncRef),
+            TableElements::ExternRefs(x) => x
+                .as_slice()
+                .get(index as usize)
+                .cloned()
+                .map(TableElement::ExternRef),
         }
     }
 
@@ -130,17 +254,54 @@ impl Table {
         let mut elems = self.elements.borrow_mut();
         match &mut *elems {
             TableElements::FuncRefs(x) => {
-                let slot = x.get_mut(index as usize).ok_or(())?;
+                let slot = x.as_mut_slice().get_mut(index as usize).ok_or(())?;
                 *slot = elem.try_into().or(Err(()))?;
             }
             TableElements::ExternRefs(x) => {
-                let slot = x.get_mut(index as usize).ok_or(())?;
+                let slot = x.as_mut_slice().get_mut(index as usize).ok_or(())?;
                 *slot = elem.try_into().or(Err(()))?;
             }
         }
         Ok(())
     }
 
+    /// Fill `table[dst..dst + len]` with `val`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a trap if the range is out of bounds.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `val` is the wrong kind of table element.
+    pub fn fill(&self, dst: u32, val: TableElement, len: u32) -> Result<(), Trap> {
+        // https://webassembly.github.io/reference-types/core/exec/instructions.html#exec-table-fill
+
+        let start = usize::try_from(dst).unwrap();
+        let end = dst
+            .checked_add(len)
+            .filter(|end| *end <= self.size())
+            .ok_or_else(|| Trap::wasm(ir::TrapCode::TableOutOfBounds))?;
+        let end = usize::try_from(end).unwrap();
+
+        let mut elems = self.elements.borrow_mut();
+        match &mut *elems {
+            TableElements::FuncRefs(x) => {
+                let val = val.try_into().expect("wrong table element type");
+                for slot in &mut x.as_mut_slice()[start..end] {
+                    *slot = val;
+                }
+            }
+            TableElements::ExternRefs(x) => {
+                let val: Option<VMExternRef> = val.try_into().expect("wrong table element type");
+                for slot in &mut x.as_mut_slice()[start..end] {
+                    *slot = val.clone();
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Copy `len` elements from `src_table[src_index..]` into `dst_table[dst_index..]`.
     ///
     /// # Errors
@@ -191,11 +352,11 @@ impl Table {
         match &*self.elements.borrow() {
             TableElements::FuncRefs(x) => VMTableDefinition {
                 base: x.as_ptr() as *const u8 as *mut u8,
-                current_elements: x.len().try_into().unwrap(),
+                current_elements: x.as_slice().len().try_into().unwrap(),
             },
             TableElements::ExternRefs(x) => VMTableDefinition {
                 base: x.as_ptr() as *const u8 as *mut u8,
-                current_elements: x.len().try_into().unwrap(),
+                current_elements: x.as_slice().len().try_into().unwrap(),
             },
         }
     }
@@ -1,7 +1,8 @@
 //! WebAssembly trap handling, which is built on top of the lower-level
 //! signalhandling mechanisms.
 
-use crate::VMContext;
+use crate::instance::{DebugLocals, DebugValue};
+use crate::{VMContext, VMFrameRecord, VMInterrupts};
 use backtrace::Backtrace;
 use std::any::Any;
 use std::cell::Cell;
@@ -12,6 +13,8 @@ use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
 use std::sync::Once;
 use wasmtime_environ::ir;
 
+pub use self::tls::{TlsMark, TlsRestore};
+
 extern "C" {
     fn RegisterSetjmp(
         jmp_buf: *mut *const u8,
@@ -319,6 +322,8 @@ pub enum Trap {
         pc: usize,
         /// Native stack backtrace at the time the trap occurred
         backtrace: Backtrace,
+        /// The recorded wasm frames at the time the trap occurred
+        frames: Vec<WasmFrame>,
         /// An indicator for whether this may have been a trap generated from an
         /// interrupt, used for switching what would otherwise be a stack
         /// overflow trap to be an interrupt trap.
@@ -331,12 +336,16 @@ pub enum Trap {
         trap_code: ir::TrapCode,
         /// Native stack backtrace at the time the trap occurred
         backtrace: Backtrace,
+        /// The recorded wasm frames at the time the trap occurred
+        frames: Vec<WasmFrame>,
     },
 
     /// A trap indicating that the runtime was unable to allocate sufficient memory.
     OOM {
         /// Native stack backtrace at the time the OOM occurred
         backtrace: Backtrace,
+        /// The recorded wasm frames at the time the OOM occurred
+        frames: Vec<WasmFrame>,
     },
 }
 
@@ -349,6 +358,7 @@ impl Trap {
         Trap::Wasm {
             trap_code,
             backtrace,
+            frames: wasm_frames(),
         }
     }
 
@@ -357,10 +367,36 @@ impl Trap {
     /// Internally saves a backtrace when constructed.
     pub fn oom() -> Self {
         let backtrace = Backtrace::new_unresolved();
-        Trap::OOM { backtrace }
+        Trap::OOM {
+            backtrace,
+            frames: wasm_frames(),
+        }
     }
 }
 
+/// A wasm frame found through the `VMFrameRecord`s of code compiled with
+/// frame records enabled.
+#[derive(Debug, Clone)]
+pub struct WasmFrame {
+    /// The address of the `VMContext` of the instance the frame was
+    /// executing in.
+    pub vmctx: usize,
+    /// The values of the frame's locals, which are only recorded for code
+    /// compiled for guest debugging. Locals holding references have no
+    /// recorded value.
+    pub locals: Vec<Option<DebugValue>>,
+}
+
+/// Returns the recorded wasm frames on this thread's stack which belong to
+/// the store of the innermost call into wasm, innermost first.
+///
+/// This is empty if there's no wasm on the stack or its code wasn't compiled
+/// with frame records enabled. Frames of other stores are never included,
+/// even if wasm of another store is further up the stack.
+pub fn wasm_frames() -> Vec<WasmFrame> {
+    tls::with(|state| state.map_or_else(Vec::new, |state| state.wasm_frames()))
+}
+
 /// Catches any wasm traps that happen within the execution of `closure`,
 /// returning them as a `Result`.
 ///
@@ -404,6 +440,11 @@ pub struct CallThreadState<'a> {
     handling_trap: Cell<bool>,
     is_wasm_code: &'a (dyn Fn(usize) -> bool + 'a),
     signal_handler: Option<&'a SignalHandler<'a>>,
+    prev: Cell<*const CallThreadState<'static>>,
+    /// The store's frame records as they were when this call into wasm
+    /// started, which is also the innermost record of the calling wasm if
+    /// this is a call back into the same store.
+    prev_frame_records: Cell<*const VMFrameRecord>,
 }
 
 enum UnwindReason {
@@ -411,7 +452,11 @@ enum UnwindReason {
     Panic(Box<dyn Any + Send>),
     UserTrap(Box<dyn Error + Send + Sync>),
     LibTrap(Trap),
-    JitTrap { backtrace: Backtrace, pc: usize },
+    JitTrap {
+        backtrace: Backtrace,
+        pc: usize,
+        frames: Vec<WasmFrame>,
+    },
 }
 
 impl<'a> CallThreadState<'a> {
@@ -427,6 +472,8 @@ impl<'a> CallThreadState<'a> {
             handling_trap: Cell::new(false),
             is_wasm_code,
             signal_handler,
+            prev: Cell::new(ptr::null()),
+            prev_frame_records: Cell::new(ptr::null()),
         }
     }
 
@@ -436,7 +483,19 @@ impl<'a> CallThreadState<'a> {
         closure: impl FnOnce(&CallThreadState) -> i32,
     ) -> Result<(), Trap> {
         let _reset = self.update_stack_limit(max_wasm_stack)?;
+
+        // Each call into wasm starts a new list of frame records, which is
+        // put back afterwards since a trap skips the unlinking of the records
+        // of the frames it unwinds.
+        let frame_records = unsafe { (*self.interrupts()).frame_records.get() };
+        unsafe {
+            self.prev_frame_records
+                .set(std::mem::replace(&mut *frame_records, ptr::null()));
+        }
         let ret = tls::set(&self, || closure(&self));
+        unsafe {
+            *frame_records = self.prev_frame_records.get();
+        }
         match self.unwind.replace(UnwindReason::None) {
             UnwindReason::None => {
                 debug_assert_eq!(ret, 1);
@@ -447,7 +506,11 @@ impl<'a> CallThreadState<'a> {
                 Err(Trap::User(data))
             }
             UnwindReason::LibTrap(trap) => Err(trap),
-            UnwindReason::JitTrap { backtrace, pc } => {
+            UnwindReason::JitTrap {
+                backtrace,
+                pc,
+                frames,
+            } => {
                 debug_assert_eq!(ret, 0);
                 let maybe_interrupted = unsafe {
                     (*self.vmctx).instance().interrupts.stack_limit.load(SeqCst)
@@ -456,6 +519,7 @@ impl<'a> CallThreadState<'a> {
                 Err(Trap::Jit {
                     pc,
                     backtrace,
+                    frames,
                     maybe_interrupted,
                 })
             }
@@ -523,6 +587,7 @@ impl<'a> CallThreadState<'a> {
                 return Err(Trap::Wasm {
                     trap_code: ir::TrapCode::Interrupt,
                     backtrace: Backtrace::new_unresolved(),
+                    frames: wasm_frames(),
                 });
             }
             Err(_) => {
@@ -547,6 +612,41 @@ impl<'a> CallThreadState<'a> {
         Ok(Reset(reset_stack_limit, &interrupts.stack_limit))
     }
 
+    fn interrupts(&self) -> *const VMInterrupts {
+        unsafe { *(&*self.vmctx).instance().interrupts() }
+    }
+
+    /// Collects the frame records of this call's store, walking out through
+    /// all of the calls into the same store on this thread's stack.
+    fn wasm_frames(&self) -> Vec<WasmFrame> {
+        let interrupts = self.interrupts();
+        let mut frames = Vec::new();
+        unsafe {
+            let mut record = *(*interrupts).frame_records.get();
+            let mut state = self as *const CallThreadState<'_> as *const CallThreadState<'static>;
+            while !state.is_null() {
+                if (*state).interrupts() == interrupts {
+                    while !record.is_null() {
+                        let locals = if (*record).locals.is_null() {
+                            Vec::new()
+                        } else {
+                            let locals = DebugLocals::new((*record).locals, (*record).num_locals);
+                            (0..locals.len()).map(|i| locals.get(i)).collect()
+                        };
+                        frames.push(WasmFrame {
+                            vmctx: (*record).vmctx as usize,
+                            locals,
+                        });
+                        record = (*record).prev;
+                    }
+                    record = (*state).prev_frame_records.get();
+                }
+                state = (*state).prev.get();
+            }
+        }
+        frames
+    }
+
     fn unwind_with(&self, reason: UnwindReason) -> ! {
         self.unwind.replace(reason);
         unsafe {
@@ -617,6 +717,7 @@ impl<'a> CallThreadState<'a> {
         self.unwind.replace(UnwindReason::JitTrap {
             backtrace,
             pc: pc as usize,
+            frames: self.wasm_frames(),
         });
         self.jmp_buf.get()
     }
@@ -636,22 +737,128 @@ impl<T: Copy> Drop for ResetCell<'_, T> {
 // do with the trap. This `tls` module is used to persist that information from
 // the caller to the trap site.
 mod tls {
-    use super::CallThreadState;
+    use super::{CallThreadState, Trap};
     use std::cell::Cell;
     use std::mem;
     use std::ptr;
 
     thread_local!(static PTR: Cell<*const CallThreadState<'static>> = Cell::new(ptr::null()));
 
+    /// A position in this thread's stack of `CallThreadState`s, used to mark
+    /// where the states belonging to a fiber begin.
+    #[derive(Copy, Clone)]
+    pub struct TlsMark(*const CallThreadState<'static>);
+
+    impl TlsMark {
+        /// Returns the position of the innermost state currently configured
+        /// on this thread.
+        pub fn current() -> TlsMark {
+            TlsMark(PTR.with(|p| p.get()))
+        }
+    }
+
+    /// Opaque state used to help control TLS state across stack switches for
+    /// async support.
+    ///
+    /// When wasm running on a fiber is suspended, the `CallThreadState`s for
+    /// that fiber's stack must be removed from this thread's TLS since the
+    /// thread may go on to run other code (including other wasm). They're
+    /// then pushed back on top of whatever state is current when the fiber is
+    /// resumed, which may be on a different thread.
+    ///
+    /// A fiber may have any number of states on its stack, for example when
+    /// wasm calls a host function which synchronously calls back into wasm,
+    /// so this holds the whole chain of them from `innermost` out to
+    /// `outermost`.
+    pub struct TlsRestore {
+        innermost: *const CallThreadState<'static>,
+        outermost: *const CallThreadState<'static>,
+    }
+
+    impl TlsRestore {
+        /// Takes every TLS state that was pushed on top of `mark` and returns
+        /// a token that is used to push them again later.
+        ///
+        /// # Unsafety
+        ///
+        /// This is not safe because it's intended to only be used when a
+        /// native stack has been switched away from, where `mark` was current
+        /// when that stack was switched to. The returned token must be passed
+        /// to `replace` before the stack is resumed.
+        pub unsafe fn take(mark: TlsMark) -> TlsRestore {
+            PTR.with(|p| {
+                let innermost = p.get();
+                let mut outermost = ptr::null();
+                let mut raw = innermost;
+                while raw != mark.0 {
+                    debug_assert!(!raw.is_null(), "mark isn't on this thread's stack");
+                    outermost = raw;
+                    raw = (*raw).prev.get();
+                }
+                if outermost.is_null() {
+                    return TlsRestore {
+                        innermost: ptr::null(),
+                        outermost,
+                    };
+                }
+
+                // Pop the whole chain off the call stack, restoring whatever
+                // was current before it was pushed, and null out the
+                // outermost previous field in case it's accidentally used
+                // while we're detached.
+                p.set((*outermost).prev.replace(ptr::null()));
+                TlsRestore {
+                    innermost,
+                    outermost,
+                }
+            })
+        }
+
+        /// Restores a previous tls state back into this thread's TLS.
+        ///
+        /// The states are always pushed back, and an error is only returned
+        /// if this thread couldn't be set up for handling traps.
+        ///
+        /// # Unsafety
+        ///
+        /// This is unsafe because it's intended to only be used within the
+        /// context of stack switching when the stack the `take`n state was
+        /// configured on is being resumed.
+        pub unsafe fn replace(self) -> Result<(), Trap> {
+            if !self.innermost.is_null() {
+                PTR.with(|p| {
+                    // Our previous state is whatever happens to be on this
+                    // thread's TLS at this time, and we push ourselves on top
+                    // of it.
+                    debug_assert!((*self.outermost).prev.get().is_null());
+                    (*self.outermost).prev.set(p.get());
+                    p.set(self.innermost);
+                });
+            }
+
+            // The thread we're being resumed on may not have been set up for
+            // handling traps yet.
+            #[cfg(unix)]
+            super::setup_unix_sigaltstack()?;
+
+            Ok(())
+        }
+    }
+
     /// Configures thread local state such that for the duration of the
-    /// execution of `closure` any call to `with` will yield `ptr`, unless this
-    /// is recursively called again.
-    pub fn set<R>(ptr: &CallThreadState<'_>, closure: impl FnOnce() -> R) -> R {
-        struct Reset<'a, T: Copy>(&'a Cell<T>, T);
+    /// execution of `closure` any call to `with` will yield `state`, unless
+    /// this is recursively called again.
+    pub fn set<R>(state: &CallThreadState<'_>, closure: impl FnOnce() -> R) -> R {
+        struct Reset<'a, 'b>(&'a CallThreadState<'b>);
 
-        impl<T: Copy> Drop for Reset<'_, T> {
+        impl Drop for Reset<'_, '_> {
             fn drop(&mut self) {
-                self.0.set(self.1);
+                // Pop our state off the call stack. Note that our previous
+                // state isn't necessarily what was on TLS when `set` was
+                // called since a fiber may have been suspended and resumed
+                // elsewhere in the meantime.
+                let prev = self.0.prev.replace(ptr::null());
+                PTR.with(|p| p.set(prev));
             }
         }
 
@@ -660,9 +867,10 @@ mod tls {
             // safe because we only ever access it below with an anonymous
             // lifetime, meaning `'static` never leaks out of this module.
             let ptr = unsafe {
-                mem::transmute::<*const CallThreadState<'_>, *const CallThreadState<'static>>(ptr)
+                mem::transmute::<*const CallThreadState<'_>, *const CallThreadState<'static>>(state)
             };
-            let _r = Reset(p, p.replace(ptr));
+            state.prev.set(p.replace(ptr));
+            let _r = Reset(state);
             closure()
         })
     }
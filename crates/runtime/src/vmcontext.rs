@@ -1,10 +1,12 @@
 //! This file declares `VMContext` and several related structs which contain
 //! fields that compiled wasm code accesses directly.
 
+use crate::externref::VMExternRef;
 use crate::instance::Instance;
 use std::any::Any;
-use std::ptr::NonNull;
-use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+use std::cell::UnsafeCell;
+use std::ptr::{self, NonNull};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering::SeqCst};
 use std::u32;
 use wasmtime_environ::BuiltinFunctionIndex;
 
@@ -423,6 +425,30 @@ impl VMGlobalDefinition {
     pub unsafe fn as_u128_bits_mut(&mut self) -> &mut [u8; 16] {
         &mut *(self.storage.as_mut().as_mut_ptr() as *mut [u8; 16])
     }
+
+    /// Return a reference to the value as an externref.
+    #[allow(clippy::cast_ptr_alignment)]
+    pub unsafe fn as_externref(&self) -> &Option<VMExternRef> {
+        &*(self.storage.as_ref().as_ptr() as *const Option<VMExternRef>)
+    }
+
+    /// Return a mutable reference to the value as an externref.
+    #[allow(clippy::cast_ptr_alignment)]
+    pub unsafe fn as_externref_mut(&mut self) -> &mut Option<VMExternRef> {
+        &mut *(self.storage.as_mut().as_mut_ptr() as *mut Option<VMExternRef>)
+    }
+
+    /// Return a reference to the value as a funcref.
+    #[allow(clippy::cast_ptr_alignment)]
+    pub unsafe fn as_anyfunc(&self) -> &*mut VMCallerCheckedAnyfunc {
+        &*(self.storage.as_ref().as_ptr() as *const *mut VMCallerCheckedAnyfunc)
+    }
+
+    /// Return a mutable reference to the value as a funcref.
+    #[allow(clippy::cast_ptr_alignment)]
+    pub unsafe fn as_anyfunc_mut(&mut self) -> &mut *mut VMCallerCheckedAnyfunc {
+        &mut *(self.storage.as_mut().as_mut_ptr() as *mut *mut VMCallerCheckedAnyfunc)
+    }
 }
 
 /// An index into the shared signature registry, usable for checking signatures
@@ -555,6 +581,26 @@ impl VMBuiltinFunctionsArray {
             wasmtime_imported_memory_fill as usize;
         ptrs[BuiltinFunctionIndex::memory_init().index() as usize] = wasmtime_memory_init as usize;
         ptrs[BuiltinFunctionIndex::data_drop().index() as usize] = wasmtime_data_drop as usize;
+        ptrs[BuiltinFunctionIndex::table_fill_funcref().index() as usize] =
+            wasmtime_table_fill as usize;
+        ptrs[BuiltinFunctionIndex::table_fill_externref().index() as usize] =
+            wasmtime_table_fill as usize;
+        ptrs[BuiltinFunctionIndex::table_get_externref().index() as usize] =
+            wasmtime_table_get_externref as usize;
+        ptrs[BuiltinFunctionIndex::table_set_externref().index() as usize] =
+            wasmtime_table_set_externref as usize;
+        ptrs[BuiltinFunctionIndex::externref_global_get().index() as usize] =
+            wasmtime_externref_global_get as usize;
+        ptrs[BuiltinFunctionIndex::externref_global_set().index() as usize] =
+            wasmtime_externref_global_set as usize;
+        ptrs[BuiltinFunctionIndex::memory_atomic_notify().index() as usize] =
+            wasmtime_memory_atomic_notify as usize;
+        ptrs[BuiltinFunctionIndex::memory_atomic_wait32().index() as usize] =
+            wasmtime_memory_atomic_wait32 as usize;
+        ptrs[BuiltinFunctionIndex::memory_atomic_wait64().index() as usize] =
+            wasmtime_memory_atomic_wait64 as usize;
+        ptrs[BuiltinFunctionIndex::new_epoch().index() as usize] = wasmtime_new_epoch as usize;
+        ptrs[BuiltinFunctionIndex::debug_hook().index() as usize] = wasmtime_debug_hook as usize;
 
         if cfg!(debug_assertions) {
             for i in 0..ptrs.len() {
@@ -601,8 +647,7 @@ impl VMInvokeArgument {
     }
 }
 
-/// Structure used to control interrupting wasm code, currently with only one
-/// atomic flag internally used.
+/// Structure used to control interrupting wasm code.
 #[derive(Debug)]
 #[repr(C)]
 pub struct VMInterrupts {
@@ -611,8 +656,45 @@ pub struct VMInterrupts {
     /// This is used to control both stack overflow as well as interrupting wasm
     /// modules. For more information see `crates/environ/src/cranelift.rs`.
     pub stack_limit: AtomicUsize,
+
+    /// Indicator of how much fuel has been consumed and is remaining to
+    /// WebAssembly.
+    ///
+    /// This field is typically negative and increments towards positive. Upon
+    /// turning positive a wasm trap will be generated. This field is only
+    /// modified if wasm is configured to consume fuel.
+    pub fuel_consumed: UnsafeCell<i64>,
+
+    /// The epoch at which WebAssembly stops executing normally and calls into
+    /// the runtime to either trap or pick a new deadline. This field is only
+    /// read if wasm is configured for epoch interruption.
+    ///
+    /// This may be lowered from other threads to make wasm call into the
+    /// runtime at its next epoch check.
+    pub epoch_deadline: AtomicU64,
+
+    /// Pointer to the engine-wide epoch counter which is compared against
+    /// `epoch_deadline`.
+    ///
+    /// This is null by default and must be filled in before executing any
+    /// wasm compiled with epoch interruption enabled.
+    pub epoch_ptr: *const AtomicU64,
+
+    /// The innermost record of a wasm frame executing in this store, whose
+    /// `prev` links lead through the rest of the current wasm call's frames.
+    ///
+    /// This is only maintained by code compiled with frame records enabled,
+    /// and is reset to null for the duration of each call into wasm.
+    pub frame_records: UnsafeCell<*const VMFrameRecord>,
 }
 
+// The `VMInterrupts` type is a pod-type with no destructor, and we only access
+// the `stack_limit` and `epoch_deadline` from other threads, so add in these
+// trait impls which are otherwise not available due to the `fuel_consumed`,
+// `epoch_ptr` and `frame_records` variables in `VMInterrupts`.
+unsafe impl Send for VMInterrupts {}
+unsafe impl Sync for VMInterrupts {}
+
 impl VMInterrupts {
     /// Flag that an interrupt should occur
     pub fn interrupt(&self) {
@@ -625,6 +707,10 @@ impl Default for VMInterrupts {
     fn default() -> VMInterrupts {
         VMInterrupts {
             stack_limit: AtomicUsize::new(usize::max_value()),
+            fuel_consumed: UnsafeCell::new(0),
+            epoch_deadline: AtomicU64::new(0),
+            epoch_ptr: ptr::null(),
+            frame_records: UnsafeCell::new(ptr::null()),
         }
     }
 }
@@ -644,6 +730,81 @@ mod test_vminterrupts {
             offset_of!(VMInterrupts, stack_limit),
             usize::from(offsets.vminterrupts_stack_limit())
         );
+        assert_eq!(
+            offset_of!(VMInterrupts, fuel_consumed),
+            usize::from(offsets.vminterrupts_fuel_consumed())
+        );
+        assert_eq!(
+            offset_of!(VMInterrupts, epoch_deadline),
+            usize::from(offsets.vminterrupts_epoch_deadline())
+        );
+        assert_eq!(
+            offset_of!(VMInterrupts, epoch_ptr),
+            usize::from(offsets.vminterrupts_epoch_ptr())
+        );
+        assert_eq!(
+            offset_of!(VMInterrupts, frame_records),
+            usize::from(offsets.vminterrupts_frame_records())
+        );
+    }
+}
+
+/// A record of a wasm frame, which compiled code keeps in a stack slot of
+/// each frame for the duration of the call when frame records are enabled.
+///
+/// The records of a wasm call's frames form a list through `prev`, starting
+/// at `VMInterrupts::frame_records`, which is what lets a trap find the
+/// instance each frame on the stack is executing in.
+#[derive(Debug)]
+#[repr(C)]
+pub struct VMFrameRecord {
+    /// The record of the calling wasm frame, or null if this is the first
+    /// wasm frame of the current call into wasm.
+    pub prev: *const VMFrameRecord,
+
+    /// The `VMContext` of the instance the frame is executing in.
+    pub vmctx: *mut VMContext,
+
+    /// The values of the frame's locals as last passed to the debug hook,
+    /// laid out as for `DebugLocals`, or null unless the code was compiled
+    /// for guest debugging.
+    pub locals: *const u8,
+
+    /// The number of locals at `locals`.
+    pub num_locals: usize,
+}
+
+#[cfg(test)]
+mod test_vmframe_record {
+    use super::VMFrameRecord;
+    use memoffset::offset_of;
+    use std::mem::size_of;
+    use wasmtime_environ::{Module, VMOffsets};
+
+    #[test]
+    fn check_vmframe_record_offsets() {
+        let module = Module::new();
+        let offsets = VMOffsets::new(size_of::<*mut u8>() as u8, &module.local);
+        assert_eq!(
+            size_of::<VMFrameRecord>(),
+            usize::from(offsets.size_of_vmframe_record())
+        );
+        assert_eq!(
+            offset_of!(VMFrameRecord, prev),
+            usize::from(offsets.vmframe_record_prev())
+        );
+        assert_eq!(
+            offset_of!(VMFrameRecord, vmctx),
+            usize::from(offsets.vmframe_record_vmctx())
+        );
+        assert_eq!(
+            offset_of!(VMFrameRecord, locals),
+            usize::from(offsets.vmframe_record_locals())
+        );
+        assert_eq!(
+            offset_of!(VMFrameRecord, num_locals),
+            usize::from(offsets.vmframe_record_num_locals())
+        );
     }
 }
 
@@ -8,13 +8,12 @@ use crate::virtfs::{VirtualDir, VirtualDirEntry};
 use crate::wasi::types;
 use crate::wasi::{Errno, Result};
 use std::borrow::Borrow;
-use std::cell::RefCell;
 use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::ffi::{self, CString, OsString};
 use std::fs::File;
 use std::path::{Path, PathBuf};
-use std::rc::Rc;
+use std::sync::{Arc, Mutex};
 use std::{env, io, string};
 
 /// Possible errors when `WasiCtxBuilder` fails building
@@ -392,14 +391,14 @@ impl WasiCtxBuilder {
         Ok(WasiCtx {
             args,
             env,
-            entries: RefCell::new(entries),
+            entries: Mutex::new(entries),
         })
     }
 }
 
 struct EntryTable {
     fd_pool: FdPool,
-    entries: HashMap<types::Fd, Rc<Entry>>,
+    entries: HashMap<types::Fd, Arc<Entry>>,
 }
 
 impl EntryTable {
@@ -416,19 +415,19 @@ impl EntryTable {
 
     fn insert(&mut self, entry: Entry) -> Option<types::Fd> {
         let fd = self.fd_pool.allocate()?;
-        self.entries.insert(fd, Rc::new(entry));
+        self.entries.insert(fd, Arc::new(entry));
         Some(fd)
     }
 
-    fn insert_at(&mut self, fd: &types::Fd, entry: Rc<Entry>) {
+    fn insert_at(&mut self, fd: &types::Fd, entry: Arc<Entry>) {
         self.entries.insert(*fd, entry);
     }
 
-    fn get(&self, fd: &types::Fd) -> Option<Rc<Entry>> {
-        self.entries.get(fd).map(Rc::clone)
+    fn get(&self, fd: &types::Fd) -> Option<Arc<Entry>> {
+        self.entries.get(fd).map(Arc::clone)
     }
 
-    fn remove(&mut self, fd: types::Fd) -> Option<Rc<Entry>> {
+    fn remove(&mut self, fd: types::Fd) -> Option<Arc<Entry>> {
         let entry = self.entries.remove(&fd)?;
         self.fd_pool.deallocate(fd);
         Some(entry)
@@ -436,7 +435,7 @@ impl EntryTable {
 }
 
 pub struct WasiCtx {
-    entries: RefCell<EntryTable>,
+    entries: Mutex<EntryTable>,
     pub(crate) args: Vec<CString>,
     pub(crate) env: Vec<CString>,
 }
@@ -459,12 +458,12 @@ impl WasiCtx {
 
     /// Check if `WasiCtx` contains the specified raw WASI `fd`.
     pub(crate) fn contains_entry(&self, fd: types::Fd) -> bool {
-        self.entries.borrow().contains(&fd)
+        self.entries.lock().unwrap().contains(&fd)
     }
 
     /// Get an immutable `Entry` corresponding to the specified raw WASI `fd`.
-    pub(crate) fn get_entry(&self, fd: types::Fd) -> Result<Rc<Entry>> {
-        match self.entries.borrow().get(&fd) {
+    pub(crate) fn get_entry(&self, fd: types::Fd) -> Result<Arc<Entry>> {
+        match self.entries.lock().unwrap().get(&fd) {
             Some(entry) => Ok(entry),
             None => Err(Errno::Badf),
         }
@@ -475,17 +474,21 @@ impl WasiCtx {
     /// The `Entry` will automatically get another free raw WASI `fd` assigned. Note that
     /// the two subsequent free raw WASI `fd`s do not have to be stored contiguously.
     pub(crate) fn insert_entry(&self, entry: Entry) -> Result<types::Fd> {
-        self.entries.borrow_mut().insert(entry).ok_or(Errno::Mfile)
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(entry)
+            .ok_or(Errno::Mfile)
     }
 
     /// Insert the specified `Entry` with the specified raw WASI `fd` key into the `WasiCtx`
     /// object.
-    pub(crate) fn insert_entry_at(&self, fd: types::Fd, entry: Rc<Entry>) {
-        self.entries.borrow_mut().insert_at(&fd, entry)
+    pub(crate) fn insert_entry_at(&self, fd: types::Fd, entry: Arc<Entry>) {
+        self.entries.lock().unwrap().insert_at(&fd, entry)
     }
 
     /// Remove `Entry` corresponding to the specified raw WASI `fd` from the `WasiCtx` object.
-    pub(crate) fn remove_entry(&self, fd: types::Fd) -> Result<Rc<Entry>> {
-        self.entries.borrow_mut().remove(fd).ok_or(Errno::Badf)
+    pub(crate) fn remove_entry(&self, fd: types::Fd) -> Result<Arc<Entry>> {
+        self.entries.lock().unwrap().remove(fd).ok_or(Errno::Badf)
     }
 }
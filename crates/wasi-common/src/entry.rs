@@ -3,18 +3,18 @@ use crate::wasi::types::Filetype;
 use crate::wasi::{Errno, Result};
 use std::ops::Deref;
 use std::path::PathBuf;
-use std::rc::Rc;
+use std::sync::Arc;
 
-pub(crate) struct EntryHandle(Rc<dyn Handle>);
+pub(crate) struct EntryHandle(Arc<dyn Handle>);
 
 impl EntryHandle {
     #[allow(dead_code)]
     pub(crate) fn new<T: Handle + 'static>(handle: T) -> Self {
-        Self(Rc::new(handle))
+        Self(Arc::new(handle))
     }
 
     pub(crate) fn get(&self) -> Self {
-        Self(Rc::clone(&self.0))
+        Self(Arc::clone(&self.0))
     }
 }
 
@@ -79,7 +79,10 @@ impl fmt::Display for HandleRights {
 /// let some_file = OpenOptions::new().read(true).open("some_file").unwrap();
 /// let wasi_handle: Box<dyn Handle> = some_file.try_into().unwrap();
 /// ```
-pub trait Handle {
+///
+/// Handles are shared between the entries of a `WasiCtx`, which may move to another thread
+/// together with the `wasmtime::Store` it is defined in, hence the `Send + Sync` bound.
+pub trait Handle: Send + Sync {
     fn as_any(&self) -> &dyn Any;
     fn try_clone(&self) -> io::Result<Box<dyn Handle>>;
     fn get_file_type(&self) -> types::Filetype;
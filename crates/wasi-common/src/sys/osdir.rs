@@ -26,17 +26,17 @@ impl Handle for OsDir {
     }
     fn try_clone(&self) -> io::Result<Box<dyn Handle>> {
         let handle = self.handle.try_clone()?;
-        let new = Self::new(self.rights.get(), handle)?;
+        let new = Self::new(self.get_rights(), handle)?;
         Ok(Box::new(new))
     }
     fn get_file_type(&self) -> types::Filetype {
         types::Filetype::Directory
     }
     fn get_rights(&self) -> HandleRights {
-        self.rights.get()
+        *self.rights.lock().unwrap()
     }
     fn set_rights(&self, rights: HandleRights) {
-        self.rights.set(rights)
+        *self.rights.lock().unwrap() = rights
     }
     // FdOps
     fn fdstat_get(&self) -> Result<types::Fdflags> {
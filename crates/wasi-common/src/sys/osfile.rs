@@ -3,10 +3,10 @@ use super::{fd, AsFile};
 use crate::handle::{Handle, HandleRights};
 use crate::wasi::{types, Errno, Result};
 use std::any::Any;
-use std::cell::Cell;
 use std::fs::File;
 use std::io::{self, Read, Seek, SeekFrom, Write};
 use std::ops::Deref;
+use std::sync::Mutex;
 
 #[derive(Debug)]
 /// A file backed by the operating system's file system. Dereferences to a
@@ -27,13 +27,13 @@ use std::ops::Deref;
 /// let os_file = OsFile::try_from(file).unwrap();
 /// ```
 pub struct OsFile {
-    rights: Cell<HandleRights>,
+    rights: Mutex<HandleRights>,
     handle: RawOsHandle,
 }
 
 impl OsFile {
     pub(super) fn new(rights: HandleRights, handle: RawOsHandle) -> Self {
-        let rights = Cell::new(rights);
+        let rights = Mutex::new(rights);
         Self { rights, handle }
     }
 }
@@ -52,17 +52,17 @@ impl Handle for OsFile {
     }
     fn try_clone(&self) -> io::Result<Box<dyn Handle>> {
         let handle = self.handle.try_clone()?;
-        let rights = self.rights.clone();
+        let rights = Mutex::new(self.get_rights());
         Ok(Box::new(Self { rights, handle }))
     }
     fn get_file_type(&self) -> types::Filetype {
         types::Filetype::RegularFile
     }
     fn get_rights(&self) -> HandleRights {
-        self.rights.get()
+        *self.rights.lock().unwrap()
     }
     fn set_rights(&self, rights: HandleRights) {
-        self.rights.set(rights)
+        *self.rights.lock().unwrap() = rights
     }
     // FdOps
     fn advise(
@@ -5,10 +5,10 @@ use crate::sandboxed_tty_writer::SandboxedTTYWriter;
 use crate::wasi::types::{self, Filetype};
 use crate::wasi::Result;
 use std::any::Any;
-use std::cell::Cell;
 use std::fs::File;
 use std::io::{self, Read, Write};
 use std::ops::Deref;
+use std::sync::Mutex;
 
 /// `OsOther` is something of a catch-all for everything not covered with the specific handle
 /// types (`OsFile`, `OsDir`, `Stdio`). It currently encapsulates handles such as OS pipes,
@@ -32,13 +32,13 @@ use std::ops::Deref;
 #[derive(Debug)]
 pub struct OsOther {
     file_type: Filetype,
-    rights: Cell<HandleRights>,
+    rights: Mutex<HandleRights>,
     handle: RawOsHandle,
 }
 
 impl OsOther {
     pub(super) fn new(file_type: Filetype, rights: HandleRights, handle: RawOsHandle) -> Self {
-        let rights = Cell::new(rights);
+        let rights = Mutex::new(rights);
         Self {
             file_type,
             rights,
@@ -62,7 +62,7 @@ impl Handle for OsOther {
     fn try_clone(&self) -> io::Result<Box<dyn Handle>> {
         let file_type = self.file_type;
         let handle = self.handle.try_clone()?;
-        let rights = self.rights.clone();
+        let rights = Mutex::new(self.get_rights());
         Ok(Box::new(Self {
             file_type,
             rights,
@@ -73,10 +73,10 @@ impl Handle for OsOther {
         self.file_type
     }
     fn get_rights(&self) -> HandleRights {
-        self.rights.get()
+        *self.rights.lock().unwrap()
     }
     fn set_rights(&self, new_rights: HandleRights) {
-        self.rights.set(new_rights)
+        *self.rights.lock().unwrap() = new_rights
     }
     // FdOps
     fn fdstat_get(&self) -> Result<types::Fdflags> {
@@ -22,19 +22,19 @@ use crate::sandboxed_tty_writer::SandboxedTTYWriter;
 use crate::wasi::types::{self, Filetype};
 use crate::wasi::{Errno, Result, RightsExt};
 use std::any::Any;
-use std::cell::Cell;
 use std::convert::TryInto;
 use std::io::{self, Read, Write};
+use std::sync::Mutex;
 
 pub(crate) trait StdinExt: Sized {
     /// Create `Stdin` from `io::stdin`.
     fn stdin() -> io::Result<Box<dyn Handle>>;
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub(crate) struct Stdin {
     pub(crate) file_type: Filetype,
-    pub(crate) rights: Cell<HandleRights>,
+    pub(crate) rights: Mutex<HandleRights>,
 }
 
 impl Handle for Stdin {
@@ -42,16 +42,18 @@ impl Handle for Stdin {
         self
     }
     fn try_clone(&self) -> io::Result<Box<dyn Handle>> {
-        Ok(Box::new(self.clone()))
+        let file_type = self.file_type;
+        let rights = Mutex::new(self.get_rights());
+        Ok(Box::new(Self { file_type, rights }))
     }
     fn get_file_type(&self) -> Filetype {
         self.file_type
     }
     fn get_rights(&self) -> HandleRights {
-        self.rights.get()
+        *self.rights.lock().unwrap()
     }
     fn set_rights(&self, new_rights: HandleRights) {
-        self.rights.set(new_rights)
+        *self.rights.lock().unwrap() = new_rights
     }
     // FdOps
     fn fdstat_get(&self) -> Result<types::Fdflags> {
@@ -77,10 +79,10 @@ pub(crate) trait StdoutExt: Sized {
     fn stdout() -> io::Result<Box<dyn Handle>>;
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub(crate) struct Stdout {
     pub(crate) file_type: Filetype,
-    pub(crate) rights: Cell<HandleRights>,
+    pub(crate) rights: Mutex<HandleRights>,
 }
 
 impl Handle for Stdout {
@@ -88,16 +90,18 @@ impl Handle for Stdout {
         self
     }
     fn try_clone(&self) -> io::Result<Box<dyn Handle>> {
-        Ok(Box::new(self.clone()))
+        let file_type = self.file_type;
+        let rights = Mutex::new(self.get_rights());
+        Ok(Box::new(Self { file_type, rights }))
     }
     fn get_file_type(&self) -> Filetype {
         self.file_type
     }
     fn get_rights(&self) -> HandleRights {
-        self.rights.get()
+        *self.rights.lock().unwrap()
     }
     fn set_rights(&self, new_rights: HandleRights) {
-        self.rights.set(new_rights)
+        *self.rights.lock().unwrap() = new_rights
     }
     // FdOps
     fn fdstat_get(&self) -> Result<types::Fdflags> {
@@ -131,10 +135,10 @@ pub(crate) trait StderrExt: Sized {
     fn stderr() -> io::Result<Box<dyn Handle>>;
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub(crate) struct Stderr {
     pub(crate) file_type: Filetype,
-    pub(crate) rights: Cell<HandleRights>,
+    pub(crate) rights: Mutex<HandleRights>,
 }
 
 impl Handle for Stderr {
@@ -142,16 +146,18 @@ impl Handle for Stderr {
         self
     }
     fn try_clone(&self) -> io::Result<Box<dyn Handle>> {
-        Ok(Box::new(self.clone()))
+        let file_type = self.file_type;
+        let rights = Mutex::new(self.get_rights());
+        Ok(Box::new(Self { file_type, rights }))
     }
     fn get_file_type(&self) -> Filetype {
         self.file_type
     }
     fn get_rights(&self) -> HandleRights {
-        self.rights.get()
+        *self.rights.lock().unwrap()
     }
     fn set_rights(&self, new_rights: HandleRights) {
-        self.rights.set(new_rights)
+        *self.rights.lock().unwrap() = new_rights
     }
     // FdOps
     fn fdstat_get(&self) -> Result<types::Fdflags> {
@@ -176,10 +182,10 @@ impl Handle for Stderr {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub(crate) struct NullDevice {
-    pub(crate) rights: Cell<HandleRights>,
-    pub(crate) fd_flags: Cell<types::Fdflags>,
+    pub(crate) rights: Mutex<HandleRights>,
+    pub(crate) fd_flags: Mutex<types::Fdflags>,
 }
 
 impl NullDevice {
@@ -188,9 +194,9 @@ impl NullDevice {
             types::Rights::character_device_base(),
             types::Rights::character_device_inheriting(),
         );
-        let rights = Cell::new(rights);
+        let rights = Mutex::new(rights);
         let fd_flags = types::Fdflags::empty();
-        let fd_flags = Cell::new(fd_flags);
+        let fd_flags = Mutex::new(fd_flags);
         Self { rights, fd_flags }
     }
 }
@@ -200,23 +206,25 @@ impl Handle for NullDevice {
         self
     }
     fn try_clone(&self) -> io::Result<Box<dyn Handle>> {
-        Ok(Box::new(self.clone()))
+        let rights = Mutex::new(self.get_rights());
+        let fd_flags = Mutex::new(*self.fd_flags.lock().unwrap());
+        Ok(Box::new(Self { rights, fd_flags }))
     }
     fn get_file_type(&self) -> types::Filetype {
         types::Filetype::CharacterDevice
     }
     fn get_rights(&self) -> HandleRights {
-        self.rights.get()
+        *self.rights.lock().unwrap()
     }
     fn set_rights(&self, rights: HandleRights) {
-        self.rights.set(rights)
+        *self.rights.lock().unwrap() = rights
     }
     // FdOps
     fn fdstat_get(&self) -> Result<types::Fdflags> {
-        Ok(self.fd_flags.get())
+        Ok(*self.fd_flags.lock().unwrap())
     }
     fn fdstat_set_flags(&self, fdflags: types::Fdflags) -> Result<()> {
-        self.fd_flags.set(fdflags);
+        *self.fd_flags.lock().unwrap() = fdflags;
         Ok(())
     }
     fn read_vectored(&self, _iovs: &mut [io::IoSliceMut]) -> Result<usize> {
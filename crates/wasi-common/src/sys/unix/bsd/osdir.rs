@@ -1,8 +1,8 @@
 use crate::handle::HandleRights;
 use crate::sys::sys_impl::oshandle::RawOsHandle;
 use crate::wasi::Result;
-use std::cell::{Cell, RefCell, RefMut};
 use std::io;
+use std::sync::{Mutex, MutexGuard};
 use yanix::dir::Dir;
 
 #[derive(Debug)]
@@ -24,7 +24,7 @@ use yanix::dir::Dir;
 /// let os_dir = OsDir::try_from(dir).unwrap();
 /// ```
 pub struct OsDir {
-    pub(crate) rights: Cell<HandleRights>,
+    pub(crate) rights: Mutex<HandleRights>,
     pub(crate) handle: RawOsHandle,
     // When the client makes a `fd_readdir` syscall on this descriptor,
     // we will need to cache the `libc::DIR` pointer manually in order
@@ -36,12 +36,12 @@ pub struct OsDir {
     //   > of the DIR pointer, dirp, from which they are derived.
     //   > If the directory is closed and then reopened, prior values
     //   > returned by telldir() will no longer be valid.
-    stream_ptr: RefCell<Dir>,
+    stream_ptr: Mutex<Dir>,
 }
 
 impl OsDir {
     pub(crate) fn new(rights: HandleRights, handle: RawOsHandle) -> io::Result<Self> {
-        let rights = Cell::new(rights);
+        let rights = Mutex::new(rights);
         // We need to duplicate the handle, because `opendir(3)`:
         //     Upon successful return from fdopendir(), the file descriptor is under
         //     control of the system, and if any attempt is made to close the file
@@ -49,7 +49,7 @@ impl OsDir {
         //     than by means of closedir(), readdir(), readdir_r(), or rewinddir(),
         //     the behaviour is undefined.
         let stream_ptr = Dir::from(handle.try_clone()?)?;
-        let stream_ptr = RefCell::new(stream_ptr);
+        let stream_ptr = Mutex::new(stream_ptr);
         Ok(Self {
             rights,
             handle,
@@ -59,7 +59,7 @@ impl OsDir {
     /// Returns the `Dir` stream pointer associated with this `OsDir`. Duck
     /// typing: sys::unix::fd::readdir expects the configured OsDir to have
     /// this method.
-    pub(crate) fn stream_ptr(&self) -> Result<RefMut<Dir>> {
-        Ok(self.stream_ptr.borrow_mut())
+    pub(crate) fn stream_ptr(&self) -> Result<MutexGuard<Dir>> {
+        Ok(self.stream_ptr.lock().unwrap())
     }
 }
@@ -1,8 +1,8 @@
 use crate::handle::HandleRights;
 use crate::sys::sys_impl::oshandle::RawOsHandle;
 use crate::wasi::Result;
-use std::cell::Cell;
 use std::io;
+use std::sync::Mutex;
 use yanix::dir::Dir;
 
 #[derive(Debug)]
@@ -24,13 +24,13 @@ use yanix::dir::Dir;
 /// let os_dir = OsDir::try_from(dir).unwrap();
 /// ```
 pub struct OsDir {
-    pub(crate) rights: Cell<HandleRights>,
+    pub(crate) rights: Mutex<HandleRights>,
     pub(crate) handle: RawOsHandle,
 }
 
 impl OsDir {
     pub(crate) fn new(rights: HandleRights, handle: RawOsHandle) -> io::Result<Self> {
-        let rights = Cell::new(rights);
+        let rights = Mutex::new(rights);
         Ok(Self { rights, handle })
     }
     /// Returns the `Dir` stream pointer associated with this `OsDir`. Duck typing:
@@ -1,11 +1,11 @@
 use super::oshandle::RawOsHandle;
 use crate::handle::HandleRights;
 use crate::wasi::{types, RightsExt};
-use std::cell::Cell;
 use std::convert::TryFrom;
 use std::fs::File;
 use std::io;
 use std::os::windows::prelude::{AsRawHandle, FromRawHandle, IntoRawHandle};
+use std::sync::Mutex;
 
 #[derive(Debug)]
 /// A directory in the operating system's file system. Its impl of `Handle` is
@@ -28,13 +28,13 @@ use std::os::windows::prelude::{AsRawHandle, FromRawHandle, IntoRawHandle};
 /// let os_dir = OsDir::try_from(dir).unwrap();
 /// ```
 pub struct OsDir {
-    pub(crate) rights: Cell<HandleRights>,
+    pub(crate) rights: Mutex<HandleRights>,
     pub(crate) handle: RawOsHandle,
 }
 
 impl OsDir {
     pub(crate) fn new(rights: HandleRights, handle: RawOsHandle) -> io::Result<Self> {
-        let rights = Cell::new(rights);
+        let rights = Mutex::new(rights);
         Ok(Self { rights, handle })
     }
 }
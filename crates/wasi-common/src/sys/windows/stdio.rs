@@ -1,11 +1,11 @@
 use super::{get_file_type, get_rights};
 use crate::handle::Handle;
 use crate::sys::stdio::{Stderr, StderrExt, Stdin, StdinExt, Stdout, StdoutExt};
-use std::cell::Cell;
 use std::fs::File;
 use std::io;
 use std::mem::ManuallyDrop;
 use std::os::windows::prelude::{AsRawHandle, FromRawHandle, RawHandle};
+use std::sync::Mutex;
 
 impl AsRawHandle for Stdin {
     fn as_raw_handle(&self) -> RawHandle {
@@ -31,7 +31,7 @@ impl StdinExt for Stdin {
         let file = ManuallyDrop::new(file);
         let file_type = get_file_type(&file)?;
         let rights = get_rights(&file_type)?;
-        let rights = Cell::new(rights);
+        let rights = Mutex::new(rights);
         Ok(Box::new(Self { file_type, rights }))
     }
 }
@@ -42,7 +42,7 @@ impl StdoutExt for Stdout {
         let file = ManuallyDrop::new(file);
         let file_type = get_file_type(&file)?;
         let rights = get_rights(&file_type)?;
-        let rights = Cell::new(rights);
+        let rights = Mutex::new(rights);
         Ok(Box::new(Self { file_type, rights }))
     }
 }
@@ -53,7 +53,7 @@ impl StderrExt for Stderr {
         let file = ManuallyDrop::new(file);
         let file_type = get_file_type(&file)?;
         let rights = get_rights(&file_type)?;
-        let rights = Cell::new(rights);
+        let rights = Mutex::new(rights);
         Ok(Box::new(Self { file_type, rights }))
     }
 }
@@ -2,14 +2,13 @@ use crate::handle::{Handle, HandleRights};
 use crate::wasi::{self, types, Errno, Result, RightsExt};
 use log::trace;
 use std::any::Any;
-use std::cell::{Cell, RefCell};
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::io;
 use std::io::SeekFrom;
 use std::path::{Path, PathBuf};
-use std::rc::Rc;
+use std::sync::{Arc, Mutex};
 
 /// An entry in a virtual filesystem
 pub enum VirtualDirEntry {
@@ -33,7 +32,7 @@ pub(crate) trait MovableFile {
     fn set_parent(&self, new_parent: Option<Box<dyn Handle>>);
 }
 
-pub trait FileContents {
+pub trait FileContents: Send {
     /// The implementation-defined maximum size of the store corresponding to a `FileContents`
     /// implementation.
     fn max_size(&self) -> types::Filesize;
@@ -137,11 +136,11 @@ impl VecFileContents {
 /// a filesystem wherein a file descriptor is one view into a possibly-shared underlying collection
 /// of data and permissions on a filesystem.
 pub struct InMemoryFile {
-    rights: Cell<HandleRights>,
-    cursor: Cell<types::Filesize>,
-    parent: Rc<RefCell<Option<Box<dyn Handle>>>>,
-    fd_flags: Cell<types::Fdflags>,
-    data: Rc<RefCell<Box<dyn FileContents>>>,
+    rights: Mutex<HandleRights>,
+    cursor: Mutex<types::Filesize>,
+    parent: Arc<Mutex<Option<Box<dyn Handle>>>>,
+    fd_flags: Mutex<types::Fdflags>,
+    data: Arc<Mutex<Box<dyn FileContents>>>,
 }
 
 impl InMemoryFile {
@@ -154,20 +153,20 @@ impl InMemoryFile {
             types::Rights::regular_file_base(),
             types::Rights::regular_file_inheriting(),
         );
-        let rights = Cell::new(rights);
+        let rights = Mutex::new(rights);
         Self {
             rights,
-            cursor: Cell::new(0),
-            fd_flags: Cell::new(types::Fdflags::empty()),
-            parent: Rc::new(RefCell::new(None)),
-            data: Rc::new(RefCell::new(contents)),
+            cursor: Mutex::new(0),
+            fd_flags: Mutex::new(types::Fdflags::empty()),
+            parent: Arc::new(Mutex::new(None)),
+            data: Arc::new(Mutex::new(contents)),
         }
     }
 }
 
 impl MovableFile for InMemoryFile {
     fn set_parent(&self, new_parent: Option<Box<dyn Handle>>) {
-        *self.parent.borrow_mut() = new_parent;
+        *self.parent.lock().unwrap() = new_parent;
     }
 }
 
@@ -177,21 +176,21 @@ impl Handle for InMemoryFile {
     }
     fn try_clone(&self) -> io::Result<Box<dyn Handle>> {
         Ok(Box::new(Self {
-            rights: self.rights.clone(),
-            cursor: Cell::new(0),
-            fd_flags: self.fd_flags.clone(),
-            parent: Rc::clone(&self.parent),
-            data: Rc::clone(&self.data),
+            rights: Mutex::new(self.get_rights()),
+            cursor: Mutex::new(0),
+            fd_flags: Mutex::new(*self.fd_flags.lock().unwrap()),
+            parent: Arc::clone(&self.parent),
+            data: Arc::clone(&self.data),
         }))
     }
     fn get_file_type(&self) -> types::Filetype {
         types::Filetype::RegularFile
     }
     fn get_rights(&self) -> HandleRights {
-        self.rights.get()
+        *self.rights.lock().unwrap()
     }
     fn set_rights(&self, rights: HandleRights) {
-        self.rights.set(rights)
+        *self.rights.lock().unwrap() = rights
     }
     // FdOps
     fn advise(
@@ -205,7 +204,7 @@ impl Handle for InMemoryFile {
     }
     fn allocate(&self, offset: types::Filesize, len: types::Filesize) -> Result<()> {
         let new_limit = offset.checked_add(len).ok_or(Errno::Fbig)?;
-        let mut data = self.data.borrow_mut();
+        let mut data = self.data.lock().unwrap();
 
         if new_limit > data.max_size() {
             return Err(Errno::Fbig);
@@ -218,10 +217,10 @@ impl Handle for InMemoryFile {
         Ok(())
     }
     fn fdstat_get(&self) -> Result<types::Fdflags> {
-        Ok(self.fd_flags.get())
+        Ok(*self.fd_flags.lock().unwrap())
     }
     fn fdstat_set_flags(&self, fdflags: types::Fdflags) -> Result<()> {
-        self.fd_flags.set(fdflags);
+        *self.fd_flags.lock().unwrap() = fdflags;
         Ok(())
     }
     fn filestat_get(&self) -> Result<types::Filestat> {
@@ -229,7 +228,7 @@ impl Handle for InMemoryFile {
             dev: 0,
             ino: 0,
             nlink: 0,
-            size: self.data.borrow().size(),
+            size: self.data.lock().unwrap().size(),
             atim: 0,
             ctim: 0,
             mtim: 0,
@@ -238,66 +237,70 @@ impl Handle for InMemoryFile {
         Ok(stat)
     }
     fn filestat_set_size(&self, st_size: types::Filesize) -> Result<()> {
-        let mut data = self.data.borrow_mut();
+        let mut data = self.data.lock().unwrap();
         if st_size > data.max_size() {
             return Err(Errno::Fbig);
         }
         data.resize(st_size)
     }
     fn preadv(&self, buf: &mut [io::IoSliceMut], offset: types::Filesize) -> Result<usize> {
-        self.data.borrow_mut().preadv(buf, offset)
+        self.data.lock().unwrap().preadv(buf, offset)
     }
     fn pwritev(&self, buf: &[io::IoSlice], offset: types::Filesize) -> Result<usize> {
-        self.data.borrow_mut().pwritev(buf, offset)
+        self.data.lock().unwrap().pwritev(buf, offset)
     }
     fn read_vectored(&self, iovs: &mut [io::IoSliceMut]) -> Result<usize> {
         trace!("read_vectored(iovs={:?})", iovs);
-        trace!("     | *read_start={:?}", self.cursor.get());
-        self.data.borrow_mut().preadv(iovs, self.cursor.get())
+        trace!("     | *read_start={:?}", *self.cursor.lock().unwrap());
+        self.data
+            .lock()
+            .unwrap()
+            .preadv(iovs, *self.cursor.lock().unwrap())
     }
     fn seek(&self, offset: SeekFrom) -> Result<types::Filesize> {
-        let content_len = self.data.borrow().size();
+        let content_len = self.data.lock().unwrap().size();
+        let mut cursor = self.cursor.lock().unwrap();
         match offset {
             SeekFrom::Current(offset) => {
                 let new_cursor = if offset < 0 {
-                    self.cursor
-                        .get()
+                    cursor
                         .checked_sub(offset.wrapping_neg() as u64)
                         .ok_or(Errno::Inval)?
                 } else {
-                    self.cursor
-                        .get()
-                        .checked_add(offset as u64)
-                        .ok_or(Errno::Inval)?
+                    cursor.checked_add(offset as u64).ok_or(Errno::Inval)?
                 };
-                self.cursor.set(std::cmp::min(content_len, new_cursor));
+                *cursor = std::cmp::min(content_len, new_cursor);
             }
             SeekFrom::End(offset) => {
                 // A negative offset from the end would be past the end of the file,
                 let offset: u64 = offset.try_into().map_err(|_| Errno::Inval)?;
-                self.cursor.set(content_len.saturating_sub(offset));
+                *cursor = content_len.saturating_sub(offset);
             }
             SeekFrom::Start(offset) => {
                 // A negative offset from the end would be before the start of the file.
                 let offset: u64 = offset.try_into().map_err(|_| Errno::Inval)?;
-                self.cursor.set(std::cmp::min(content_len, offset));
+                *cursor = std::cmp::min(content_len, offset);
             }
         }
 
-        Ok(self.cursor.get())
+        Ok(*cursor)
     }
     fn write_vectored(&self, iovs: &[io::IoSlice]) -> Result<usize> {
         trace!("write_vectored(iovs={:?})", iovs);
-        let mut data = self.data.borrow_mut();
+        let mut data = self.data.lock().unwrap();
 
-        let append_mode = self.fd_flags.get().contains(&types::Fdflags::APPEND);
-        trace!("     | fd_flags={}", self.fd_flags.get());
+        let append_mode = self
+            .fd_flags
+            .lock()
+            .unwrap()
+            .contains(&types::Fdflags::APPEND);
+        trace!("     | fd_flags={}", *self.fd_flags.lock().unwrap());
 
         // If this file is in append mode, we write to the end.
         let write_start = if append_mode {
             data.size()
         } else {
-            self.cursor.get()
+            *self.cursor.lock().unwrap()
         };
 
         let max_size = iovs
@@ -326,8 +329,7 @@ impl Handle for InMemoryFile {
         // If we are not appending, adjust the cursor appropriately for the write, too. This can't
         // overflow, as we checked against that before writing any data.
         if !append_mode {
-            let update = self.cursor.get() + written as u64;
-            self.cursor.set(update);
+            *self.cursor.lock().unwrap() += written as u64;
         }
 
         Ok(written)
@@ -365,7 +367,7 @@ impl Handle for InMemoryFile {
         if path == "." {
             return self.try_clone().map_err(Into::into);
         } else if path == ".." {
-            match &*self.parent.borrow() {
+            match &*self.parent.lock().unwrap() {
                 Some(file) => file.try_clone().map_err(Into::into),
                 None => self.try_clone().map_err(Into::into),
             }
@@ -404,12 +406,12 @@ impl Handle for InMemoryFile {
 
 /// A clonable read/write directory.
 pub struct VirtualDir {
-    rights: Cell<HandleRights>,
+    rights: Mutex<HandleRights>,
     writable: bool,
     // All copies of this `VirtualDir` must share `parent`, and changes in one copy's `parent`
-    // must be reflected in all handles, so they share `Rc` of an underlying `parent`.
-    parent: Rc<RefCell<Option<Box<dyn Handle>>>>,
-    entries: Rc<RefCell<HashMap<PathBuf, Box<dyn Handle>>>>,
+    // must be reflected in all handles, so they share `Arc` of an underlying `parent`.
+    parent: Arc<Mutex<Option<Box<dyn Handle>>>>,
+    entries: Arc<Mutex<HashMap<PathBuf, Box<dyn Handle>>>>,
 }
 
 impl VirtualDir {
@@ -418,12 +420,12 @@ impl VirtualDir {
             types::Rights::directory_base(),
             types::Rights::directory_inheriting(),
         );
-        let rights = Cell::new(rights);
+        let rights = Mutex::new(rights);
         Self {
             rights,
             writable,
-            parent: Rc::new(RefCell::new(None)),
-            entries: Rc::new(RefCell::new(HashMap::new())),
+            parent: Arc::new(Mutex::new(None)),
+            entries: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
@@ -438,7 +440,8 @@ impl VirtualDir {
         let entry = Box::new(dir);
         entry.set_parent(Some(self.try_clone().expect("can clone self")));
         self.entries
-            .borrow_mut()
+            .lock()
+            .unwrap()
             .insert(path.as_ref().to_owned(), entry);
     }
 
@@ -453,14 +456,15 @@ impl VirtualDir {
         let entry = Box::new(InMemoryFile::new(content));
         entry.set_parent(Some(self.try_clone().expect("can clone self")));
         self.entries
-            .borrow_mut()
+            .lock()
+            .unwrap()
             .insert(path.as_ref().to_owned(), entry);
     }
 }
 
 impl MovableFile for VirtualDir {
     fn set_parent(&self, new_parent: Option<Box<dyn Handle>>) {
-        *self.parent.borrow_mut() = new_parent;
+        *self.parent.lock().unwrap() = new_parent;
     }
 }
 
@@ -477,20 +481,20 @@ impl Handle for VirtualDir {
     }
     fn try_clone(&self) -> io::Result<Box<dyn Handle>> {
         Ok(Box::new(Self {
-            rights: self.rights.clone(),
+            rights: Mutex::new(self.get_rights()),
             writable: self.writable,
-            parent: Rc::clone(&self.parent),
-            entries: Rc::clone(&self.entries),
+            parent: Arc::clone(&self.parent),
+            entries: Arc::clone(&self.entries),
         }))
     }
     fn get_file_type(&self) -> types::Filetype {
         types::Filetype::Directory
     }
     fn get_rights(&self) -> HandleRights {
-        self.rights.get()
+        *self.rights.lock().unwrap()
     }
     fn set_rights(&self, rights: HandleRights) {
-        self.rights.set(rights)
+        *self.rights.lock().unwrap() = rights
     }
     // FdOps
     fn filestat_get(&self) -> Result<types::Filestat> {
@@ -512,7 +516,7 @@ impl Handle for VirtualDir {
     ) -> Result<Box<dyn Iterator<Item = Result<(types::Dirent, String)>>>> {
         struct VirtualDirIter {
             start: u32,
-            entries: Rc<RefCell<HashMap<PathBuf, Box<dyn Handle>>>>,
+            entries: Arc<Mutex<HashMap<PathBuf, Box<dyn Handle>>>>,
         }
         impl Iterator for VirtualDirIter {
             type Item = Result<(types::Dirent, String)>;
@@ -542,7 +546,7 @@ impl Handle for VirtualDir {
                     return Some(Ok((dirent, name)));
                 }
 
-                let entries = self.entries.borrow();
+                let entries = self.entries.lock().unwrap();
 
                 // Adjust `start` to be an appropriate number of HashMap entries.
                 let start = self.start - RESERVED_ENTRY_COUNT;
@@ -584,12 +588,12 @@ impl Handle for VirtualDir {
         };
         Ok(Box::new(VirtualDirIter {
             start: cookie,
-            entries: Rc::clone(&self.entries),
+            entries: Arc::clone(&self.entries),
         }))
     }
     // PathOps
     fn create_directory(&self, path: &str) -> Result<()> {
-        let mut entries = self.entries.borrow_mut();
+        let mut entries = self.entries.lock().unwrap();
         match entries.entry(PathBuf::from(path)) {
             Entry::Occupied(_) => Err(Errno::Exist),
             Entry::Vacant(v) => {
@@ -654,7 +658,7 @@ impl Handle for VirtualDir {
         if path == "." {
             return self.try_clone().map_err(Into::into);
         } else if path == ".." {
-            match &*self.parent.borrow() {
+            match &*self.parent.lock().unwrap() {
                 Some(file) => {
                     return file.try_clone().map_err(Into::into);
                 }
@@ -667,7 +671,7 @@ impl Handle for VirtualDir {
         // openat may have been passed a path with a trailing slash, but files are mapped to paths
         // with trailing slashes normalized out.
         let file_name = Path::new(path).file_name().ok_or(Errno::Inval)?;
-        let mut entries = self.entries.borrow_mut();
+        let mut entries = self.entries.lock().unwrap();
         let entry_count = entries.len();
         match entries.entry(Path::new(file_name).to_path_buf()) {
             Entry::Occupied(e) => {
@@ -704,7 +708,7 @@ impl Handle for VirtualDir {
                     log::trace!("VirtualDir::openat creating an InMemoryFile named {}", path);
 
                     let file = Box::new(InMemoryFile::memory_backed());
-                    file.fd_flags.set(fd_flags);
+                    *file.fd_flags.lock().unwrap() = fd_flags;
                     file.set_parent(Some(self.try_clone().expect("can clone self")));
                     v.insert(file).try_clone().map_err(Into::into)
                 } else {
@@ -719,7 +723,7 @@ impl Handle for VirtualDir {
     }
     fn remove_directory(&self, path: &str) -> Result<()> {
         let trimmed_path = path.trim_end_matches('/');
-        let mut entries = self.entries.borrow_mut();
+        let mut entries = self.entries.lock().unwrap();
         match entries.entry(Path::new(trimmed_path).to_path_buf()) {
             Entry::Occupied(e) => {
                 // first, does this name a directory?
@@ -737,7 +741,7 @@ impl Handle for VirtualDir {
                 let removed = e.remove_entry();
 
                 // TODO refactor
-                // And sever the file's parent ref to avoid Rc cycles.
+                // And sever the file's parent ref to avoid Arc cycles.
                 if let Some(dir) = removed.1.as_any().downcast_ref::<Self>() {
                     dir.set_parent(None);
                 } else if let Some(file) = removed.1.as_any().downcast_ref::<InMemoryFile>() {
@@ -767,7 +771,7 @@ impl Handle for VirtualDir {
             return Err(Errno::Isdir);
         }
 
-        let mut entries = self.entries.borrow_mut();
+        let mut entries = self.entries.lock().unwrap();
         match entries.entry(Path::new(trimmed_path).to_path_buf()) {
             Entry::Occupied(e) => {
                 // Directories must be removed through `remove_directory`, not `unlink_file`.
@@ -778,7 +782,7 @@ impl Handle for VirtualDir {
                 let removed = e.remove_entry();
 
                 // TODO refactor
-                // Sever the file's parent ref to avoid Rc cycles.
+                // Sever the file's parent ref to avoid Arc cycles.
                 if let Some(dir) = removed.1.as_any().downcast_ref::<Self>() {
                     dir.set_parent(None);
                 } else if let Some(file) = removed.1.as_any().downcast_ref::<InMemoryFile>() {
@@ -215,7 +215,7 @@ pub fn define_struct(args: TokenStream) -> TokenStream {
                                 }
                             };
                             hostcalls::#name_ident(
-                                &mut my_cx.borrow_mut(),
+                                &mut my_cx.lock().unwrap(),
                                 memory.data_unchecked_mut(),
                                 #(#hostcall_args),*
                             ) #cvt_ret
@@ -245,7 +245,7 @@ pub fn define_struct(args: TokenStream) -> TokenStream {
             /// configuration of the wasi instance itself should be all
             /// contained in the `cx` parameter.
             pub fn new(store: &wasmtime::Store, cx: WasiCtx) -> Wasi {
-                let cx = std::rc::Rc::new(std::cell::RefCell::new(cx));
+                let cx = std::sync::Arc::new(std::sync::Mutex::new(cx));
                 #(#ctor_externs)*
 
                 Wasi {
@@ -477,7 +477,7 @@ pub fn define_struct_for_wiggle(args: TokenStream) -> TokenStream {
                             let bc = wiggle::BorrowChecker::new();
                             let mem = WasiMemory { mem, bc };
                             wasi_common::wasi::#module_id::#name_ident(
-                                &mut my_cx.borrow_mut(),
+                                &mut my_cx.lock().unwrap(),
                                 &mem,
                                 #(#hostcall_args),*
                             ) #cvt_ret
@@ -524,7 +524,7 @@ pub fn define_struct_for_wiggle(args: TokenStream) -> TokenStream {
             /// configuration of the wasi instance itself should be all
             /// contained in the `cx` parameter.
             pub fn new(store: &wasmtime::Store, cx: WasiCtx) -> Wasi {
-                let cx = std::rc::Rc::new(std::cell::RefCell::new(cx));
+                let cx = std::sync::Arc::new(std::sync::Mutex::new(cx));
                 #(#ctor_externs)*
 
                 Wasi {
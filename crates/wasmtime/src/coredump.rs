@@ -0,0 +1,469 @@
+use crate::encode::{
+    encode_custom_section, encode_i64, encode_name, encode_section, encode_u32, encode_u32_vec,
+};
+use crate::{FrameInfo, Store, Val, ValType};
+use std::collections::HashMap;
+use std::fmt;
+use std::slice;
+use wasmtime_environ::entity::EntityRef;
+use wasmtime_environ::wasm::{GlobalIndex, MemoryIndex};
+use wasmtime_environ::EntityIndex;
+use wasmtime_runtime::{
+    DebugValue, Export, ExportGlobal, ExportMemory, VMGlobalDefinition, VMMemoryDefinition,
+    WasmFrame,
+};
+
+const WASM_PAGE_SIZE: usize = 0x10000;
+
+/// A snapshot of the WebAssembly state of a [`Store`] taken when a trap
+/// happened, for post-mortem debugging.
+///
+/// Core dumps are only captured when [`Config::coredump_on_trap`] is enabled,
+/// and are then available through [`Trap::coredump`]. A core dump records
+/// the contents of every linear memory and the value of every global used by
+/// the instances in the store, along with the WebAssembly call stack at the
+/// time of the trap.
+///
+/// Use [`WasmCoreDump::serialize`] to write the core dump out in the standard
+/// [wasm coredump format] so it can be loaded by other tooling.
+///
+/// [`Config::coredump_on_trap`]: crate::Config::coredump_on_trap
+/// [`Trap::coredump`]: crate::Trap::coredump
+/// [wasm coredump format]: https://github.com/WebAssembly/tool-conventions/blob/main/Coredump.md
+pub struct WasmCoreDump {
+    modules: Vec<String>,
+    instances: Vec<CoreDumpInstance>,
+    memories: Vec<CoreDumpMemory>,
+    globals: Vec<CoreDumpGlobal>,
+    frames: Vec<CoreDumpFrame>,
+}
+
+struct CoreDumpInstance {
+    module: u32,
+    memories: Vec<u32>,
+    globals: Vec<u32>,
+}
+
+struct CoreDumpMemory {
+    data: Vec<u8>,
+    maximum: Option<u32>,
+    shared: bool,
+}
+
+struct CoreDumpGlobal {
+    ty: ValType,
+    mutable: bool,
+    value: CoreDumpValue,
+}
+
+enum CoreDumpValue {
+    I32(i32),
+    I64(i64),
+    F32(u32),
+    F64(u64),
+    V128(u128),
+    /// Reference values can't be meaningfully recorded, so they're written
+    /// out as null references.
+    Null,
+}
+
+/// A frame of the WebAssembly call stack recorded in a [`WasmCoreDump`].
+#[derive(Debug, Clone)]
+pub struct CoreDumpFrame {
+    instance: usize,
+    func_index: u32,
+    func_offset: u32,
+    locals: Vec<Option<DebugValue>>,
+}
+
+impl CoreDumpFrame {
+    /// Returns the index of the instance this frame was executing in, within
+    /// the instances recorded by the core dump.
+    ///
+    /// Instances are recorded in the order they were created in the store.
+    pub fn instance(&self) -> usize {
+        self.instance
+    }
+
+    /// Returns the index of this frame's function in its module's function
+    /// index space.
+    pub fn func_index(&self) -> u32 {
+        self.func_index
+    }
+
+    /// Returns the offset of this frame's instruction from the start of its
+    /// function in the original wasm module.
+    pub fn func_offset(&self) -> u32 {
+        self.func_offset
+    }
+
+    /// Returns the values of this frame's locals, including its parameters,
+    /// as they were before the frame's current instruction executed.
+    ///
+    /// Compiled code keeps locals in registers and in stack slots of its own
+    /// choosing, so their values can only be recovered from code compiled
+    /// with [`Config::guest_debug`](crate::Config::guest_debug), which
+    /// writes them out for the debugger before every instruction. Otherwise
+    /// this is empty. Locals holding references are `None`, as their values
+    /// aren't recorded.
+    pub fn locals(&self) -> impl ExactSizeIterator<Item = Option<Val>> + '_ {
+        self.locals.iter().map(|local| {
+            local.map(|value| match value {
+                DebugValue::I32(i) => Val::I32(i),
+                DebugValue::I64(i) => Val::I64(i),
+                DebugValue::F32(bits) => Val::F32(bits),
+                DebugValue::F64(bits) => Val::F64(bits),
+                DebugValue::V128(bits) => Val::V128(bits),
+            })
+        })
+    }
+}
+
+impl WasmCoreDump {
+    /// Captures the state of `store`, with the call stack given by the
+    /// `trace` of a trap along with its recorded wasm `frames`.
+    pub(crate) fn capture(
+        store: &Store,
+        trace: &[FrameInfo],
+        frames: &[WasmFrame],
+    ) -> WasmCoreDump {
+        let mut dump = WasmCoreDump {
+            modules: Vec::new(),
+            instances: Vec::new(),
+            memories: Vec::new(),
+            globals: Vec::new(),
+            frames: Vec::new(),
+        };
+        let mut module_indices = HashMap::new();
+        let mut memory_indices = HashMap::new();
+        let mut global_indices = HashMap::new();
+        let mut instance_modules = Vec::new();
+        let mut instance_vmctxs = Vec::new();
+
+        store.for_each_wasm_instance(|handle| {
+            let module = handle.module();
+            let next = dump.modules.len() as u32;
+            let module_index = *module_indices.entry(module.id).or_insert_with(|| {
+                dump.modules.push(module.name.clone().unwrap_or_default());
+                next
+            });
+
+            // Imported memories and globals resolve to their defining
+            // instance's storage, so instances sharing one get the same index.
+            let memories = (0..module.local.memory_plans.len())
+                .map(|i| {
+                    let export =
+                        handle.lookup_by_declaration(&EntityIndex::Memory(MemoryIndex::new(i)));
+                    match export {
+                        Export::Memory(m) => dump.memory_index(&mut memory_indices, m),
+                        _ => unreachable!(),
+                    }
+                })
+                .collect();
+            let globals = (0..module.local.globals.len())
+                .map(|i| {
+                    let export =
+                        handle.lookup_by_declaration(&EntityIndex::Global(GlobalIndex::new(i)));
+                    match export {
+                        Export::Global(g) => dump.global_index(&mut global_indices, g),
+                        _ => unreachable!(),
+                    }
+                })
+                .collect();
+
+            dump.instances.push(CoreDumpInstance {
+                module: module_index,
+                memories,
+                globals,
+            });
+            instance_modules.push(module.id);
+            instance_vmctxs.push(handle.vmctx_ptr() as usize);
+        });
+
+        // Compiled code records the instance of each of its frames, and those
+        // records list the frames of the trace in the same order. Some frames
+        // have no record though: those of other stores' wasm further up the
+        // stack, and a frame that overflowed the stack before it could write
+        // its record. Such frames are skipped, unless their module only has
+        // a single instance in this store which they must belong to.
+        let mut records = frames.iter().peekable();
+        for frame in trace {
+            let recorded = records.peek().and_then(|record| {
+                let i = instance_vmctxs.iter().position(|v| *v == record.vmctx)?;
+                if instance_modules[i] == frame.module_id() {
+                    Some((i, record.locals.clone()))
+                } else {
+                    None
+                }
+            });
+            let (instance, locals) = match recorded {
+                Some(recorded) => {
+                    records.next();
+                    recorded
+                }
+                None => {
+                    let mut instances = instance_modules
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, id)| **id == frame.module_id());
+                    match (instances.next(), instances.next()) {
+                        (Some((i, _)), None) => (i, Vec::new()),
+                        _ => continue,
+                    }
+                }
+            };
+            dump.frames.push(CoreDumpFrame {
+                instance,
+                func_index: frame.func_index(),
+                func_offset: frame.func_offset() as u32,
+                locals,
+            });
+        }
+        dump
+    }
+
+    fn memory_index(
+        &mut self,
+        indices: &mut HashMap<*mut VMMemoryDefinition, u32>,
+        export: ExportMemory,
+    ) -> u32 {
+        let memories = &mut self.memories;
+        *indices.entry(export.definition).or_insert_with(|| {
+            let data = unsafe {
+                let definition = &*export.definition;
+                slice::from_raw_parts(definition.base, definition.current_length).to_vec()
+            };
+            memories.push(CoreDumpMemory {
+                data,
+                maximum: export.memory.memory.maximum,
+                shared: export.memory.memory.shared,
+            });
+            memories.len() as u32 - 1
+        })
+    }
+
+    fn global_index(
+        &mut self,
+        indices: &mut HashMap<*mut VMGlobalDefinition, u32>,
+        export: ExportGlobal,
+    ) -> u32 {
+        let globals = &mut self.globals;
+        *indices.entry(export.definition).or_insert_with(|| {
+            let ty = ValType::from_wasm_type(&export.global.wasm_ty)
+                .expect("core wasm type should be supported");
+            let value = unsafe {
+                let definition = &*export.definition;
+                match ty {
+                    ValType::I32 => CoreDumpValue::I32(*definition.as_i32()),
+                    ValType::I64 => CoreDumpValue::I64(*definition.as_i64()),
+                    ValType::F32 => CoreDumpValue::F32(*definition.as_f32_bits()),
+                    ValType::F64 => CoreDumpValue::F64(*definition.as_f64_bits()),
+                    ValType::V128 => CoreDumpValue::V128(*definition.as_u128()),
+                    ValType::ExternRef | ValType::FuncRef => CoreDumpValue::Null,
+                }
+            };
+            globals.push(CoreDumpGlobal {
+                ty,
+                mutable: export.global.mutability,
+                value,
+            });
+            globals.len() as u32 - 1
+        })
+    }
+
+    /// Returns the WebAssembly call stack at the time of the trap, starting
+    /// with the frame that trapped.
+    ///
+    /// The values of the operand stack are never recorded, since compiled
+    /// code doesn't keep an operand stack, and locals are only recorded as
+    /// described in [`CoreDumpFrame::locals`].
+    pub fn frames(&self) -> &[CoreDumpFrame] {
+        &self.frames
+    }
+
+    /// Returns the contents of each linear memory recorded in this core dump,
+    /// as they were at the time of the trap.
+    pub fn memories(&self) -> impl ExactSizeIterator<Item = &[u8]> + '_ {
+        self.memories.iter().map(|m| &m.data[..])
+    }
+
+    /// Encodes this core dump as a wasm coredump file.
+    ///
+    /// The `name` is recorded as the name of the program that trapped. The
+    /// returned bytes are themselves a WebAssembly module whose memories and
+    /// globals are initialized with the recorded state, with custom sections
+    /// describing the instances and the call stack.
+    pub fn serialize(&self, name: &str) -> Vec<u8> {
+        let mut bytes = b"\0asm\x01\0\0\0".to_vec();
+
+        let mut core = vec![0x00];
+        encode_name(&mut core, name);
+        encode_custom_section(&mut bytes, "core", &core);
+
+        let mut modules = Vec::new();
+        encode_u32(&mut modules, self.modules.len() as u32);
+        for module in self.modules.iter() {
+            modules.push(0x00);
+            encode_name(&mut modules, module);
+        }
+        encode_custom_section(&mut bytes, "coremodules", &modules);
+
+        let mut instances = Vec::new();
+        encode_u32(&mut instances, self.instances.len() as u32);
+        for instance in self.instances.iter() {
+            instances.push(0x00);
+            encode_u32(&mut instances, instance.module);
+            encode_u32_vec(&mut instances, &instance.memories);
+            encode_u32_vec(&mut instances, &instance.globals);
+        }
+        encode_custom_section(&mut bytes, "coreinstances", &instances);
+
+        let mut stack = vec![0x00];
+        encode_name(&mut stack, "main");
+        encode_u32(&mut stack, self.frames.len() as u32);
+        for frame in self.frames.iter() {
+            stack.push(0x00);
+            encode_u32(&mut stack, frame.instance as u32);
+            encode_u32(&mut stack, frame.func_index);
+            encode_u32(&mut stack, frame.func_offset);
+            encode_u32(&mut stack, frame.locals.len() as u32);
+            for local in frame.locals.iter() {
+                encode_local(&mut stack, local);
+            }
+            // No operand stack values.
+            encode_u32(&mut stack, 0);
+        }
+        encode_custom_section(&mut bytes, "corestack", &stack);
+
+        if !self.memories.is_empty() {
+            let mut section = Vec::new();
+            encode_u32(&mut section, self.memories.len() as u32);
+            for memory in self.memories.iter() {
+                let minimum = (memory.data.len() / WASM_PAGE_SIZE) as u32;
+                match (memory.maximum, memory.shared) {
+                    (None, _) => {
+                        section.push(0x00);
+                        encode_u32(&mut section, minimum);
+                    }
+                    (Some(maximum), shared) => {
+                        section.push(if shared { 0x03 } else { 0x01 });
+                        encode_u32(&mut section, minimum);
+                        encode_u32(&mut section, maximum);
+                    }
+                }
+            }
+            encode_section(&mut bytes, 5, &section);
+        }
+
+        if !self.globals.is_empty() {
+            let mut section = Vec::new();
+            encode_u32(&mut section, self.globals.len() as u32);
+            for global in self.globals.iter() {
+                section.push(encode_val_type(&global.ty));
+                section.push(global.mutable as u8);
+                match global.value {
+                    CoreDumpValue::I32(i) => {
+                        section.push(0x41);
+                        encode_i64(&mut section, i.into());
+                    }
+                    CoreDumpValue::I64(i) => {
+                        section.push(0x42);
+                        encode_i64(&mut section, i);
+                    }
+                    CoreDumpValue::F32(bits) => {
+                        section.push(0x43);
+                        section.extend_from_slice(&bits.to_le_bytes());
+                    }
+                    CoreDumpValue::F64(bits) => {
+                        section.push(0x44);
+                        section.extend_from_slice(&bits.to_le_bytes());
+                    }
+                    CoreDumpValue::V128(bits) => {
+                        section.extend_from_slice(&[0xfd, 0x0c]);
+                        section.extend_from_slice(&bits.to_le_bytes());
+                    }
+                    CoreDumpValue::Null => {
+                        section.push(0xd0);
+                        section.push(encode_val_type(&global.ty));
+                    }
+                }
+                section.push(0x0b);
+            }
+            encode_section(&mut bytes, 6, &section);
+        }
+
+        if !self.memories.is_empty() {
+            let mut section = Vec::new();
+            encode_u32(&mut section, self.memories.len() as u32);
+            for (index, memory) in self.memories.iter().enumerate() {
+                // Trailing zeroes are already what a fresh memory contains, so
+                // leave them out to keep mostly-empty memories small.
+                let len = memory
+                    .data
+                    .iter()
+                    .rposition(|b| *b != 0)
+                    .map_or(0, |i| i + 1);
+                if index == 0 {
+                    section.push(0x00);
+                } else {
+                    section.push(0x02);
+                    encode_u32(&mut section, index as u32);
+                }
+                section.extend_from_slice(&[0x41, 0x00, 0x0b]);
+                encode_u32(&mut section, len as u32);
+                section.extend_from_slice(&memory.data[..len]);
+            }
+            encode_section(&mut bytes, 11, &section);
+        }
+
+        bytes
+    }
+}
+
+impl fmt::Debug for WasmCoreDump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WasmCoreDump")
+            .field("modules", &self.modules)
+            .field("instances", &self.instances.len())
+            .field("memories", &self.memories.len())
+            .field("globals", &self.globals.len())
+            .field("frames", &self.frames)
+            .finish()
+    }
+}
+
+/// Encodes a value of the `corestack` section. The format has no encoding of
+/// `v128` values, so those are written out as missing like references are.
+fn encode_local(bytes: &mut Vec<u8>, local: &Option<DebugValue>) {
+    match local {
+        Some(DebugValue::I32(i)) => {
+            bytes.push(0x7f);
+            encode_i64(bytes, (*i).into());
+        }
+        Some(DebugValue::I64(i)) => {
+            bytes.push(0x7e);
+            encode_i64(bytes, *i);
+        }
+        Some(DebugValue::F32(bits)) => {
+            bytes.push(0x7d);
+            bytes.extend_from_slice(&bits.to_le_bytes());
+        }
+        Some(DebugValue::F64(bits)) => {
+            bytes.push(0x7c);
+            bytes.extend_from_slice(&bits.to_le_bytes());
+        }
+        Some(DebugValue::V128(_)) | None => bytes.push(0x01),
+    }
+}
+
+fn encode_val_type(ty: &ValType) -> u8 {
+    match ty {
+        ValType::I32 => 0x7f,
+        ValType::I64 => 0x7e,
+        ValType::F32 => 0x7d,
+        ValType::F64 => 0x7c,
+        ValType::V128 => 0x7b,
+        ValType::FuncRef => 0x70,
+        ValType::ExternRef => 0x6f,
+    }
+}
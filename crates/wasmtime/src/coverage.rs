@@ -0,0 +1,159 @@
+use crate::Module;
+use anyhow::{bail, Result};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Write;
+
+/// Execution counts of the basic blocks of a module's code, collected when
+/// [`Config::coverage`](crate::Config::coverage) is enabled.
+///
+/// Every instance keeps its own counters, which are read with
+/// [`Instance::coverage`](crate::Instance::coverage), and
+/// [`Store::coverage`](crate::Store::coverage) adds up those of all
+/// instances of each module in a store.
+///
+/// Blocks are delimited by offsets in the original wasm binary. With the
+/// DWARF debug information in the module they can be mapped back to source
+/// lines, and written out as an lcov report with [`Coverage::write_lcov`].
+#[derive(Clone)]
+pub struct Coverage {
+    module: Module,
+    counts: Vec<u64>,
+}
+
+/// A basic block of wasm code and the number of times it was executed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CoverageBlock {
+    start: u32,
+    end: u32,
+    count: u64,
+}
+
+impl CoverageBlock {
+    /// Returns the offset of the block's first instruction in the wasm
+    /// binary.
+    pub fn start(&self) -> u32 {
+        self.start
+    }
+
+    /// Returns the offset in the wasm binary just past the block's last
+    /// instruction.
+    pub fn end(&self) -> u32 {
+        self.end
+    }
+
+    /// Returns the number of times the block was entered.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+impl Coverage {
+    pub(crate) fn new(module: &Module, counts: &[u64]) -> Coverage {
+        debug_assert_eq!(counts.len(), Coverage::blocks_of(module).len());
+        Coverage {
+            module: module.clone(),
+            counts: counts.to_vec(),
+        }
+    }
+
+    fn blocks_of(module: &Module) -> &[(u32, u32)] {
+        &module.compiled_module().module().local.coverage_blocks
+    }
+
+    /// Returns the module this coverage is of.
+    pub fn module(&self) -> &Module {
+        &self.module
+    }
+
+    /// Returns the basic blocks of the module's code in order, with their
+    /// execution counts.
+    ///
+    /// Blocks in code that can never be reached are reported with a count
+    /// of zero.
+    pub fn blocks(&self) -> impl ExactSizeIterator<Item = CoverageBlock> + '_ {
+        Coverage::blocks_of(&self.module)
+            .iter()
+            .zip(&self.counts)
+            .map(|((start, end), count)| CoverageBlock {
+                start: *start,
+                end: *end,
+                count: *count,
+            })
+    }
+
+    /// Adds the counts of `other` to this coverage.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `other` is the coverage of a different module.
+    pub fn merge(&mut self, other: &Coverage) -> Result<()> {
+        if !self.same_module(&other.module) {
+            bail!("cannot merge the coverage of different modules");
+        }
+        self.add(&other.counts);
+        Ok(())
+    }
+
+    pub(crate) fn add(&mut self, counts: &[u64]) {
+        for (count, other) in self.counts.iter_mut().zip(counts) {
+            *count += *other;
+        }
+    }
+
+    pub(crate) fn same_module(&self, module: &Module) -> bool {
+        self.module.compiled_module().module().id == module.compiled_module().module().id
+    }
+
+    /// Writes this coverage to `output` as an lcov tracefile, with line
+    /// counts taken from the module's DWARF line table.
+    ///
+    /// A line's count is the largest count of the blocks containing code
+    /// for it. Source files are named as they are in the DWARF, which are
+    /// usually the paths they were compiled from.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the module has no DWARF line information, or if
+    /// writing to `output` fails.
+    pub fn write_lcov(&self, mut output: impl Write) -> Result<()> {
+        let symbols = match self.module.compiled_module().symbols() {
+            Some(symbols) => symbols,
+            None => bail!("module has no DWARF line information to map coverage to"),
+        };
+
+        let mut files: BTreeMap<&str, BTreeMap<u32, u64>> = BTreeMap::new();
+        for block in self.blocks() {
+            let offsets = u64::from(block.start)..u64::from(block.end);
+            for (file, line) in symbols.lines(offsets) {
+                let count = files.entry(file).or_default().entry(line).or_default();
+                *count = (*count).max(block.count);
+            }
+        }
+
+        for (file, lines) in files {
+            writeln!(output, "TN:")?;
+            writeln!(output, "SF:{}", file)?;
+            for (line, count) in &lines {
+                writeln!(output, "DA:{},{}", line, count)?;
+            }
+            writeln!(output, "LF:{}", lines.len())?;
+            writeln!(output, "LH:{}", lines.values().filter(|c| **c > 0).count())?;
+            writeln!(output, "end_of_record")?;
+        }
+        output.flush()?;
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Coverage")
+            .field("blocks", &self.counts.len())
+            .field(
+                "blocks_hit",
+                &self.counts.iter().filter(|c| **c > 0).count(),
+            )
+            .finish()
+    }
+}
@@ -0,0 +1,77 @@
+//! Helpers for writing out the WebAssembly binary format, shared by the
+//! modules which produce wasm files of their own: core dumps and snapshots.
+
+/// Appends `value` to `bytes` as an unsigned LEB128 integer.
+pub(crate) fn encode_u32(bytes: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+/// Appends `value` to `bytes` as a signed LEB128 integer.
+pub(crate) fn encode_i64(bytes: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if done {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+/// Appends a vector of unsigned integers, prefixed by its length.
+pub(crate) fn encode_u32_vec(bytes: &mut Vec<u8>, values: &[u32]) {
+    encode_u32(bytes, values.len() as u32);
+    for value in values {
+        encode_u32(bytes, *value);
+    }
+}
+
+/// Appends a name, prefixed by its length in bytes.
+pub(crate) fn encode_name(bytes: &mut Vec<u8>, name: &str) {
+    encode_u32(bytes, name.len() as u32);
+    bytes.extend_from_slice(name.as_bytes());
+}
+
+/// Appends a section with the given `id` and `contents`.
+pub(crate) fn encode_section(bytes: &mut Vec<u8>, id: u8, contents: &[u8]) {
+    bytes.push(id);
+    encode_u32(bytes, contents.len() as u32);
+    bytes.extend_from_slice(contents);
+}
+
+/// Appends a custom section with the given `name` and `contents`.
+pub(crate) fn encode_custom_section(bytes: &mut Vec<u8>, name: &str, contents: &[u8]) {
+    let mut section = Vec::new();
+    encode_name(&mut section, name);
+    section.extend_from_slice(contents);
+    encode_section(bytes, 0, &section);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::encode_i64;
+
+    #[test]
+    fn sleb() {
+        let encode = |value| {
+            let mut out = Vec::new();
+            encode_i64(&mut out, value);
+            out
+        };
+        assert_eq!(encode(0), [0x00]);
+        assert_eq!(encode(63), [0x3f]);
+        assert_eq!(encode(64), [0xc0, 0x00]);
+        assert_eq!(encode(-1), [0x7f]);
+        assert_eq!(encode(-65), [0xbf, 0x7f]);
+    }
+}
@@ -1,15 +1,40 @@
 use crate::trampoline::{
-    generate_global_export, generate_memory_export, generate_table_export, StoreInstanceHandle,
+    generate_global_export, generate_memory_export, generate_shared_memory_export,
+    generate_table_export, StoreInstanceHandle,
 };
 use crate::values::{from_checked_anyfunc, into_checked_anyfunc, Val};
 use crate::{
-    ExternRef, ExternType, Func, GlobalType, MemoryType, Mutability, Store, TableType, Trap,
-    ValType,
+    Engine, ExternRef, ExternType, Func, GlobalType, MemoryType, Mutability, Store, TableType,
+    Trap, ValType,
 };
 use anyhow::{anyhow, bail, Result};
+use std::fmt;
+use std::rc::Rc;
 use std::slice;
 use wasmtime_environ::wasm;
-use wasmtime_runtime::{self as runtime, InstanceHandle};
+use wasmtime_runtime::{self as runtime, InstanceHandle, RuntimeLinearMemory, VMMemoryDefinition};
+
+/// Defines `Memory` methods which read and write little-endian integers.
+macro_rules! memory_int_accessors {
+    ($(
+        $(#[$attr:meta])*
+        $read:ident / $write:ident: $ty:ident;
+    )*) => {$(
+        $(#[$attr])*
+        pub fn $read(&self, offset: usize) -> Result<$ty, MemoryAccessError> {
+            let mut bytes = [0; std::mem::size_of::<$ty>()];
+            self.read(offset, &mut bytes)?;
+            Ok($ty::from_le_bytes(bytes))
+        }
+
+        /// Writes `value` to this memory at `offset`, in little-endian order.
+        ///
+        /// See [`Memory::write`] for when this returns an error or panics.
+        pub fn $write(&self, offset: usize, value: $ty) -> Result<(), MemoryAccessError> {
+            self.write(offset, &value.to_le_bytes())
+        }
+    )*};
+}
 
 // Externals
 
@@ -122,6 +147,45 @@ impl Extern {
         };
         Store::same(my_store, store)
     }
+
+    /// Returns a reference to this item which doesn't keep its store alive.
+    pub(crate) fn downgrade(&self) -> WeakExtern {
+        let instance = match self {
+            Extern::Func(f) => f.instance(),
+            Extern::Global(g) => &g.instance,
+            Extern::Memory(m) => &m.instance,
+            Extern::Table(t) => &t.instance,
+        };
+        WeakExtern {
+            export: self.get_wasmtime_export(),
+            // Safety: the instance lives as long as its store, which must be
+            // alive again for the handle to be used, see `WeakExtern::upgrade`.
+            handle: unsafe { instance.handle.clone() },
+            _token: instance.token.clone(),
+        }
+    }
+}
+
+/// An [`Extern`] which doesn't keep its store alive, for items captured by
+/// functions defined in that same store, which would otherwise keep it alive
+/// forever.
+///
+/// It still counts as a handle to the item's instance for
+/// `Store::drop_instance`.
+pub(crate) struct WeakExtern {
+    export: wasmtime_runtime::Export,
+    handle: InstanceHandle,
+    _token: Rc<()>,
+}
+
+impl WeakExtern {
+    /// Returns the item as an `Extern` again, given the `store` it was
+    /// downgraded from.
+    pub(crate) fn upgrade(&self, store: &Store) -> Extern {
+        let handle = unsafe { self.handle.clone() };
+        let instance = store.existing_instance_handle(handle);
+        Extern::from_wasmtime_export(self.export.clone(), instance)
+    }
 }
 
 impl From<Func> for Extern {
@@ -227,6 +291,18 @@ impl Global {
                 ValType::I64 => Val::from(*definition.as_i64()),
                 ValType::F32 => Val::F32(*definition.as_u32()),
                 ValType::F64 => Val::F64(*definition.as_u64()),
+                ValType::ExternRef => {
+                    let store = &self.instance.store;
+                    Val::ExternRef(
+                        definition
+                            .as_externref()
+                            .clone()
+                            .map(|inner| ExternRef::from_vm_externref(store, inner)),
+                    )
+                }
+                ValType::FuncRef => {
+                    from_checked_anyfunc(*definition.as_anyfunc(), &self.instance.store)
+                }
                 ty => unimplemented!("Global::get for {:?}", ty),
             }
         }
@@ -256,6 +332,10 @@ impl Global {
                 Val::I64(i) => *definition.as_i64_mut() = i,
                 Val::F32(f) => *definition.as_u32_mut() = f,
                 Val::F64(f) => *definition.as_u64_mut() = f,
+                Val::ExternRef(x) => *definition.as_externref_mut() = x.map(|x| x.inner),
+                Val::FuncRef(_) => {
+                    *definition.as_anyfunc_mut() = into_checked_anyfunc(val, &self.instance.store)?
+                }
                 _ => unimplemented!("Global::set for {:?}", val.ty()),
             }
         }
@@ -355,10 +435,9 @@ impl Table {
                 Some(unsafe { from_checked_anyfunc(f, &self.instance.store) })
             }
             runtime::TableElement::ExternRef(None) => Some(Val::ExternRef(None)),
-            runtime::TableElement::ExternRef(Some(x)) => Some(Val::ExternRef(Some(ExternRef {
-                inner: x,
-                store: self.instance.store.weak(),
-            }))),
+            runtime::TableElement::ExternRef(Some(x)) => Some(Val::ExternRef(Some(
+                ExternRef::from_vm_externref(&self.instance.store, x),
+            ))),
         }
     }
 
@@ -514,7 +593,29 @@ impl Table {
 /// lot to keep in mind! It's hopefully though sort of setting the stage as to
 /// what you can safely do with memories.
 ///
-/// Let's run through a few safe examples first of how you can use a `Memory`.
+/// The simplest way to avoid all of this is to not hold pointers into memory
+/// at all. [`Memory::read`] and [`Memory::write`], along with the typed
+/// helpers such as [`Memory::read_u32`], copy data in and out of memory with
+/// bounds checks, and [`Memory::data_mut`] lends out a slice of the memory
+/// for the duration of a closure during which the memory can't grow and no
+/// wasm can run:
+///
+/// ```rust
+/// use wasmtime::{Memory, MemoryAccessError};
+///
+/// fn checked_examples(mem: &Memory) -> Result<(), MemoryAccessError> {
+///     let mut buf = [0; 4];
+///     mem.read(0x123, &mut buf)?;
+///     mem.write(0x456, &buf)?;
+///     let x = mem.read_u32(0x200)?;
+///     mem.write_u32(0x200, x + 1)?;
+///     mem.data_mut(|data| data[0x123] = 3);
+///     Ok(())
+/// }
+/// ```
+///
+/// Let's run through a few examples of how you can use a `Memory` through its
+/// unchecked accessors, which are only safe when used carefully.
 ///
 /// ```rust
 /// use wasmtime::Memory;
@@ -639,9 +740,9 @@ impl Table {
 ///
 /// ## `Memory` Safety and Threads
 ///
-/// Currently the `wasmtime` crate does not implement the wasm threads proposal,
-/// but it is planned to do so. It's additionally worthwhile discussing how this
-/// affects memory safety and what was previously just discussed as well.
+/// Memories can be shared between threads with the wasm threads proposal, see
+/// [`SharedMemory`]. It's worthwhile discussing how this affects memory safety
+/// and what was previously just discussed as well.
 ///
 /// Once threads are added into the mix, all of the above rules still apply.
 /// There's an additional, rule, however, that all reads and writes can
@@ -680,6 +781,12 @@ impl Memory {
     /// otherwise the memory will immediately be allocated according to the
     /// type's configuration. All WebAssembly memory is initialized to zero.
     ///
+    /// # Errors
+    ///
+    /// Returns an error if the memory could not be allocated, for example if
+    /// the store's [`ResourceLimiter`](crate::ResourceLimiter) denies its
+    /// minimum size.
+    ///
     /// # Examples
     ///
     /// ```
@@ -689,7 +796,7 @@ impl Memory {
     /// let store = Store::new(&engine);
     ///
     /// let memory_ty = MemoryType::new(Limits::new(1, None));
-    /// let memory = Memory::new(&store, memory_ty);
+    /// let memory = Memory::new(&store, memory_ty)?;
     ///
     /// let module = Module::new(&engine, "(module (memory (import \"\" \"\") 1))")?;
     /// let instance = Instance::new(&store, &module, &[memory.into()])?;
@@ -697,13 +804,12 @@ impl Memory {
     /// # Ok(())
     /// # }
     /// ```
-    pub fn new(store: &Store, ty: MemoryType) -> Memory {
-        let (instance, wasmtime_export) =
-            generate_memory_export(store, &ty).expect("generated memory");
-        Memory {
+    pub fn new(store: &Store, ty: MemoryType) -> Result<Memory> {
+        let (instance, wasmtime_export) = generate_memory_export(store, &ty)?;
+        Ok(Memory {
             instance,
             wasmtime_export,
-        }
+        })
     }
 
     /// Returns the underlying type of this memory.
@@ -774,7 +880,7 @@ impl Memory {
     /// For more information and examples see the documentation on the
     /// [`Memory`] type.
     pub unsafe fn data_unchecked_mut(&self) -> &mut [u8] {
-        let definition = &*self.wasmtime_export.definition;
+        let definition = self.definition();
         slice::from_raw_parts_mut(definition.base, definition.current_length)
     }
 
@@ -788,7 +894,7 @@ impl Memory {
     /// For more information and examples see the documentation on the
     /// [`Memory`] type.
     pub fn data_ptr(&self) -> *mut u8 {
-        unsafe { (*self.wasmtime_export.definition).base }
+        self.definition().base
     }
 
     /// Returns the byte length of this memory.
@@ -798,7 +904,21 @@ impl Memory {
     /// For more information and examples see the documentation on the
     /// [`Memory`] type.
     pub fn data_size(&self) -> usize {
-        unsafe { (*self.wasmtime_export.definition).current_length }
+        self.definition().current_length
+    }
+
+    /// Returns the current definition of this memory.
+    ///
+    /// This store's definition of a shared memory goes stale when the memory
+    /// is grown through another store, so it's refreshed from the memory
+    /// itself first.
+    fn definition(&self) -> VMMemoryDefinition {
+        let definition = unsafe { &*self.wasmtime_export.definition };
+        if !self.wasmtime_export.memory.memory.shared {
+            return *definition;
+        }
+        let index = self.instance.memory_index(definition);
+        self.instance.defined_memory(index)
     }
 
     /// Returns the size, in pages, of this wasm memory.
@@ -806,6 +926,146 @@ impl Memory {
         (self.data_size() / wasmtime_environ::WASM_PAGE_SIZE as usize) as u32
     }
 
+    /// Copies `buffer.len()` bytes out of this memory, starting at `offset`,
+    /// into `buffer`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the range being read is out of bounds, in which
+    /// case `buffer` is left untouched.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this memory is currently borrowed by [`Memory::data_mut`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let engine = Engine::default();
+    /// let store = Store::new(&engine);
+    /// let module = Module::new(&engine, r#"(module (memory (export "mem") 1) (data (i32.const 8) "hello"))"#)?;
+    /// let instance = Instance::new(&store, &module, &[])?;
+    /// let memory = instance.get_memory("mem").unwrap();
+    ///
+    /// let mut buf = [0; 5];
+    /// memory.read(8, &mut buf)?;
+    /// assert_eq!(&buf, b"hello");
+    /// assert!(memory.read(0x10000 - 4, &mut buf).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read(&self, offset: usize, buffer: &mut [u8]) -> Result<(), MemoryAccessError> {
+        let src = self.checked_ptr(offset, buffer.len())?;
+        unsafe {
+            std::ptr::copy(src, buffer.as_mut_ptr(), buffer.len());
+        }
+        Ok(())
+    }
+
+    /// Copies the contents of `buffer` into this memory, starting at `offset`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the range being written is out of bounds, in which
+    /// case the memory is left untouched.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this memory is currently borrowed by [`Memory::data_mut`].
+    pub fn write(&self, offset: usize, buffer: &[u8]) -> Result<(), MemoryAccessError> {
+        let dst = self.checked_ptr(offset, buffer.len())?;
+        unsafe {
+            std::ptr::copy(buffer.as_ptr(), dst, buffer.len());
+        }
+        Ok(())
+    }
+
+    memory_int_accessors! {
+        /// Reads a `u8` from this memory at `offset`.
+        ///
+        /// See [`Memory::read`] for when this returns an error or panics.
+        read_u8 / write_u8: u8;
+        /// Reads a little-endian `u16` from this memory at `offset`.
+        ///
+        /// See [`Memory::read`] for when this returns an error or panics.
+        read_u16 / write_u16: u16;
+        /// Reads a little-endian `u32` from this memory at `offset`.
+        ///
+        /// See [`Memory::read`] for when this returns an error or panics.
+        read_u32 / write_u32: u32;
+        /// Reads a little-endian `u64` from this memory at `offset`.
+        ///
+        /// See [`Memory::read`] for when this returns an error or panics.
+        read_u64 / write_u64: u64;
+    }
+
+    /// Calls `f` with a mutable slice of the entire contents of this memory.
+    ///
+    /// Unlike [`Memory::data_unchecked_mut`] this is safe, because for the
+    /// duration of `f` nothing else can touch the memory:
+    ///
+    /// * [`Memory::grow`] fails, so the slice can't be invalidated by growth.
+    /// * Calling any wasm in this memory's [`Store`] fails with a trap, since
+    ///   wasm could otherwise read or write the memory.
+    /// * Instantiating a module in the store fails, since its data segments
+    ///   could otherwise be copied into the memory.
+    /// * [`Memory::read`], [`Memory::write`] and nested calls to this method
+    ///   on the same memory panic, much like a `RefCell`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this memory is already borrowed, or if it's a shared memory
+    /// since other threads may access shared memories at any time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let engine = Engine::default();
+    /// let store = Store::new(&engine);
+    /// let memory = Memory::new(&store, MemoryType::new(Limits::new(1, None)))?;
+    ///
+    /// memory.data_mut(|data| data[..5].copy_from_slice(b"hello"));
+    /// assert_eq!(memory.read_u8(4)?, b'o');
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn data_mut<R>(&self, f: impl FnOnce(&mut [u8]) -> R) -> R {
+        assert!(
+            !self.ty().is_shared(),
+            "cannot mutably borrow a shared memory"
+        );
+        let _borrow = self
+            .instance
+            .store
+            .borrow_memory(self.wasmtime_export.definition);
+        f(unsafe { self.data_unchecked_mut() })
+    }
+
+    /// Returns a pointer to the `len` bytes at `offset` in this memory, or an
+    /// error if they're out of bounds.
+    fn checked_ptr(&self, offset: usize, len: usize) -> Result<*mut u8, MemoryAccessError> {
+        assert!(
+            !self
+                .instance
+                .store
+                .is_memory_borrowed(self.wasmtime_export.definition),
+            "memory is already mutably borrowed"
+        );
+        let memory_size = self.data_size();
+        match offset.checked_add(len) {
+            Some(end) if end <= memory_size => Ok(unsafe { self.data_ptr().add(offset) }),
+            _ => Err(MemoryAccessError {
+                offset,
+                len,
+                memory_size,
+            }),
+        }
+    }
+
     /// Grows this WebAssembly memory by `delta` pages.
     ///
     /// This will attempt to add `delta` more pages of memory on to the end of
@@ -842,6 +1102,13 @@ impl Memory {
     /// # }
     /// ```
     pub fn grow(&self, delta: u32) -> Result<u32> {
+        if self
+            .instance
+            .store
+            .is_memory_borrowed(self.wasmtime_export.definition)
+        {
+            bail!("cannot grow a memory while it's mutably borrowed");
+        }
         let index = self
             .instance
             .memory_index(unsafe { &*self.wasmtime_export.definition });
@@ -861,6 +1128,175 @@ impl Memory {
     }
 }
 
+/// The error returned when a [`Memory`] is accessed out of bounds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAccessError {
+    offset: usize,
+    len: usize,
+    memory_size: usize,
+}
+
+impl MemoryAccessError {
+    /// Returns the offset at which the failed access started.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the number of bytes that the failed access covered.
+    pub fn access_len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns the size, in bytes, of the memory at the time of the access.
+    pub fn memory_size(&self) -> usize {
+        self.memory_size
+    }
+}
+
+impl fmt::Display for MemoryAccessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "out of bounds memory access: {} bytes at offset {:#x} in memory of {:#x} bytes",
+            self.len, self.offset, self.memory_size
+        )
+    }
+}
+
+impl std::error::Error for MemoryAccessError {}
+
+/// A WebAssembly linear memory which can be shared between stores and threads.
+///
+/// Unlike [`Memory`], a `SharedMemory` doesn't belong to any one [`Store`].
+/// It's created for an [`Engine`] and then given to each store that needs it
+/// with [`SharedMemory::memory`], after which it can be imported like any
+/// other memory. Wasm instances in different stores, running on different
+/// threads, then all see the same contents, and can synchronize with atomic
+/// instructions and `memory.atomic.wait`/`memory.atomic.notify`.
+///
+/// Shared memories must have a maximum size, and they're always allocated
+/// up-front so that growing them never moves their base pointer. All of the
+/// caveats in [`Memory`] about concurrent access apply.
+///
+/// # Examples
+///
+/// ```
+/// # use wasmtime::*;
+/// # fn main() -> anyhow::Result<()> {
+/// let mut config = Config::new();
+/// config.wasm_threads(true);
+/// let engine = Engine::new(&config);
+/// let memory = SharedMemory::new(&engine, MemoryType::shared(Limits::new(1, Some(2))))?;
+///
+/// let module = Module::new(&engine, "(module (memory (import \"\" \"\") 1 2 shared))")?;
+/// let handle = std::thread::spawn({
+///     let memory = memory.clone();
+///     move || -> anyhow::Result<()> {
+///         let store = Store::new(&engine);
+///         let instance = Instance::new(&store, &module, &[memory.memory(&store)?.into()])?;
+///         // ...
+///         Ok(())
+///     }
+/// });
+/// handle.join().unwrap()?;
+/// assert_eq!(memory.size(), 1);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct SharedMemory {
+    engine: Engine,
+    memory: runtime::SharedMemory,
+}
+
+impl SharedMemory {
+    /// Creates a new shared memory for use in stores of `engine`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `ty` isn't a shared memory type, if it has no
+    /// maximum size, or if the maximum is too large for the engine's
+    /// [`Config::static_memory_maximum_size`](crate::Config::static_memory_maximum_size).
+    pub fn new(engine: &Engine, ty: MemoryType) -> Result<SharedMemory> {
+        if !ty.is_shared() {
+            bail!("shared memories must have a shared memory type");
+        }
+        if ty.limits().max().is_none() {
+            bail!("shared memories must have a maximum size");
+        }
+        let memory = wasm::Memory {
+            minimum: ty.limits().min(),
+            maximum: ty.limits().max(),
+            shared: true,
+        };
+        let plan = wasmtime_environ::MemoryPlan::for_memory(memory, &engine.config().tunables);
+        let memory = runtime::SharedMemory::new(plan).map_err(|e| anyhow!(e))?;
+        Ok(SharedMemory {
+            engine: engine.clone(),
+            memory,
+        })
+    }
+
+    /// Returns the underlying type of this memory.
+    pub fn ty(&self) -> MemoryType {
+        MemoryType::from_wasmtime_memory(&self.memory.plan().memory)
+    }
+
+    /// Returns the base pointer, in the host's address space, that the memory
+    /// is located at. This never changes.
+    pub fn data_ptr(&self) -> *mut u8 {
+        self.memory.vmmemory().base
+    }
+
+    /// Returns the byte length of this memory.
+    ///
+    /// Other threads may grow the memory at any time, so this is only a
+    /// lower bound by the time it returns.
+    pub fn data_size(&self) -> usize {
+        self.memory.vmmemory().current_length
+    }
+
+    /// Returns the size, in pages, of this wasm memory.
+    pub fn size(&self) -> u32 {
+        self.memory.size()
+    }
+
+    /// Grows this WebAssembly memory by `delta` pages, returning the number of
+    /// pages it previously had.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if memory could not be grown, for example if it exceeds
+    /// the maximum limits of this memory.
+    pub fn grow(&self, delta: u32) -> Result<u32> {
+        self.memory
+            .grow(delta)
+            .ok_or_else(|| anyhow!("failed to grow memory"))
+    }
+
+    /// Returns a [`Memory`] in `store` which refers to this shared memory, so
+    /// that it can be imported by instances in `store`.
+    ///
+    /// Note that [`Memory::data_size`] on the returned memory may not reflect
+    /// growth done through other stores, while [`SharedMemory::data_size`]
+    /// always does.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `store` belongs to a different engine than this
+    /// memory.
+    pub fn memory(&self, store: &Store) -> Result<Memory> {
+        if !Engine::same(store.engine(), &self.engine) {
+            bail!("cross-`Engine` shared memories are not supported");
+        }
+        let (instance, wasmtime_export) = generate_shared_memory_export(store, &self.memory)?;
+        Ok(Memory {
+            instance,
+            wasmtime_export,
+        })
+    }
+}
+
 /// A linear memory. This trait provides an interface for raw memory buffers which are used
 /// by wasmtime, e.g. inside ['Memory']. Such buffers are in principle not thread safe.
 /// By implementing this trait together with MemoryCreator,
@@ -946,7 +1382,7 @@ mod tests {
             .dynamic_memory_guard_size(0);
         let store = Store::new(&Engine::new(&cfg));
         let ty = MemoryType::new(Limits::new(1, None));
-        let mem = Memory::new(&store, ty);
+        let mem = Memory::new(&store, ty).unwrap();
         assert_eq!(mem.wasmtime_export.memory.offset_guard_size, 0);
         match mem.wasmtime_export.memory.style {
             wasmtime_environ::MemoryStyle::Dynamic => {}
@@ -5,7 +5,7 @@ use wasmtime_environ::entity::EntityRef;
 use wasmtime_environ::ir;
 use wasmtime_environ::wasm::FuncIndex;
 use wasmtime_environ::{FunctionAddressMap, Module, TrapInformation};
-use wasmtime_jit::CompiledModule;
+use wasmtime_jit::{CompiledModule, ModuleSymbols};
 
 lazy_static::lazy_static! {
     /// This is a global cache of backtrace frame information for all active
@@ -42,6 +42,7 @@ struct ModuleFrameInfo {
     start: usize,
     functions: BTreeMap<usize, FunctionInfo>,
     module: Arc<Module>,
+    symbols: Option<Arc<ModuleSymbols>>,
     #[allow(dead_code)]
     module_code: Arc<dyn std::any::Any + Send + Sync>,
 }
@@ -102,12 +103,30 @@ impl GlobalFrameInfo {
             Some(pos) => func.instr_map.instructions[pos].srcloc,
             None => func.instr_map.start_srcloc,
         };
+        // Resolve the wasm offset to source locations if the module came with
+        // DWARF; this is empty otherwise.
+        let symbols = match &module.symbols {
+            Some(symbols) => symbols
+                .lookup(u64::from(instr.bits()))
+                .into_iter()
+                .map(|symbol| FrameSymbol {
+                    name: symbol.name,
+                    file: symbol.file,
+                    line: symbol.line,
+                    column: symbol.column,
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+
         Some(FrameInfo {
+            module_id: module.module.id,
             module_name: module.module.name.clone(),
             func_index: func.index.index() as u32,
             func_name: module.module.func_names.get(&func.index).cloned(),
             instr,
             func_start: func.instr_map.start_srcloc,
+            symbols,
         })
     }
 
@@ -194,6 +213,7 @@ pub fn register(module: &CompiledModule) -> Option<GlobalFrameInfoRegistration>
             start: min,
             functions,
             module: module.module().clone(),
+            symbols: module.symbols().cloned(),
             module_code: module.code().clone(),
         },
     );
@@ -210,14 +230,20 @@ pub fn register(module: &CompiledModule) -> Option<GlobalFrameInfoRegistration>
 /// [`Trap`]: crate::Trap
 #[derive(Debug)]
 pub struct FrameInfo {
+    module_id: usize,
     module_name: Option<String>,
     func_index: u32,
     func_name: Option<String>,
     func_start: ir::SourceLoc,
     instr: ir::SourceLoc,
+    symbols: Vec<FrameSymbol>,
 }
 
 impl FrameInfo {
+    pub(crate) fn module_id(&self) -> usize {
+        self.module_id
+    }
+
     /// Returns the WebAssembly function index for this frame.
     ///
     /// This function index is the index in the function index space of the
@@ -275,4 +301,53 @@ impl FrameInfo {
     pub fn func_offset(&self) -> usize {
         (self.instr.bits() - self.func_start.bits()) as usize
     }
+
+    /// Returns the source-level symbols for this frame's program counter.
+    ///
+    /// These are resolved from the DWARF debug information in the original
+    /// wasm module, if any; without it this list is empty. Multiple symbols
+    /// are returned when code was inlined: the first is the innermost inlined
+    /// function, and each subsequent one the function it was inlined into,
+    /// located at the point of the inlined call.
+    pub fn symbols(&self) -> &[FrameSymbol] {
+        &self.symbols
+    }
+}
+
+/// Source-level debug information for a [`FrameInfo`].
+///
+/// Each of these describes one function at a frame's program counter, as
+/// described by the DWARF in the wasm module. See [`FrameInfo::symbols`].
+#[derive(Debug)]
+pub struct FrameSymbol {
+    name: Option<String>,
+    file: Option<String>,
+    line: Option<u32>,
+    column: Option<u32>,
+}
+
+impl FrameSymbol {
+    /// Returns the function name of this symbol, if known.
+    ///
+    /// This is the name recorded in the DWARF, so it may be mangled.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns the source file this symbol is located in, if known.
+    pub fn file(&self) -> Option<&str> {
+        self.file.as_deref()
+    }
+
+    /// Returns the 1-based line number of this symbol within its file, if
+    /// known.
+    pub fn line(&self) -> Option<u32> {
+        self.line
+    }
+
+    /// Returns the 1-based column number of this symbol within its line, if
+    /// known.
+    pub fn column(&self) -> Option<u32> {
+        self.column
+    }
 }
@@ -1,13 +1,17 @@
 use crate::runtime::StoreInner;
 use crate::trampoline::StoreInstanceHandle;
-use crate::{Extern, FuncType, Memory, Store, Trap, Val, ValType};
+use crate::{CallHook, Extern, FuncType, Memory, Store, Trap, Val, ValType};
 use anyhow::{bail, ensure, Context as _, Result};
 use std::cmp::max;
 use std::fmt;
+use std::future::Future;
 use std::mem;
 use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
 use std::ptr::{self, NonNull};
 use std::rc::Weak;
+use std::sync::atomic::{AtomicBool, Ordering::Relaxed};
+use std::sync::Arc;
 use wasmtime_runtime::{
     raise_user_trap, Export, InstanceHandle, VMContext, VMFunctionBody, VMTrampoline,
 };
@@ -255,14 +259,18 @@ impl Func {
                 }
             }
             let mut returns = vec![Val::null(); ty_clone.results().len()];
-            func(
+            store.invoke_call_hook(CallHook::CallingHost)?;
+            let result = func(
                 Caller {
                     store: &store_weak,
                     caller_vmctx,
                 },
                 &args,
                 &mut returns,
-            )?;
+            );
+            let hook_result = store.invoke_call_hook(CallHook::ReturningFromHost);
+            result?;
+            hook_result?;
 
             // Unlike our arguments we need to dynamically check that the return
             // values produced are correct. There could be a bug in `func` that
@@ -289,6 +297,94 @@ impl Func {
         }
     }
 
+    /// Creates a new host-defined WebAssembly function which, when called,
+    /// will run the asynchronous computation defined by `func` to completion
+    /// and then return the result to WebAssembly.
+    ///
+    /// This function is the asynchronous analogue of [`Func::new`] and much of
+    /// that documentation applies to this as well. The key difference is that
+    /// `func` returns a future instead of simply a `Result`. Note that the
+    /// returned future can close over any of the arguments, but it cannot be
+    /// `'static` since it closes over the arguments it's given. That's why
+    /// `data` is provided as well: it's passed by reference to each invocation
+    /// of `func` for state the future may need to borrow.
+    ///
+    /// While the future returned by `func` is pending, the WebAssembly calling
+    /// this function is suspended and the future driving that WebAssembly,
+    /// from [`Func::call_async`] or [`Instance::new_async`], returns
+    /// `Poll::Pending`. Calling this function from WebAssembly executing
+    /// synchronously, for example through [`Func::call`], results in a trap.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `store` is not associated with an
+    /// [async config](crate::Config::async_support).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// // Simulate some application-specific state as well as asynchronous
+    /// // functions to query that state.
+    /// struct DatabaseConnection {
+    ///     // ...
+    /// }
+    ///
+    /// impl DatabaseConnection {
+    ///     async fn query(&self, row: i32) -> i32 {
+    ///         // ...
+    /// #       row * 2
+    ///     }
+    /// }
+    ///
+    /// let mut config = Config::new();
+    /// config.async_support(true);
+    /// let engine = Engine::new(&config);
+    /// let store = Store::new(&engine);
+    ///
+    /// // Define a type signature and our function in terms of it, passing a
+    /// // connection along as the function's `data`.
+    /// let ty = FuncType::new(Box::new([ValType::I32]), Box::new([ValType::I32]));
+    /// let connection = DatabaseConnection {};
+    /// let query = Func::new_async(&store, ty, connection, |_caller, connection, params, results| {
+    ///     Box::new(async move {
+    ///         let row = params[0].unwrap_i32();
+    ///         results[0] = Val::I32(connection.query(row).await);
+    ///         Ok(())
+    ///     })
+    /// });
+    ///
+    /// // ... use `query` as an import of an instance created with
+    /// // `Instance::new_async` ...
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new_async<T, F>(store: &Store, ty: FuncType, data: T, func: F) -> Func
+    where
+        T: 'static,
+        F: for<'a> Fn(
+                Caller<'a>,
+                &'a T,
+                &'a [Val],
+                &'a mut [Val],
+            ) -> Box<dyn Future<Output = Result<(), Trap>> + 'a>
+            + 'static,
+    {
+        assert!(
+            store.async_support(),
+            "cannot use `new_async` without enabling async support in the config"
+        );
+        Func::new(store, ty, move |caller, params, results| {
+            let store = caller.store();
+            let mut future = Pin::from(func(caller, &data, params, results));
+            match store.block_on(future.as_mut()) {
+                Ok(Ok(())) => Ok(()),
+                Ok(Err(trap)) | Err(trap) => Err(trap),
+            }
+        })
+    }
+
     /// Creates a new `Func` from the given Rust closure.
     ///
     /// This function will create a new `Func` which, when called, will
@@ -588,6 +684,35 @@ impl Func {
         Ok(results.into())
     }
 
+    /// Invokes this function with the `params` given, returning the results
+    /// asynchronously.
+    ///
+    /// This is the asynchronous version of [`Func::call`]. The WebAssembly is
+    /// executed on a separate native stack so that any async host functions
+    /// it calls, created with [`Func::new_async`], can suspend it while their
+    /// futures are pending. When that happens the future returned here
+    /// returns `Poll::Pending` and execution picks back up where it left off
+    /// once it's polled again.
+    ///
+    /// Dropping the returned future before it completes will cause the
+    /// suspended WebAssembly to trap so its stack can be cleaned up.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is called on a function in a [`Store`] whose
+    /// [`Config`](crate::Config) doesn't have async support enabled.
+    pub async fn call_async(&self, params: &[Val]) -> Result<Box<[Val]>> {
+        assert!(
+            self.store().async_support(),
+            "cannot use `call_async` without enabling async support in the config"
+        );
+        self.store().on_fiber(|| self.call(params)).await?
+    }
+
+    pub(crate) fn instance(&self) -> &StoreInstanceHandle {
+        &self.instance
+    }
+
     pub(crate) fn wasmtime_function(&self) -> &wasmtime_runtime::ExportFunction {
         &self.export
     }
@@ -755,22 +880,39 @@ pub(crate) fn invoke_wasm_and_catch_traps(
     store: &Store,
     closure: impl FnMut(),
 ) -> Result<(), Trap> {
-    let signalhandler = store.signal_handler();
-    unsafe {
-        let canary = 0;
-        let _auto_reset_canary = store
-            .externref_activations_table()
-            .set_stack_canary(&canary);
-
-        wasmtime_runtime::catch_traps(
-            vmctx,
-            store.engine().config().max_wasm_stack,
-            |addr| store.is_in_jit_code(addr),
-            signalhandler.as_deref(),
-            closure,
-        )
-        .map_err(Trap::from_runtime)
+    // Wasm may access any memory in the store, so it can't run while the host
+    // holds a mutable borrow of one.
+    if store.any_memory_borrowed() {
+        return Err(Trap::new(
+            "cannot call into wasm while a memory is mutably borrowed",
+        ));
     }
+
+    store.invoke_call_hook(CallHook::CallingWasm)?;
+    let result = {
+        let _executing = store.enter_wasm();
+        let signalhandler = store.signal_handler();
+        unsafe {
+            let canary = 0;
+            let _auto_reset_canary = store
+                .externref_activations_table()
+                .set_stack_canary(&canary);
+
+            wasmtime_runtime::catch_traps(
+                vmctx,
+                store.engine().config().max_wasm_stack,
+                |addr| store.is_in_jit_code(addr),
+                signalhandler.as_deref(),
+                closure,
+            )
+            .map_err(|trap| Trap::from_runtime(trap).with_coredump(store))
+        }
+    };
+    // Wasm has left either way, so let the hook know even after a trap, but
+    // report the trap rather than any error from the hook.
+    let hook_result = store.invoke_call_hook(CallHook::ReturningFromWasm);
+    result?;
+    hook_result
 }
 
 /// A trait implemented for types which can be arguments to closures passed to
@@ -1007,6 +1149,14 @@ unsafe impl<T: WasmTy> WasmRet for Result<T, Trap> {
     }
 }
 
+/// Raises a trap returned by the call hook around a `Func::wrap` function.
+///
+/// This is out of line so that, like in `WasmRet::into_abi`, nothing with a
+/// destructor is live in the shim when unwinding past it.
+fn raise_call_hook_trap(trap: Trap) -> ! {
+    unsafe { raise_user_trap(trap.into()) }
+}
+
 /// Internal trait implemented for all arguments that can be passed to
 /// [`Func::wrap`].
 ///
@@ -1015,6 +1165,13 @@ unsafe impl<T: WasmTy> WasmRet for Result<T, Trap> {
 pub trait IntoFunc<Params, Results> {
     #[doc(hidden)]
     fn into_func(self, store: &Store) -> Func;
+
+    /// Returns a constructor of this function for any number of stores, see
+    /// [`Linker::shared_func`](crate::Linker::shared_func).
+    #[doc(hidden)]
+    fn into_func_factory(self) -> Box<dyn Fn(&Store) -> Func>
+    where
+        Self: Sync;
 }
 
 /// A structure representing the *caller's* context when creating a function
@@ -1088,20 +1245,26 @@ impl Caller<'_> {
             // custom host data. This function should only be invoke-able while
             // the `Store` is active, so this upgrade should always succeed.
             debug_assert!(self.store.upgrade().is_some());
-            let handle =
-                Store::from_inner(self.store.upgrade()?).existing_instance_handle(instance);
+            let handle = Store::upgrade(self.store)?.existing_instance_handle(instance);
             let mem = Memory::from_wasmtime_memory(export, handle);
             Some(Extern::Memory(mem))
         }
     }
 
     /// Get a handle to this caller's store.
+    ///
+    /// This is also how host functions reach the store's embedder data, see
+    /// [`Store::data`].
     pub fn store(&self) -> Store {
         // See comment above the `store` member for why this unwrap is OK.
         Store::upgrade(&self.store).unwrap()
     }
 }
 
+/// The host state of functions created with `Func::wrap`: the closure itself,
+/// the store it belongs to, and that store's `has_call_hook` flag.
+type HostState<F> = (F, Weak<StoreInner>, Arc<AtomicBool>);
+
 macro_rules! impl_into_func {
     ($(
         ($($args:ident)*)
@@ -1121,6 +1284,20 @@ macro_rules! impl_into_func {
                     self($($args),*)
                 })
             }
+
+            #[allow(non_snake_case)]
+            fn into_func_factory(self) -> Box<dyn Fn(&Store) -> Func>
+            where
+                Self: Sync,
+            {
+                let func = Arc::new(self);
+                Box::new(move |store: &Store| {
+                    let func = func.clone();
+                    Func::wrap(store, move |_: Caller<'_>, $($args:$args),*| {
+                        (*func)($($args),*)
+                    })
+                })
+            }
         }
 
         #[allow(non_snake_case)]
@@ -1149,17 +1326,33 @@ macro_rules! impl_into_func {
                         // Double-check ourselves in debug mode, but we control
                         // the `Any` here so an unsafe downcast should also
                         // work.
-                        debug_assert!(state.is::<(F, Weak<StoreInner>)>());
-                        let (func, store) = &*(state as *const _ as *const (F, Weak<StoreInner>));
-                        panic::catch_unwind(AssertUnwindSafe(|| {
-                            func(
+                        debug_assert!(state.is::<HostState<F>>());
+                        let (func, store, has_call_hook) =
+                            &*(state as *const _ as *const HostState<F>);
+                        panic::catch_unwind(AssertUnwindSafe(|| -> Result<R, Trap> {
+                            // Most stores have no call hook, so don't pay for
+                            // upgrading the weak reference to the store on
+                            // every call unless there's a hook to run.
+                            let inner = if has_call_hook.load(Relaxed) {
+                                let inner = store.upgrade().unwrap();
+                                inner.invoke_call_hook(CallHook::CallingHost)?;
+                                Some(inner)
+                            } else {
+                                None
+                            };
+                            let ret = func(
                                 Caller { store, caller_vmctx },
                                 $($args,)*
-                            )
+                            );
+                            if let Some(inner) = inner {
+                                inner.invoke_call_hook(CallHook::ReturningFromHost)?;
+                            }
+                            Ok(ret)
                         }))
                     };
                     match ret {
-                        Ok(ret) => ret.into_abi(),
+                        Ok(Ok(ret)) => ret.into_abi(),
+                        Ok(Err(trap)) => raise_call_hook_trap(trap),
                         Err(panic) => wasmtime_runtime::resume_panic(panic),
                     }
                 }
@@ -1205,7 +1398,7 @@ macro_rules! impl_into_func {
                         ),
                         trampoline,
                         store,
-                        Box::new((self, store_weak)),
+                        Box::new((self, store_weak, store.has_call_hook())),
                     )
                     .expect("failed to generate export")
                 };
@@ -1215,6 +1408,19 @@ macro_rules! impl_into_func {
                     trampoline,
                 }
             }
+
+            fn into_func_factory(self) -> Box<dyn Fn(&Store) -> Func>
+            where
+                Self: Sync,
+            {
+                let func = Arc::new(self);
+                Box::new(move |store: &Store| {
+                    let func = func.clone();
+                    Func::wrap(store, move |caller: Caller<'_>, $($args:$args),*| {
+                        (*func)(caller, $($args),*)
+                    })
+                })
+            }
         }
     )*)
 }
@@ -0,0 +1,668 @@
+use crate::frame_info::FRAME_INFO;
+use crate::instance::ModuleInstanceState;
+use crate::{Store, Trap, ValType};
+use anyhow::{bail, Result};
+use backtrace::Backtrace;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt::{self, Write as _};
+use std::io::{self, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::rc::{Rc, Weak};
+use std::slice;
+use wasmtime_environ::entity::EntityRef;
+use wasmtime_environ::wasm::{GlobalIndex, MemoryIndex};
+use wasmtime_environ::EntityIndex;
+use wasmtime_runtime::{DebugLocals, DebugValue, Export, InstanceHandle, VMContext};
+
+/// A debug server speaking the GDB remote serial protocol for the
+/// WebAssembly running in a [`Store`].
+///
+/// Rather than debugging the native code wasm is compiled to, the stub
+/// presents the wasm itself to the debugger: breakpoints are set at offsets
+/// in a module's code, stepping advances one wasm instruction at a time, and
+/// backtraces, locals, globals and linear memory are those of the wasm
+/// program. This requires [`Config::guest_debug`](crate::Config::guest_debug)
+/// to be enabled so that compiled code reports each instruction as it's
+/// executed.
+///
+/// Wasm isn't a target GDB itself knows about, so only LLDB can debug
+/// through the stub: it implements the wasm extensions to the protocol
+/// understood by LLDB (`process connect --plugin wasm
+/// connect://localhost:<port>`).
+///
+/// Addresses follow LLDB's wasm address space: the top two bits are `0` for
+/// linear memory or `1` for a module's code, bits 32 to 61 identify the
+/// instance, numbered from 1 in the order the debugger first sees them, and
+/// the low 32 bits are the offset into the memory or module binary.
+///
+/// Execution starts out stopped before the first wasm instruction so that
+/// breakpoints can be set before anything runs.
+pub struct GdbStub {
+    state: Rc<RefCell<StubState>>,
+}
+
+impl GdbStub {
+    /// Waits for a debugger to connect on `addr`, then attaches it to
+    /// `store` as with [`GdbStub::attach`].
+    pub fn listen(store: &Store, addr: impl ToSocketAddrs) -> Result<GdbStub> {
+        let listener = TcpListener::bind(addr)?;
+        let (stream, _) = listener.accept()?;
+        GdbStub::attach(store, stream)
+    }
+
+    /// Serves the debugger connected through `stream` whenever the wasm in
+    /// `store` stops.
+    ///
+    /// This replaces any other debugger attached to the store.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store's engine wasn't configured with
+    /// [`Config::guest_debug`](crate::Config::guest_debug).
+    pub fn attach(store: &Store, stream: TcpStream) -> Result<GdbStub> {
+        if !store.engine().config().tunables.guest_debug {
+            bail!("debugging wasm requires `Config::guest_debug` to be enabled");
+        }
+        let state = Rc::new(RefCell::new(StubState {
+            conn: Some(Connection::new(stream)?),
+            breakpoints: HashSet::new(),
+            stepping: true,
+            running: false,
+            instances: Vec::new(),
+        }));
+        let hook_state = state.clone();
+        store.set_debug_hook(move |store, vmctx, offset, locals| {
+            hook_state
+                .borrow_mut()
+                .before_instruction(store, vmctx, offset, locals)
+        });
+        Ok(GdbStub { state })
+    }
+
+    /// Tells the debugger that the program has finished and disconnects it.
+    ///
+    /// `status` is the program's exit status, or `None` if it was terminated
+    /// by a trap.
+    pub fn report_exit(&self, status: Option<i32>) {
+        let mut state = self.state.borrow_mut();
+        if let Some(mut conn) = state.conn.take() {
+            let reply = match status {
+                Some(status) => format!("W{:02x}", status as u8),
+                None => "X05".to_string(),
+            };
+            let _ = conn.send(&reply);
+        }
+    }
+}
+
+impl fmt::Debug for GdbStub {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state.borrow();
+        f.debug_struct("GdbStub")
+            .field("connected", &state.conn.is_some())
+            .field("breakpoints", &state.breakpoints.len())
+            .finish()
+    }
+}
+
+const ADDRESS_MEMORY: u64 = 0;
+const ADDRESS_CODE: u64 = 1;
+
+fn code_address(id: u32, offset: u32) -> u64 {
+    (ADDRESS_CODE << 62) | (u64::from(id) << 32) | u64::from(offset)
+}
+
+/// Splits an address into its kind, instance id and offset.
+fn split_address(addr: u64) -> (u64, u32, u32) {
+    (addr >> 62, ((addr >> 32) & 0x3fff_ffff) as u32, addr as u32)
+}
+
+struct StubState {
+    /// The debugger, until it detaches or the connection fails.
+    conn: Option<Connection>,
+    /// Breakpoints as instance id and module offset, where an id of 0 matches
+    /// every instance.
+    breakpoints: HashSet<(u32, u32)>,
+    /// Whether to stop before the next instruction.
+    stepping: bool,
+    /// Whether the debugger resumed execution and is waiting to hear about
+    /// the next stop.
+    running: bool,
+    /// The token of each instance seen so far, indexed by id - 1. Holding on
+    /// to the tokens keeps ids from being reused by later instances.
+    instances: Vec<Weak<()>>,
+}
+
+/// Where execution is stopped.
+struct Stop<'a> {
+    store: &'a Store,
+    id: u32,
+    offset: u32,
+    locals: &'a DebugLocals<'a>,
+}
+
+enum Action {
+    Reply(String),
+    Continue,
+    Step,
+    Detach,
+    Kill,
+}
+
+impl StubState {
+    fn before_instruction(
+        &mut self,
+        store: &Store,
+        vmctx: *mut VMContext,
+        offset: u32,
+        locals: &DebugLocals<'_>,
+    ) -> Result<(), Trap> {
+        if self.conn.is_none() {
+            return Ok(());
+        }
+        let id = self.instance_id(store.instance_token(vmctx));
+        if !self.stepping
+            && !self.breakpoints.contains(&(id, offset))
+            && !self.breakpoints.contains(&(0, offset))
+        {
+            return Ok(());
+        }
+        self.stepping = false;
+
+        let stop = Stop {
+            store,
+            id,
+            offset,
+            locals,
+        };
+        match self.serve(&stop) {
+            Ok(true) => Ok(()),
+            Ok(false) => {
+                self.conn = None;
+                Err(Trap::new("killed by debugger"))
+            }
+            // Losing the debugger shouldn't take the program down with it.
+            Err(_) => {
+                self.detach();
+                Ok(())
+            }
+        }
+    }
+
+    /// Answers the debugger's requests until it resumes execution, returning
+    /// whether execution should go on at all.
+    fn serve(&mut self, stop: &Stop<'_>) -> io::Result<bool> {
+        if self.running {
+            self.running = false;
+            let reply = self.stop_reply(stop);
+            self.conn().send(&reply)?;
+        }
+        loop {
+            let packet = match self.conn().recv()? {
+                Some(packet) => packet,
+                None => {
+                    self.detach();
+                    return Ok(true);
+                }
+            };
+            match self.handle(stop, &packet) {
+                Action::Reply(reply) => {
+                    self.conn().send(&reply)?;
+                    // The reply to this is the last one to be acknowledged.
+                    if packet == "QStartNoAckMode" {
+                        self.conn().ack = false;
+                    }
+                }
+                Action::Continue => {
+                    self.running = true;
+                    return Ok(true);
+                }
+                Action::Step => {
+                    self.running = true;
+                    self.stepping = true;
+                    return Ok(true);
+                }
+                Action::Detach => {
+                    self.conn().send("OK")?;
+                    self.detach();
+                    return Ok(true);
+                }
+                Action::Kill => {
+                    self.conn().send("X09")?;
+                    return Ok(false);
+                }
+            }
+        }
+    }
+
+    fn conn(&mut self) -> &mut Connection {
+        self.conn.as_mut().unwrap()
+    }
+
+    fn detach(&mut self) {
+        self.conn = None;
+        self.breakpoints.clear();
+        self.stepping = false;
+    }
+
+    fn handle(&mut self, stop: &Stop<'_>, packet: &str) -> Action {
+        let reply = match packet {
+            "?" => self.stop_reply(stop),
+            "QStartNoAckMode" => "OK".to_string(),
+            "qAttached" => "1".to_string(),
+            "qC" => "QC1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            "qHostInfo" => format!("triple:{};endian:little;ptrsize:4;", hex(TRIPLE.as_bytes())),
+            "qProcessInfo" => format!(
+                "pid:1;parent-pid:1;triple:{};endian:little;ptrsize:4;",
+                hex(TRIPLE.as_bytes())
+            ),
+            "qRegisterInfo0" => "name:pc;alt-name:pc;bitsize:64;offset:0;encoding:uint;\
+                                 format:hex;set:General Purpose Registers;gcc:16;dwarf:16;\
+                                 generic:pc;"
+                .to_string(),
+            "g" | "p0" => hex(&code_address(stop.id, stop.offset).to_le_bytes()),
+            "vCont?" => "vCont;c;C;s;S".to_string(),
+            "c" => return Action::Continue,
+            "s" => return Action::Step,
+            "D" => return Action::Detach,
+            "k" => return Action::Kill,
+            _ if packet.starts_with("qSupported") => {
+                "PacketSize=1000;QStartNoAckMode+;qXfer:libraries:read+".to_string()
+            }
+            _ if packet.starts_with('H') => "OK".to_string(),
+            _ if packet.starts_with("qRegisterInfo") || packet.starts_with('p') => {
+                "E45".to_string()
+            }
+            _ if packet.starts_with("vCont;") => {
+                // There's a single thread, so only the first action matters.
+                return match packet[6..].chars().next() {
+                    Some('c') | Some('C') => Action::Continue,
+                    Some('s') | Some('S') => Action::Step,
+                    _ => Action::Reply("E01".to_string()),
+                };
+            }
+            _ if packet.starts_with("vKill") => return Action::Kill,
+            _ if packet.starts_with("qWasmCallStack") => {
+                let mut reply = String::new();
+                for (id, offset) in self.call_stack(stop) {
+                    reply.push_str(&hex(&code_address(id, offset).to_le_bytes()));
+                }
+                reply
+            }
+            _ if packet.starts_with("qWasmLocal:") => self
+                .wasm_local(stop, &packet[11..])
+                .unwrap_or_else(|| "E03".to_string()),
+            _ if packet.starts_with("qWasmGlobal:") => self
+                .wasm_global(stop, &packet[12..])
+                .unwrap_or_else(|| "E03".to_string()),
+            _ if packet.starts_with("qWasmMem:") => self
+                .wasm_mem(stop, &packet[9..])
+                .unwrap_or_else(|| "E03".to_string()),
+            _ if packet.starts_with("qXfer:libraries:read::") => self
+                .libraries(stop, &packet[22..])
+                .unwrap_or_else(|| "E01".to_string()),
+            _ if packet.starts_with('m') => self
+                .read_memory(stop, &packet[1..])
+                .unwrap_or_else(|| "E03".to_string()),
+            _ if packet.starts_with("Z0,") || packet.starts_with("z0,") => {
+                match parse_breakpoint(&packet[3..]) {
+                    Some(bp) => {
+                        if packet.starts_with('Z') {
+                            self.breakpoints.insert(bp);
+                        } else {
+                            self.breakpoints.remove(&bp);
+                        }
+                        "OK".to_string()
+                    }
+                    None => "E01".to_string(),
+                }
+            }
+            // An empty reply tells the debugger a packet isn't supported.
+            _ => String::new(),
+        };
+        Action::Reply(reply)
+    }
+
+    fn stop_reply(&self, stop: &Stop<'_>) -> String {
+        let pc = code_address(stop.id, stop.offset);
+        format!(
+            "T05thread:1;thread-pcs:{:x};00:{};reason:trace;",
+            pc,
+            hex(&pc.to_le_bytes())
+        )
+    }
+
+    /// Returns the id for the instance with `token`, assigning the next one if
+    /// it's new.
+    fn instance_id(&mut self, token: Weak<()>) -> u32 {
+        match self.instances.iter().position(|t| t.ptr_eq(&token)) {
+            Some(i) => i as u32 + 1,
+            None => {
+                self.instances.push(token);
+                self.instances.len() as u32
+            }
+        }
+    }
+
+    /// Assigns ids to all the store's instances, returning them along with
+    /// each instance's module.
+    fn all_instances(&mut self, store: &Store) -> Vec<(u32, usize)> {
+        let mut tokens = Vec::new();
+        store.for_each_wasm_instance(|handle| {
+            tokens.push((store.instance_token(handle.vmctx_ptr()), handle.module().id))
+        });
+        tokens
+            .into_iter()
+            .map(|(token, module)| (self.instance_id(token), module))
+            .collect()
+    }
+
+    fn with_instance<R>(
+        &self,
+        store: &Store,
+        id: u32,
+        f: impl FnOnce(&InstanceHandle) -> R,
+    ) -> Option<R> {
+        let token = self.instances.get((id as usize).checked_sub(1)?)?;
+        let mut f = Some(f);
+        let mut result = None;
+        store.for_each_wasm_instance(|handle| {
+            if store.instance_token(handle.vmctx_ptr()).ptr_eq(token) {
+                result = f.take().map(|f| f(handle));
+            }
+        });
+        result
+    }
+
+    /// Returns the instance id and module offset of each wasm frame on the
+    /// stack, innermost first.
+    fn call_stack(&mut self, stop: &Stop<'_>) -> Vec<(u32, u32)> {
+        let mut stack = vec![(stop.id, stop.offset)];
+        let instances = self.all_instances(stop.store);
+        let trace = Backtrace::new_unresolved();
+        let info = FRAME_INFO.read().unwrap();
+        let frames = trace
+            .frames()
+            .iter()
+            .filter(|frame| !frame.ip().is_null())
+            .filter_map(|frame| info.lookup_frame_info(frame.ip() as usize - 1));
+        // The innermost wasm frame is the one which called into the stub, and
+        // is already accounted for by `stop`. As with core dumps, frames are
+        // attributed to the most recent instance of their module.
+        for frame in frames.skip(1) {
+            if let Some((id, _)) = instances
+                .iter()
+                .rev()
+                .find(|(_, module)| *module == frame.module_id())
+            {
+                stack.push((*id, frame.module_offset() as u32));
+            }
+        }
+        stack
+    }
+
+    /// Returns the id of the instance executing frame `frame`.
+    fn frame_instance(&mut self, stop: &Stop<'_>, frame: &str) -> Option<u32> {
+        let frame: usize = frame.parse().ok()?;
+        if frame == 0 {
+            return Some(stop.id);
+        }
+        self.call_stack(stop).get(frame).map(|(id, _)| *id)
+    }
+
+    fn wasm_local(&mut self, stop: &Stop<'_>, args: &str) -> Option<String> {
+        let mut args = args.split(';');
+        let frame = args.next()?;
+        let index: usize = args.next()?.parse().ok()?;
+        // Only the stopped function's locals are known.
+        if frame != "0" {
+            return None;
+        }
+        Some(match stop.locals.get(index)? {
+            DebugValue::I32(v) => hex(&v.to_le_bytes()),
+            DebugValue::I64(v) => hex(&v.to_le_bytes()),
+            DebugValue::F32(v) => hex(&v.to_le_bytes()),
+            DebugValue::F64(v) => hex(&v.to_le_bytes()),
+            DebugValue::V128(v) => hex(&v.to_le_bytes()),
+        })
+    }
+
+    fn wasm_global(&mut self, stop: &Stop<'_>, args: &str) -> Option<String> {
+        let mut args = args.split(';');
+        let id = self.frame_instance(stop, args.next()?)?;
+        let index: usize = args.next()?.parse().ok()?;
+        self.with_instance(stop.store, id, |handle| {
+            if index >= handle.module().local.globals.len() {
+                return None;
+            }
+            let export =
+                handle.lookup_by_declaration(&EntityIndex::Global(GlobalIndex::new(index)));
+            let global = match export {
+                Export::Global(g) => g,
+                _ => unreachable!(),
+            };
+            let size = match ValType::from_wasm_type(&global.global.wasm_ty)? {
+                ValType::I32 | ValType::F32 => 4,
+                ValType::I64 | ValType::F64 => 8,
+                ValType::V128 => 16,
+                ValType::ExternRef | ValType::FuncRef => return None,
+            };
+            let bytes = unsafe { slice::from_raw_parts(global.definition as *const u8, size) };
+            Some(hex(bytes))
+        })?
+    }
+
+    fn wasm_mem(&mut self, stop: &Stop<'_>, args: &str) -> Option<String> {
+        let mut args = args.split(';');
+        let id = self.frame_instance(stop, args.next()?)?;
+        let addr = u32::from_str_radix(args.next()?, 16).ok()?;
+        let len = usize::from_str_radix(args.next()?, 16).ok()?;
+        self.read_linear_memory(stop.store, id, addr, len)
+    }
+
+    fn read_memory(&mut self, stop: &Stop<'_>, args: &str) -> Option<String> {
+        let mut args = args.split(',');
+        let addr = u64::from_str_radix(args.next()?, 16).ok()?;
+        let len = usize::from_str_radix(args.next()?, 16).ok()?;
+        let (kind, id, offset) = split_address(addr);
+        match kind {
+            ADDRESS_MEMORY => self.read_linear_memory(stop.store, id, offset, len),
+            ADDRESS_CODE => self.with_instance(stop.store, id, |handle| {
+                let state = handle.host_state().downcast_ref::<ModuleInstanceState>()?;
+                let wasm = state.module.compiled_module().wasm();
+                let start = (offset as usize).min(wasm.len());
+                let end = start.saturating_add(len).min(wasm.len());
+                if start == end && len > 0 {
+                    return None;
+                }
+                Some(hex(&wasm[start..end]))
+            })?,
+            _ => None,
+        }
+    }
+
+    /// Reads up to `len` bytes at `addr` in the first linear memory of
+    /// instance `id`.
+    fn read_linear_memory(&self, store: &Store, id: u32, addr: u32, len: usize) -> Option<String> {
+        self.with_instance(store, id, |handle| {
+            if handle.module().local.memory_plans.is_empty() {
+                return None;
+            }
+            let export = handle.lookup_by_declaration(&EntityIndex::Memory(MemoryIndex::new(0)));
+            let memory = match export {
+                Export::Memory(m) => m,
+                _ => unreachable!(),
+            };
+            let data = unsafe {
+                let definition = &*memory.definition;
+                slice::from_raw_parts(definition.base, definition.current_length)
+            };
+            let start = (addr as usize).min(data.len());
+            let end = start.saturating_add(len).min(data.len());
+            if start == end && len > 0 {
+                return None;
+            }
+            Some(hex(&data[start..end]))
+        })?
+    }
+
+    /// Lists each instance as a library whose code is loaded at its code
+    /// address, returning the part of the list requested by `range`.
+    fn libraries(&mut self, stop: &Stop<'_>, range: &str) -> Option<String> {
+        let mut range = range.split(',');
+        let offset = usize::from_str_radix(range.next()?, 16).ok()?;
+        let len = usize::from_str_radix(range.next()?, 16).ok()?;
+
+        let mut xml = "<library-list>".to_string();
+        for (id, _) in self.all_instances(stop.store) {
+            let name = self
+                .with_instance(stop.store, id, |handle| handle.module().name.clone())
+                .flatten()
+                .unwrap_or_else(|| format!("module{}.wasm", id));
+            write!(
+                xml,
+                "<library name=\"{}\"><section address=\"0x{:x}\"/></library>",
+                xml_escape(&name),
+                code_address(id, 0)
+            )
+            .unwrap();
+        }
+        xml.push_str("</library-list>");
+
+        let start = offset.min(xml.len());
+        let end = start.saturating_add(len).min(xml.len());
+        let marker = if end == xml.len() { 'l' } else { 'm' };
+        Some(format!("{}{}", marker, &xml[start..end]))
+    }
+}
+
+const TRIPLE: &str = "wasm32-unknown-unknown-wasm";
+
+fn parse_breakpoint(args: &str) -> Option<(u32, u32)> {
+    let addr = u64::from_str_radix(args.split(',').next()?, 16).ok()?;
+    let (kind, id, offset) = split_address(addr);
+    if kind != ADDRESS_CODE {
+        return None;
+    }
+    Some((id, offset))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        write!(s, "{:02x}", b).unwrap();
+    }
+    s
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The framing of packets as `$<data>#<checksum>`, acknowledged with `+`
+/// until the debugger asks for no-ack mode.
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    ack: bool,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> io::Result<Connection> {
+        stream.set_nodelay(true)?;
+        Ok(Connection {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            ack: true,
+        })
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+        match self.reader.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    /// Reads the next packet, or `None` once the debugger disconnects.
+    fn recv(&mut self) -> io::Result<Option<String>> {
+        loop {
+            // Skip acknowledgements and interrupt requests, which don't mean
+            // anything while stopped.
+            loop {
+                match self.read_byte()? {
+                    Some(b'$') => break,
+                    Some(_) => {}
+                    None => return Ok(None),
+                }
+            }
+            let mut data = Vec::new();
+            let mut sum = 0u8;
+            loop {
+                match self.read_byte()? {
+                    Some(b'#') => break,
+                    Some(b) => {
+                        sum = sum.wrapping_add(b);
+                        data.push(b);
+                    }
+                    None => return Ok(None),
+                }
+            }
+            let mut checksum = [0; 2];
+            self.reader.read_exact(&mut checksum)?;
+            let valid = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|c| u8::from_str_radix(c, 16).ok())
+                == Some(sum);
+            if self.ack {
+                self.writer.write_all(if valid { b"+" } else { b"-" })?;
+            }
+            if !valid {
+                continue;
+            }
+            return Ok(Some(String::from_utf8_lossy(&unescape(&data)).into_owned()));
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let mut packet = vec![b'$'];
+        let mut sum = 0u8;
+        let mut push = |b: u8| {
+            sum = sum.wrapping_add(b);
+            packet.push(b);
+        };
+        for &b in data.as_bytes() {
+            match b {
+                b'$' | b'#' | b'}' | b'*' => {
+                    push(b'}');
+                    push(b ^ 0x20);
+                }
+                _ => push(b),
+            }
+        }
+        packet.extend_from_slice(format!("#{:02x}", sum).as_bytes());
+        self.writer.write_all(&packet)
+    }
+}
+
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut bytes = data.iter();
+    while let Some(&b) = bytes.next() {
+        if b == b'}' {
+            if let Some(&b) = bytes.next() {
+                out.push(b ^ 0x20);
+            }
+        } else {
+            out.push(b);
+        }
+    }
+    out
+}
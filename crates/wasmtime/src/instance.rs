@@ -1,14 +1,24 @@
+use crate::frame_info::GlobalFrameInfoRegistration;
+use crate::linker::Definition;
 use crate::trampoline::StoreInstanceHandle;
-use crate::{Engine, Export, Extern, Func, Global, Memory, Module, Store, Table, Trap};
-use anyhow::{bail, Error, Result};
+use crate::{Coverage, Engine, Export, Extern, Func, Global, Memory, Module, Store, Table, Trap};
+use anyhow::{anyhow, bail, Error, Result};
 use std::any::Any;
 use std::mem;
+use std::sync::Arc;
 use wasmtime_environ::EntityIndex;
 use wasmtime_jit::{CompiledModule, Resolver};
 use wasmtime_runtime::{
     InstantiationError, StackMapRegistry, VMContext, VMExternRefActivationsTable, VMFunctionBody,
 };
 
+/// The host state of each instance of a wasm module, keeping its module
+/// reachable from the runtime's `InstanceHandle`.
+pub(crate) struct ModuleInstanceState {
+    pub(crate) module: Module,
+    _frame_info_registration: Option<Arc<GlobalFrameInfoRegistration>>,
+}
+
 struct SimpleResolver<'a> {
     imports: &'a [Extern],
 }
@@ -35,6 +45,13 @@ fn instantiate(
         }
     }
 
+    // Data segments may be copied into any memory in the store, including
+    // imported ones, so instantiation is off limits while the host holds a
+    // mutable borrow of one, just like calling into wasm.
+    if store.any_memory_borrowed() {
+        bail!("cannot instantiate a module while a memory is mutably borrowed");
+    }
+
     if imports.len() != compiled_module.module().imports.len() {
         bail!(
             "wrong number of imports provided, {} != {}",
@@ -43,18 +60,31 @@ fn instantiate(
         );
     }
 
+    store.bump_resource_counts(compiled_module.module())?;
+
     let mut resolver = SimpleResolver { imports };
     let config = store.engine().config();
     let instance = unsafe {
-        let instance = compiled_module.instantiate(
+        let instance = match compiled_module.instantiate(
             &mut resolver,
             &mut store.signatures_mut(),
-            config.memory_creator.as_ref().map(|a| a as _),
+            store.engine().allocator(),
             store.interrupts().clone(),
             host,
             &**store.externref_activations_table() as *const VMExternRefActivationsTable as *mut _,
             &**store.stack_map_registry() as *const StackMapRegistry as *mut _,
-        )?;
+            store.limiter().clone(),
+            store.epoch_deadline_handler(),
+            store.debug_handler(),
+        ) {
+            Ok(instance) => instance,
+            Err(e) => {
+                // Nothing was allocated, so it doesn't count against the
+                // store's limits.
+                store.unbump_resource_counts(compiled_module.module());
+                return Err(e.into());
+            }
+        };
 
         // After we've created the `InstanceHandle` we still need to run
         // initialization to set up data/elements/etc. We do this after adding
@@ -63,7 +93,7 @@ fn instantiate(
         // initializers may have run which placed elements into other instance's
         // tables. This means that from this point on, regardless of whether
         // initialization is successful, we need to keep the instance alive.
-        let instance = store.add_instance(instance);
+        let instance = store.add_instance(instance, false);
         instance
             .initialize(
                 config.validating_config.operator_config.enable_bulk_memory,
@@ -71,7 +101,9 @@ fn instantiate(
             )
             .map_err(|e| -> Error {
                 match e {
-                    InstantiationError::Trap(trap) => Trap::from_runtime(trap).into(),
+                    InstantiationError::Trap(trap) => {
+                        Trap::from_runtime(trap).with_coredump(store).into()
+                    }
                     other => other.into(),
                 }
             })?;
@@ -193,7 +225,10 @@ impl Instance {
             let frame_info_registration = module.register_frame_info();
             store.register_jit_code(module.compiled_module().jit_code_ranges());
             store.register_stack_maps(&module);
-            frame_info_registration
+            ModuleInstanceState {
+                module: module.clone(),
+                _frame_info_registration: frame_info_registration,
+            }
         });
 
         let handle = instantiate(store, module.compiled_module(), imports, host_info)?;
@@ -205,6 +240,33 @@ impl Instance {
         })
     }
 
+    /// Same as [`Instance::new`], except for usage in [asynchronous stores].
+    ///
+    /// The instance's `start` function, if any, is executed on a separate
+    /// native stack which allows it to call async host functions created with
+    /// [`Func::new_async`]. While such a function's future is pending the
+    /// returned future will also be pending.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if it is called with a store that isn't
+    /// configured for async support.
+    ///
+    /// [asynchronous stores]: crate::Config::async_support
+    pub async fn new_async(
+        store: &Store,
+        module: &Module,
+        imports: &[Extern],
+    ) -> Result<Instance, Error> {
+        assert!(
+            store.async_support(),
+            "cannot use `new_async` without enabling async support in the config"
+        );
+        store
+            .on_fiber(|| Instance::new(store, module, imports))
+            .await?
+    }
+
     /// Returns the associated [`Store`] that this `Instance` is compiled into.
     ///
     /// This is the [`Store`] that generally serves as a sort of global cache
@@ -266,4 +328,104 @@ impl Instance {
     pub fn get_global(&self, name: &str) -> Option<Global> {
         self.get_export(name)?.into_global()
     }
+
+    /// Returns how many times each basic block of this instance's code has
+    /// been executed so far.
+    ///
+    /// Returns `None` unless [`Config::coverage`](crate::Config::coverage)
+    /// was enabled.
+    pub fn coverage(&self) -> Option<Coverage> {
+        if !self.store.engine().config().tunables.coverage {
+            return None;
+        }
+        Some(Coverage::new(&self.module, self.handle.coverage_counters()))
+    }
+}
+
+/// A [`Module`] whose imports have already been resolved, ready to be
+/// instantiated many times.
+///
+/// This is created with [`Linker::instantiate_pre`](crate::Linker::instantiate_pre),
+/// which looks up and type-checks each of the module's imports once. Each call
+/// to [`InstancePre::instantiate`] then only performs the work specific to a
+/// new instance.
+///
+/// An `InstancePre` isn't tied to the linker's [`Store`]: it can instantiate
+/// its module in any store of the same [`Engine`], creating host functions
+/// defined with [`Linker::shared_func`](crate::Linker::shared_func) in each
+/// of them. Other items, such as globals, memories, tables and exports of
+/// instances, only exist within the linker's store and can't be imported in
+/// other stores.
+#[derive(Clone)]
+pub struct InstancePre {
+    module: Module,
+    imports: Vec<Definition>,
+}
+
+impl InstancePre {
+    pub(crate) fn new(module: &Module, imports: Vec<Definition>) -> InstancePre {
+        InstancePre {
+            module: module.clone(),
+            imports,
+        }
+    }
+
+    /// Returns the [`Module`] this will instantiate.
+    pub fn module(&self) -> &Module {
+        &self.module
+    }
+
+    /// Instantiates the module in `store` with its pre-resolved imports.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `store` belongs to a different [`Engine`] than the
+    /// module, or if an import only exists within the store of the linker
+    /// this was created with and `store` is another one.
+    ///
+    /// Instantiation can also fail just like [`Instance::new`] does, for
+    /// example when the start function traps or resource limits are
+    /// exceeded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an import was defined with
+    /// [`Linker::shared_func_async`](crate::Linker::shared_func_async) and
+    /// `store` isn't configured for async support.
+    pub fn instantiate(&self, store: &Store) -> Result<Instance> {
+        let imports = self.imports(store)?;
+        Instance::new(store, &self.module, &imports)
+    }
+
+    /// Same as [`InstancePre::instantiate`], except for usage in
+    /// [asynchronous stores], see [`Instance::new_async`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `store` isn't configured for async support.
+    ///
+    /// [asynchronous stores]: crate::Config::async_support
+    pub async fn instantiate_async(&self, store: &Store) -> Result<Instance> {
+        let imports = self.imports(store)?;
+        Instance::new_async(store, &self.module, &imports).await
+    }
+
+    fn imports(&self, store: &Store) -> Result<Vec<Extern>> {
+        if !Engine::same(store.engine(), self.module.engine()) {
+            bail!("cross-`Engine` instantiation is not currently supported");
+        }
+        self.module
+            .imports()
+            .zip(&self.imports)
+            .map(|(import, definition)| {
+                definition.to_extern(store).ok_or_else(|| {
+                    anyhow!(
+                        "import `{}::{}` was defined in a different store",
+                        import.module(),
+                        import.name()
+                    )
+                })
+            })
+            .collect()
+    }
 }
@@ -10,25 +10,37 @@
 #![doc(test(attr(deny(warnings))))]
 #![doc(test(attr(allow(dead_code, unused_variables, unused_mut))))]
 
+mod coredump;
+mod coverage;
+mod encode;
 mod externals;
 mod frame_info;
 mod func;
+mod gdbstub;
 mod instance;
+mod limits;
 mod linker;
 mod module;
+mod profiling;
 mod r#ref;
 mod runtime;
+mod snapshot;
 mod trampoline;
 mod trap;
 mod types;
 mod values;
 
+pub use crate::coredump::{CoreDumpFrame, WasmCoreDump};
+pub use crate::coverage::{Coverage, CoverageBlock};
 pub use crate::externals::*;
-pub use crate::frame_info::FrameInfo;
+pub use crate::frame_info::{FrameInfo, FrameSymbol};
 pub use crate::func::*;
-pub use crate::instance::Instance;
+pub use crate::gdbstub::GdbStub;
+pub use crate::instance::{Instance, InstancePre};
+pub use crate::limits::*;
 pub use crate::linker::*;
 pub use crate::module::Module;
+pub use crate::profiling::GuestProfiler;
 pub use crate::r#ref::ExternRef;
 pub use crate::runtime::*;
 pub use crate::trap::Trap;
@@ -0,0 +1,230 @@
+use wasmtime_runtime::{DEFAULT_INSTANCE_LIMIT, DEFAULT_MEMORY_LIMIT, DEFAULT_TABLE_LIMIT};
+
+/// Used by hosts to limit resource consumption of instances.
+///
+/// A [`Store`](crate::Store) can be created with a resource limiter so that
+/// hosts can take into account non-WebAssembly resource usage to determine if
+/// a linear memory or table should grow.
+///
+/// The limiter is consulted whenever a linear memory or table is created or
+/// grown, both by WebAssembly instructions such as `memory.grow` and by host
+/// APIs such as [`Memory::grow`](crate::Memory::grow).
+pub trait ResourceLimiter {
+    /// Notifies the resource limiter that an instance's linear memory has been
+    /// requested to grow.
+    ///
+    /// * `current` is the current size of the linear memory in WebAssembly
+    ///   page units.
+    /// * `desired` is the desired size of the linear memory in WebAssembly
+    ///   page units.
+    /// * `maximum` is the linear memory's declared maximum, also in
+    ///   WebAssembly page units. A value of `None` indicates that the linear
+    ///   memory is unbounded.
+    ///
+    /// Creating a linear memory is reported as growing it from zero pages to
+    /// its minimum size.
+    ///
+    /// This function should return `true` to indicate that the growing
+    /// operation is permitted or `false` if not permitted. Returning `false`
+    /// makes `memory.grow` return -1, and makes instantiation fail if the
+    /// memory was being created.
+    ///
+    /// Returning `true` when a maximum has been exceeded will have no effect
+    /// as the linear memory will not grow past its maximum.
+    fn memory_growing(&self, current: u32, desired: u32, maximum: Option<u32>) -> bool;
+
+    /// Notifies the resource limiter that an instance's table has been
+    /// requested to grow.
+    ///
+    /// * `current` is the current number of elements in the table.
+    /// * `desired` is the desired number of elements in the table.
+    /// * `maximum` is the table's declared maximum. A value of `None`
+    ///   indicates that the table is unbounded.
+    ///
+    /// As with memories, creating a table is reported as growing it from zero
+    /// elements to its minimum size.
+    ///
+    /// This function should return `true` to indicate that the growing
+    /// operation is permitted or `false` if not permitted.
+    ///
+    /// Returning `true` when a maximum has been exceeded will have no effect
+    /// as the table will not grow past its maximum.
+    fn table_growing(&self, current: u32, desired: u32, maximum: Option<u32>) -> bool;
+
+    /// The maximum number of instances that can be created for a
+    /// [`Store`](crate::Store).
+    ///
+    /// Module instantiation will fail if this limit is exceeded.
+    ///
+    /// This value defaults to 10,000.
+    fn instances(&self) -> usize {
+        DEFAULT_INSTANCE_LIMIT
+    }
+
+    /// The maximum number of tables that can be created for a
+    /// [`Store`](crate::Store).
+    ///
+    /// Module instantiation will fail if this limit is exceeded.
+    ///
+    /// This value defaults to 10,000.
+    fn tables(&self) -> usize {
+        DEFAULT_TABLE_LIMIT
+    }
+
+    /// The maximum number of linear memories that can be created for a
+    /// [`Store`](crate::Store).
+    ///
+    /// Module instantiation will fail if this limit is exceeded.
+    ///
+    /// This value defaults to 10,000.
+    fn memories(&self) -> usize {
+        DEFAULT_MEMORY_LIMIT
+    }
+}
+
+/// Adapts a `ResourceLimiter` from this crate to the runtime's trait of the
+/// same name.
+pub(crate) struct ResourceLimiterProxy<T>(pub T);
+
+impl<T: ResourceLimiter> wasmtime_runtime::ResourceLimiter for ResourceLimiterProxy<T> {
+    fn memory_growing(&self, current: u32, desired: u32, maximum: Option<u32>) -> bool {
+        self.0.memory_growing(current, desired, maximum)
+    }
+
+    fn table_growing(&self, current: u32, desired: u32, maximum: Option<u32>) -> bool {
+        self.0.table_growing(current, desired, maximum)
+    }
+
+    fn instances(&self) -> usize {
+        self.0.instances()
+    }
+
+    fn tables(&self) -> usize {
+        self.0.tables()
+    }
+
+    fn memories(&self) -> usize {
+        self.0.memories()
+    }
+}
+
+/// Used to build [`StoreLimits`].
+#[derive(Default)]
+pub struct StoreLimitsBuilder(StoreLimits);
+
+impl StoreLimitsBuilder {
+    /// Creates a new [`StoreLimitsBuilder`].
+    pub fn new() -> Self {
+        Self(StoreLimits::default())
+    }
+
+    /// The maximum number of WebAssembly pages a linear memory can grow to.
+    ///
+    /// Growing a linear memory beyond this limit will fail.
+    ///
+    /// By default, linear memory pages will not be limited.
+    pub fn memory_pages(mut self, limit: u32) -> Self {
+        self.0.memory_pages = Some(limit);
+        self
+    }
+
+    /// The maximum number of elements in a table.
+    ///
+    /// Growing a table beyond this limit will fail.
+    ///
+    /// By default, table elements will not be limited.
+    pub fn table_elements(mut self, limit: u32) -> Self {
+        self.0.table_elements = Some(limit);
+        self
+    }
+
+    /// The maximum number of instances that can be created for a
+    /// [`Store`](crate::Store).
+    ///
+    /// Module instantiation will fail if this limit is exceeded.
+    ///
+    /// This value defaults to 10,000.
+    pub fn instances(mut self, limit: usize) -> Self {
+        self.0.instances = limit;
+        self
+    }
+
+    /// The maximum number of tables that can be created for a
+    /// [`Store`](crate::Store).
+    ///
+    /// Module instantiation will fail if this limit is exceeded.
+    ///
+    /// This value defaults to 10,000.
+    pub fn tables(mut self, tables: usize) -> Self {
+        self.0.tables = tables;
+        self
+    }
+
+    /// The maximum number of linear memories that can be created for a
+    /// [`Store`](crate::Store).
+    ///
+    /// Module instantiation will fail if this limit is exceeded.
+    ///
+    /// This value defaults to 10,000.
+    pub fn memories(mut self, memories: usize) -> Self {
+        self.0.memories = memories;
+        self
+    }
+
+    /// Consumes this builder and returns the [`StoreLimits`].
+    pub fn build(self) -> StoreLimits {
+        self.0
+    }
+}
+
+/// Provides limits for a [`Store`](crate::Store).
+///
+/// This is a simple, size-based [`ResourceLimiter`] suitable for hosts that
+/// only need to cap how large memories and tables may get.
+pub struct StoreLimits {
+    memory_pages: Option<u32>,
+    table_elements: Option<u32>,
+    instances: usize,
+    tables: usize,
+    memories: usize,
+}
+
+impl Default for StoreLimits {
+    fn default() -> Self {
+        Self {
+            memory_pages: None,
+            table_elements: None,
+            instances: DEFAULT_INSTANCE_LIMIT,
+            tables: DEFAULT_TABLE_LIMIT,
+            memories: DEFAULT_MEMORY_LIMIT,
+        }
+    }
+}
+
+impl ResourceLimiter for StoreLimits {
+    fn memory_growing(&self, _current: u32, desired: u32, _maximum: Option<u32>) -> bool {
+        match self.memory_pages {
+            Some(limit) if desired > limit => false,
+            _ => true,
+        }
+    }
+
+    fn table_growing(&self, _current: u32, desired: u32, _maximum: Option<u32>) -> bool {
+        match self.table_elements {
+            Some(limit) if desired > limit => false,
+            _ => true,
+        }
+    }
+
+    fn instances(&self) -> usize {
+        self.instances
+    }
+
+    fn tables(&self) -> usize {
+        self.tables
+    }
+
+    fn memories(&self) -> usize {
+        self.memories
+    }
+}
@@ -1,11 +1,14 @@
 use crate::{
-    Extern, ExternType, Func, FuncType, GlobalType, ImportType, Instance, IntoFunc, Module, Store,
-    Trap,
+    Caller, Extern, ExternType, Func, FuncType, GlobalType, ImportType, Instance, InstancePre,
+    IntoFunc, Module, Store, Trap, Val,
 };
 use anyhow::{anyhow, bail, Context, Error, Result};
 use log::warn;
 use std::collections::hash_map::{Entry, HashMap};
+use std::future::Future;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+use std::sync::Arc;
 
 /// Structure used to link wasm modules/instances together.
 ///
@@ -41,10 +44,53 @@ pub struct Linker {
     store: Store,
     string2idx: HashMap<Rc<str>, usize>,
     strings: Vec<Rc<str>>,
-    map: HashMap<ImportKey, Extern>,
+    map: HashMap<ImportKey, Definition>,
     allow_shadowing: bool,
 }
 
+/// An item defined in a [`Linker`].
+#[derive(Clone)]
+pub(crate) enum Definition {
+    /// An item which only exists within the linker's store.
+    Extern(Extern),
+    /// A host function which can also be created in other stores.
+    HostFunc(Rc<HostFunc>),
+}
+
+/// A host function defined with [`Linker::shared_func`] or
+/// [`Linker::shared_func_async`].
+pub(crate) struct HostFunc {
+    /// Identifies this function among all others in every store's cache of
+    /// shared functions, see `Store::shared_func`.
+    id: usize,
+    /// The function within the linker's store.
+    func: Extern,
+    /// Creates the function again within another store.
+    new: Box<dyn Fn(&Store) -> Func>,
+}
+
+impl Definition {
+    /// Returns the item within the linker's store.
+    fn item(&self) -> &Extern {
+        match self {
+            Definition::Extern(item) => item,
+            Definition::HostFunc(func) => &func.func,
+        }
+    }
+
+    /// Returns the item within `store`, or `None` if it can't be used there.
+    pub(crate) fn to_extern(&self, store: &Store) -> Option<Extern> {
+        let item = self.item();
+        if item.comes_from_same_store(store) {
+            return Some(item.clone());
+        }
+        match self {
+            Definition::Extern(_) => None,
+            Definition::HostFunc(func) => Some(store.shared_func(func.id, &func.new)),
+        }
+    }
+}
+
 #[derive(Hash, PartialEq, Eq)]
 struct ImportKey {
     name: usize,
@@ -168,7 +214,21 @@ impl Linker {
         if !item.comes_from_same_store(&self.store) {
             bail!("all linker items must be from the same store");
         }
-        self.insert(module, name, item)?;
+        self.insert(module, name, Definition::Extern(item))?;
+        Ok(self)
+    }
+
+    fn define_host_func(
+        &mut self,
+        module: &str,
+        name: &str,
+        new: Box<dyn Fn(&Store) -> Func>,
+    ) -> Result<&mut Self> {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        let id = NEXT_ID.fetch_add(1, Relaxed);
+        let func = new(&self.store).into();
+        let func = HostFunc { id, func, new };
+        self.insert(module, name, Definition::HostFunc(Rc::new(func)))?;
         Ok(self)
     }
 
@@ -217,6 +277,120 @@ impl Linker {
         self._define(module, name, Func::wrap(&self.store, func).into())
     }
 
+    /// Creates a [`Func::new_async`]-style function named in this linker.
+    ///
+    /// For more information see [`Linker::func`] and [`Func::new_async`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if this linker's [`Store`] isn't configured for async support.
+    pub fn func_async<T, F>(
+        &mut self,
+        module: &str,
+        name: &str,
+        ty: FuncType,
+        data: T,
+        func: F,
+    ) -> Result<&mut Self>
+    where
+        T: 'static,
+        F: for<'a> Fn(
+                Caller<'a>,
+                &'a T,
+                &'a [Val],
+                &'a mut [Val],
+            ) -> Box<dyn Future<Output = Result<(), Trap>> + 'a>
+            + 'static,
+    {
+        let func = Func::new_async(&self.store, ty, data, func);
+        self._define(module, name, func.into())
+    }
+
+    /// Same as [`Linker::func`], except that the function isn't tied to this
+    /// linker's store.
+    ///
+    /// Items defined in a linker otherwise belong to its store. A function
+    /// defined this way can also be imported by an [`InstancePre`] created by
+    /// this linker in other stores of the same [`Engine`](crate::Engine), in
+    /// each of which it's created again the first time it's needed. Since
+    /// `func` is then shared by all of those stores, it must be `Sync`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `module` and `name` already identify an item
+    /// of the same type and if shadowing is disallowed, see [`Linker::func`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let store = Store::default();
+    /// let mut linker = Linker::new(&store);
+    /// linker.shared_func("host", "double", |x: i32| x * 2)?;
+    ///
+    /// let wat = r#"
+    ///     (module
+    ///         (import "host" "double" (func (param i32) (result i32)))
+    ///     )
+    /// "#;
+    /// let module = Module::new(store.engine(), wat)?;
+    /// let pre = linker.instantiate_pre(&module)?;
+    /// for _ in 0..10 {
+    ///     let store = Store::new(linker.store().engine());
+    ///     pre.instantiate(&store)?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn shared_func<Params, Args>(
+        &mut self,
+        module: &str,
+        name: &str,
+        func: impl IntoFunc<Params, Args> + Sync,
+    ) -> Result<&mut Self> {
+        self.define_host_func(module, name, func.into_func_factory())
+    }
+
+    /// Same as [`Linker::func_async`], except that the function isn't tied to
+    /// this linker's store, see [`Linker::shared_func`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if this linker's [`Store`] isn't configured for async support,
+    /// and when instantiating an [`InstancePre`] which imports this function
+    /// in another store which isn't.
+    pub fn shared_func_async<T, F>(
+        &mut self,
+        module: &str,
+        name: &str,
+        ty: FuncType,
+        data: T,
+        func: F,
+    ) -> Result<&mut Self>
+    where
+        T: Sync + 'static,
+        F: for<'a> Fn(
+                Caller<'a>,
+                &'a T,
+                &'a [Val],
+                &'a mut [Val],
+            ) -> Box<dyn Future<Output = Result<(), Trap>> + 'a>
+            + Sync
+            + 'static,
+    {
+        let shared = Arc::new((data, func));
+        let new = move |store: &Store| {
+            Func::new_async(
+                store,
+                ty.clone(),
+                shared.clone(),
+                |caller, shared, params, results| (shared.1)(caller, &shared.0, params, results),
+            )
+        };
+        self.define_host_func(module, name, Box::new(new))
+    }
+
     /// Convenience wrapper to define an entire [`Instance`] in this linker.
     ///
     /// This function is a convenience wrapper around [`Linker::define`] which
@@ -267,7 +441,8 @@ impl Linker {
             bail!("all linker items must be from the same store");
         }
         for export in instance.exports() {
-            self.insert(module_name, export.name(), export.into_extern())?;
+            let item = Definition::Extern(export.into_extern());
+            self.insert(module_name, export.name(), item)?;
         }
         Ok(self)
     }
@@ -399,35 +574,50 @@ impl Linker {
     fn command(&mut self, module_name: &str, module: &Module) -> Result<&mut Self> {
         for export in module.exports() {
             if let Some(func_ty) = export.ty().func() {
-                let imports = self.compute_imports(module)?;
-                let store = self.store.clone();
+                // The function lives in the same store as the imports, so it
+                // only holds on to them weakly to not keep the store alive.
+                let imports = self
+                    .compute_imports(module)?
+                    .iter()
+                    .map(Extern::downgrade)
+                    .collect::<Vec<_>>();
                 let module = module.clone();
                 let export_name = export.name().to_owned();
-                let func = Func::new(&self.store, func_ty.clone(), move |_, params, results| {
-                    // Create a new instance for this command execution.
-                    let instance = Instance::new(&store, &module, &imports)?;
-
-                    // `unwrap()` everything here because we know the instance contains a
-                    // function export with the given name and signature because we're
-                    // iterating over the module it was instantiated from.
-                    let command_results = instance
-                        .get_export(&export_name)
-                        .unwrap()
-                        .into_func()
-                        .unwrap()
-                        .call(params)
-                        .map_err(|error| error.downcast::<Trap>().unwrap())?;
-
-                    // Copy the return values into the output slice.
-                    for (result, command_result) in
-                        results.iter_mut().zip(command_results.into_vec())
-                    {
-                        *result = command_result;
-                    }
-
-                    Ok(())
-                });
-                self.insert(module_name, export.name(), func.into())?;
+                let func = Func::new(
+                    &self.store,
+                    func_ty.clone(),
+                    move |caller, params, results| {
+                        let store = caller.store();
+                        let imports = imports
+                            .iter()
+                            .map(|import| import.upgrade(&store))
+                            .collect::<Vec<_>>();
+
+                        // Create a new instance for this command execution.
+                        let instance = Instance::new(&store, &module, &imports)?;
+
+                        // `unwrap()` everything here because we know the instance contains a
+                        // function export with the given name and signature because we're
+                        // iterating over the module it was instantiated from.
+                        let command_results = instance
+                            .get_export(&export_name)
+                            .unwrap()
+                            .into_func()
+                            .unwrap()
+                            .call(params)
+                            .map_err(|error| error.downcast::<Trap>().unwrap())?;
+
+                        // Copy the return values into the output slice.
+                        for (result, command_result) in
+                            results.iter_mut().zip(command_results.into_vec())
+                        {
+                            *result = command_result;
+                        }
+
+                        Ok(())
+                    },
+                );
+                self.insert(module_name, export.name(), Definition::Extern(func.into()))?;
             } else if export.name() == "memory" && export.ty().memory().is_some() {
                 // Allow an exported "memory" memory for now.
             } else if export.name() == "__indirect_function_table" && export.ty().table().is_some()
@@ -468,18 +658,19 @@ impl Linker {
     /// items.
     pub fn alias(&mut self, module: &str, as_module: &str) -> Result<()> {
         let items = self
+            .map
             .iter()
-            .filter(|(m, _, _)| *m == module)
-            .map(|(_, name, item)| (name.to_string(), item))
+            .filter(|(key, _)| &*self.strings[key.module] == module)
+            .map(|(key, item)| (self.strings[key.name].clone(), item.clone()))
             .collect::<Vec<_>>();
         for (name, item) in items {
-            self.define(as_module, &name, item)?;
+            self.insert(as_module, &name, item)?;
         }
         Ok(())
     }
 
-    fn insert(&mut self, module: &str, name: &str, item: Extern) -> Result<()> {
-        let key = self.import_key(module, name, item.ty());
+    fn insert(&mut self, module: &str, name: &str, item: Definition) -> Result<()> {
+        let key = self.import_key(module, name, item.item().ty());
         match self.map.entry(key) {
             Entry::Occupied(o) if !self.allow_shadowing => bail!(
                 "import of `{}::{}` with kind {:?} defined twice",
@@ -567,9 +758,75 @@ impl Linker {
     /// # }
     /// ```
     pub fn instantiate(&self, module: &Module) -> Result<Instance> {
-        let imports = self.compute_imports(module)?;
+        self.instantiate_pre(module)?.instantiate(&self.store)
+    }
+
+    /// Resolves the imports of `module` once so that it can be instantiated
+    /// repeatedly without looking them up again.
+    ///
+    /// Each import of `module` is looked up and type-checked just like in
+    /// [`Linker::instantiate`], and the resulting [`InstancePre`] remembers
+    /// the items found. Items defined in this linker afterwards don't affect
+    /// it.
+    ///
+    /// The [`InstancePre`] can be instantiated in this linker's store as
+    /// well as in other stores of the same [`Engine`](crate::Engine), as long
+    /// as every import was defined with [`Linker::shared_func`] or
+    /// [`Linker::shared_func_async`], see [`InstancePre::instantiate`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an import isn't defined in this linker, or is
+    /// defined with an incompatible type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let store = Store::default();
+    /// let mut linker = Linker::new(&store);
+    /// linker.shared_func("host", "double", |x: i32| x * 2)?;
+    ///
+    /// let wat = r#"
+    ///     (module
+    ///         (import "host" "double" (func (param i32) (result i32)))
+    ///     )
+    /// "#;
+    /// let module = Module::new(store.engine(), wat)?;
+    /// let pre = linker.instantiate_pre(&module)?;
+    /// for _ in 0..10 {
+    ///     let store = Store::new(linker.store().engine());
+    ///     pre.instantiate(&store)?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn instantiate_pre(&self, module: &Module) -> Result<InstancePre> {
+        let imports = module
+            .imports()
+            .map(|import| {
+                self.get_definition(&import)
+                    .cloned()
+                    .ok_or_else(|| self.link_error(&import))
+            })
+            .collect::<Result<_>>()?;
+        Ok(InstancePre::new(module, imports))
+    }
 
-        Instance::new(&self.store, module, &imports)
+    /// Attempts to instantiate the `module` provided, asynchronously.
+    ///
+    /// This is the same as [`Linker::instantiate`] except that it uses
+    /// [`Instance::new_async`] so the module's start function can call async
+    /// host functions.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this linker's [`Store`] isn't configured for async support.
+    pub async fn instantiate_async(&self, module: &Module) -> Result<Instance> {
+        self.instantiate_pre(module)?
+            .instantiate_async(&self.store)
+            .await
     }
 
     fn compute_imports(&self, module: &Module) -> Result<Vec<Extern>> {
@@ -628,7 +885,7 @@ impl Linker {
             (
                 &*self.strings[key.module],
                 &*self.strings[key.name],
-                item.clone(),
+                item.item().clone(),
             )
         })
     }
@@ -638,12 +895,16 @@ impl Linker {
     ///
     /// Returns `None` if no match was found.
     pub fn get(&self, import: &ImportType) -> Option<Extern> {
+        Some(self.get_definition(import)?.item().clone())
+    }
+
+    fn get_definition(&self, import: &ImportType) -> Option<&Definition> {
         let key = ImportKey {
             module: *self.string2idx.get(import.module())?,
             name: *self.string2idx.get(import.name())?,
             kind: self.import_kind(import.ty()),
         };
-        self.map.get(&key).cloned()
+        self.map.get(&key)
     }
 
     /// Returns all items defined for the `module` and `name` pair.
@@ -660,7 +921,7 @@ impl Linker {
             .filter(move |(key, _item)| {
                 &*self.strings[key.module] == module && &*self.strings[key.name] == name
             })
-            .map(|(_, item)| item)
+            .map(|(_, item)| item.item())
     }
 
     /// Returns the single item defined for the `module` and `name` pair.
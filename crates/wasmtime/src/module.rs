@@ -1,11 +1,16 @@
 use crate::frame_info::GlobalFrameInfoRegistration;
 use crate::runtime::Engine;
 use crate::types::{EntityType, ExportType, ExternType, ImportType};
-use anyhow::{Error, Result};
+use anyhow::{bail, Context, Error, Result};
+use serde::{Deserialize, Serialize};
 use std::path::Path;
 use std::sync::{Arc, Mutex};
 use wasmparser::validate;
-use wasmtime_jit::CompiledModule;
+use wasmtime_environ::Tunables;
+use wasmtime_jit::{CompilationArtifacts, CompiledModule};
+
+/// Bytes that every serialized module starts with.
+const SERIALIZED_MODULE_MAGIC: &[u8] = b"\0wasmtime-module";
 
 /// A compiled WebAssembly module, ready to be instantiated.
 ///
@@ -300,14 +305,172 @@ impl Module {
         validate(binary, Some(config)).map_err(Error::new)
     }
 
+    /// Pre-initializes the WebAssembly module in `bytes` by instantiating it,
+    /// running its exported `init_func`, and returning a new module binary
+    /// whose initial state is the state the instance was left in.
+    ///
+    /// This moves the cost of initialization which computes the same state on
+    /// every run to build time. In the returned module:
+    ///
+    /// * Data segments hold the contents of each memory, whose minimum size is
+    ///   its size after initialization.
+    /// * Every global is initialized to its value after initialization.
+    /// * Element segments hold the contents of each table, whose minimum size
+    ///   is its size after initialization.
+    /// * The start function, which already ran, is no longer called, and
+    ///   `init_func` is no longer exported.
+    ///
+    /// `init_func` must take no parameters and return nothing. As with
+    /// [`Module::new`], `bytes` may be in the text format if the `wat` feature
+    /// is enabled, but the returned module is always in the binary format.
+    ///
+    /// # Errors
+    ///
+    /// Pre-initialization is refused whenever the snapshot can't reproduce
+    /// the effects of initialization, which is the case if:
+    ///
+    /// * initialization, including the start function, calls an imported
+    ///   function, since what it did to the host won't happen again;
+    /// * the module imports a memory, table or global, whose state lives
+    ///   outside of it;
+    /// * a table or global holds a non-null `externref`, or a reference to a
+    ///   function from outside the module;
+    /// * the module has a shared memory, passive segments, or uses
+    ///   `memory.init` or `data.drop`.
+    ///
+    /// Errors from compiling, instantiating or running the module are also
+    /// returned.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let engine = Engine::default();
+    /// let wat = r#"
+    ///     (module
+    ///         (memory (export "memory") 1)
+    ///         (global $ready (mut i32) (i32.const 0))
+    ///         (func (export "init")
+    ///             (i32.store (i32.const 8) (i32.const 42))
+    ///             (global.set $ready (i32.const 1)))
+    ///         (func (export "ready") (result i32) (global.get $ready)))
+    /// "#;
+    /// let wasm = Module::preinitialize(&engine, wat, "init")?;
+    ///
+    /// let store = Store::new(&engine);
+    /// let module = Module::new(&engine, &wasm)?;
+    /// let instance = Instance::new(&store, &module, &[])?;
+    /// assert_eq!(instance.get_func("ready").unwrap().get0::<i32>()?()?, 1);
+    /// assert!(instance.get_func("init").is_none());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn preinitialize(
+        engine: &Engine,
+        bytes: impl AsRef<[u8]>,
+        init_func: &str,
+    ) -> Result<Vec<u8>> {
+        #[cfg(feature = "wat")]
+        let bytes = wat::parse_bytes(bytes.as_ref())?;
+        crate::snapshot::preinitialize(engine, bytes.as_ref(), init_func)
+    }
+
     unsafe fn compile(engine: &Engine, binary: &[u8]) -> Result<Self> {
-        let compiled = CompiledModule::new(engine.compiler(), binary, &*engine.config().profiler)?;
+        let mut compiled =
+            CompiledModule::new(engine.compiler(), binary, &*engine.config().profiler)?;
+        if engine.config().memory_init_cow {
+            compiled.create_memory_images()?;
+        }
+        Ok(Module::from_compiled(engine, compiled))
+    }
 
-        Ok(Module {
+    fn from_compiled(engine: &Engine, compiled: CompiledModule) -> Module {
+        Module {
             engine: engine.clone(),
             compiled: Arc::new(compiled),
             frame_info_registration: Arc::new(Mutex::new(None)),
-        })
+        }
+    }
+
+    /// Serializes this module's compiled code and metadata into a list of
+    /// bytes.
+    ///
+    /// The returned bytes can later be loaded with [`Module::deserialize`],
+    /// which skips compilation entirely. This is intended for ahead-of-time
+    /// compilation, where modules are compiled once as part of a build step
+    /// and then loaded quickly at startup.
+    ///
+    /// The serialized form records the version of Wasmtime and the
+    /// configuration of this module's [`Engine`], and can only be loaded by
+    /// an engine which matches on both.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let engine = Engine::default();
+    /// let module = Module::new(&engine, "(module (func (export \"f\")))")?;
+    /// let bytes = module.serialize()?;
+    /// let module = unsafe { Module::deserialize(&engine, &bytes)? };
+    /// assert!(module.get_export("f").is_some());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn serialize(&self) -> Result<Vec<u8>> {
+        let mut bytes = SERIALIZED_MODULE_MAGIC.to_vec();
+        let header = SerializedModuleHeader::new(&self.engine);
+        let name = &self.compiled.module().name;
+        bincode::serialize_into(&mut bytes, &header)
+            .context("failed to serialize module header")?;
+        bincode::serialize_into(&mut bytes, name).context("failed to serialize module name")?;
+        bincode::serialize_into(&mut bytes, self.compiled.artifacts())
+            .context("failed to serialize module artifacts")?;
+        Ok(bytes)
+    }
+
+    /// Creates a `Module` from bytes previously produced by
+    /// [`Module::serialize`], without compiling anything.
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if `bytes` don't look like a serialized module,
+    /// or if they were produced by a different version of Wasmtime or by an
+    /// [`Engine`] whose target, code generation settings, tunables or enabled
+    /// WebAssembly features differ from `engine`.
+    ///
+    /// # Unsafety
+    ///
+    /// This function is `unsafe` because the machine code contained in
+    /// `bytes` is loaded and made executable as-is. Only the header is
+    /// checked, so `bytes` must come from a trusted source and must not have
+    /// been tampered with since [`Module::serialize`] produced them.
+    pub unsafe fn deserialize(engine: &Engine, bytes: &[u8]) -> Result<Module> {
+        if !bytes.starts_with(SERIALIZED_MODULE_MAGIC) {
+            bail!("bytes are not a serialized wasmtime module");
+        }
+        let mut reader = &bytes[SERIALIZED_MODULE_MAGIC.len()..];
+
+        let header: SerializedModuleHeader = bincode::deserialize_from(&mut reader)
+            .context("failed to deserialize module header")?;
+        header.check_compatible(engine)?;
+
+        let name: Option<String> =
+            bincode::deserialize_from(&mut reader).context("failed to deserialize module name")?;
+        let artifacts: CompilationArtifacts = bincode::deserialize_from(&mut reader)
+            .context("failed to deserialize module artifacts")?;
+
+        let mut compiled = CompiledModule::from_artifacts(
+            engine.compiler(),
+            artifacts,
+            &*engine.config().profiler,
+        )?;
+        compiled.module_mut().expect("mutable module").name = name;
+        if engine.config().memory_init_cow {
+            compiled.create_memory_images()?;
+        }
+        Ok(Module::from_compiled(engine, compiled))
     }
 
     pub(crate) fn compiled_module(&self) -> &CompiledModule {
@@ -536,6 +699,77 @@ impl Module {
     }
 }
 
+/// Describes the engine a module was serialized with, so that it is only ever
+/// loaded into an engine which would have produced the same code.
+///
+/// Settings which only have an effect once code is loaded, like the profiler
+/// or the instance allocator, are left out.
+#[derive(Serialize, Deserialize)]
+struct SerializedModuleHeader {
+    version: String,
+    target: String,
+    isa_flags: String,
+    strategy: String,
+    tunables: Tunables,
+    /// Every WebAssembly feature setting of the engine, in their `Debug`
+    /// form so that none can be forgotten.
+    features: String,
+}
+
+impl SerializedModuleHeader {
+    fn new(engine: &Engine) -> Self {
+        let isa = engine.compiler().isa();
+        let config = engine.config();
+        SerializedModuleHeader {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            target: isa.triple().to_string(),
+            isa_flags: isa.to_string(),
+            strategy: format!("{:?}", config.strategy),
+            tunables: engine.compiler().tunables().clone(),
+            features: format!("{:?}", config.validating_config.operator_config),
+        }
+    }
+
+    fn check_compatible(&self, engine: &Engine) -> Result<()> {
+        let expected = SerializedModuleHeader::new(engine);
+        if self.version != expected.version {
+            bail!(
+                "module was serialized by Wasmtime {} but this is Wasmtime {}",
+                self.version,
+                expected.version
+            );
+        }
+        if self.target != expected.target {
+            bail!(
+                "module was compiled for target `{}` but the engine targets `{}`",
+                self.target,
+                expected.target
+            );
+        }
+        if self.isa_flags != expected.isa_flags {
+            bail!("module was compiled with different code generation settings than the engine");
+        }
+        if self.strategy != expected.strategy {
+            bail!(
+                "module was compiled with the {} strategy but the engine uses {}",
+                self.strategy,
+                expected.strategy
+            );
+        }
+        if self.tunables != expected.tunables {
+            bail!("module was compiled with different tunables than the engine");
+        }
+        if self.features != expected.features {
+            bail!(
+                "module was compiled with WebAssembly features {} but the engine has {}",
+                self.features,
+                expected.features
+            );
+        }
+        Ok(())
+    }
+}
+
 fn _assert_send_sync() {
     fn _assert<T: Send + Sync>() {}
     _assert::<Module>();
@@ -0,0 +1,257 @@
+use crate::frame_info::FRAME_INFO;
+use crate::runtime::EpochSampler;
+use crate::{FrameInfo, Store};
+use anyhow::{bail, Result};
+use backtrace::Backtrace;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::sync::atomic::AtomicBool;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// A sampling profiler for the WebAssembly code running in a [`Store`].
+///
+/// Native profilers such as `perf` see wasm as anonymous jitted code mixed in
+/// with the host's own frames. A `GuestProfiler` instead periodically records
+/// only the wasm frames of the stack, named after the functions in the wasm
+/// module, and writes them out as a profile which can be opened in the
+/// [Firefox Profiler] or [speedscope].
+///
+/// Samples are taken at the epoch checks of compiled code, so this requires
+/// [`Config::epoch_interruption`](crate::Config::epoch_interruption) to be
+/// enabled. [`GuestProfiler::start`] spawns a thread which, every
+/// `interval`, makes the next epoch check of wasm running in the store
+/// record a sample. Neither the engine's epoch nor the store's own epoch
+/// deadline and deadline behavior are affected by this, so they still need
+/// to be configured as usual, for example with
+/// `store.set_epoch_deadline(u64::MAX)` if epochs aren't otherwise used.
+/// Only the most recently started profiler of a store records samples.
+///
+/// Samples can additionally be recorded at any point wasm is on the stack,
+/// for example from within a host function, with [`GuestProfiler::sample`].
+///
+/// [Firefox Profiler]: https://profiler.firefox.com
+/// [speedscope]: https://www.speedscope.app
+pub struct GuestProfiler {
+    profile: Arc<Mutex<Profile>>,
+    timer: Option<(mpsc::Sender<()>, JoinHandle<()>)>,
+}
+
+impl GuestProfiler {
+    /// Starts sampling the wasm running in `store` every `interval`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store's engine wasn't configured with
+    /// [`Config::epoch_interruption`](crate::Config::epoch_interruption).
+    pub fn start(store: &Store, interval: Duration) -> Result<GuestProfiler> {
+        if !store.engine().config().tunables.epoch_interruption {
+            bail!("guest profiling requires `Config::epoch_interruption` to be enabled");
+        }
+
+        // The store only holds on to the profile weakly, dropping the
+        // sampler once the profiler is gone.
+        let profile = Arc::new(Mutex::new(Profile::new()));
+        let sampler_profile = Arc::downgrade(&profile);
+        let due = Arc::new(AtomicBool::new(false));
+        store.set_epoch_sampler(EpochSampler {
+            due: due.clone(),
+            sample: Box::new(move || match sampler_profile.upgrade() {
+                Some(profile) => {
+                    profile.lock().unwrap().sample();
+                    true
+                }
+                None => false,
+            }),
+        });
+
+        let interrupts = store.interrupts().clone();
+        let (stop, stopped) = mpsc::channel();
+        let thread = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                EpochSampler::request(&due, &interrupts);
+            }
+        });
+
+        Ok(GuestProfiler {
+            profile,
+            timer: Some((stop, thread)),
+        })
+    }
+
+    /// Records a sample of the wasm frames currently on the stack.
+    ///
+    /// Samples taken while no wasm is executing are discarded.
+    pub fn sample(&self) {
+        self.profile.lock().unwrap().sample();
+    }
+
+    /// Stops sampling and writes the profile to `output`.
+    ///
+    /// The profile is written in the JSON `.cpuprofile` format produced by
+    /// Chrome's devtools, which both the Firefox Profiler and speedscope can
+    /// import.
+    pub fn finish(mut self, mut output: impl Write) -> Result<()> {
+        self.stop_timer();
+        let profile = self.profile.lock().unwrap();
+        serde_json::to_writer(&mut output, &*profile)?;
+        output.flush()?;
+        Ok(())
+    }
+
+    fn stop_timer(&mut self) {
+        if let Some((stop, thread)) = self.timer.take() {
+            let _ = stop.send(());
+            thread.join().unwrap();
+        }
+    }
+}
+
+impl Drop for GuestProfiler {
+    fn drop(&mut self) {
+        self.stop_timer();
+    }
+}
+
+impl fmt::Debug for GuestProfiler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let profile = self.profile.lock().unwrap();
+        f.debug_struct("GuestProfiler")
+            .field("samples", &profile.samples.len())
+            .finish()
+    }
+}
+
+/// The samples recorded so far, laid out as a `.cpuprofile`: a call tree of
+/// nodes, with each sample pointing at the node of its innermost frame.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Profile {
+    nodes: Vec<Node>,
+    start_time: u64,
+    end_time: u64,
+    samples: Vec<u32>,
+    time_deltas: Vec<u64>,
+    #[serde(skip)]
+    start: Instant,
+    #[serde(skip)]
+    children: HashMap<(u32, usize, u32), u32>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Node {
+    id: u32,
+    call_frame: CallFrame,
+    hit_count: u64,
+    children: Vec<u32>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CallFrame {
+    function_name: String,
+    script_id: String,
+    url: String,
+    line_number: i32,
+    column_number: i32,
+}
+
+const ROOT_NODE: u32 = 1;
+
+impl Profile {
+    fn new() -> Profile {
+        Profile {
+            nodes: vec![Node {
+                id: ROOT_NODE,
+                call_frame: CallFrame {
+                    function_name: "(root)".to_string(),
+                    script_id: "0".to_string(),
+                    url: String::new(),
+                    line_number: -1,
+                    column_number: -1,
+                },
+                hit_count: 0,
+                children: Vec::new(),
+            }],
+            start_time: 0,
+            end_time: 0,
+            samples: Vec::new(),
+            time_deltas: Vec::new(),
+            start: Instant::now(),
+            children: HashMap::new(),
+        }
+    }
+
+    fn sample(&mut self) {
+        // Only the wasm frames are of interest, and those are exactly the
+        // ones which frame info knows about. As in trap backtraces, return
+        // addresses are looked up one byte back to land in the call itself.
+        let trace = Backtrace::new_unresolved();
+        let mut stack = Vec::new();
+        {
+            let info = FRAME_INFO.read().unwrap();
+            for frame in trace.frames() {
+                let pc = frame.ip() as usize;
+                if pc == 0 {
+                    continue;
+                }
+                if let Some(frame) = info.lookup_frame_info(pc - 1) {
+                    stack.push(frame);
+                }
+            }
+        }
+        if stack.is_empty() {
+            return;
+        }
+
+        // Frames come innermost first, so walk down the call tree from the
+        // outermost one.
+        let mut node = ROOT_NODE;
+        for frame in stack.iter().rev() {
+            node = self.child(node, frame);
+        }
+        self.nodes[node as usize - 1].hit_count += 1;
+
+        let now = self.start.elapsed().as_micros() as u64;
+        self.samples.push(node);
+        self.time_deltas.push(now - self.end_time);
+        self.end_time = now;
+    }
+
+    /// Returns the node for calling `frame`'s function from `parent`,
+    /// creating it if this call path hasn't been sampled yet.
+    fn child(&mut self, parent: u32, frame: &FrameInfo) -> u32 {
+        let key = (parent, frame.module_id(), frame.func_index());
+        if let Some(id) = self.children.get(&key) {
+            return *id;
+        }
+        let id = self.nodes.len() as u32 + 1;
+        let function_name = match frame.func_name() {
+            Some(name) => match rustc_demangle::try_demangle(name) {
+                Ok(name) => name.to_string(),
+                Err(_) => name.to_string(),
+            },
+            None => format!("<wasm function {}>", frame.func_index()),
+        };
+        self.nodes.push(Node {
+            id,
+            call_frame: CallFrame {
+                function_name,
+                script_id: frame.module_id().to_string(),
+                url: frame.module_name().unwrap_or("<unknown>").to_string(),
+                line_number: -1,
+                column_number: -1,
+            },
+            hit_count: 0,
+            children: Vec::new(),
+        });
+        self.nodes[parent as usize - 1].children.push(id);
+        self.children.insert(key, id);
+        id
+    }
+}
@@ -1,27 +1,40 @@
 #![allow(missing_docs)]
 
 use std::any::Any;
-use std::cell::RefCell;
 use std::fmt;
 use std::rc::{Rc, Weak};
+use std::sync::{Arc, Mutex};
 use wasmtime_runtime::VMExternRef;
 
 /// Represents an opaque reference to any data within WebAssembly.
+///
+/// The values wrapped by an `ExternRef` move along with its store to other
+/// threads, and may be dropped on any of them, so they must be `Send` and
+/// `Sync`.
 #[derive(Clone)]
 pub struct ExternRef {
     pub(crate) inner: VMExternRef,
     pub(crate) store: Weak<crate::runtime::StoreInner>,
+    /// Keeps the store from moving to another thread while this reference is
+    /// alive, see `Store::into_send`.
+    _token: Rc<()>,
 }
 
 impl ExternRef {
     /// Creates a new instance of `ExternRef` wrapping the given value.
     pub fn new<T>(store: &crate::Store, value: T) -> ExternRef
     where
-        T: 'static + Any,
+        T: 'static + Any + Send + Sync,
     {
-        let inner = VMExternRef::new(value);
-        let store = store.weak();
-        ExternRef { inner, store }
+        ExternRef::from_vm_externref(store, VMExternRef::new(value))
+    }
+
+    pub(crate) fn from_vm_externref(store: &crate::Store, inner: VMExternRef) -> ExternRef {
+        ExternRef {
+            inner,
+            store: store.weak(),
+            _token: store.externref_token(),
+        }
     }
 
     /// Get this reference's store.
@@ -51,24 +64,24 @@ impl ExternRef {
 
     /// Returns the host information for this `externref`, if previously created
     /// with `set_host_info`.
-    pub fn host_info(&self) -> Option<Rc<RefCell<dyn Any>>> {
+    pub fn host_info(&self) -> Option<Arc<Mutex<dyn Any + Send>>> {
         let store = crate::Store::upgrade(&self.store)?;
         store.host_info(self)
     }
 
     /// Set the host information for this `externref`, returning the old host
     /// information if it was previously set.
-    pub fn set_host_info<T>(&self, info: T) -> Option<Rc<RefCell<dyn Any>>>
+    pub fn set_host_info<T>(&self, info: T) -> Option<Arc<Mutex<dyn Any + Send>>>
     where
-        T: 'static + Any,
+        T: 'static + Any + Send,
     {
         let store = crate::Store::upgrade(&self.store)?;
-        store.set_host_info(self, Some(Rc::new(RefCell::new(info))))
+        store.set_host_info(self, Some(Arc::new(Mutex::new(info))))
     }
 
     /// Remove the host information for this `externref`, returning the old host
     /// information if it was previously set.
-    pub fn remove_host_info(&self) -> Option<Rc<RefCell<dyn Any>>> {
+    pub fn remove_host_info(&self) -> Option<Arc<Mutex<dyn Any + Send>>> {
         let store = crate::Store::upgrade(&self.store)?;
         store.set_host_info(self, None)
     }
@@ -76,7 +89,7 @@ impl ExternRef {
 
 impl fmt::Debug for ExternRef {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let ExternRef { inner, store: _ } = self;
+        let ExternRef { inner, .. } = self;
         let store = self.store();
         f.debug_struct("ExternRef")
             .field("inner", &inner)
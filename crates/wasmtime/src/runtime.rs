@@ -1,29 +1,43 @@
-use crate::externals::MemoryCreator;
+use crate::externals::{MemoryCreator, WeakExtern};
+use crate::instance::ModuleInstanceState;
+use crate::limits::ResourceLimiterProxy;
 use crate::r#ref::ExternRef;
 use crate::trampoline::{MemoryCreatorProxy, StoreInstanceHandle};
-use crate::Module;
+use crate::{Coverage, Extern, Func, Instance, Module, ResourceLimiter, Trap};
 use anyhow::{bail, Result};
 use std::any::Any;
-use std::cell::RefCell;
+use std::cell::{Cell, Ref, RefCell, RefMut};
 use std::cmp;
 use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::fmt;
+use std::future::Future;
 use std::hash::{Hash, Hasher};
 use std::path::Path;
+use std::pin::Pin;
+use std::ptr::{self, NonNull};
 use std::rc::{Rc, Weak};
-use std::sync::Arc;
+use std::sync::atomic::Ordering::{Relaxed, SeqCst};
+use std::sync::atomic::{AtomicBool, AtomicU64};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
 use wasmparser::{OperatorValidatorConfig, ValidatingParserConfig};
 use wasmtime_environ::settings::{self, Configurable, SetError};
 use wasmtime_environ::{ir, isa, isa::TargetIsa, wasm, CacheConfig, Tunables};
 use wasmtime_jit::{native, CompilationStrategy, Compiler};
-use wasmtime_profiling::{JitDumpAgent, NullProfilerAgent, ProfilingAgent, VTuneAgent};
+use wasmtime_profiling::{
+    JitDumpAgent, NullProfilerAgent, PerfMapAgent, ProfilingAgent, VTuneAgent,
+};
 use wasmtime_runtime::{
-    debug_builtins, InstanceHandle, RuntimeMemoryCreator, SignalHandler, SignatureRegistry,
-    StackMapRegistry, VMExternRef, VMExternRefActivationsTable, VMInterrupts,
-    VMSharedSignatureIndex,
+    debug_builtins, DebugLocals, InstanceAllocator, InstanceHandle, OnDemandInstanceAllocator,
+    PoolingInstanceAllocator, RuntimeMemoryCreator, SignalHandler, SignatureRegistry,
+    StackMapRegistry, TlsMark, TlsRestore, VMContext, VMExternRef, VMExternRefActivationsTable,
+    VMFrameRecord, VMInterrupts, VMMemoryDefinition, VMSharedSignatureIndex,
 };
 
+pub use wasmtime_environ::{CacheStore, InMemoryCacheStore};
+pub use wasmtime_runtime::InstanceLimits;
+
 // Runtime Environment
 
 // Configuration
@@ -43,7 +57,12 @@ pub struct Config {
     pub(crate) cache_config: CacheConfig,
     pub(crate) profiler: Arc<dyn ProfilingAgent>,
     pub(crate) memory_creator: Option<MemoryCreatorProxy>,
+    pub(crate) allocation_strategy: InstanceAllocationStrategy,
+    pub(crate) instance_allocator: Option<Arc<dyn InstanceAllocator>>,
     pub(crate) max_wasm_stack: usize,
+    pub(crate) async_support: bool,
+    pub(crate) memory_init_cow: bool,
+    pub(crate) coredump_on_trap: bool,
 }
 
 impl Config {
@@ -100,7 +119,12 @@ impl Config {
             cache_config: CacheConfig::new_cache_disabled(),
             profiler: Arc::new(NullProfilerAgent),
             memory_creator: None,
+            allocation_strategy: InstanceAllocationStrategy::OnDemand,
+            instance_allocator: None,
             max_wasm_stack: 1 << 20,
+            async_support: false,
+            memory_init_cow: true,
+            coredump_on_trap: false,
         }
     }
 
@@ -125,6 +149,80 @@ impl Config {
         self
     }
 
+    /// Configures whether execution of WebAssembly will "consume fuel" to
+    /// either halt or yield execution as desired.
+    ///
+    /// This option is similar in purpose to [`Config::interruptable`] where
+    /// you can prevent infinitely-executing WebAssembly code. The difference
+    /// is that this option allows deterministic execution of WebAssembly code
+    /// by instrumenting generated code to consume fuel as it executes. When fuel
+    /// runs out the WebAssembly code will trap.
+    ///
+    /// Each [`Store`] starts out with no fuel, so if you enable this option
+    /// you'll have to be sure to pour some fuel into [`Store`] via
+    /// [`Store::add_fuel`] before executing some code.
+    ///
+    /// By default this option is `false`.
+    pub fn consume_fuel(&mut self, enable: bool) -> &mut Self {
+        self.tunables.consume_fuel = enable;
+        self
+    }
+
+    /// Configures whether WebAssembly will check the [`Engine`]'s epoch
+    /// counter against a per-[`Store`] deadline to decide when to stop
+    /// executing.
+    ///
+    /// This is a cheaper alternative to [`Config::interruptable`]. Rather than
+    /// interrupting each [`Store`] individually, the epoch is a single counter
+    /// shared by all stores of an [`Engine`] which is advanced with
+    /// [`Engine::increment_epoch`], typically from a timer thread. Function
+    /// entries and loop headers compare the current epoch with the deadline
+    /// set by [`Store::set_epoch_deadline`], and once the deadline is reached
+    /// the store either traps (the default) or calls back into the host as
+    /// configured with [`Store::epoch_deadline_callback`] and
+    /// [`Store::epoch_deadline_async_yield_and_update`].
+    ///
+    /// Note that each [`Store`] starts out with a deadline of zero, so a
+    /// deadline must be set before executing any WebAssembly or it will trap
+    /// straight away.
+    ///
+    /// By default this option is `false`.
+    pub fn epoch_interruption(&mut self, enable: bool) -> &mut Self {
+        self.tunables.epoch_interruption = enable;
+        self
+    }
+
+    /// Configures whether compiled code calls back into the host before every
+    /// WebAssembly instruction, so that it can be debugged at the level of
+    /// wasm itself.
+    ///
+    /// This is what [`GdbStub`](crate::GdbStub) uses to implement breakpoints,
+    /// single-stepping and inspection of locals. Every instruction reports
+    /// its offset in the module along with the current values of the
+    /// function's locals, which makes this extremely slow, so this should
+    /// only be enabled while debugging.
+    ///
+    /// By default this option is `false`.
+    pub fn guest_debug(&mut self, enable: bool) -> &mut Self {
+        self.tunables.guest_debug = enable;
+        self
+    }
+
+    /// Configures whether compiled code counts how many times each basic
+    /// block of WebAssembly is executed, for measuring code coverage.
+    ///
+    /// The counts are kept per instance and are available through
+    /// [`Instance::coverage`] and [`Store::coverage`], from which an lcov
+    /// report can be written if the module contains DWARF debug
+    /// information. Counting adds a memory increment to every basic block,
+    /// so this should only be enabled while testing.
+    ///
+    /// By default this option is `false`.
+    pub fn coverage(&mut self, enable: bool) -> &mut Self {
+        self.tunables.coverage = enable;
+        self
+    }
+
     /// Configures the maximum amount of native stack space available to
     /// executing WebAssembly code.
     ///
@@ -144,6 +242,31 @@ impl Config {
         self
     }
 
+    /// Configures whether WebAssembly can be executed asynchronously.
+    ///
+    /// When enabled, [`Func::call_async`](crate::Func::call_async) and
+    /// [`Instance::new_async`](crate::Instance::new_async) can be used to
+    /// execute wasm as a Rust future. Host functions defined with
+    /// [`Func::new_async`](crate::Func::new_async) may then be called from
+    /// that wasm: whenever the future they return is pending the wasm is
+    /// suspended and `Poll::Pending` is propagated to whomever is polling the
+    /// outermost future.
+    ///
+    /// To make this possible each asynchronous call executes wasm on a
+    /// separate native stack. That stack is sized to hold
+    /// [`Config::max_wasm_stack`] bytes of wasm frames plus some extra space
+    /// for host code called from wasm.
+    ///
+    /// Synchronous APIs like [`Func::call`](crate::Func::call) still work when
+    /// this is enabled, but async host functions called from them will trap
+    /// since there's nowhere to suspend to.
+    ///
+    /// By default this option is `false`.
+    pub fn async_support(&mut self, enable: bool) -> &mut Self {
+        self.async_support = enable;
+        self
+    }
+
     /// Configures whether the WebAssembly threads proposal will be enabled for
     /// compilation.
     ///
@@ -158,10 +281,13 @@ impl Config {
     ///
     /// This is `false` by default.
     ///
-    /// > **Note**: Wasmtime does not implement everything for the wasm threads
-    /// > spec at this time, so bugs, panics, and possibly segfaults should be
-    /// > expected. This should not be enabled in a production setting right
-    /// > now.
+    /// A shared memory can be created on the host with
+    /// [`SharedMemory`](crate::SharedMemory) and then imported by instances in
+    /// any number of stores, each running on its own thread.
+    ///
+    /// > **Note**: Atomic instructions are not yet supported by the
+    /// > experimental x64 backend. The proposal itself is still changing, so
+    /// > this should not be enabled in a production setting right now.
     ///
     /// [threads]: https://github.com/webassembly/threads
     pub fn wasm_threads(&mut self, enable: bool) -> &mut Self {
@@ -177,8 +303,7 @@ impl Config {
     /// enabled for compilation.
     ///
     /// The [WebAssembly reference types proposal][proposal] is not currently
-    /// fully standardized and is undergoing development. Additionally the
-    /// support in wasmtime itself is still being worked on. Support for this
+    /// fully standardized and is undergoing development. Support for this
     /// feature can be enabled through this method for appropriate wasm
     /// modules.
     ///
@@ -188,10 +313,8 @@ impl Config {
     ///
     /// This is `false` by default.
     ///
-    /// > **Note**: Wasmtime does not implement everything for the reference
-    /// > types proposal spec at this time, so bugs, panics, and possibly
-    /// > segfaults should be expected. This should not be enabled in a
-    /// > production setting right now.
+    /// > **Note**: reference types are currently only supported by the
+    /// > Cranelift backend on x86_64.
     ///
     /// [proposal]: https://github.com/webassembly/reference-types
     pub fn wasm_reference_types(&mut self, enable: bool) -> &mut Self {
@@ -309,6 +432,7 @@ impl Config {
         self.profiler = match profile {
             ProfilingStrategy::JitDump => Arc::new(JitDumpAgent::new()?) as Arc<dyn ProfilingAgent>,
             ProfilingStrategy::VTune => Arc::new(VTuneAgent::new()?) as Arc<dyn ProfilingAgent>,
+            ProfilingStrategy::PerfMap => Arc::new(PerfMapAgent::new()?) as Arc<dyn ProfilingAgent>,
             ProfilingStrategy::None => Arc::new(NullProfilerAgent),
         };
         Ok(self)
@@ -434,6 +558,106 @@ impl Config {
         Ok(self)
     }
 
+    /// Caches compiled code in `store` instead of the cache directory.
+    ///
+    /// This replaces any cache configuration loaded before. Engines
+    /// configured with the same store share their compiled code, and a
+    /// custom [`CacheStore`] can share it with other machines, for example
+    /// through a key-value service. [`InMemoryCacheStore`] keeps it in memory
+    /// for the lifetime of the store.
+    ///
+    /// By default cache configuration is not enabled or loaded.
+    pub fn cache_store(&mut self, store: Arc<dyn CacheStore>) -> &mut Self {
+        self.cache_config = CacheConfig::new_cache_with_store(store);
+        self
+    }
+
+    /// Sets the strategy used to allocate the memories and tables of
+    /// instantiated modules.
+    ///
+    /// The default, [`InstanceAllocationStrategy::OnDemand`], creates them
+    /// afresh for each instantiation. [`InstanceAllocationStrategy::Pooling`]
+    /// instead reserves memory for a fixed number of instances right away,
+    /// which makes instantiation cheaper at the cost of a large up-front
+    /// virtual memory reservation.
+    ///
+    /// The pool's linear memory slots are sized according to the memory
+    /// settings in effect when this method is called, such as
+    /// [`Config::static_memory_maximum_size`], so those should be configured
+    /// first. Modules whose memories don't fit in a slot, or which exceed the
+    /// pool's limits, fail to instantiate.
+    ///
+    /// When pooling, a memory creator set with [`Config::with_host_memory`]
+    /// is only used for memories created by the host through
+    /// [`Memory::new`](crate::Memory::new).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the limits are invalid or if the pool's memory
+    /// couldn't be reserved.
+    pub fn allocation_strategy(
+        &mut self,
+        strategy: InstanceAllocationStrategy,
+    ) -> Result<&mut Self> {
+        self.instance_allocator = match &strategy {
+            InstanceAllocationStrategy::OnDemand => None,
+            InstanceAllocationStrategy::Pooling { instance_limits } => {
+                let allocator = PoolingInstanceAllocator::new(*instance_limits, &self.tunables)
+                    .map_err(anyhow::Error::msg)?;
+                Some(Arc::new(allocator))
+            }
+        };
+        self.allocation_strategy = strategy;
+        Ok(self)
+    }
+
+    /// Configures whether linear memories are initialized by mapping in a
+    /// copy-on-write image of their data segments.
+    ///
+    /// When enabled, the static data segments of each module are written
+    /// once, when the module is compiled or deserialized, into an image for
+    /// each of its memories. Instantiation then maps that image into new
+    /// memories instead of copying every data segment, so pages of data are
+    /// only copied when an instance writes to them. This can make
+    /// instantiating modules with lots of data much faster.
+    ///
+    /// Images are only used on Linux, by the default
+    /// [`InstanceAllocationStrategy::OnDemand`] strategy, when no memory
+    /// creator has been set with [`Config::with_host_memory`]. Data segments
+    /// whose offsets depend on an imported global, or which don't fit in
+    /// their memory's initial size, are still copied in at instantiation
+    /// time, as are any segments after them.
+    ///
+    /// By default this option is `true`.
+    pub fn memory_init_cow(&mut self, enable: bool) -> &mut Self {
+        self.memory_init_cow = enable;
+        self
+    }
+
+    /// Configures whether a [`WasmCoreDump`](crate::WasmCoreDump) is captured
+    /// when wasm code traps.
+    ///
+    /// When enabled, the contents of the linear memories and globals of the
+    /// store's instances, along with the wasm call stack, are copied out when
+    /// a trap happens and made available through
+    /// [`Trap::coredump`](crate::Trap::coredump). Copying memories can be
+    /// expensive for large heaps, which is why this is opt-in. Traps
+    /// representing an explicit exit, such as WASI's `proc_exit`, don't
+    /// capture a core dump.
+    ///
+    /// Enabling this also makes each compiled wasm function record which
+    /// instance it's executing in on entry, so that every frame of the call
+    /// stack can be attributed to the right instance, which makes calls
+    /// slightly slower. The values of frames' locals are only recorded when
+    /// [`Config::guest_debug`] is enabled too.
+    ///
+    /// By default this option is `false`.
+    pub fn coredump_on_trap(&mut self, enable: bool) -> &mut Self {
+        self.coredump_on_trap = enable;
+        self.tunables.record_frames = enable;
+        self
+    }
+
     /// Sets a custom memory creator
     pub fn with_host_memory(&mut self, mem_creator: Arc<dyn MemoryCreator>) -> &mut Self {
         self.memory_creator = Some(MemoryCreatorProxy { mem_creator });
@@ -645,7 +869,15 @@ impl fmt::Debug for Config {
         let features = &self.validating_config.operator_config;
         f.debug_struct("Config")
             .field("debug_info", &self.tunables.debug_info)
+            .field("consume_fuel", &self.tunables.consume_fuel)
+            .field("epoch_interruption", &self.tunables.epoch_interruption)
+            .field("guest_debug", &self.tunables.guest_debug)
+            .field("coverage", &self.tunables.coverage)
             .field("strategy", &self.strategy)
+            .field("allocation_strategy", &self.allocation_strategy)
+            .field("async_support", &self.async_support)
+            .field("memory_init_cow", &self.memory_init_cow)
+            .field("coredump_on_trap", &self.coredump_on_trap)
             .field("wasm_threads", &features.enable_threads)
             .field("wasm_reference_types", &features.enable_reference_types)
             .field("wasm_bulk_memory", &features.enable_bulk_memory)
@@ -714,6 +946,34 @@ pub enum ProfilingStrategy {
 
     /// Collect profiling info using the "ittapi", used with `VTune` on Linux.
     VTune,
+
+    /// Append the address, size and name of all compiled code to
+    /// `/tmp/perf-<pid>.map`, the "perf map" format understood by `perf`
+    /// without any post-processing.
+    PerfMap,
+}
+
+/// Select how the memories and tables of instances are allocated.
+///
+/// This is used as an argument to the [`Config::allocation_strategy`] method.
+#[derive(Debug, Clone)]
+pub enum InstanceAllocationStrategy {
+    /// Create memories and tables when a module is instantiated, and free
+    /// them when its store is dropped. This is the default.
+    OnDemand,
+
+    /// Reserve memories and tables for a fixed number of instances up front
+    /// and reuse them across instantiations.
+    Pooling {
+        /// The limits which instances created from the pool must fit in.
+        instance_limits: InstanceLimits,
+    },
+}
+
+impl Default for InstanceAllocationStrategy {
+    fn default() -> Self {
+        InstanceAllocationStrategy::OnDemand
+    }
 }
 
 // Engine
@@ -748,6 +1008,9 @@ pub struct Engine {
 struct EngineInner {
     config: Config,
     compiler: Compiler,
+    allocator: Arc<dyn InstanceAllocator>,
+    host_allocator: OnDemandInstanceAllocator,
+    epoch: AtomicU64,
 }
 
 impl Engine {
@@ -755,10 +1018,23 @@ impl Engine {
     /// configuration settings.
     pub fn new(config: &Config) -> Engine {
         debug_builtins::ensure_exported();
+        let host_allocator = OnDemandInstanceAllocator::new(
+            config
+                .memory_creator
+                .clone()
+                .map(|creator| Arc::new(creator) as Arc<dyn RuntimeMemoryCreator>),
+        );
+        let allocator = match &config.instance_allocator {
+            Some(allocator) => allocator.clone(),
+            None => Arc::new(host_allocator.clone()),
+        };
         Engine {
             inner: Arc::new(EngineInner {
                 config: config.clone(),
                 compiler: config.build_compiler(),
+                allocator,
+                host_allocator,
+                epoch: AtomicU64::new(0),
             }),
         }
     }
@@ -772,6 +1048,37 @@ impl Engine {
         &self.inner.compiler
     }
 
+    /// Returns the allocator used for instances of wasm modules.
+    pub(crate) fn allocator(&self) -> &dyn InstanceAllocator {
+        &*self.inner.allocator
+    }
+
+    /// Returns the allocator used for instances backing host-defined items,
+    /// which never come out of a pool.
+    pub(crate) fn host_allocator(&self) -> &OnDemandInstanceAllocator {
+        &self.inner.host_allocator
+    }
+
+    /// Increments the epoch counter shared by all stores of this engine.
+    ///
+    /// WebAssembly compiled with [`Config::epoch_interruption`] enabled
+    /// compares this counter against its [`Store`]'s deadline, set with
+    /// [`Store::set_epoch_deadline`], at function entries and loop headers.
+    ///
+    /// This is a single atomic increment, so it may be called from any thread
+    /// at any time, and is even safe to call from a signal handler.
+    pub fn increment_epoch(&self) {
+        self.inner.epoch.fetch_add(1, Relaxed);
+    }
+
+    pub(crate) fn current_epoch(&self) -> u64 {
+        self.inner.epoch.load(Relaxed)
+    }
+
+    pub(crate) fn epoch_counter(&self) -> &AtomicU64 {
+        &self.inner.epoch
+    }
+
     /// Returns whether the engine `a` and `b` refer to the same configuration.
     pub fn same(a: &Engine, b: &Engine) -> bool {
         Arc::ptr_eq(&a.inner, &b.inner)
@@ -798,6 +1105,16 @@ impl Default for Engine {
 /// entirely new store, but rather just a new reference to the existing object.
 /// In other words it's a shallow copy, not a deep copy.
 ///
+/// ## Stores and threads
+///
+/// A `Store`, and handles to items within it such as [`Func`](crate::Func),
+/// [`Instance`](crate::Instance) and [`Memory`](crate::Memory), can only be
+/// used on the thread they were created on. To move a store to another
+/// thread, for example to resume a long-lived tenant on whichever worker
+/// thread is free, first turn it into a [`SendStore`] with
+/// [`Store::into_send`], which requires every other clone of the store and
+/// every handle into it to have been dropped.
+///
 /// ## Stores and `Default`
 ///
 /// You can create a store with default configuration settings using
@@ -812,12 +1129,135 @@ pub(crate) struct StoreInner {
     engine: Engine,
     interrupts: Arc<VMInterrupts>,
     signatures: RefCell<SignatureRegistry>,
-    instances: RefCell<Vec<InstanceHandle>>,
+    instances: RefCell<Vec<StoreInstance>>,
+    /// The token of each instance in `instances`, keyed by the address of its
+    /// `VMContext`. Tokens are cloned into every `StoreInstanceHandle` to
+    /// their instance, so that the instance is known to be unreachable when
+    /// the store holds the only reference.
+    instance_tokens: RefCell<HashMap<usize, Rc<()>>>,
+    /// A token cloned into every `ExternRef` created in this store, which
+    /// refer to it only weakly, so that they keep it from moving to another
+    /// thread.
+    externref_token: Rc<()>,
     signal_handler: RefCell<Option<Box<SignalHandler<'static>>>>,
     jit_code_ranges: RefCell<Vec<(usize, usize)>>,
-    host_info: RefCell<HashMap<HostInfoKey, Rc<RefCell<dyn Any>>>>,
+    host_info: RefCell<HashMap<HostInfoKey, Arc<Mutex<dyn Any + Send>>>>,
     externref_activations_table: Rc<VMExternRefActivationsTable>,
     stack_map_registry: Rc<StackMapRegistry>,
+    /// An adjustment to add to the fuel consumed value in `interrupts` above
+    /// to get the true amount of fuel consumed.
+    fuel_adj: Cell<i64>,
+    /// The handle used to suspend the fiber currently executing wasm for this
+    /// store, if any. Only set while that fiber is actually running.
+    current_suspend: Cell<*const wasmtime_fiber::Suspend<Result<(), Trap>, (), Result<(), Trap>>>,
+    /// The context of the future currently polling a fiber for this store,
+    /// only set for the duration of that `poll`.
+    current_poll_cx: Cell<*mut Context<'static>>,
+    limiter: Option<Rc<dyn wasmtime_runtime::ResourceLimiter>>,
+    instance_count: Cell<usize>,
+    memory_count: Cell<usize>,
+    table_count: Cell<usize>,
+    /// What to do when wasm reaches the epoch deadline in `interrupts`.
+    epoch_deadline_behavior: RefCell<EpochDeadline>,
+    /// The epoch deadline last set with `Store::set_epoch_deadline`. The copy
+    /// in `interrupts` is lowered whenever an `EpochSampler` is due.
+    epoch_deadline: Cell<u64>,
+    /// The sampler of a `GuestProfiler` running on this store, if any.
+    epoch_sampler: RefCell<Option<EpochSampler>>,
+    /// The definitions of memories currently borrowed by `Memory::data_mut`.
+    borrowed_memories: RefCell<Vec<*mut VMMemoryDefinition>>,
+    /// Embedder data set with `Store::set_data`.
+    data: RefCell<Option<Box<dyn Any>>>,
+    /// The number of calls into wasm currently on the stack, which prevent
+    /// `Store::drop_instance` from deallocating anything.
+    wasm_calls: Cell<usize>,
+    /// The hook set with `Store::call_hook`, run on every transition between
+    /// host and wasm code.
+    call_hook: RefCell<Option<Box<dyn FnMut(CallHook) -> Result<(), Trap>>>>,
+    /// Set once a call hook is installed. Host function shims hold a clone of
+    /// this so they only need to upgrade their weak reference to the store
+    /// when there's a hook to run.
+    has_call_hook: Arc<AtomicBool>,
+    /// The hook set with `Store::set_debug_hook`, run before every
+    /// instruction of code compiled with `Config::guest_debug`.
+    debug_hook: RefCell<Option<Box<DebugHook>>>,
+    /// The functions created in this store for `Linker::shared_func`, keyed
+    /// by their id, so that each is only created once per store. They only
+    /// refer to the store weakly since it owns them.
+    shared_funcs: RefCell<HashMap<usize, WeakExtern>>,
+}
+
+/// A hook run before each wasm instruction, given the instance executing it,
+/// the instruction's offset in the module, and the locals of the function.
+pub(crate) type DebugHook =
+    dyn FnMut(&Store, *mut VMContext, u32, &DebugLocals<'_>) -> Result<(), Trap>;
+
+/// The action a store takes when its epoch deadline is reached.
+enum EpochDeadline {
+    /// Trap with an interrupt.
+    Trap,
+    /// Ask the host for the number of ticks to extend the deadline by.
+    Callback(Box<dyn FnMut(&Store) -> Result<u64, Trap>>),
+    /// Yield to the async executor and then extend the deadline by `delta`.
+    YieldAndExtendDeadline { delta: u64 },
+}
+
+/// A sampler run by the epoch check following a request from another thread,
+/// without consuming the store's epoch deadline.
+pub(crate) struct EpochSampler {
+    /// Set along with expiring the deadline in `interrupts` to request a
+    /// sample, see `EpochSampler::request`.
+    pub(crate) due: Arc<AtomicBool>,
+    /// Records a sample, returning `false` once the sampler is no longer
+    /// wanted.
+    pub(crate) sample: Box<dyn FnMut() -> bool + Send>,
+}
+
+impl EpochSampler {
+    /// Makes wasm running in the store with `interrupts` run the sampler
+    /// owning `due` at its next epoch check.
+    pub(crate) fn request(due: &AtomicBool, interrupts: &VMInterrupts) {
+        due.store(true, SeqCst);
+        interrupts.epoch_deadline.store(0, SeqCst);
+    }
+}
+
+/// A transition between host and wasm code, as passed to the hook configured
+/// with [`Store::call_hook`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CallHook {
+    /// The host is about to call into wasm, for example through
+    /// [`Func::call`](crate::Func::call) or to run a start function.
+    CallingWasm,
+    /// Wasm has returned to the host which called it, either normally or
+    /// with a trap.
+    ReturningFromWasm,
+    /// Wasm is about to call a host-defined [`Func`](crate::Func).
+    CallingHost,
+    /// A host-defined function is returning to the wasm which called it.
+    ReturningFromHost,
+}
+
+impl CallHook {
+    /// Returns whether this transition enters wasm code.
+    pub fn entering_wasm(&self) -> bool {
+        match self {
+            CallHook::CallingWasm | CallHook::ReturningFromHost => true,
+            CallHook::ReturningFromWasm | CallHook::CallingHost => false,
+        }
+    }
+
+    /// Returns whether this transition leaves wasm code for the host.
+    pub fn exiting_wasm(&self) -> bool {
+        !self.entering_wasm()
+    }
+}
+
+struct StoreInstance {
+    handle: InstanceHandle,
+    /// Whether this instance came from the engine's host allocator rather
+    /// than its (possibly pooling) wasm instance allocator.
+    host: bool,
 }
 
 struct HostInfoKey(VMExternRef);
@@ -842,6 +1282,32 @@ impl Hash for HostInfoKey {
 impl Store {
     /// Creates a new store to be associated with the given [`Engine`].
     pub fn new(engine: &Engine) -> Store {
+        Store::new_(engine, None)
+    }
+
+    /// Creates a new store to be associated with the given [`Engine`] and
+    /// using the supplied resource limiter.
+    ///
+    /// The limiter is consulted whenever a linear memory or table in this
+    /// store is created or grown, and bounds how many instances, memories and
+    /// tables may be instantiated in the store.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use wasmtime::*;
+    ///
+    /// let engine = Engine::default();
+    /// let store = Store::new_with_limits(
+    ///     &engine,
+    ///     StoreLimitsBuilder::new().instances(10).memory_pages(16).build(),
+    /// );
+    /// ```
+    pub fn new_with_limits(engine: &Engine, limiter: impl ResourceLimiter + 'static) -> Store {
+        Store::new_(engine, Some(Rc::new(ResourceLimiterProxy(limiter))))
+    }
+
+    fn new_(engine: &Engine, limiter: Option<Rc<dyn wasmtime_runtime::ResourceLimiter>>) -> Store {
         // Ensure that wasmtime_runtime's signal handlers are configured. Note
         // that at the `Store` level it means we should perform this
         // once-per-thread. Platforms like Unix, however, only require this
@@ -852,20 +1318,133 @@ impl Store {
         Store {
             inner: Rc::new(StoreInner {
                 engine: engine.clone(),
-                interrupts: Arc::new(Default::default()),
+                interrupts: Arc::new(VMInterrupts {
+                    epoch_ptr: engine.epoch_counter(),
+                    ..Default::default()
+                }),
                 signatures: RefCell::new(Default::default()),
                 instances: RefCell::new(Vec::new()),
+                instance_tokens: RefCell::new(HashMap::new()),
+                externref_token: Rc::new(()),
                 signal_handler: RefCell::new(None),
                 jit_code_ranges: RefCell::new(Vec::new()),
                 host_info: RefCell::new(HashMap::new()),
                 externref_activations_table: Rc::new(VMExternRefActivationsTable::new()),
                 stack_map_registry: Rc::new(StackMapRegistry::default()),
+                fuel_adj: Cell::new(0),
+                current_suspend: Cell::new(ptr::null()),
+                current_poll_cx: Cell::new(ptr::null_mut()),
+                limiter,
+                instance_count: Default::default(),
+                memory_count: Default::default(),
+                table_count: Default::default(),
+                epoch_deadline_behavior: RefCell::new(EpochDeadline::Trap),
+                epoch_deadline: Cell::new(0),
+                epoch_sampler: RefCell::new(None),
+                borrowed_memories: RefCell::new(Vec::new()),
+                data: RefCell::new(None),
+                wasm_calls: Cell::new(0),
+                call_hook: RefCell::new(None),
+                has_call_hook: Arc::new(AtomicBool::new(false)),
+                debug_hook: RefCell::new(None),
+                shared_funcs: Default::default(),
             }),
         }
     }
 
-    pub(crate) fn from_inner(inner: Rc<StoreInner>) -> Store {
-        Store { inner }
+    /// Records that the memory with `definition` is mutably borrowed by the
+    /// host until the returned guard is dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the memory is already borrowed.
+    pub(crate) fn borrow_memory(&self, definition: *mut VMMemoryDefinition) -> MemoryBorrow<'_> {
+        assert!(
+            !self.is_memory_borrowed(definition),
+            "memory is already mutably borrowed"
+        );
+        self.inner.borrowed_memories.borrow_mut().push(definition);
+        MemoryBorrow {
+            store: self,
+            definition,
+        }
+    }
+
+    /// Returns whether the memory with `definition` is currently borrowed.
+    pub(crate) fn is_memory_borrowed(&self, definition: *mut VMMemoryDefinition) -> bool {
+        self.inner.borrowed_memories.borrow().contains(&definition)
+    }
+
+    /// Returns whether any memory in this store is currently borrowed, in
+    /// which case wasm must not execute since it could access the memory.
+    pub(crate) fn any_memory_borrowed(&self) -> bool {
+        !self.inner.borrowed_memories.borrow().is_empty()
+    }
+
+    /// Returns the function with the `id` of a `Linker::shared_func` in this
+    /// store, creating it with `new` the first time.
+    pub(crate) fn shared_func(&self, id: usize, new: impl FnOnce(&Store) -> Func) -> Extern {
+        if let Some(func) = self.inner.shared_funcs.borrow().get(&id) {
+            return func.upgrade(self);
+        }
+        let func = Extern::from(new(self));
+        self.inner
+            .shared_funcs
+            .borrow_mut()
+            .insert(id, func.downgrade());
+        func
+    }
+
+    pub(crate) fn limiter(&self) -> &Option<Rc<dyn wasmtime_runtime::ResourceLimiter>> {
+        &self.inner.limiter
+    }
+
+    /// Counts an instantiation of `module` against the limiter's limits on
+    /// instances, memories and tables, failing if any would be exceeded.
+    ///
+    /// Nothing is counted unless all three are within their limits. Stores
+    /// without a limiter don't keep count.
+    pub(crate) fn bump_resource_counts(&self, module: &wasmtime_environ::Module) -> Result<()> {
+        let limiter = match self.limiter() {
+            Some(limiter) => limiter,
+            None => return Ok(()),
+        };
+
+        let inner = &self.inner;
+        let (memories, tables) = defined_memories_and_tables(module);
+        let counts = [
+            (&inner.instance_count, limiter.instances(), 1, "instance"),
+            (&inner.memory_count, limiter.memories(), memories, "memory"),
+            (&inner.table_count, limiter.tables(), tables, "table"),
+        ];
+        for (slot, max, amt, desc) in counts.iter() {
+            let new = slot.get().saturating_add(*amt);
+            if new > *max {
+                bail!(
+                    "resource limit exceeded: {} count too high at {}",
+                    desc,
+                    new
+                );
+            }
+        }
+        for (slot, _, amt, _) in counts.iter() {
+            slot.set(slot.get() + amt);
+        }
+
+        Ok(())
+    }
+
+    /// Undoes `bump_resource_counts` for an instance of `module` which was
+    /// deallocated, or which failed to be allocated in the first place.
+    pub(crate) fn unbump_resource_counts(&self, module: &wasmtime_environ::Module) {
+        if self.limiter().is_none() {
+            return;
+        }
+        let (memories, tables) = defined_memories_and_tables(module);
+        let unbump = |slot: &Cell<usize>, amt: usize| slot.set(slot.get().saturating_sub(amt));
+        unbump(&self.inner.instance_count, 1);
+        unbump(&self.inner.memory_count, memories);
+        unbump(&self.inner.table_count, tables);
     }
 
     /// Returns the [`Engine`] that this store is associated with.
@@ -873,15 +1452,6 @@ impl Store {
         &self.inner.engine
     }
 
-    /// Returns an optional reference to a ['RuntimeMemoryCreator']
-    pub(crate) fn memory_creator(&self) -> Option<&dyn RuntimeMemoryCreator> {
-        self.engine()
-            .config()
-            .memory_creator
-            .as_ref()
-            .map(|x| x as _)
-    }
-
     pub(crate) fn lookup_signature(&self, sig_index: VMSharedSignatureIndex) -> wasm::WasmFuncType {
         self.inner
             .signatures
@@ -950,11 +1520,24 @@ impl Store {
         );
     }
 
-    pub(crate) unsafe fn add_instance(&self, handle: InstanceHandle) -> StoreInstanceHandle {
-        self.inner.instances.borrow_mut().push(handle.clone());
+    pub(crate) unsafe fn add_instance(
+        &self,
+        handle: InstanceHandle,
+        host: bool,
+    ) -> StoreInstanceHandle {
+        let token = Rc::new(());
+        self.inner
+            .instance_tokens
+            .borrow_mut()
+            .insert(handle.vmctx_ptr() as usize, token.clone());
+        self.inner.instances.borrow_mut().push(StoreInstance {
+            handle: handle.clone(),
+            host,
+        });
         StoreInstanceHandle {
             store: self.clone(),
             handle,
+            token,
         }
     }
 
@@ -964,10 +1547,142 @@ impl Store {
             .instances
             .borrow()
             .iter()
-            .any(|i| i.vmctx_ptr() == handle.vmctx_ptr()));
+            .any(|i| i.handle.vmctx_ptr() == handle.vmctx_ptr()));
+        let token = self.inner.instance_tokens.borrow()[&(handle.vmctx_ptr() as usize)].clone();
         StoreInstanceHandle {
             store: self.clone(),
             handle,
+            token,
+        }
+    }
+
+    /// Deallocates `instance` now rather than when the store is dropped,
+    /// releasing its memories and tables.
+    ///
+    /// This allows a long-lived store to instantiate short-lived modules
+    /// without accumulating their resources. Since the instance's memory goes
+    /// away for good, this fails and leaves the instance in place if anything
+    /// could still reach it:
+    ///
+    /// * any other handle to the instance or its exports is still alive, for
+    ///   example a [`Func`](crate::Func) or [`Memory`](crate::Memory) taken
+    ///   from it, including those registered in a
+    ///   [`Linker`](crate::Linker) or wrapped in an
+    ///   [`ExternRef`](crate::ExternRef);
+    /// * another instance in the store imports one of its items, or holds a
+    ///   reference to one of its functions in a table or global;
+    /// * wasm is currently executing in this store, including from within a
+    ///   host function or on a suspended async call.
+    ///
+    /// An error is also returned if `instance` belongs to a different store.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let store = Store::default();
+    /// let module = Module::new(
+    ///     store.engine(),
+    ///     r#"(module (memory 1) (func (export "run")))"#,
+    /// )?;
+    /// for _ in 0..100 {
+    ///     let instance = Instance::new(&store, &module, &[])?;
+    ///     instance.get_func("run").unwrap().call(&[])?;
+    ///     store.drop_instance(instance)?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn drop_instance(&self, instance: Instance) -> Result<()> {
+        if !Store::same(self, instance.store()) {
+            bail!("cannot drop an instance from a different store");
+        }
+        if self.inner.wasm_calls.get() > 0 {
+            bail!("cannot drop an instance while wasm is executing in its store");
+        }
+
+        let mut instances = self.inner.instances.borrow_mut();
+        let index = instances
+            .iter()
+            .position(|i| i.handle.vmctx_ptr() == instance.handle.vmctx_ptr())
+            .expect("instance not in its store");
+        let key = instance.handle.vmctx_ptr() as usize;
+        // One reference is ours and one is `instance`'s own.
+        if Rc::strong_count(&self.inner.instance_tokens.borrow()[&key]) > 2 {
+            bail!("cannot drop an instance while handles to it are still alive");
+        }
+        let target = &instances[index].handle;
+        if instances
+            .iter()
+            .enumerate()
+            .any(|(i, other)| i != index && other.handle.depends_on(target))
+        {
+            bail!("cannot drop an instance which other instances depend on");
+        }
+
+        let removed = instances.remove(index);
+        drop(instances);
+        drop(instance);
+        let token = self.inner.instance_tokens.borrow_mut().remove(&key);
+        debug_assert_eq!(token.map(|t| Rc::strong_count(&t)), Some(1));
+        debug_assert!(!removed.host);
+
+        self.unbump_resource_counts(removed.handle.module());
+        unsafe {
+            self.engine().allocator().deallocate(&removed.handle);
+        }
+        Ok(())
+    }
+
+    /// Records that wasm is executing in this store until the returned guard
+    /// is dropped.
+    pub(crate) fn enter_wasm(&self) -> impl Drop + '_ {
+        let calls = self.inner.wasm_calls.get();
+        self.inner.wasm_calls.set(calls + 1);
+        Reset(&self.inner.wasm_calls, calls)
+    }
+
+    /// Returns the coverage of each module instantiated in this store, adding
+    /// up the counts of all of its instances still in the store.
+    ///
+    /// Modules are listed in the order they were first instantiated. The list
+    /// is empty unless [`Config::coverage`] was enabled.
+    pub fn coverage(&self) -> Vec<Coverage> {
+        let mut coverage: Vec<Coverage> = Vec::new();
+        if !self.engine().config().tunables.coverage {
+            return coverage;
+        }
+        self.for_each_wasm_instance(|handle| {
+            let module = match handle.host_state().downcast_ref::<ModuleInstanceState>() {
+                Some(state) => &state.module,
+                None => return,
+            };
+            let counts = handle.coverage_counters();
+            match coverage.iter_mut().find(|c| c.same_module(module)) {
+                Some(c) => c.add(counts),
+                None => coverage.push(Coverage::new(module, counts)),
+            }
+        });
+        coverage
+    }
+
+    /// Returns the token of the instance at `vmctx`, held weakly.
+    ///
+    /// Unlike the address of the instance, which may be reused once it's
+    /// dropped, the token is never shared with another instance for as long
+    /// as the weak reference is kept.
+    pub(crate) fn instance_token(&self, vmctx: *mut VMContext) -> Weak<()> {
+        Rc::downgrade(&self.inner.instance_tokens.borrow()[&(vmctx as usize)])
+    }
+
+    /// Calls `f` with each instance of a wasm module in this store, in the
+    /// order they were created.
+    pub(crate) fn for_each_wasm_instance(&self, mut f: impl FnMut(&InstanceHandle)) {
+        for instance in self.inner.instances.borrow().iter() {
+            if !instance.host {
+                f(&instance.handle);
+            }
         }
     }
 
@@ -980,9 +1695,13 @@ impl Store {
         Some(Self { inner })
     }
 
-    pub(crate) fn host_info(&self, externref: &ExternRef) -> Option<Rc<RefCell<dyn Any>>> {
+    pub(crate) fn externref_token(&self) -> Rc<()> {
+        self.inner.externref_token.clone()
+    }
+
+    pub(crate) fn host_info(&self, externref: &ExternRef) -> Option<Arc<Mutex<dyn Any + Send>>> {
         debug_assert!(
-            std::rc::Weak::ptr_eq(&self.weak(), &externref.store),
+            Weak::ptr_eq(&self.weak(), &externref.store),
             "externref must be from this store"
         );
         let infos = self.inner.host_info.borrow();
@@ -992,10 +1711,10 @@ impl Store {
     pub(crate) fn set_host_info(
         &self,
         externref: &ExternRef,
-        info: Option<Rc<RefCell<dyn Any>>>,
-    ) -> Option<Rc<RefCell<dyn Any>>> {
+        info: Option<Arc<Mutex<dyn Any + Send>>>,
+    ) -> Option<Arc<Mutex<dyn Any + Send>>> {
         debug_assert!(
-            std::rc::Weak::ptr_eq(&self.weak(), &externref.store),
+            Weak::ptr_eq(&self.weak(), &externref.store),
             "externref must be from this store"
         );
         let mut infos = self.inner.host_info.borrow_mut();
@@ -1030,6 +1749,136 @@ impl Store {
         Rc::ptr_eq(&a.inner, &b.inner)
     }
 
+    /// Prepares this store to be moved to another thread, see [`SendStore`].
+    ///
+    /// # Errors
+    ///
+    /// Returns the store back if anything else still refers to it, such as
+    /// another clone of it, a handle to an item within it like a
+    /// [`Func`](crate::Func) or an [`Instance`](crate::Instance), or an
+    /// [`ExternRef`] created in it.
+    ///
+    /// # Safety
+    ///
+    /// Host functions, store data, hooks and the resource limiter aren't
+    /// required to be `Send` when they're given to a store, so the caller
+    /// must make sure that everything it placed in this store, and anything
+    /// those hold on to, may be moved to another thread.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let store = Store::default();
+    /// let module = Module::new(store.engine(), r#"(module (func (export "run")))"#)?;
+    /// let instance = Instance::new(&store, &module, &[])?;
+    ///
+    /// // The instance still refers to the store.
+    /// let store = unsafe { store.into_send() }.unwrap_err();
+    /// drop(instance);
+    ///
+    /// // Nothing which isn't `Send` was placed in the store.
+    /// let store = unsafe { store.into_send() }.unwrap();
+    /// std::thread::spawn(move || {
+    ///     let store = store.into_store();
+    ///     // ...
+    /// })
+    /// .join()
+    /// .unwrap();
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub unsafe fn into_send(self) -> Result<SendStore, Store> {
+        if Rc::strong_count(&self.inner) != 1 || Rc::strong_count(&self.inner.externref_token) != 1
+        {
+            return Err(self);
+        }
+        Ok(SendStore { inner: self.inner })
+    }
+
+    /// Sets the embedder data associated with this `Store`, replacing any
+    /// previous data.
+    ///
+    /// Each store has a single slot for arbitrary host state, which can later
+    /// be retrieved with [`Store::data`] and [`Store::data_mut`]. Host
+    /// functions, including those defined through a [`Linker`](crate::Linker),
+    /// reach it through [`Caller::store`](crate::Caller::store), so state
+    /// shared by many host functions doesn't need to be captured in each of
+    /// their closures.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the data is currently borrowed through [`Store::data`] or
+    /// [`Store::data_mut`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// struct Counter {
+    ///     hits: u32,
+    /// }
+    ///
+    /// let store = Store::default();
+    /// store.set_data(Counter { hits: 0 });
+    /// let hit = Func::wrap(&store, |caller: Caller<'_>| {
+    ///     let store = caller.store();
+    ///     store.data_mut::<Counter>().unwrap().hits += 1;
+    /// });
+    ///
+    /// hit.get0::<()>()?()?;
+    /// hit.get0::<()>()?()?;
+    /// assert_eq!(store.data::<Counter>().unwrap().hits, 2);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_data<T: 'static>(&self, data: T) {
+        // The old data is dropped only once the store no longer borrows it,
+        // since its destructor may well reach back into the store.
+        let old = self.inner.data.replace(Some(Box::new(data)));
+        drop(old);
+    }
+
+    /// Returns a reference to the embedder data set with
+    /// [`Store::set_data`].
+    ///
+    /// Returns `None` if no data has been set, or if it isn't of type `T`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the data is currently borrowed through
+    /// [`Store::data_mut`].
+    pub fn data<T: 'static>(&self) -> Option<Ref<'_, T>> {
+        let data = self.inner.data.borrow();
+        if !data.as_ref().map_or(false, |data| data.is::<T>()) {
+            return None;
+        }
+        Some(Ref::map(data, |data| {
+            data.as_ref().unwrap().downcast_ref().unwrap()
+        }))
+    }
+
+    /// Returns a mutable reference to the embedder data set with
+    /// [`Store::set_data`].
+    ///
+    /// Returns `None` if no data has been set, or if it isn't of type `T`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the data is currently borrowed through [`Store::data`] or
+    /// [`Store::data_mut`].
+    pub fn data_mut<T: 'static>(&self) -> Option<RefMut<'_, T>> {
+        let data = self.inner.data.borrow_mut();
+        if !data.as_ref().map_or(false, |data| data.is::<T>()) {
+            return None;
+        }
+        Some(RefMut::map(data, |data| {
+            data.as_mut().unwrap().downcast_mut().unwrap()
+        }))
+    }
+
     /// Creates an [`InterruptHandle`] which can be used to interrupt the
     /// execution of instances within this `Store`.
     ///
@@ -1121,6 +1970,498 @@ impl Store {
         }
     }
 
+    /// Returns the amount of fuel consumed by this store's execution so far.
+    ///
+    /// If fuel consumption is not enabled via
+    /// [`Config::consume_fuel`] then this function will return `None`.
+    /// Also note that fuel, if enabled, must be originally configured via
+    /// [`Store::add_fuel`].
+    pub fn fuel_consumed(&self) -> Option<u64> {
+        if !self.engine().config().tunables.consume_fuel {
+            return None;
+        }
+        let consumed = unsafe { *self.inner.interrupts.fuel_consumed.get() };
+        Some(u64::try_from(self.inner.fuel_adj.get() + consumed).unwrap())
+    }
+
+    /// Adds fuel to this [`Store`] for wasm to consume while executing.
+    ///
+    /// For this method to work fuel consumption must be enabled via
+    /// [`Config::consume_fuel`]. By default a [`Store`] starts with 0 fuel
+    /// for wasm to execute with (meaning it will immediately trap).
+    /// This function must be called for the store to have
+    /// some fuel to allow WebAssembly to execute.
+    ///
+    /// Note that at this time when fuel is entirely consumed it will cause
+    /// wasm to trap. More usages of fuel are planned for the future.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if fuel consumption is not enabled
+    /// via [`Config::consume_fuel`].
+    pub fn add_fuel(&self, fuel: u64) -> Result<()> {
+        if !self.engine().config().tunables.consume_fuel {
+            bail!("fuel is not configured in this store");
+        }
+
+        // Fuel is stored as an i64, so we need to cast it. If the provided fuel
+        // value overflows that just assume that i64::max will suffice. Wasm
+        // execution isn't fast enough to burn through i64::max fuel in any
+        // reasonable amount of time anyway.
+        let fuel = i64::try_from(fuel).unwrap_or(i64::max_value());
+        let adj = self.inner.fuel_adj.get();
+        let consumed_ptr = unsafe { &mut *self.inner.interrupts.fuel_consumed.get() };
+
+        match (consumed_ptr.checked_sub(fuel), adj.checked_add(fuel)) {
+            // If we succesfully did arithmetic without overflowing then we can
+            // just update our fields.
+            (Some(consumed), Some(adj)) => {
+                self.inner.fuel_adj.set(adj);
+                *consumed_ptr = consumed;
+            }
+
+            // Otherwise something overflowed. Make sure that we preserve the
+            // amount of fuel that's already consumed, but otherwise assume that
+            // we were given infinite fuel.
+            _ => {
+                self.inner.fuel_adj.set(i64::max_value());
+                *consumed_ptr = (*consumed_ptr + adj) - i64::max_value();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sets the epoch deadline to `ticks_beyond_current` ticks past the
+    /// [`Engine`]'s current epoch.
+    ///
+    /// WebAssembly executing in this store will stop, as configured by
+    /// [`Store::epoch_deadline_trap`], [`Store::epoch_deadline_callback`] or
+    /// [`Store::epoch_deadline_async_yield_and_update`], once
+    /// [`Engine::increment_epoch`] has been called that many times.
+    ///
+    /// This only has an effect if [`Config::epoch_interruption`] is enabled.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let engine = Engine::new(Config::new().epoch_interruption(true));
+    /// let store = Store::new(&engine);
+    /// let module = Module::new(&engine, r#"(func (export "run") (loop br 0))"#)?;
+    /// let instance = Instance::new(&store, &module, &[])?;
+    /// let run = instance.get_func("run").unwrap().get0::<()>()?;
+    ///
+    /// // Let the loop spin until the epoch is next incremented.
+    /// store.set_epoch_deadline(1);
+    /// std::thread::spawn(move || {
+    ///     std::thread::sleep(std::time::Duration::from_millis(10));
+    ///     engine.increment_epoch();
+    /// });
+    ///
+    /// let trap = run().unwrap_err();
+    /// assert!(trap.to_string().contains("wasm trap: interrupt"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_epoch_deadline(&self, ticks_beyond_current: u64) {
+        let deadline = self
+            .engine()
+            .current_epoch()
+            .saturating_add(ticks_beyond_current);
+        self.inner.epoch_deadline.set(deadline);
+        self.publish_epoch_deadline();
+    }
+
+    /// Makes the store's epoch deadline the one compiled code checks against.
+    fn publish_epoch_deadline(&self) {
+        let deadline = self.inner.epoch_deadline.get();
+        self.inner.interrupts.epoch_deadline.store(deadline, SeqCst);
+        // Don't lose a sample requested while the deadline was being moved.
+        if let Some(sampler) = &*self.inner.epoch_sampler.borrow() {
+            if sampler.due.load(SeqCst) {
+                self.inner.interrupts.epoch_deadline.store(0, SeqCst);
+            }
+        }
+    }
+
+    /// Configures this store to trap whenever the epoch deadline is reached.
+    ///
+    /// This is the default behavior, and the trap produced is the same
+    /// interrupt trap raised by [`InterruptHandle::interrupt`].
+    pub fn epoch_deadline_trap(&self) {
+        *self.inner.epoch_deadline_behavior.borrow_mut() = EpochDeadline::Trap;
+    }
+
+    /// Configures this store to invoke `callback` whenever the epoch deadline
+    /// is reached.
+    ///
+    /// The callback returns the number of ticks past the current epoch to
+    /// set the new deadline to, after which execution resumes, or a trap to
+    /// abort execution with. This lets the host decide, for example, whether
+    /// a long-running guest has used up its time slice.
+    pub fn epoch_deadline_callback(
+        &self,
+        callback: impl FnMut(&Store) -> Result<u64, Trap> + 'static,
+    ) {
+        *self.inner.epoch_deadline_behavior.borrow_mut() =
+            EpochDeadline::Callback(Box::new(callback));
+    }
+
+    /// Configures this store to yield to the async executor whenever the
+    /// epoch deadline is reached, and to then extend the deadline by `delta`
+    /// ticks and resume execution.
+    ///
+    /// This allows wasm running on an async call to be periodically
+    /// preempted so that other futures get a chance to run.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if the store's [`Config`] doesn't have async
+    /// support enabled.
+    pub fn epoch_deadline_async_yield_and_update(&self, delta: u64) {
+        assert!(
+            self.async_support(),
+            "cannot use `epoch_deadline_async_yield_and_update` without enabling async support in the config"
+        );
+        *self.inner.epoch_deadline_behavior.borrow_mut() =
+            EpochDeadline::YieldAndExtendDeadline { delta };
+    }
+
+    /// Returns the handler to give to instances so that they can reach back
+    /// into this store when hitting the epoch deadline.
+    pub(crate) fn epoch_deadline_handler(
+        &self,
+    ) -> Option<Rc<dyn wasmtime_runtime::EpochDeadlineHandler>> {
+        if !self.engine().config().tunables.epoch_interruption {
+            return None;
+        }
+        Some(Rc::new(EpochDeadlineProxy(self.weak())))
+    }
+
+    /// Installs `sampler`, replacing the one of any previously started
+    /// `GuestProfiler`.
+    pub(crate) fn set_epoch_sampler(&self, sampler: EpochSampler) {
+        *self.inner.epoch_sampler.borrow_mut() = Some(sampler);
+    }
+
+    /// Carries out the configured epoch deadline behavior, returning the new
+    /// deadline if execution is to continue.
+    fn new_epoch(&self) -> Result<u64, wasmtime_runtime::Trap> {
+        // The deadline may only have been expired early to take a sample, in
+        // which case execution carries on with the deadline the store had.
+        {
+            let mut sampler = self.inner.epoch_sampler.borrow_mut();
+            if let Some(s) = &mut *sampler {
+                if s.due.swap(false, SeqCst) && !(s.sample)() {
+                    *sampler = None;
+                }
+            }
+        }
+        if self.engine().current_epoch() < self.inner.epoch_deadline.get() {
+            self.publish_epoch_deadline();
+            return Ok(self.inner.epoch_deadline.get());
+        }
+
+        // Take the behavior out of the store while running it, since a
+        // callback may well reconfigure the store.
+        let mut behavior = self
+            .inner
+            .epoch_deadline_behavior
+            .replace(EpochDeadline::Trap);
+        let delta = match &mut behavior {
+            EpochDeadline::Trap => Err(wasmtime_runtime::Trap::wasm(ir::TrapCode::Interrupt)),
+            EpochDeadline::Callback(callback) => callback(self).map_err(user_trap),
+            EpochDeadline::YieldAndExtendDeadline { delta } => {
+                let mut future = YieldNow(false);
+                let future = Pin::new(&mut future);
+                self.block_on(future).map(|()| *delta).map_err(user_trap)
+            }
+        };
+        self.inner.epoch_deadline_behavior.replace(behavior);
+
+        self.set_epoch_deadline(delta?);
+        return Ok(self.inner.epoch_deadline.get());
+
+        fn user_trap(trap: Trap) -> wasmtime_runtime::Trap {
+            wasmtime_runtime::Trap::User(Box::new(trap))
+        }
+    }
+
+    /// Returns the handler to give to instances so that they can reach back
+    /// into this store before each instruction when debugging.
+    pub(crate) fn debug_handler(&self) -> Option<Rc<dyn wasmtime_runtime::DebugHandler>> {
+        if !self.engine().config().tunables.guest_debug {
+            return None;
+        }
+        Some(Rc::new(DebugProxy(self.weak())))
+    }
+
+    /// Sets the hook run before every instruction of code compiled with
+    /// `Config::guest_debug`, replacing any previous one.
+    pub(crate) fn set_debug_hook(
+        &self,
+        hook: impl FnMut(&Store, *mut VMContext, u32, &DebugLocals<'_>) -> Result<(), Trap> + 'static,
+    ) {
+        *self.inner.debug_hook.borrow_mut() = Some(Box::new(hook));
+    }
+
+    fn before_instruction(
+        &self,
+        vmctx: *mut VMContext,
+        offset: u32,
+        locals: &DebugLocals<'_>,
+    ) -> Result<(), wasmtime_runtime::Trap> {
+        // As with epoch callbacks, take the hook out while it runs. It's only
+        // put back if it didn't install a new one in the meantime.
+        let mut hook = match self.inner.debug_hook.borrow_mut().take() {
+            Some(hook) => hook,
+            None => return Ok(()),
+        };
+        let result = hook(self, vmctx, offset, locals);
+        let mut slot = self.inner.debug_hook.borrow_mut();
+        if slot.is_none() {
+            *slot = Some(hook);
+        }
+        result.map_err(|trap| wasmtime_runtime::Trap::User(Box::new(trap)))
+    }
+
+    /// Configures a hook to be invoked on every transition between host and
+    /// wasm code in this store.
+    ///
+    /// The hook runs when the host calls into wasm, be it through
+    /// [`Func::call`](crate::Func::call), a typed function obtained from
+    /// [`Func::get1`](crate::Func::get1) and friends, or an instance's start
+    /// function, and again once that wasm returns or traps. It also runs
+    /// whenever wasm calls a host-defined [`Func`](crate::Func), and once
+    /// that function returns. This makes it possible to, for example,
+    /// account separately for time spent in guest and in host code.
+    ///
+    /// If the hook returns an error, then that error is raised as a trap in
+    /// place of the transition: a call doesn't happen, and a return traps
+    /// instead of handing back its results.
+    ///
+    /// Only one hook may be set at a time; setting another replaces it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+    /// use std::sync::Arc;
+    ///
+    /// let store = Store::default();
+    /// let host_calls = Arc::new(AtomicUsize::new(0));
+    /// let counter = host_calls.clone();
+    /// store.call_hook(move |kind| {
+    ///     if kind == CallHook::CallingHost {
+    ///         counter.fetch_add(1, SeqCst);
+    ///     }
+    ///     Ok(())
+    /// });
+    ///
+    /// let module = Module::new(
+    ///     store.engine(),
+    ///     r#"(module (import "" "" (func)) (func (export "run") call 0))"#,
+    /// )?;
+    /// let host = Func::wrap(&store, || {});
+    /// let instance = Instance::new(&store, &module, &[host.into()])?;
+    /// instance.get_func("run").unwrap().get0::<()>()?()?;
+    /// assert_eq!(host_calls.load(SeqCst), 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn call_hook(&self, hook: impl FnMut(CallHook) -> Result<(), Trap> + 'static) {
+        *self.inner.call_hook.borrow_mut() = Some(Box::new(hook));
+        self.inner.has_call_hook.store(true, Relaxed);
+    }
+
+    /// Returns the flag which is set once this store has a call hook.
+    pub(crate) fn has_call_hook(&self) -> Arc<AtomicBool> {
+        self.inner.has_call_hook.clone()
+    }
+
+    pub(crate) fn invoke_call_hook(&self, kind: CallHook) -> Result<(), Trap> {
+        self.inner.invoke_call_hook(kind)
+    }
+
+    pub(crate) fn async_support(&self) -> bool {
+        self.engine().config().async_support
+    }
+
+    /// Executes `func` on a separate native stack, returning a future which
+    /// completes once `func` does.
+    ///
+    /// While `func` is running any async host function it calls can use
+    /// `block_on` to wait on its own future, which suspends the fiber and
+    /// returns `Poll::Pending` from the future returned here.
+    pub(crate) async fn on_fiber<R>(&self, func: impl FnOnce() -> R) -> Result<R, Trap> {
+        debug_assert!(self.async_support());
+
+        // Wasm gets `max_wasm_stack` bytes of the fiber's stack, and we reserve
+        // some more for the host code that runs on it as well.
+        let stack_size = self.engine().config().max_wasm_stack + (1 << 20);
+
+        let mut slot = None;
+        let fiber = wasmtime_fiber::Fiber::new(stack_size, |keep_going, suspend| {
+            // The future may have been dropped before we ever started, in
+            // which case there's nothing to do.
+            keep_going?;
+
+            // Configure this store's suspension handle for the rest of the
+            // execution of this fiber. The raw pointer is only valid for the
+            // duration of this closure, so put back whatever was there before
+            // once we're done since another fiber's handle may have been
+            // active.
+            let prev = self.inner.current_suspend.replace(suspend);
+            let _reset = Reset(&self.inner.current_suspend, prev);
+
+            slot = Some(func());
+            Ok(())
+        })
+        .map_err(|e| Trap::from(anyhow::Error::from(e)))?;
+
+        FiberFuture {
+            fiber,
+            store: self,
+            tls: None,
+            frame_records: ptr::null(),
+        }
+        .await?;
+        return Ok(slot.unwrap());
+
+        struct FiberFuture<'a> {
+            fiber: wasmtime_fiber::Fiber<'a, Result<(), Trap>, (), Result<(), Trap>>,
+            store: &'a Store,
+            // The trap-handling state and the store's frame records of the
+            // wasm frames on the fiber's stack while it's suspended.
+            tls: Option<TlsRestore>,
+            frame_records: *const VMFrameRecord,
+        }
+
+        impl FiberFuture<'_> {
+            /// Resumes the fiber with `val`, returning `Err(())` if it
+            /// suspended itself on a pending host future.
+            ///
+            /// Wasm frames on the fiber push `CallThreadState`s onto the TLS
+            /// of whichever thread resumes it, and link their records into
+            /// the store's list of frame records. When the fiber suspends
+            /// both are detached until it's resumed again, possibly on
+            /// another thread, since this thread and store may go on to run
+            /// other wasm in the meantime.
+            fn resume(&mut self, val: Result<(), Trap>) -> Result<Result<(), Trap>, ()> {
+                let mark = TlsMark::current();
+                let val = match self.tls.take() {
+                    // The states are put back even if this thread couldn't
+                    // be set up for handling traps, in which case the fiber
+                    // is resumed with the error so that it unwinds.
+                    Some(tls) => unsafe { tls.replace() }
+                        .map_err(Trap::from_runtime)
+                        .and(val),
+                    None => val,
+                };
+                let frame_records = self.store.inner.interrupts.frame_records.get();
+                let outside = unsafe { ptr::replace(frame_records, self.frame_records) };
+
+                let result = self.fiber.resume(val);
+
+                self.frame_records = unsafe { ptr::replace(frame_records, outside) };
+                if result.is_err() {
+                    self.tls = Some(unsafe { TlsRestore::take(mark) });
+                }
+                result
+            }
+        }
+
+        impl Future for FiberFuture<'_> {
+            type Output = Result<(), Trap>;
+
+            fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+                // The fiber needs this `cx` to poll the futures of any async
+                // host functions it calls. It's only valid for this call to
+                // `poll`, so it's stashed in the store with an extended
+                // lifetime and then the previous context is restored on exit.
+                let cx =
+                    unsafe { std::mem::transmute::<&mut Context<'_>, *mut Context<'static>>(cx) };
+                let prev = self.store.inner.current_poll_cx.replace(cx);
+                let _reset = Reset(&self.store.inner.current_poll_cx, prev);
+
+                match self.resume(Ok(())) {
+                    Ok(result) => Poll::Ready(result),
+                    Err(()) => Poll::Pending,
+                }
+            }
+        }
+
+        // If the future is dropped while the fiber is suspended then the fiber
+        // still has live frames on its stack which need to be cleaned up. To
+        // do that it's resumed with a trap which is returned from the pending
+        // `block_on`, unwinding the wasm and host frames back out of the fiber.
+        impl Drop for FiberFuture<'_> {
+            fn drop(&mut self) {
+                if !self.fiber.done() {
+                    let result = self.resume(Err(Trap::new("future dropped")));
+                    // This should always complete the fiber. Host code could
+                    // technically catch the trap and keep going, but that's
+                    // not something that's supported.
+                    debug_assert!(result.is_ok());
+                }
+            }
+        }
+    }
+
+    /// Waits on `future` from within a fiber started by `on_fiber`, appearing
+    /// to block synchronously.
+    ///
+    /// Each time `future` is pending the fiber is suspended, propagating
+    /// `Poll::Pending` out of the future returned by `on_fiber`, and then it
+    /// is polled again once the fiber is resumed.
+    ///
+    /// Returns an error if this isn't executing on a fiber, or if the outer
+    /// future was dropped while suspended. In the latter case the trap should
+    /// be propagated back out to wasm, and executing more wasm shouldn't be
+    /// attempted.
+    pub(crate) fn block_on<T>(
+        &self,
+        mut future: Pin<&mut dyn Future<Output = T>>,
+    ) -> Result<T, Trap> {
+        // Take our `Suspend` handle for the duration of this function. While
+        // we're polling or suspended other wasm may run for this store, either
+        // synchronously or on its own fiber, and that must not see our handle.
+        let suspend = self.inner.current_suspend.replace(ptr::null());
+        let _reset = Reset(&self.inner.current_suspend, suspend);
+        if suspend.is_null() {
+            return Err(Trap::new(
+                "async host functions can only be called from wasm executing \
+                 on an async call",
+            ));
+        }
+
+        loop {
+            // Our fiber's wasm frames aren't the ones executing until we get
+            // control back, so detach the store's state describing them.
+            let _detached = DetachedWasm::new(self);
+
+            let future_result = {
+                let poll_cx = self.inner.current_poll_cx.replace(ptr::null_mut());
+                let _reset = Reset(&self.inner.current_poll_cx, poll_cx);
+                assert!(!poll_cx.is_null());
+                future.as_mut().poll(unsafe { &mut *poll_cx })
+            };
+
+            match future_result {
+                Poll::Ready(t) => break Ok(t),
+                Poll::Pending => {}
+            }
+
+            unsafe {
+                (*suspend).suspend(())?;
+            }
+        }
+    }
+
     pub(crate) fn externref_activations_table(&self) -> &Rc<VMExternRefActivationsTable> {
         &self.inner.externref_activations_table
     }
@@ -1156,16 +2497,189 @@ impl fmt::Debug for Store {
     }
 }
 
+/// A [`Store`] which is ready to move to another thread.
+///
+/// Created with [`Store::into_send`], which makes sure that nothing outside
+/// of the store refers to it anymore, and turned back into a `Store` with
+/// [`SendStore::into_store`] once it has reached its new thread.
+pub struct SendStore {
+    inner: Rc<StoreInner>,
+}
+
+// Safety: `Store::into_send` checked that `inner` is the only strong
+// reference to the store, and that no `ExternRef` holds on to it weakly. What
+// remains are the weak references and handles held by the store's own
+// instances and host functions, which all move along with it. That everything
+// placed in the store by the embedder may move too is up to the caller of
+// `Store::into_send`.
+unsafe impl Send for SendStore {}
+
+impl SendStore {
+    /// Returns the store, to be used on the current thread.
+    pub fn into_store(self) -> Store {
+        // Signal handlers may need to be configured on this thread, see
+        // `Store::new`.
+        wasmtime_runtime::init_traps();
+        Store { inner: self.inner }
+    }
+}
+
+impl fmt::Debug for SendStore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let inner = &*self.inner as *const StoreInner;
+        f.debug_struct("SendStore").field("inner", &inner).finish()
+    }
+}
+
+impl StoreInner {
+    /// Runs the hook set with `Store::call_hook`, if any.
+    ///
+    /// This is on `StoreInner` so that host function shims, which only hold
+    /// a weak reference to their store, can call it on their fast path.
+    pub(crate) fn invoke_call_hook(&self, kind: CallHook) -> Result<(), Trap> {
+        // Take the hook out of the store while running it so that it's not
+        // borrowed should it end up reconfiguring the store.
+        let mut hook = match self.call_hook.borrow_mut().take() {
+            Some(hook) => hook,
+            None => return Ok(()),
+        };
+        let result = hook(kind);
+        let mut slot = self.call_hook.borrow_mut();
+        if slot.is_none() {
+            *slot = Some(hook);
+        }
+        result
+    }
+}
+
 impl Drop for StoreInner {
     fn drop(&mut self) {
+        let engine = &self.engine;
         for instance in self.instances.get_mut().iter() {
             unsafe {
-                instance.dealloc();
+                if instance.host {
+                    engine.host_allocator().deallocate(&instance.handle);
+                } else {
+                    engine.allocator().deallocate(&instance.handle);
+                }
             }
         }
     }
 }
 
+/// Adapts a [`Store`] to the runtime's `EpochDeadlineHandler` trait.
+///
+/// Instances are owned by their store, so a weak reference is used here to
+/// avoid a cycle.
+struct EpochDeadlineProxy(Weak<StoreInner>);
+
+impl wasmtime_runtime::EpochDeadlineHandler for EpochDeadlineProxy {
+    fn new_epoch(&self) -> Result<u64, wasmtime_runtime::Trap> {
+        // The store must be alive for its instances to be executing.
+        Store::upgrade(&self.0).unwrap().new_epoch()
+    }
+}
+
+/// Adapts a [`Store`] to the runtime's `DebugHandler` trait, in the same way
+/// as `EpochDeadlineProxy`.
+struct DebugProxy(Weak<StoreInner>);
+
+impl wasmtime_runtime::DebugHandler for DebugProxy {
+    fn before_instruction(
+        &self,
+        vmctx: *mut VMContext,
+        offset: u32,
+        locals: &DebugLocals<'_>,
+    ) -> Result<(), wasmtime_runtime::Trap> {
+        Store::upgrade(&self.0)
+            .unwrap()
+            .before_instruction(vmctx, offset, locals)
+    }
+}
+
+/// A future which is pending the first time it's polled, used to yield back
+/// to the executor.
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if self.0 {
+            Poll::Ready(())
+        } else {
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+/// A guard for a memory borrowed with `Store::borrow_memory`.
+pub(crate) struct MemoryBorrow<'a> {
+    store: &'a Store,
+    definition: *mut VMMemoryDefinition,
+}
+
+impl Drop for MemoryBorrow<'_> {
+    fn drop(&mut self) {
+        let mut borrowed = self.store.inner.borrowed_memories.borrow_mut();
+        let i = borrowed.iter().position(|d| *d == self.definition).unwrap();
+        borrowed.swap_remove(i);
+    }
+}
+
+struct Reset<'a, T: Copy>(&'a Cell<T>, T);
+
+impl<T: Copy> Drop for Reset<'_, T> {
+    fn drop(&mut self) {
+        self.0.set(self.1);
+    }
+}
+
+/// The parts of a store's state that describe the wasm frames of the native
+/// stack currently executing, saved while a fiber is switched away from.
+///
+/// The wasm stack limit is cleared so that other wasm can compute a limit
+/// relative to its own stack, and the `externref` stack canary is detached so
+/// that garbage collection doesn't sweep away references still held by our
+/// suspended frames.
+struct DetachedWasm<'a> {
+    store: &'a Store,
+    stack_limit: usize,
+    stack_canary: Option<NonNull<u8>>,
+}
+
+impl<'a> DetachedWasm<'a> {
+    fn new(store: &'a Store) -> DetachedWasm<'a> {
+        let limit = &store.inner.interrupts.stack_limit;
+        let stack_limit = limit.load(SeqCst);
+        // Leave a pending interrupt in place for whatever runs next. If an
+        // interrupt races with this exchange then it's left in place too.
+        if stack_limit != wasmtime_environ::INTERRUPTED {
+            let _ = limit.compare_exchange(stack_limit, usize::max_value(), SeqCst, SeqCst);
+        }
+        DetachedWasm {
+            store,
+            stack_limit,
+            stack_canary: store.externref_activations_table().suspend_stack(),
+        }
+    }
+}
+
+impl Drop for DetachedWasm<'_> {
+    fn drop(&mut self) {
+        let limit = &self.store.inner.interrupts.stack_limit;
+        let current = limit.load(SeqCst);
+        if current != wasmtime_environ::INTERRUPTED {
+            let _ = limit.compare_exchange(current, self.stack_limit, SeqCst, SeqCst);
+        }
+        self.store
+            .externref_activations_table()
+            .resume_stack(self.stack_canary);
+    }
+}
+
 /// A threadsafe handle used to interrupt instances executing within a
 /// particular `Store`.
 ///
@@ -1187,6 +2701,16 @@ impl InterruptHandle {
     }
 }
 
+/// Returns the number of memories and tables defined, rather than imported,
+/// by `module`.
+fn defined_memories_and_tables(module: &wasmtime_environ::Module) -> (usize, usize) {
+    let local = &module.local;
+    (
+        local.memory_plans.len() - local.num_imported_memories,
+        local.table_plans.len() - local.num_imported_tables,
+    )
+}
+
 fn _assert_send_sync() {
     fn _assert<T: Send + Sync>() {}
     _assert::<Engine>();
@@ -1263,4 +2787,38 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn cache_store_is_shared_between_engines() -> Result<()> {
+        let store = Arc::new(InMemoryCacheStore::new());
+
+        let engine = Engine::new(Config::new().cache_store(store.clone()));
+        Module::new(&engine, "(module (func))")?;
+        assert_eq!(engine.config().cache_config.cache_hits(), 0);
+        assert_eq!(engine.config().cache_config.cache_misses(), 1);
+
+        let engine = Engine::new(Config::new().cache_store(store));
+        Module::new(&engine, "(module (func))")?;
+        assert_eq!(engine.config().cache_config.cache_hits(), 1);
+        assert_eq!(engine.config().cache_config.cache_misses(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn shared_funcs_are_created_once_per_store() -> Result<()> {
+        let engine = Engine::default();
+        let mut linker = crate::Linker::new(&Store::new(&engine));
+        linker.shared_func("host", "f", || {})?;
+        let module = Module::new(&engine, r#"(module (import "host" "f" (func)))"#)?;
+        let pre = linker.instantiate_pre(&module)?;
+
+        let store = Store::new(&engine);
+        for _ in 0..3 {
+            pre.instantiate(&store)?;
+        }
+        // Three instances of the module, and a single one for the function.
+        assert_eq!(store.inner.instances.borrow().len(), 4);
+        assert_eq!(store.inner.shared_funcs.borrow().len(), 1);
+        Ok(())
+    }
 }
@@ -0,0 +1,577 @@
+//! Pre-initialization of modules, by running their initialization function
+//! ahead of time and writing the resulting state back into a new module.
+//!
+//! See [`Module::preinitialize`](crate::Module::preinitialize) for details.
+
+use crate::encode::{encode_i64, encode_name, encode_section, encode_u32};
+use crate::{Engine, Extern, ExternType, Func, Instance, Module, Store, Trap, Val};
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::HashMap;
+use std::ops::Range;
+use wasmparser::{
+    DataKind, ElementItem, ElementKind, Export, ExternalKind, ImportSectionEntryType, MemoryType,
+    ModuleReader, SectionCode, TableType, Type,
+};
+use wasmtime_environ::wasm::{FuncIndex, GlobalIndex, MemoryIndex, TableIndex};
+use wasmtime_environ::EntityIndex;
+
+/// Runs of at least this many zero bytes split a snapshotted memory into
+/// separate data segments; shorter ones cost less to keep than a segment
+/// header.
+const MIN_ZERO_GAP: usize = 16;
+
+/// The most data segments emitted per memory, well within what validators
+/// accept. Memories which would need more have their segments merged.
+const MAX_DATA_SEGMENTS: usize = 10_000;
+
+/// A section of the original module, either kept or replaced in the output.
+struct RawSection<'a> {
+    /// The section id, zero for custom sections.
+    id: u8,
+    /// The whole section, including its header.
+    bytes: &'a [u8],
+}
+
+/// What the snapshot needs to know about the original module beyond what the
+/// instance provides.
+#[derive(Default)]
+struct ModuleInfo<'a> {
+    sections: Vec<RawSection<'a>>,
+    num_funcs: u32,
+    tables: Vec<TableType>,
+    memories: Vec<MemoryType>,
+    globals: Vec<wasmparser::GlobalType>,
+    exports: Vec<Export<'a>>,
+    /// Functions of declared element segments, which must be kept for the
+    /// `ref.func` instructions that rely on them to validate.
+    declared: Vec<Vec<u32>>,
+}
+
+pub(crate) fn preinitialize(engine: &Engine, wasm: &[u8], init_func: &str) -> Result<Vec<u8>> {
+    let info = parse(wasm)?;
+    let module = Module::new(engine, wasm)?;
+    let store = Store::new(engine);
+
+    // Imported functions may be called during instantiation as long as
+    // nothing actually calls them, since their effects on the host would be
+    // lost in the snapshot.
+    let imports = module
+        .imports()
+        .map(|import| {
+            let ty = match import.ty() {
+                ExternType::Func(ty) => ty,
+                _ => unreachable!("non-function imports are rejected when parsing"),
+            };
+            let name = format!("{}::{}", import.module(), import.name());
+            Func::new(&store, ty, move |_, _, _| {
+                Err(Trap::new(format!(
+                    "cannot snapshot a module whose initialization calls the import `{}`",
+                    name
+                )))
+            })
+            .into()
+        })
+        .collect::<Vec<Extern>>();
+    let instance = Instance::new(&store, &module, &imports)?;
+
+    let init = instance
+        .get_func(init_func)
+        .ok_or_else(|| anyhow!("no exported function named `{}`", init_func))?;
+    let ty = init.ty();
+    if !ty.params().is_empty() || !ty.results().is_empty() {
+        bail!(
+            "initialization function `{}` must take no parameters and return nothing",
+            init_func
+        );
+    }
+    init.call(&[])
+        .with_context(|| format!("failed to run initialization function `{}`", init_func))?;
+
+    let snapshot = Snapshot::new(&info, &instance)?;
+    Ok(snapshot.encode(wasm, &info, init_func))
+}
+
+fn parse(wasm: &[u8]) -> Result<ModuleInfo<'_>> {
+    let mut info = ModuleInfo::default();
+    let mut reader = ModuleReader::new(wasm)?;
+    while !reader.eof() {
+        let start = reader.current_position();
+        let section = reader.read()?;
+        let id = match section.code {
+            SectionCode::Custom { .. } => 0,
+            SectionCode::Type => 1,
+            SectionCode::Import => 2,
+            SectionCode::Function => 3,
+            SectionCode::Table => 4,
+            SectionCode::Memory => 5,
+            SectionCode::Global => 6,
+            SectionCode::Export => 7,
+            SectionCode::Start => 8,
+            SectionCode::Element => 9,
+            SectionCode::Code => 10,
+            SectionCode::Data => 11,
+            SectionCode::DataCount => 12,
+        };
+        match section.code {
+            SectionCode::Import => {
+                let mut imports = section.get_import_section_reader()?;
+                for _ in 0..imports.get_count() {
+                    let import = imports.read()?;
+                    let kind = match import.ty {
+                        ImportSectionEntryType::Function(_) => {
+                            info.num_funcs += 1;
+                            continue;
+                        }
+                        ImportSectionEntryType::Table(_) => "table",
+                        ImportSectionEntryType::Memory(_) => "memory",
+                        ImportSectionEntryType::Global(_) => "global",
+                    };
+                    bail!(
+                        "cannot snapshot a module importing {} `{}::{}`, whose state lives \
+                         outside of it",
+                        kind,
+                        import.module,
+                        import.field
+                    );
+                }
+            }
+            SectionCode::Function => {
+                info.num_funcs += section.get_function_section_reader()?.get_count();
+            }
+            SectionCode::Table => {
+                let mut tables = section.get_table_section_reader()?;
+                for _ in 0..tables.get_count() {
+                    info.tables.push(tables.read()?);
+                }
+            }
+            SectionCode::Memory => {
+                let mut memories = section.get_memory_section_reader()?;
+                for _ in 0..memories.get_count() {
+                    let memory = memories.read()?;
+                    if memory.shared {
+                        bail!("cannot snapshot a module with a shared memory");
+                    }
+                    info.memories.push(memory);
+                }
+            }
+            SectionCode::Global => {
+                let mut globals = section.get_global_section_reader()?;
+                for _ in 0..globals.get_count() {
+                    info.globals.push(globals.read()?.ty);
+                }
+            }
+            SectionCode::Export => {
+                let mut exports = section.get_export_section_reader()?;
+                for _ in 0..exports.get_count() {
+                    info.exports.push(exports.read()?);
+                }
+            }
+            SectionCode::Element => {
+                let mut elements = section.get_element_section_reader()?;
+                for _ in 0..elements.get_count() {
+                    let element = elements.read()?;
+                    match element.kind {
+                        ElementKind::Active { .. } => continue,
+                        ElementKind::Passive => bail!(
+                            "cannot snapshot a module with passive element segments, since \
+                             whether initialization dropped them is not captured"
+                        ),
+                        ElementKind::Declared => {}
+                    }
+                    let mut items = element.items.get_items_reader()?;
+                    let mut funcs = Vec::new();
+                    for _ in 0..items.get_count() {
+                        if let ElementItem::Func(index) = items.read()? {
+                            funcs.push(index);
+                        }
+                    }
+                    info.declared.push(funcs);
+                }
+            }
+            SectionCode::Data => {
+                let mut data = section.get_data_section_reader()?;
+                for _ in 0..data.get_count() {
+                    if let DataKind::Passive = data.read()?.kind {
+                        bail!(
+                            "cannot snapshot a module with passive data segments, since \
+                             whether initialization dropped them is not captured"
+                        );
+                    }
+                }
+            }
+            SectionCode::DataCount => bail!(
+                "cannot snapshot a module using `memory.init` or `data.drop`, since its data \
+                 segments are replaced"
+            ),
+            _ => {}
+        }
+        info.sections.push(RawSection {
+            id,
+            bytes: &wasm[start..section.range().end],
+        });
+    }
+    Ok(info)
+}
+
+/// The state of an instance after initialization, in terms of the original
+/// module's index spaces.
+struct Snapshot {
+    /// The current size of each table, and its non-null elements as runs of
+    /// function indices keyed by their offset.
+    tables: Vec<(u32, Vec<(u32, Vec<u32>)>)>,
+    /// The current size of each memory in pages, and its non-zero contents
+    /// keyed by their offset.
+    memories: Vec<(u32, Vec<(u32, Vec<u8>)>)>,
+    /// Each global's current value, as an encoded constant expression.
+    globals: Vec<Vec<u8>>,
+}
+
+impl Snapshot {
+    fn new(info: &ModuleInfo, instance: &Instance) -> Result<Snapshot> {
+        let entity = |index| {
+            let export = instance.handle.lookup_by_declaration(&index);
+            Extern::from_wasmtime_export(export, instance.handle.clone())
+        };
+
+        // Function references are captured by their index, which is found by
+        // their `VMCallerCheckedAnyfunc` in the instance.
+        let funcs = (0..info.num_funcs)
+            .map(|i| {
+                let func = entity(EntityIndex::Function(FuncIndex::from_u32(i)))
+                    .into_func()
+                    .unwrap();
+                (func.caller_checked_anyfunc().as_ptr() as usize, i)
+            })
+            .collect::<HashMap<_, _>>();
+        let func_index = |func: &Func| {
+            funcs
+                .get(&(func.caller_checked_anyfunc().as_ptr() as usize))
+                .copied()
+                .ok_or_else(|| {
+                    anyhow!("cannot snapshot a reference to a function from outside the module")
+                })
+        };
+
+        let mut tables = Vec::with_capacity(info.tables.len());
+        for i in 0..info.tables.len() {
+            let table = entity(EntityIndex::Table(TableIndex::new(i)))
+                .into_table()
+                .unwrap();
+            let mut runs: Vec<(u32, Vec<u32>)> = Vec::new();
+            for j in 0..table.size() {
+                let func = match table.get(j).unwrap() {
+                    Val::FuncRef(Some(func)) => func,
+                    Val::FuncRef(None) | Val::ExternRef(None) => continue,
+                    _ => {
+                        bail!("cannot snapshot a non-null `externref`, which refers to host state")
+                    }
+                };
+                let index = func_index(&func)?;
+                match runs.last_mut() {
+                    Some((offset, run)) if *offset + run.len() as u32 == j => run.push(index),
+                    _ => runs.push((j, vec![index])),
+                }
+            }
+            tables.push((table.size(), runs));
+        }
+
+        let mut memories = Vec::with_capacity(info.memories.len());
+        for i in 0..info.memories.len() {
+            let memory = entity(EntityIndex::Memory(MemoryIndex::new(i)))
+                .into_memory()
+                .unwrap();
+            let data = unsafe { std::slice::from_raw_parts(memory.data_ptr(), memory.data_size()) };
+            let segments = data_segments(data)
+                .into_iter()
+                .map(|range| (range.start as u32, data[range].to_vec()))
+                .collect();
+            memories.push((memory.size(), segments));
+        }
+
+        let mut globals = Vec::with_capacity(info.globals.len());
+        for i in 0..info.globals.len() {
+            let global = entity(EntityIndex::Global(GlobalIndex::new(i)))
+                .into_global()
+                .unwrap();
+            let mut expr = Vec::new();
+            match global.get() {
+                Val::I32(x) => {
+                    expr.push(0x41);
+                    encode_i64(&mut expr, x.into());
+                }
+                Val::I64(x) => {
+                    expr.push(0x42);
+                    encode_i64(&mut expr, x);
+                }
+                Val::F32(bits) => {
+                    expr.push(0x43);
+                    expr.extend_from_slice(&bits.to_le_bytes());
+                }
+                Val::F64(bits) => {
+                    expr.push(0x44);
+                    expr.extend_from_slice(&bits.to_le_bytes());
+                }
+                Val::V128(bits) => {
+                    expr.push(0xfd);
+                    encode_u32(&mut expr, 0x0c);
+                    expr.extend_from_slice(&bits.to_le_bytes());
+                }
+                Val::FuncRef(Some(func)) => {
+                    expr.push(0xd2);
+                    encode_u32(&mut expr, func_index(&func)?);
+                }
+                Val::FuncRef(None) => expr.extend_from_slice(&[0xd0, 0x70]),
+                Val::ExternRef(None) => expr.extend_from_slice(&[0xd0, 0x6f]),
+                Val::ExternRef(Some(_)) => {
+                    bail!("cannot snapshot a non-null `externref`, which refers to host state")
+                }
+            }
+            expr.push(0x0b);
+            globals.push(expr);
+        }
+
+        Ok(Snapshot {
+            tables,
+            memories,
+            globals,
+        })
+    }
+
+    /// Writes `wasm` back out with its state replaced by this snapshot, its
+    /// start function removed (it has already run) and without the export of
+    /// `init_func`.
+    fn encode(&self, wasm: &[u8], info: &ModuleInfo, init_func: &str) -> Vec<u8> {
+        let mut replaced = HashMap::new();
+        if !info.tables.is_empty() {
+            let mut body = Vec::new();
+            encode_u32(&mut body, info.tables.len() as u32);
+            for (table, (size, _)) in info.tables.iter().zip(&self.tables) {
+                body.push(encode_type(table.element_type));
+                write_limits(&mut body, *size, table.limits.maximum);
+            }
+            replaced.insert(4, Some(body));
+        }
+        if !info.memories.is_empty() {
+            let mut body = Vec::new();
+            encode_u32(&mut body, info.memories.len() as u32);
+            for (memory, (size, _)) in info.memories.iter().zip(&self.memories) {
+                write_limits(&mut body, *size, memory.limits.maximum);
+            }
+            replaced.insert(5, Some(body));
+        }
+        if !info.globals.is_empty() {
+            let mut body = Vec::new();
+            encode_u32(&mut body, info.globals.len() as u32);
+            for (global, expr) in info.globals.iter().zip(&self.globals) {
+                body.push(encode_type(global.content_type));
+                body.push(global.mutable as u8);
+                body.extend_from_slice(expr);
+            }
+            replaced.insert(6, Some(body));
+        }
+        if !info.exports.is_empty() {
+            let exports = info
+                .exports
+                .iter()
+                .filter(|e| match e.kind {
+                    ExternalKind::Function => e.field != init_func,
+                    _ => true,
+                })
+                .collect::<Vec<_>>();
+            let mut body = Vec::new();
+            encode_u32(&mut body, exports.len() as u32);
+            for export in exports {
+                encode_name(&mut body, export.field);
+                body.push(match export.kind {
+                    ExternalKind::Function => 0x00,
+                    ExternalKind::Table => 0x01,
+                    ExternalKind::Memory => 0x02,
+                    ExternalKind::Global => 0x03,
+                });
+                encode_u32(&mut body, export.index);
+            }
+            replaced.insert(7, Some(body));
+        }
+        replaced.insert(8, None);
+        {
+            let mut count = 0;
+            let mut body = Vec::new();
+            for (table, (_, runs)) in self.tables.iter().enumerate() {
+                for (offset, funcs) in runs {
+                    if table == 0 {
+                        body.push(0x00);
+                    } else {
+                        body.push(0x02);
+                        encode_u32(&mut body, table as u32);
+                    }
+                    write_offset(&mut body, *offset);
+                    if table != 0 {
+                        body.push(0x00);
+                    }
+                    encode_u32(&mut body, funcs.len() as u32);
+                    for func in funcs {
+                        encode_u32(&mut body, *func);
+                    }
+                    count += 1;
+                }
+            }
+            for funcs in &info.declared {
+                body.extend_from_slice(&[0x03, 0x00]);
+                encode_u32(&mut body, funcs.len() as u32);
+                for func in funcs {
+                    encode_u32(&mut body, *func);
+                }
+                count += 1;
+            }
+            replaced.insert(9, with_count(count, body));
+        }
+        {
+            let mut count = 0;
+            let mut body = Vec::new();
+            for (memory, (_, segments)) in self.memories.iter().enumerate() {
+                for (offset, data) in segments {
+                    if memory == 0 {
+                        body.push(0x00);
+                    } else {
+                        body.push(0x02);
+                        encode_u32(&mut body, memory as u32);
+                    }
+                    write_offset(&mut body, *offset);
+                    encode_u32(&mut body, data.len() as u32);
+                    body.extend_from_slice(data);
+                    count += 1;
+                }
+            }
+            replaced.insert(11, with_count(count, body));
+        }
+
+        // Sections which the original module didn't have are placed before
+        // the first section which must follow them.
+        let mut added = replaced
+            .iter()
+            .filter(|(id, body)| body.is_some() && !info.sections.iter().any(|s| s.id == **id))
+            .map(|(id, _)| *id)
+            .collect::<Vec<u8>>();
+        added.sort_by_key(|id| section_order(*id));
+        let mut added = added.into_iter().peekable();
+
+        let mut out = wasm[..8].to_vec();
+        for section in &info.sections {
+            if section.id == 0 {
+                out.extend_from_slice(section.bytes);
+                continue;
+            }
+            while let Some(id) = added.peek().copied() {
+                if section_order(id) > section_order(section.id) {
+                    break;
+                }
+                encode_section(&mut out, id, replaced[&id].as_ref().unwrap());
+                added.next();
+            }
+            match replaced.get(&section.id) {
+                Some(Some(body)) => encode_section(&mut out, section.id, body),
+                Some(None) => {}
+                None => out.extend_from_slice(section.bytes),
+            }
+        }
+        for id in added {
+            encode_section(&mut out, id, replaced[&id].as_ref().unwrap());
+        }
+        out
+    }
+}
+
+/// Splits `data` into the ranges covering its non-zero bytes.
+fn data_segments(data: &[u8]) -> Vec<Range<usize>> {
+    let mut gap = MIN_ZERO_GAP;
+    loop {
+        let mut segments: Vec<Range<usize>> = Vec::new();
+        let mut zeros = 0;
+        for (i, byte) in data.iter().enumerate() {
+            if *byte == 0 {
+                zeros += 1;
+                continue;
+            }
+            match segments.last_mut() {
+                Some(segment) if zeros < gap => segment.end = i + 1,
+                _ => segments.push(i..i + 1),
+            }
+            zeros = 0;
+        }
+        if segments.len() <= MAX_DATA_SEGMENTS {
+            return segments;
+        }
+        gap *= 2;
+    }
+}
+
+/// Returns where a section with `id` goes in a module, which for the data
+/// count section isn't its id.
+fn section_order(id: u8) -> u32 {
+    match id {
+        12 => 95,
+        id => u32::from(id) * 10,
+    }
+}
+
+fn with_count(count: u32, body: Vec<u8>) -> Option<Vec<u8>> {
+    if count == 0 {
+        return None;
+    }
+    let mut section = Vec::with_capacity(body.len() + 5);
+    encode_u32(&mut section, count);
+    section.extend(body);
+    Some(section)
+}
+
+fn encode_type(ty: Type) -> u8 {
+    match ty {
+        Type::I32 => 0x7f,
+        Type::I64 => 0x7e,
+        Type::F32 => 0x7d,
+        Type::F64 => 0x7c,
+        Type::V128 => 0x7b,
+        Type::FuncRef => 0x70,
+        Type::ExternRef => 0x6f,
+        _ => unreachable!("not a value type: {:?}", ty),
+    }
+}
+
+fn write_limits(out: &mut Vec<u8>, min: u32, max: Option<u32>) {
+    match max {
+        Some(max) => {
+            out.push(0x01);
+            encode_u32(out, min);
+            encode_u32(out, max);
+        }
+        None => {
+            out.push(0x00);
+            encode_u32(out, min);
+        }
+    }
+}
+
+/// Writes the `i32.const` offset expression of an active segment.
+fn write_offset(out: &mut Vec<u8>, offset: u32) {
+    out.push(0x41);
+    encode_i64(out, i64::from(offset as i32));
+    out.push(0x0b);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{data_segments, MIN_ZERO_GAP};
+
+    #[test]
+    fn segments_skip_long_zero_runs() {
+        let mut data = vec![0; 100];
+        data[3] = 1;
+        data[5] = 2;
+        data[5 + MIN_ZERO_GAP + 1] = 3;
+        assert_eq!(
+            data_segments(&data),
+            vec![3..6, 5 + MIN_ZERO_GAP + 1..5 + MIN_ZERO_GAP + 2]
+        );
+        assert!(data_segments(&[0; 10]).is_empty());
+    }
+}
@@ -10,8 +10,9 @@ use wasmtime_environ::entity::PrimaryMap;
 use wasmtime_environ::wasm::DefinedFuncIndex;
 use wasmtime_environ::Module;
 use wasmtime_runtime::{
-    Imports, InstanceHandle, StackMapRegistry, VMExternRefActivationsTable, VMFunctionBody,
-    VMSharedSignatureIndex, VMTrampoline,
+    Imports, InstanceAllocationRequest, InstanceAllocator, OnDemandInstanceAllocator,
+    StackMapRegistry, VMExternRefActivationsTable, VMFunctionBody, VMSharedSignatureIndex,
+    VMTrampoline,
 };
 
 pub(crate) fn create_handle(
@@ -20,6 +21,29 @@ pub(crate) fn create_handle(
     finished_functions: PrimaryMap<DefinedFuncIndex, *mut [VMFunctionBody]>,
     trampolines: HashMap<VMSharedSignatureIndex, VMTrampoline>,
     state: Box<dyn Any>,
+) -> Result<StoreInstanceHandle> {
+    create_handle_with_allocator(
+        module,
+        store,
+        finished_functions,
+        trampolines,
+        state,
+        store.engine().host_allocator(),
+    )
+}
+
+/// Like `create_handle`, but allocates the instance with `allocator` rather
+/// than the engine's host allocator.
+///
+/// The instance is still deallocated by the engine's host allocator, so
+/// `allocator` must be an `OnDemandInstanceAllocator` too.
+pub(crate) fn create_handle_with_allocator(
+    module: Module,
+    store: &Store,
+    finished_functions: PrimaryMap<DefinedFuncIndex, *mut [VMFunctionBody]>,
+    trampolines: HashMap<VMSharedSignatureIndex, VMTrampoline>,
+    state: Box<dyn Any>,
+    allocator: &OnDemandInstanceAllocator,
 ) -> Result<StoreInstanceHandle> {
     let imports = Imports::new(
         PrimaryMap::new(),
@@ -37,19 +61,24 @@ pub(crate) fn create_handle(
         .collect::<PrimaryMap<_, _>>();
 
     unsafe {
-        let handle = InstanceHandle::new(
-            Arc::new(module),
-            Arc::new(()),
-            finished_functions.into_boxed_slice(),
+        let handle = allocator.allocate(InstanceAllocationRequest {
+            module: Arc::new(module),
+            code: Arc::new(()),
+            finished_functions: finished_functions.into_boxed_slice(),
             trampolines,
             imports,
-            store.memory_creator(),
-            signatures.into_boxed_slice(),
-            state,
-            store.interrupts().clone(),
-            &**store.externref_activations_table() as *const VMExternRefActivationsTable as *mut _,
-            &**store.stack_map_registry() as *const StackMapRegistry as *mut _,
-        )?;
-        Ok(store.add_instance(handle))
+            vmshared_signatures: signatures.into_boxed_slice(),
+            host_state: state,
+            interrupts: store.interrupts().clone(),
+            externref_activations_table: &**store.externref_activations_table()
+                as *const VMExternRefActivationsTable
+                as *mut _,
+            stack_map_registry: &**store.stack_map_registry() as *const StackMapRegistry as *mut _,
+            limiter: store.limiter().clone(),
+            epoch_deadline_handler: None,
+            debug_handler: None,
+            memory_images: None,
+        })?;
+        Ok(store.add_instance(handle, true))
     }
 }
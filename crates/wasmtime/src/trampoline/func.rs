@@ -232,13 +232,13 @@ pub fn create_handle_with_function(
     module
         .exports
         .insert("trampoline".to_string(), EntityIndex::Function(func_id));
-    let trampoline = make_trampoline(isa.as_ref(), &mut code_memory, &mut fn_builder_ctx, &sig);
-    finished_functions.push(trampoline);
+    let stub = make_trampoline(isa.as_ref(), &mut code_memory, &mut fn_builder_ctx, &sig);
+    finished_functions.push(stub);
 
     // ... and then we also need a trampoline with the standard "trampoline ABI"
     // which enters into the ABI specified by `ft`. Note that this is only used
     // if `Func::call` is called on an object created by `Func::new`.
-    let trampoline = wasmtime_jit::make_trampoline(
+    let (trampoline, trampoline_body) = wasmtime_jit::make_trampoline(
         &*isa,
         &mut code_memory,
         &mut fn_builder_ctx,
@@ -252,6 +252,9 @@ pub fn create_handle_with_function(
     // code memory (makes it executable) and ensuring all our various bits of
     // state make it into the instance constructors.
     code_memory.publish(isa.as_ref());
+    let profiler = &store.engine().config().profiler;
+    profiler.trampoline_load("wasm::host-function-stub", stub);
+    profiler.trampoline_load("wasm::host-function-trampoline", trampoline_body);
     let trampoline_state = TrampolineState { func, code_memory };
     create_handle(
         module,
@@ -1,6 +1,7 @@
 use super::create_handle::create_handle;
 use crate::trampoline::StoreInstanceHandle;
-use crate::{GlobalType, Mutability, Store, Val};
+use crate::values::into_checked_anyfunc;
+use crate::{GlobalType, Mutability, Store, Val, ValType};
 use anyhow::{bail, Result};
 use wasmtime_environ::entity::PrimaryMap;
 use wasmtime_environ::{wasm, EntityIndex, Module};
@@ -8,9 +9,12 @@ use wasmtime_environ::{wasm, EntityIndex, Module};
 pub fn create_global(store: &Store, gt: &GlobalType, val: Val) -> Result<StoreInstanceHandle> {
     let global = wasm::Global {
         wasm_ty: gt.content().to_wasm_type(),
-        ty: match gt.content().get_wasmtime_type() {
-            Some(t) => t,
-            None => bail!("cannot support {:?} as a wasm global type", gt.content()),
+        ty: match gt.content() {
+            ValType::FuncRef => store.engine().config().target_isa().pointer_type(),
+            content => match content.get_wasmtime_type() {
+                Some(t) => t,
+                None => bail!("cannot support {:?} as a wasm global type", content),
+            },
         },
         mutability: match gt.mutability() {
             Mutability::Const => false,
@@ -21,6 +25,9 @@ pub fn create_global(store: &Store, gt: &GlobalType, val: Val) -> Result<StoreIn
             Val::I64(i) => wasm::GlobalInit::I64Const(i),
             Val::F32(f) => wasm::GlobalInit::F32Const(f),
             Val::F64(f) => wasm::GlobalInit::F64Const(f),
+            // Reference values can't be expressed as a constant initializer,
+            // so start out null and store the real value below.
+            Val::ExternRef(_) | Val::FuncRef(_) => wasm::GlobalInit::RefNullConst,
             _ => unimplemented!("create_global for {:?}", gt),
         },
     };
@@ -36,5 +43,18 @@ pub fn create_global(store: &Store, gt: &GlobalType, val: Val) -> Result<StoreIn
         Default::default(),
         Box::new(()),
     )?;
+
+    let definition = match handle.lookup("global").expect("global export") {
+        wasmtime_runtime::Export::Global(g) => g.definition,
+        _ => unreachable!(),
+    };
+    unsafe {
+        match val {
+            Val::ExternRef(x) => *(*definition).as_externref_mut() = x.map(|x| x.inner),
+            Val::FuncRef(_) => *(*definition).as_anyfunc_mut() = into_checked_anyfunc(val, store)?,
+            _ => {}
+        }
+    }
+
     Ok(handle)
 }
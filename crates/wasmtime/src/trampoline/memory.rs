@@ -1,4 +1,4 @@
-use super::create_handle::create_handle;
+use super::create_handle::{create_handle, create_handle_with_allocator};
 use crate::externals::{LinearMemory, MemoryCreator};
 use crate::trampoline::StoreInstanceHandle;
 use crate::Store;
@@ -6,7 +6,10 @@ use crate::{Limits, MemoryType};
 use anyhow::Result;
 use wasmtime_environ::entity::PrimaryMap;
 use wasmtime_environ::{wasm, EntityIndex, MemoryPlan, MemoryStyle, Module, WASM_PAGE_SIZE};
-use wasmtime_runtime::{RuntimeLinearMemory, RuntimeMemoryCreator, VMMemoryDefinition};
+use wasmtime_runtime::{
+    OnDemandInstanceAllocator, RuntimeLinearMemory, RuntimeMemoryCreator, SharedMemory,
+    VMMemoryDefinition,
+};
 
 use std::sync::Arc;
 
@@ -14,30 +17,63 @@ pub fn create_handle_with_memory(
     store: &Store,
     memory: &MemoryType,
 ) -> Result<StoreInstanceHandle> {
-    let mut module = Module::new();
-
     let memory = wasm::Memory {
         minimum: memory.limits().min(),
         maximum: memory.limits().max(),
-        shared: false, // TODO
+        shared: memory.is_shared(),
     };
 
     let memory_plan =
         wasmtime_environ::MemoryPlan::for_memory(memory, &store.engine().config().tunables);
-    let memory_id = module.local.memory_plans.push(memory_plan);
-    module
-        .exports
-        .insert("memory".to_string(), EntityIndex::Memory(memory_id));
 
     create_handle(
-        module,
+        memory_module(memory_plan),
+        store,
+        PrimaryMap::new(),
+        Default::default(),
+        Box::new(()),
+    )
+}
+
+pub fn create_handle_with_shared_memory(
+    store: &Store,
+    memory: &SharedMemory,
+) -> Result<StoreInstanceHandle> {
+    // The instance's memory is a handle to `memory` itself, so that every
+    // store using it sees the same contents.
+    let allocator = OnDemandInstanceAllocator::new(Some(Arc::new(SharedMemoryCreator {
+        memory: memory.clone(),
+    })));
+
+    create_handle_with_allocator(
+        memory_module(memory.plan().clone()),
         store,
         PrimaryMap::new(),
         Default::default(),
         Box::new(()),
+        &allocator,
     )
 }
 
+fn memory_module(memory_plan: MemoryPlan) -> Module {
+    let mut module = Module::new();
+    let memory_id = module.local.memory_plans.push(memory_plan);
+    module
+        .exports
+        .insert("memory".to_string(), EntityIndex::Memory(memory_id));
+    module
+}
+
+struct SharedMemoryCreator {
+    memory: SharedMemory,
+}
+
+impl RuntimeMemoryCreator for SharedMemoryCreator {
+    fn new_memory(&self, _plan: &MemoryPlan) -> Result<Box<dyn RuntimeLinearMemory>, String> {
+        Ok(Box::new(self.memory.clone()))
+    }
+}
+
 struct LinearMemoryProxy {
     mem: Box<dyn LinearMemory>,
 }
@@ -66,7 +102,12 @@ pub(crate) struct MemoryCreatorProxy {
 
 impl RuntimeMemoryCreator for MemoryCreatorProxy {
     fn new_memory(&self, plan: &MemoryPlan) -> Result<Box<dyn RuntimeLinearMemory>, String> {
-        let ty = MemoryType::new(Limits::new(plan.memory.minimum, plan.memory.maximum));
+        let limits = Limits::new(plan.memory.minimum, plan.memory.maximum);
+        let ty = if plan.memory.shared {
+            MemoryType::shared(limits)
+        } else {
+            MemoryType::new(limits)
+        };
         let reserved_size_in_bytes = match plan.style {
             MemoryStyle::Static { bound } => Some(bound as u64 * WASM_PAGE_SIZE as u64),
             MemoryStyle::Dynamic => None,
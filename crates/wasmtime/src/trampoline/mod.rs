@@ -10,21 +10,24 @@ pub(crate) use memory::MemoryCreatorProxy;
 
 use self::func::create_handle_with_function;
 use self::global::create_global;
-use self::memory::create_handle_with_memory;
+use self::memory::{create_handle_with_memory, create_handle_with_shared_memory};
 use self::table::create_handle_with_table;
 use crate::{FuncType, GlobalType, MemoryType, Store, TableType, Trap, Val};
 use anyhow::Result;
 use std::any::Any;
 use std::ops::Deref;
+use std::rc::Rc;
 use wasmtime_runtime::{InstanceHandle, VMContext, VMFunctionBody, VMTrampoline};
 
 /// A wrapper around `wasmtime_runtime::InstanceHandle` which pairs it with the
 /// `Store` that it's rooted within. The instance is deallocated when `Store` is
-/// deallocated, so this is a safe handle in terms of memory management for the
-/// `Store`.
+/// deallocated, or by `Store::drop_instance` once no handles to it remain, so
+/// this is a safe handle in terms of memory management for the `Store`.
 pub struct StoreInstanceHandle {
     pub store: Store,
     pub handle: InstanceHandle,
+    /// Shared by all handles to this instance, letting the store count them.
+    pub(crate) token: Rc<()>,
 }
 
 impl Clone for StoreInstanceHandle {
@@ -34,6 +37,7 @@ impl Clone for StoreInstanceHandle {
             // Note should be safe because the lifetime of the instance handle
             // is tied to the `Store` which this is paired with.
             handle: unsafe { self.handle.clone() },
+            token: self.token.clone(),
         }
     }
 }
@@ -101,6 +105,17 @@ pub fn generate_memory_export(
     }
 }
 
+pub fn generate_shared_memory_export(
+    store: &Store,
+    m: &wasmtime_runtime::SharedMemory,
+) -> Result<(StoreInstanceHandle, wasmtime_runtime::ExportMemory)> {
+    let instance = create_handle_with_shared_memory(store, m)?;
+    match instance.lookup("memory").expect("memory export") {
+        wasmtime_runtime::Export::Memory(m) => Ok((instance, m)),
+        _ => unreachable!(),
+    }
+}
+
 pub fn generate_table_export(
     store: &Store,
     t: &TableType,
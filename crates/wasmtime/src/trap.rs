@@ -1,9 +1,10 @@
 use crate::frame_info::{GlobalFrameInfo, FRAME_INFO};
-use crate::FrameInfo;
+use crate::{FrameInfo, MemoryAccessError, Store, WasmCoreDump};
 use backtrace::Backtrace;
 use std::fmt;
 use std::sync::Arc;
 use wasmtime_environ::ir::TrapCode;
+use wasmtime_runtime::WasmFrame;
 
 /// A struct representing an aborted instruction execution, with a message
 /// indicating the cause.
@@ -39,6 +40,8 @@ struct TrapInner {
     reason: TrapReason,
     wasm_trace: Vec<FrameInfo>,
     native_trace: Backtrace,
+    wasm_frames: Vec<WasmFrame>,
+    coredump: Option<WasmCoreDump>,
 }
 
 fn _assert_trap_is_sync_and_send(t: &Trap) -> (&dyn Sync, &dyn Send) {
@@ -55,7 +58,13 @@ impl Trap {
     pub fn new<I: Into<String>>(message: I) -> Self {
         let info = FRAME_INFO.read().unwrap();
         let reason = TrapReason::Message(message.into());
-        Trap::new_with_trace(&info, None, reason, Backtrace::new_unresolved())
+        Trap::new_with_trace(
+            &info,
+            None,
+            reason,
+            Backtrace::new_unresolved(),
+            wasmtime_runtime::wasm_frames(),
+        )
     }
 
     /// Creates a new `Trap` representing an explicit program exit with a classic `i32`
@@ -66,6 +75,8 @@ impl Trap {
                 reason: TrapReason::I32Exit(status),
                 wasm_trace: Vec::new(),
                 native_trace: Backtrace::from(Vec::new()),
+                wasm_frames: Vec::new(),
+                coredump: None,
             }),
         }
     }
@@ -77,6 +88,7 @@ impl Trap {
             wasmtime_runtime::Trap::Jit {
                 pc,
                 backtrace,
+                frames,
                 maybe_interrupted,
             } => {
                 let mut code = info
@@ -86,15 +98,16 @@ impl Trap {
                 if maybe_interrupted && code == TrapCode::StackOverflow {
                     code = TrapCode::Interrupt;
                 }
-                Trap::new_wasm(&info, Some(pc), code, backtrace)
+                Trap::new_wasm(&info, Some(pc), code, backtrace, frames)
             }
             wasmtime_runtime::Trap::Wasm {
                 trap_code,
                 backtrace,
-            } => Trap::new_wasm(&info, None, trap_code, backtrace),
-            wasmtime_runtime::Trap::OOM { backtrace } => {
+                frames,
+            } => Trap::new_wasm(&info, None, trap_code, backtrace, frames),
+            wasmtime_runtime::Trap::OOM { backtrace, frames } => {
                 let reason = TrapReason::Message("out of memory".to_string());
-                Trap::new_with_trace(&info, None, reason, backtrace)
+                Trap::new_with_trace(&info, None, reason, backtrace, frames)
             }
         }
     }
@@ -104,11 +117,13 @@ impl Trap {
         trap_pc: Option<usize>,
         code: TrapCode,
         backtrace: Backtrace,
+        wasm_frames: Vec<WasmFrame>,
     ) -> Self {
         use wasmtime_environ::ir::TrapCode::*;
         let desc = match code {
             StackOverflow => "call stack exhausted",
             HeapOutOfBounds => "out of bounds memory access",
+            HeapMisaligned => "misaligned memory access",
             TableOutOfBounds => "undefined element: out of bounds table access",
             IndirectCallToNull => "uninitialized element",
             BadSignature => "indirect call type mismatch",
@@ -117,10 +132,12 @@ impl Trap {
             BadConversionToInteger => "invalid conversion to integer",
             UnreachableCodeReached => "unreachable",
             Interrupt => "interrupt",
+            OutOfFuel => "all fuel consumed by WebAssembly",
+            AtomicWaitNonSharedMemory => "atomic wait on non-shared memory",
             User(_) => unreachable!(),
         };
         let msg = TrapReason::Message(format!("wasm trap: {}", desc));
-        Trap::new_with_trace(info, trap_pc, msg, backtrace)
+        Trap::new_with_trace(info, trap_pc, msg, backtrace, wasm_frames)
     }
 
     fn new_with_trace(
@@ -128,6 +145,7 @@ impl Trap {
         trap_pc: Option<usize>,
         reason: TrapReason,
         native_trace: Backtrace,
+        wasm_frames: Vec<WasmFrame>,
     ) -> Self {
         let mut wasm_trace = Vec::new();
         for frame in native_trace.frames() {
@@ -155,6 +173,8 @@ impl Trap {
                 reason,
                 wasm_trace,
                 native_trace,
+                wasm_frames,
+                coredump: None,
             }),
         }
     }
@@ -173,6 +193,39 @@ impl Trap {
     pub fn trace(&self) -> &[FrameInfo] {
         &self.inner.wasm_trace
     }
+
+    /// Returns the core dump captured when this trap happened.
+    ///
+    /// Core dumps are only captured when
+    /// [`Config::coredump_on_trap`](crate::Config::coredump_on_trap) is
+    /// enabled, and never for explicit exits.
+    pub fn coredump(&self) -> Option<&WasmCoreDump> {
+        self.inner.coredump.as_ref()
+    }
+
+    /// Attaches a core dump of `store` to this trap if the store's engine is
+    /// configured to capture them.
+    ///
+    /// A trap that already has a core dump keeps it, which preserves the dump
+    /// taken closest to the trap as it propagates out of nested calls.
+    pub(crate) fn with_coredump(mut self, store: &Store) -> Self {
+        if !store.engine().config().coredump_on_trap {
+            return self;
+        }
+        if let Some(inner) = Arc::get_mut(&mut self.inner) {
+            if inner.coredump.is_none() {
+                if let TrapReason::I32Exit(_) = inner.reason {
+                    return self;
+                }
+                inner.coredump = Some(WasmCoreDump::capture(
+                    store,
+                    &inner.wasm_trace,
+                    &inner.wasm_frames,
+                ));
+            }
+        }
+        self
+    }
 }
 
 impl fmt::Debug for Trap {
@@ -197,18 +250,50 @@ impl fmt::Display for Trap {
             let name = frame.module_name().unwrap_or("<unknown>");
             write!(f, "  {}: {:#6x} - {}!", i, frame.module_offset(), name)?;
             match frame.func_name() {
-                Some(name) => match rustc_demangle::try_demangle(name) {
-                    Ok(name) => write!(f, "{}", name)?,
-                    Err(_) => write!(f, "{}", name)?,
-                },
+                Some(name) => write_demangled(f, name)?,
                 None => write!(f, "<wasm function {}>", frame.func_index())?,
             }
             writeln!(f, "")?;
+
+            // Source locations, innermost first; all but the last symbol are
+            // functions that were inlined into this frame's function.
+            let symbols = frame.symbols();
+            for (j, symbol) in symbols.iter().enumerate() {
+                let inlined = j + 1 < symbols.len();
+                if symbol.file().is_none() && !inlined {
+                    continue;
+                }
+                write!(f, "{:>20}", "")?;
+                if inlined {
+                    write!(f, "inlined ")?;
+                    write_demangled(f, symbol.name().unwrap_or("<unknown>"))?;
+                    if symbol.file().is_some() {
+                        write!(f, " ")?;
+                    }
+                }
+                if let Some(file) = symbol.file() {
+                    write!(f, "at {}", file)?;
+                    if let Some(line) = symbol.line() {
+                        write!(f, ":{}", line)?;
+                        if let Some(column) = symbol.column() {
+                            write!(f, ":{}", column)?;
+                        }
+                    }
+                }
+                writeln!(f, "")?;
+            }
         }
         Ok(())
     }
 }
 
+fn write_demangled(f: &mut fmt::Formatter<'_>, name: &str) -> fmt::Result {
+    match rustc_demangle::try_demangle(name) {
+        Ok(name) => write!(f, "{}", name),
+        Err(_) => write!(f, "{}", name),
+    }
+}
+
 impl std::error::Error for Trap {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match &self.inner.reason {
@@ -224,6 +309,12 @@ impl From<anyhow::Error> for Trap {
     }
 }
 
+impl From<MemoryAccessError> for Trap {
+    fn from(e: MemoryAccessError) -> Trap {
+        Box::<dyn std::error::Error + Send + Sync>::from(e).into()
+    }
+}
+
 impl From<Box<dyn std::error::Error + Send + Sync>> for Trap {
     fn from(e: Box<dyn std::error::Error + Send + Sync>) -> Trap {
         // If the top-level error is already a trap, don't be redundant and just return it.
@@ -232,7 +323,13 @@ impl From<Box<dyn std::error::Error + Send + Sync>> for Trap {
         } else {
             let info = FRAME_INFO.read().unwrap();
             let reason = TrapReason::Error(e.into());
-            Trap::new_with_trace(&info, None, reason, Backtrace::new_unresolved())
+            Trap::new_with_trace(
+                &info,
+                None,
+                reason,
+                Backtrace::new_unresolved(),
+                wasmtime_runtime::wasm_frames(),
+            )
         }
     }
 }
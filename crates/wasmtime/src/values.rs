@@ -124,10 +124,10 @@ impl Val {
                 if raw.is_null() {
                     Val::ExternRef(None)
                 } else {
-                    Val::ExternRef(Some(ExternRef {
-                        inner: VMExternRef::clone_from_raw(raw),
-                        store: store.weak(),
-                    }))
+                    Val::ExternRef(Some(ExternRef::from_vm_externref(
+                        store,
+                        VMExternRef::clone_from_raw(raw),
+                    )))
                 }
             }
             ValType::FuncRef => {
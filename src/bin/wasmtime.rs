@@ -6,7 +6,7 @@
 use anyhow::Result;
 use structopt::{clap::AppSettings, clap::ErrorKind, StructOpt};
 use wasmtime_cli::commands::{
-    ConfigCommand, RunCommand, WasmToObjCommand, WastCommand, WASM2OBJ_AFTER_HELP,
+    ConfigCommand, RunCommand, SnapshotCommand, WasmToObjCommand, WastCommand, WASM2OBJ_AFTER_HELP,
 };
 
 /// Wasmtime WebAssembly Runtime
@@ -40,6 +40,8 @@ enum WasmtimeApp {
     Config(ConfigCommand),
     /// Runs a WebAssembly module
     Run(RunCommand),
+    /// Pre-initializes a WebAssembly module by snapshotting its state
+    Snapshot(SnapshotCommand),
     /// Translates a WebAssembly module to native object file
     #[structopt(name = "wasm2obj", after_help = WASM2OBJ_AFTER_HELP)]
     WasmToObj(WasmToObjCommand),
@@ -53,6 +55,7 @@ impl WasmtimeApp {
         match self {
             Self::Config(c) => c.execute(),
             Self::Run(c) => c.execute(),
+            Self::Snapshot(c) => c.execute(),
             Self::WasmToObj(c) => c.execute(),
             Self::Wast(c) => c.execute(),
         }
@@ -2,7 +2,8 @@
 
 mod config;
 mod run;
+mod snapshot;
 mod wasm2obj;
 mod wast;
 
-pub use self::{config::*, run::*, wasm2obj::*, wast::*};
+pub use self::{config::*, run::*, snapshot::*, wasm2obj::*, wast::*};
@@ -1,26 +1,51 @@
 //! The module that implements the `wasmtime run` command.
 
-use crate::{init_file_per_thread_logger, CommonOptions};
+use crate::{init_file_per_thread_logger, CommonOptions, Profile};
 use anyhow::{bail, Context as _, Result};
 use std::thread;
 use std::time::Duration;
 use std::{
     ffi::{OsStr, OsString},
-    fs::File,
-    path::{Component, PathBuf},
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Component, Path, PathBuf},
     process,
 };
 use structopt::{clap::AppSettings, StructOpt};
 use wasi_common::{preopen_dir, WasiCtxBuilder};
-use wasmtime::{Engine, Func, Linker, Module, Store, Trap, Val, ValType};
+use wasmtime::{Engine, Func, GdbStub, GuestProfiler, Linker, Module, Store, Trap, Val, ValType};
 use wasmtime_wasi::Wasi;
 
+fn write_guest_profile(profiler: GuestProfiler, path: &Path) -> Result<()> {
+    let file =
+        File::create(path).with_context(|| format!("failed to create `{}`", path.display()))?;
+    profiler
+        .finish(BufWriter::new(file))
+        .with_context(|| format!("failed to write `{}`", path.display()))
+}
+
+fn write_coverage(store: &Store, path: &Path) -> Result<()> {
+    let file =
+        File::create(path).with_context(|| format!("failed to create `{}`", path.display()))?;
+    let mut output = BufWriter::new(file);
+    // Modules without DWARF, such as hand-written preloads, can't contribute
+    // to a source-level report, but shouldn't prevent the others from doing so.
+    for coverage in store.coverage() {
+        if let Err(e) = coverage.write_lcov(&mut output) {
+            let name = coverage.module().name().unwrap_or("<unnamed>");
+            eprintln!("warning: no coverage for module `{}`: {}", name, e);
+        }
+    }
+    output
+        .flush()
+        .with_context(|| format!("failed to write `{}`", path.display()))
+}
+
 fn parse_module(s: &OsStr) -> Result<PathBuf, OsString> {
     // Do not accept wasmtime subcommand names as the module name
     match s.to_str() {
-        Some("help") | Some("config") | Some("run") | Some("wasm2obj") | Some("wast") => {
-            Err("module name cannot be the same as a subcommand".into())
-        }
+        Some("help") | Some("config") | Some("run") | Some("snapshot") | Some("wasm2obj")
+        | Some("wast") => Err("module name cannot be the same as a subcommand".into()),
         _ => Ok(s.into()),
     }
 }
@@ -108,6 +133,21 @@ pub struct RunCommand {
     )]
     wasm_timeout: Option<Duration>,
 
+    /// Write a wasm core dump to the given path when the module traps
+    #[structopt(long = "coredump-on-trap", value_name = "PATH")]
+    coredump_on_trap: Option<PathBuf>,
+
+    /// Write an lcov report of the lines of the wasm's source executed to
+    /// the given path, using the DWARF in the modules
+    #[structopt(long = "coverage", value_name = "PATH")]
+    coverage: Option<PathBuf>,
+
+    /// Wait for LLDB to connect on the given port and debug the wasm over
+    /// the GDB remote protocol. GDB itself can't debug wasm, only LLDB with
+    /// `process connect --plugin wasm` works
+    #[structopt(long = "gdb-port", value_name = "PORT")]
+    gdb_port: Option<u16>,
+
     // NOTE: this must come last for trailing varargs
     /// The arguments to pass to the module
     #[structopt(value_name = "ARGS")]
@@ -128,8 +168,40 @@ impl RunCommand {
         if self.wasm_timeout.is_some() {
             config.interruptable(true);
         }
+        if self.coredump_on_trap.is_some() {
+            config.coredump_on_trap(true);
+        }
+        let guest_profile = match &self.common.profile {
+            Some(Profile::Guest { path, interval }) => Some((path, *interval)),
+            _ => None,
+        };
+        if guest_profile.is_some() {
+            config.epoch_interruption(true);
+        }
+        if self.gdb_port.is_some() {
+            config.guest_debug(true);
+        }
+        if self.coverage.is_some() {
+            config.coverage(true);
+        }
         let engine = Engine::new(&config);
         let store = Store::new(&engine);
+        let profiler = match guest_profile {
+            Some((path, interval)) => {
+                // Epochs are only enabled for profiling, so the store's own
+                // deadline should never be reached.
+                store.set_epoch_deadline(u64::MAX);
+                Some((path, GuestProfiler::start(&store, interval)?))
+            }
+            None => None,
+        };
+        let debugger = match self.gdb_port {
+            Some(port) => {
+                eprintln!("Waiting for LLDB to connect on 127.0.0.1:{}", port);
+                Some(GdbStub::listen(&store, ("127.0.0.1", port))?)
+            }
+            None => None,
+        };
 
         // Make wasi available by default.
         let preopen_dirs = self.compute_preopen_dirs()?;
@@ -152,10 +224,32 @@ impl RunCommand {
         }
 
         // Load the main wasm module.
-        match self
+        let result = self
             .load_main_module(&mut linker)
-            .with_context(|| format!("failed to run main module `{}`", self.module.display()))
-        {
+            .with_context(|| format!("failed to run main module `{}`", self.module.display()));
+
+        // Tell the debugger how the program ended before the process exits.
+        if let Some(debugger) = &debugger {
+            let status = match &result {
+                Ok(()) => Some(0),
+                Err(e) => e.downcast_ref::<Trap>().and_then(|t| t.i32_exit_status()),
+            };
+            debugger.report_exit(status);
+        }
+
+        // Write out the guest profile before a trap below exits the process.
+        if let Some((path, profiler)) = profiler {
+            if let Err(profile_err) = write_guest_profile(profiler, path) {
+                eprintln!("Error: failed to write guest profile: {:?}", profile_err);
+            }
+        }
+        if let Some(path) = &self.coverage {
+            if let Err(coverage_err) = write_coverage(&store, path) {
+                eprintln!("Error: failed to write coverage: {:?}", coverage_err);
+            }
+        }
+
+        match result {
             Ok(()) => (),
             Err(e) => {
                 // If the program exited because of a non-zero exit status, print
@@ -173,6 +267,10 @@ impl RunCommand {
 
                     eprintln!("Error: {:?}", e);
 
+                    if let Err(coredump_err) = self.write_coredump(trap) {
+                        eprintln!("Error: failed to write core dump: {:?}", coredump_err);
+                    }
+
                     // If the program exited because of a trap, return an error code
                     // to the outside environment indicating a more severe problem
                     // than a simple failure.
@@ -192,6 +290,18 @@ impl RunCommand {
         Ok(())
     }
 
+    fn write_coredump(&self, trap: &Trap) -> Result<()> {
+        let (path, coredump) = match (&self.coredump_on_trap, trap.coredump()) {
+            (Some(path), Some(coredump)) => (path, coredump),
+            _ => return Ok(()),
+        };
+        let name = self.compute_argv().swap_remove(0);
+        fs::write(path, coredump.serialize(&name))
+            .with_context(|| format!("failed to write `{}`", path.display()))?;
+        eprintln!("Wrote core dump to `{}`", path.display());
+        Ok(())
+    }
+
     fn compute_preopen_dirs(&self) -> Result<Vec<(String, File)>> {
         let mut preopen_dirs = Vec::new();
 
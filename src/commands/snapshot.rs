@@ -0,0 +1,56 @@
+//! The module that implements the `wasmtime snapshot` command.
+
+use crate::{init_file_per_thread_logger, CommonOptions};
+use anyhow::{Context as _, Result};
+use std::fs;
+use std::path::PathBuf;
+use structopt::{clap::AppSettings, StructOpt};
+use wasmtime::{Engine, Module};
+
+/// Pre-initializes a WebAssembly module by snapshotting its state after
+/// running an initialization function
+#[derive(StructOpt)]
+#[structopt(
+    name = "snapshot",
+    version = env!("CARGO_PKG_VERSION"),
+    setting = AppSettings::ColoredHelp,
+)]
+pub struct SnapshotCommand {
+    #[structopt(flatten)]
+    common: CommonOptions,
+
+    /// The exported function which initializes the module
+    #[structopt(long, value_name = "FUNCTION", default_value = "_initialize")]
+    init_func: String,
+
+    /// The path of the WebAssembly module to pre-initialize
+    #[structopt(index = 1, value_name = "MODULE_PATH", parse(from_os_str))]
+    module: PathBuf,
+
+    /// The path of the pre-initialized module to write
+    #[structopt(short = "o", long, value_name = "OUTPUT_PATH", parse(from_os_str))]
+    output: PathBuf,
+}
+
+impl SnapshotCommand {
+    /// Executes the command.
+    pub fn execute(&self) -> Result<()> {
+        if self.common.log_to_files {
+            let prefix = "snapshot.dbg.";
+            init_file_per_thread_logger(prefix);
+        } else {
+            pretty_env_logger::init();
+        }
+
+        let config = self.common.config()?;
+        let engine = Engine::new(&config);
+        let wasm = fs::read(&self.module)
+            .with_context(|| format!("failed to read {}", self.module.display()))?;
+        let snapshot = Module::preinitialize(&engine, &wasm, &self.init_func)
+            .with_context(|| format!("failed to pre-initialize {}", self.module.display()))?;
+        fs::write(&self.output, snapshot)
+            .with_context(|| format!("failed to write {}", self.output.display()))?;
+
+        Ok(())
+    }
+}
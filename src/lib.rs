@@ -29,6 +29,7 @@ mod obj;
 
 use anyhow::{bail, Result};
 use std::path::PathBuf;
+use std::time::Duration;
 use structopt::StructOpt;
 use wasmtime::{Config, ProfilingStrategy, Strategy};
 
@@ -43,7 +44,14 @@ fn pick_compilation_strategy(cranelift: bool, lightbeam: bool) -> Result<Strateg
     })
 }
 
-fn pick_profiling_strategy(jitdump: bool, vtune: bool) -> Result<ProfilingStrategy> {
+fn pick_profiling_strategy(
+    profile: Option<ProfilingStrategy>,
+    jitdump: bool,
+    vtune: bool,
+) -> Result<ProfilingStrategy> {
+    if let Some(profile) = profile {
+        return Ok(profile);
+    }
     Ok(match (jitdump, vtune) {
         (true, false) => ProfilingStrategy::JitDump,
         (false, true) => ProfilingStrategy::VTune,
@@ -140,6 +148,16 @@ struct CommonOptions {
     #[structopt(long, conflicts_with = "jitdump")]
     vtune: bool,
 
+    /// Profiling strategy to use: none, jitdump, vtune, perfmap, or
+    /// guest[,PATH[,INTERVAL]] for the built-in guest profiler of `run`
+    #[structopt(
+        long,
+        value_name = "STRATEGY",
+        conflicts_with_all = &["jitdump", "vtune"],
+        parse(try_from_str = parse_profile),
+    )]
+    profile: Option<Profile>,
+
     /// Run optimization passes on translated functions, on by default
     #[structopt(short = "O", long)]
     optimize: bool,
@@ -192,7 +210,11 @@ impl CommonOptions {
             .wasm_threads(self.enable_threads || self.enable_all)
             .cranelift_opt_level(self.opt_level())
             .strategy(pick_compilation_strategy(self.cranelift, self.lightbeam)?)?
-            .profiler(pick_profiling_strategy(self.jitdump, self.vtune)?)?
+            .profiler(pick_profiling_strategy(
+                self.native_profile(),
+                self.jitdump,
+                self.vtune,
+            )?)?
             .cranelift_nan_canonicalization(self.enable_cranelift_nan_canonicalization);
         for CraneliftFlag { name, value } in &self.cranelift_flags {
             unsafe {
@@ -221,6 +243,13 @@ impl CommonOptions {
         Ok(config)
     }
 
+    fn native_profile(&self) -> Option<ProfilingStrategy> {
+        match self.profile {
+            Some(Profile::Native(strategy)) => Some(strategy),
+            _ => None,
+        }
+    }
+
     fn opt_level(&self) -> wasmtime::OptLevel {
         match (self.optimize, self.opt_level.clone()) {
             (true, _) => wasmtime::OptLevel::Speed,
@@ -242,6 +271,40 @@ fn parse_opt_level(opt_level: &str) -> Result<wasmtime::OptLevel> {
     }
 }
 
+/// A profiler selected with `--profile`.
+enum Profile {
+    /// One of the profilers built into `Config::profiler`.
+    Native(ProfilingStrategy),
+    /// `wasmtime::GuestProfiler`, sampling every `interval` and writing the
+    /// profile to `path`.
+    Guest { path: PathBuf, interval: Duration },
+}
+
+fn parse_profile(profile: &str) -> Result<Profile> {
+    let parts = profile.split(',').collect::<Vec<_>>();
+    match parts.as_slice() {
+        ["none"] => Ok(Profile::Native(ProfilingStrategy::None)),
+        ["jitdump"] => Ok(Profile::Native(ProfilingStrategy::JitDump)),
+        ["vtune"] => Ok(Profile::Native(ProfilingStrategy::VTune)),
+        ["perfmap"] => Ok(Profile::Native(ProfilingStrategy::PerfMap)),
+        ["guest", rest @ ..] if rest.len() <= 2 => {
+            let path = rest.get(0).unwrap_or(&"wasmtime-guest-profile.json");
+            let interval = match rest.get(1) {
+                Some(interval) => humantime::parse_duration(interval)?,
+                None => Duration::from_millis(10),
+            };
+            Ok(Profile::Guest {
+                path: path.into(),
+                interval,
+            })
+        }
+        _ => bail!(
+            "unknown profiling strategy `{}`, only none,jitdump,vtune,perfmap,guest accepted",
+            profile
+        ),
+    }
+}
+
 struct CraneliftFlag {
     name: String,
     value: String,
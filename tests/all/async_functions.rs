@@ -0,0 +1,491 @@
+use super::dummy_waker;
+use std::cell::Cell;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+use wasmtime::*;
+
+fn run<F: Future>(future: F) -> F::Output {
+    let mut f = Box::pin(future);
+    let waker = dummy_waker();
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match f.as_mut().poll(&mut cx) {
+            Poll::Ready(val) => break val,
+            Poll::Pending => {}
+        }
+    }
+}
+
+/// A future which is pending the first time it's polled.
+struct PendingOnce {
+    already_polled: bool,
+}
+
+impl PendingOnce {
+    fn new() -> PendingOnce {
+        PendingOnce {
+            already_polled: false,
+        }
+    }
+}
+
+impl Future for PendingOnce {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if self.already_polled {
+            Poll::Ready(())
+        } else {
+            self.already_polled = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+fn pending_host_func(store: &Store, ty: FuncType) -> Func {
+    Func::new_async(store, ty, (), |_caller, _state, _params, _results| {
+        Box::new(async {
+            PendingOnce::new().await;
+            Ok(())
+        })
+    })
+}
+
+#[test]
+fn smoke() {
+    let store = Store::new(&Engine::new(Config::new().async_support(true)));
+    let func = Func::new_async(
+        &store,
+        FuncType::new(Box::new([]), Box::new([])),
+        (),
+        move |_caller, _state, _params, _results| Box::new(async { Ok(()) }),
+    );
+    run(func.call_async(&[])).unwrap();
+    run(func.call_async(&[])).unwrap();
+
+    let func = pending_host_func(&store, FuncType::new(Box::new([]), Box::new([])));
+    run(func.call_async(&[])).unwrap();
+    run(func.call_async(&[])).unwrap();
+}
+
+#[test]
+fn smoke_with_suspension() {
+    let store = Store::new(&Engine::new(Config::new().async_support(true)));
+    let func = pending_host_func(&store, FuncType::new(Box::new([]), Box::new([])));
+
+    // Polling by hand should show the call being suspended exactly once.
+    let mut future = Box::pin(func.call_async(&[]));
+    let waker = dummy_waker();
+    let mut cx = Context::from_waker(&waker);
+    assert!(future.as_mut().poll(&mut cx).is_pending());
+    match future.as_mut().poll(&mut cx) {
+        Poll::Ready(result) => assert!(result.unwrap().is_empty()),
+        Poll::Pending => panic!("should have completed"),
+    }
+}
+
+#[test]
+fn values_in_and_out() -> anyhow::Result<()> {
+    let store = Store::new(&Engine::new(Config::new().async_support(true)));
+    let ty = FuncType::new(Box::new([ValType::I32]), Box::new([ValType::I32]));
+    let double = Func::new_async(&store, ty, 2, |_caller, factor, params, results| {
+        Box::new(async move {
+            PendingOnce::new().await;
+            results[0] = Val::I32(params[0].unwrap_i32() * *factor);
+            Ok(())
+        })
+    });
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "" (func $double (param i32) (result i32)))
+                (func (export "run") (param i32) (result i32)
+                    local.get 0
+                    call $double
+                    call $double))
+        "#,
+    )?;
+    let instance = run(Instance::new_async(&store, &module, &[double.into()]))?;
+    let func = instance.get_func("run").unwrap();
+    let results = run(func.call_async(&[Val::I32(3)]))?;
+    assert_eq!(results[0].unwrap_i32(), 12);
+    Ok(())
+}
+
+#[test]
+fn start_function_suspends() -> anyhow::Result<()> {
+    let store = Store::new(&Engine::new(Config::new().async_support(true)));
+    let hits = Rc::new(Cell::new(0));
+    let func = Func::new_async(
+        &store,
+        FuncType::new(Box::new([]), Box::new([])),
+        hits.clone(),
+        |_caller, hits, _params, _results| {
+            Box::new(async move {
+                PendingOnce::new().await;
+                hits.set(hits.get() + 1);
+                Ok(())
+            })
+        },
+    );
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "" (func $f))
+                (func $start call $f call $f)
+                (start $start))
+        "#,
+    )?;
+    run(Instance::new_async(&store, &module, &[func.into()]))?;
+    assert_eq!(hits.get(), 2);
+    Ok(())
+}
+
+#[test]
+fn recursive_call() -> anyhow::Result<()> {
+    let store = Store::new(&Engine::new(Config::new().async_support(true)));
+    let async_wasm_func = Rc::new(pending_host_func(
+        &store,
+        FuncType::new(Box::new([]), Box::new([])),
+    ));
+    let weak = Rc::downgrade(&async_wasm_func);
+
+    // Create an imported function which recursively invokes another wasm
+    // function asynchronously, although this one is just our own host function
+    // which suffices for this test.
+    let func2 = Func::new_async(
+        &store,
+        FuncType::new(Box::new([]), Box::new([])),
+        (),
+        move |_caller, _state, _params, _results| {
+            let async_wasm_func = weak.upgrade().unwrap();
+            Box::new(async move {
+                async_wasm_func.call_async(&[]).await?;
+                Ok(())
+            })
+        },
+    );
+
+    // Create an instance which calls an async import twice.
+    let module = Module::new(
+        store.engine(),
+        "
+            (module
+                (import \"\" \"\" (func))
+                (func (export \"\")
+                    ;; call imported function which recursively does an async
+                    ;; call
+                    call 0
+                    ;; do it again, and our various pointers all better align
+                    call 0))
+        ",
+    )?;
+
+    run(async {
+        let instance = Instance::new_async(&store, &module, &[func2.into()]).await?;
+        let func = instance.get_func("").unwrap();
+        func.call_async(&[]).await
+    })?;
+    Ok(())
+}
+
+#[test]
+fn suspend_while_suspending() -> anyhow::Result<()> {
+    let store = Store::new(&Engine::new(Config::new().async_support(true)));
+
+    // Create a synchronous function which calls our asynchronous function and
+    // runs it locally. This shouldn't generally happen but we know everything
+    // is synchronous in this test so it's fine for us to do this.
+    //
+    // The purpose of this test is intended to stress various cases in how
+    // we manage pointers in ways that are not necessarily common but are still
+    // possible in safe code.
+    let async_thunk = Rc::new(pending_host_func(
+        &store,
+        FuncType::new(Box::new([]), Box::new([])),
+    ));
+    let weak = Rc::downgrade(&async_thunk);
+    let sync_call_async_thunk = Func::new(
+        &store,
+        FuncType::new(Box::new([]), Box::new([])),
+        move |_caller, _params, _results| {
+            let async_thunk = weak.upgrade().unwrap();
+            run(async_thunk.call_async(&[]))?;
+            Ok(())
+        },
+    );
+
+    // A small async function that simply awaits once to pump the loops and
+    // then finishes.
+    let async_import = pending_host_func(&store, FuncType::new(Box::new([]), Box::new([])));
+
+    let module = Module::new(
+        store.engine(),
+        "
+            (module
+                (import \"\" \"\" (func $sync_call_async_thunk))
+                (import \"\" \"\" (func $async_import))
+                (func (export \"\")
+                    ;; Set some store-local state and pointers
+                    call $sync_call_async_thunk
+                    ;; .. and hopefully it's all still configured correctly
+                    call $async_import))
+        ",
+    )?;
+    run(async {
+        let instance = Instance::new_async(
+            &store,
+            &module,
+            &[sync_call_async_thunk.into(), async_import.into()],
+        )
+        .await?;
+        let func = instance.get_func("").unwrap();
+        func.call_async(&[]).await
+    })?;
+    Ok(())
+}
+
+#[test]
+fn suspend_nested_sync_call() -> anyhow::Result<()> {
+    // Wasm running on a fiber calls a synchronous host function which calls
+    // back into wasm, and that wasm then suspends the fiber, so there's more
+    // than one wasm activation on the fiber's stack while it's suspended.
+    fn instantiate(store: &Store) -> anyhow::Result<Instance> {
+        let sync = Func::new(
+            store,
+            FuncType::new(Box::new([]), Box::new([])),
+            |caller, _params, _results| {
+                let inner = caller.get_export("inner").unwrap().into_func().unwrap();
+                inner.call(&[])?;
+                Ok(())
+            },
+        );
+        let async_import = pending_host_func(store, FuncType::new(Box::new([]), Box::new([])));
+        let module = Module::new(
+            store.engine(),
+            r#"
+                (module
+                    (import "" "" (func $sync))
+                    (import "" "" (func $async))
+                    (func (export "run")
+                        call $sync
+                        call $sync)
+                    (func (export "inner")
+                        call $async)
+                    (func (export "trap")
+                        unreachable))
+            "#,
+        )?;
+        Ok(run(Instance::new_async(
+            store,
+            &module,
+            &[sync.into(), async_import.into()],
+        ))?)
+    }
+
+    let a = Store::new(&Engine::new(Config::new().async_support(true)));
+    let b = Store::new(&Engine::new(Config::new().async_support(true)));
+    let instance_a = instantiate(&a)?;
+    let instance_b = instantiate(&b)?;
+    let run_a = instance_a.get_func("run").unwrap();
+    let run_b = instance_b.get_func("run").unwrap();
+
+    // Interleave the two calls on this thread so each one is suspended while
+    // the other runs, which requires each fiber's states to be fully detached
+    // from this thread while it's suspended.
+    let waker = dummy_waker();
+    let mut cx = Context::from_waker(&waker);
+    let mut future_a = Box::pin(run_a.call_async(&[]));
+    let mut future_b = Box::pin(run_b.call_async(&[]));
+    for _ in 0..2 {
+        assert!(future_a.as_mut().poll(&mut cx).is_pending());
+        assert!(future_b.as_mut().poll(&mut cx).is_pending());
+    }
+    match future_a.as_mut().poll(&mut cx) {
+        Poll::Ready(result) => result?,
+        Poll::Pending => panic!("should have finished"),
+    };
+    match future_b.as_mut().poll(&mut cx) {
+        Poll::Ready(result) => result?,
+        Poll::Pending => panic!("should have finished"),
+    };
+
+    // Traps are still handled correctly afterwards.
+    let trap = instance_a
+        .get_func("trap")
+        .unwrap()
+        .call(&[])
+        .unwrap_err()
+        .downcast::<Trap>()?;
+    assert!(
+        trap.to_string().contains("unreachable"),
+        "bad trap: {}",
+        trap
+    );
+    Ok(())
+}
+
+#[test]
+fn cancel_during_run() {
+    let store = Store::new(&Engine::new(Config::new().async_support(true)));
+    let state = Rc::new(Cell::new(0));
+    let state2 = state.clone();
+
+    let async_thunk = Func::new_async(
+        &store,
+        FuncType::new(Box::new([]), Box::new([])),
+        (),
+        move |_caller, _state, _params, _results| {
+            assert_eq!(state2.get(), 0);
+            state2.set(1);
+            let dtor = SetOnDrop(state2.clone());
+            Box::new(async move {
+                let _dtor = dtor;
+                PendingOnce::new().await;
+                Ok(())
+            })
+        },
+    );
+    // Shouldn't have called anything yet...
+    assert_eq!(state.get(), 0);
+
+    // Create our future, but as per async conventions this still doesn't
+    // actually do anything. No wasm or host function has been called yet.
+    let mut future = Pin::from(Box::new(async_thunk.call_async(&[])));
+    assert_eq!(state.get(), 0);
+
+    // Push the future forward one tick, which actually runs the host code in
+    // our async func. Our future is designed to be pending once, however.
+    let poll = future
+        .as_mut()
+        .poll(&mut Context::from_waker(&dummy_waker()));
+    assert!(poll.is_pending());
+    assert_eq!(state.get(), 1);
+
+    // Now that our future is running (on a separate, now-suspended fiber), drop
+    // the future and that should deallocate all the Rust bits as well.
+    drop(future);
+    assert_eq!(state.get(), 2);
+
+    struct SetOnDrop(Rc<Cell<u32>>);
+
+    impl Drop for SetOnDrop {
+        fn drop(&mut self) {
+            assert_eq!(self.0.get(), 1);
+            self.0.set(2);
+        }
+    }
+}
+
+#[test]
+fn async_host_func_called_synchronously_traps() {
+    let store = Store::new(&Engine::new(Config::new().async_support(true)));
+    let func = pending_host_func(&store, FuncType::new(Box::new([]), Box::new([])));
+    let err = func.call(&[]).unwrap_err();
+    assert!(
+        err.to_string().contains("async"),
+        "bad error: {}",
+        err.to_string()
+    );
+}
+
+#[test]
+fn wasm_traps_propagate() -> anyhow::Result<()> {
+    let store = Store::new(&Engine::new(Config::new().async_support(true)));
+    let func = pending_host_func(&store, FuncType::new(Box::new([]), Box::new([])));
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "" (func $f))
+                (func (export "run")
+                    call $f
+                    unreachable))
+        "#,
+    )?;
+    let instance = run(Instance::new_async(&store, &module, &[func.into()]))?;
+    let run_func = instance.get_func("run").unwrap();
+    let err = run(run_func.call_async(&[])).unwrap_err();
+    let trap = err.downcast::<Trap>()?;
+    assert!(
+        trap.to_string().contains("unreachable"),
+        "bad trap: {}",
+        trap
+    );
+
+    // The store should still be usable after the trap.
+    run(run_func.call_async(&[])).unwrap_err();
+    Ok(())
+}
+
+#[test]
+fn host_trap_propagates() -> anyhow::Result<()> {
+    let store = Store::new(&Engine::new(Config::new().async_support(true)));
+    let func = Func::new_async(
+        &store,
+        FuncType::new(Box::new([]), Box::new([])),
+        (),
+        |_caller, _state, _params, _results| {
+            Box::new(async {
+                PendingOnce::new().await;
+                Err(Trap::new("host failure"))
+            })
+        },
+    );
+    let err = run(func.call_async(&[])).unwrap_err();
+    assert!(
+        err.to_string().contains("host failure"),
+        "bad error: {}",
+        err
+    );
+    Ok(())
+}
+
+#[test]
+fn linker() -> anyhow::Result<()> {
+    let store = Store::new(&Engine::new(Config::new().async_support(true)));
+    let mut linker = Linker::new(&store);
+    linker.func_async(
+        "host",
+        "add_one",
+        FuncType::new(Box::new([ValType::I32]), Box::new([ValType::I32])),
+        (),
+        |_caller, _state, params, results| {
+            Box::new(async move {
+                PendingOnce::new().await;
+                results[0] = Val::I32(params[0].unwrap_i32() + 1);
+                Ok(())
+            })
+        },
+    )?;
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "host" "add_one" (func $add_one (param i32) (result i32)))
+                (global (export "g") (mut i32) (i32.const 0))
+                (func $start
+                    i32.const 41
+                    call $add_one
+                    global.set 0)
+                (start $start))
+        "#,
+    )?;
+    let instance = run(linker.instantiate_async(&module))?;
+    let g = instance.get_global("g").unwrap();
+    assert_eq!(g.get().unwrap_i32(), 42);
+    Ok(())
+}
+
+#[test]
+#[should_panic]
+fn call_async_requires_async_store() {
+    let store = Store::default();
+    let func = Func::wrap(&store, || {});
+    drop(run(func.call_async(&[])));
+}
@@ -0,0 +1,190 @@
+use anyhow::Result;
+use std::sync::{Arc, Mutex};
+use wasmtime::*;
+use CallHook::*;
+
+/// Installs a call hook on `store` which records every transition.
+fn record(store: &Store) -> Arc<Mutex<Vec<CallHook>>> {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let hook_log = log.clone();
+    store.call_hook(move |kind| {
+        hook_log.lock().unwrap().push(kind);
+        Ok(())
+    });
+    log
+}
+
+const CALLS_HOST: &str = r#"
+    (module
+        (import "" "" (func $host))
+        (func (export "run") call $host)
+        (func (export "nop"))
+    )
+"#;
+
+#[test]
+fn hooks_wrapped_host_calls() -> Result<()> {
+    let store = Store::default();
+    let log = record(&store);
+    let module = Module::new(store.engine(), CALLS_HOST)?;
+    let host = Func::wrap(&store, || {});
+    let instance = Instance::new(&store, &module, &[host.into()])?;
+    assert!(log.lock().unwrap().is_empty());
+
+    instance.get_func("run").unwrap().call(&[])?;
+    assert_eq!(
+        *log.lock().unwrap(),
+        [
+            CallingWasm,
+            CallingHost,
+            ReturningFromHost,
+            ReturningFromWasm
+        ]
+    );
+
+    log.lock().unwrap().clear();
+    instance.get_func("nop").unwrap().get0::<()>()?()?;
+    assert_eq!(*log.lock().unwrap(), [CallingWasm, ReturningFromWasm]);
+    Ok(())
+}
+
+#[test]
+fn hooks_dynamic_host_calls() -> Result<()> {
+    let store = Store::default();
+    let log = record(&store);
+    let module = Module::new(store.engine(), CALLS_HOST)?;
+    let host = Func::new(
+        &store,
+        FuncType::new(Box::new([]), Box::new([])),
+        |_, _, _| Ok(()),
+    );
+    let instance = Instance::new(&store, &module, &[host.into()])?;
+
+    instance.get_func("run").unwrap().get0::<()>()?()?;
+    assert_eq!(
+        *log.lock().unwrap(),
+        [
+            CallingWasm,
+            CallingHost,
+            ReturningFromHost,
+            ReturningFromWasm
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn hooks_start_function() -> Result<()> {
+    let store = Store::default();
+    let log = record(&store);
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "" (func $host))
+                (func $start call $host)
+                (start $start)
+            )
+        "#,
+    )?;
+    let host = Func::wrap(&store, || {});
+    Instance::new(&store, &module, &[host.into()])?;
+    assert_eq!(
+        *log.lock().unwrap(),
+        [
+            CallingWasm,
+            CallingHost,
+            ReturningFromHost,
+            ReturningFromWasm
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn hooks_nested_calls() -> Result<()> {
+    let store = Store::default();
+    let log = record(&store);
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "" (func $host))
+                (func (export "run") call $host)
+                (func (export "inner"))
+            )
+        "#,
+    )?;
+    let host = Func::wrap(&store, |caller: Caller<'_>| {
+        let inner = caller.get_export("inner").unwrap().into_func().unwrap();
+        inner.call(&[]).unwrap();
+    });
+    let instance = Instance::new(&store, &module, &[host.into()])?;
+
+    instance.get_func("run").unwrap().call(&[])?;
+    assert_eq!(
+        *log.lock().unwrap(),
+        [
+            CallingWasm,
+            CallingHost,
+            CallingWasm,
+            ReturningFromWasm,
+            ReturningFromHost,
+            ReturningFromWasm,
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn hook_runs_after_trap() -> Result<()> {
+    let store = Store::default();
+    let log = record(&store);
+    let module = Module::new(store.engine(), r#"(func (export "die") unreachable)"#)?;
+    let instance = Instance::new(&store, &module, &[])?;
+
+    let trap = instance.get_func("die").unwrap().get0::<()>()?().unwrap_err();
+    assert!(trap.to_string().contains("unreachable"), "{}", trap);
+    assert_eq!(*log.lock().unwrap(), [CallingWasm, ReturningFromWasm]);
+    Ok(())
+}
+
+#[test]
+fn hook_errors_become_traps() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(store.engine(), CALLS_HOST)?;
+    let called = Arc::new(Mutex::new(false));
+    let host_called = called.clone();
+    let host = Func::wrap(&store, move || *host_called.lock().unwrap() = true);
+    let instance = Instance::new(&store, &module, &[host.into()])?;
+    let run = instance.get_func("run").unwrap().get0::<()>()?;
+
+    // A failing hook on the way into the host skips the host function.
+    store.call_hook(|kind| match kind {
+        CallingHost => Err(Trap::new("no host calls")),
+        _ => Ok(()),
+    });
+    let trap = run().unwrap_err();
+    assert!(trap.to_string().contains("no host calls"), "{}", trap);
+    assert!(!*called.lock().unwrap());
+
+    // A failing hook on the way out of wasm turns a return into a trap.
+    store.call_hook(|kind| match kind {
+        ReturningFromWasm => Err(Trap::new("out of time")),
+        _ => Ok(()),
+    });
+    let trap = run().unwrap_err();
+    assert!(trap.to_string().contains("out of time"), "{}", trap);
+    assert!(*called.lock().unwrap());
+
+    // And on the way into wasm nothing runs at all.
+    *called.lock().unwrap() = false;
+    store.call_hook(|kind| match kind {
+        CallingWasm => Err(Trap::new("no wasm")),
+        _ => Ok(()),
+    });
+    let trap = run().unwrap_err();
+    assert!(trap.to_string().contains("no wasm"), "{}", trap);
+    assert!(!*called.lock().unwrap());
+    Ok(())
+}
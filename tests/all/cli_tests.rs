@@ -123,6 +123,26 @@ fn run_wasmtime_unreachable_wat() -> Result<()> {
     Ok(())
 }
 
+// Running a wat that traps, writing out a core dump.
+#[test]
+fn run_wasmtime_unreachable_coredump() -> Result<()> {
+    let wasm = build_wasm("tests/wasm/unreachable.wat")?;
+    let dir = tempfile::tempdir()?;
+    let coredump = dir.path().join("unreachable.coredump");
+    let coredump_arg = format!("--coredump-on-trap={}", coredump.display());
+    let output = run_wasmtime_for_output(&[
+        wasm.path().to_str().unwrap(),
+        "--disable-cache",
+        &coredump_arg,
+    ])?;
+    assert!(!output.status.success());
+
+    let bytes = std::fs::read(&coredump)?;
+    assert!(bytes.starts_with(b"\0asm"));
+    wasmtime::Module::validate(&wasmtime::Engine::default(), &bytes)?;
+    Ok(())
+}
+
 // Run a simple WASI hello world, snapshot0 edition.
 #[test]
 fn hello_wasi_snapshot0() -> Result<()> {
@@ -350,3 +370,27 @@ fn greeter_preload_callable_command() -> Result<()> {
     assert_eq!(stdout, "Hello _start\nHello callable greet\nHello done\n");
     Ok(())
 }
+
+// Pre-initialize a module and run the snapshot, which starts out with the
+// state left by the initialization function.
+#[test]
+fn snapshot_and_run() -> Result<()> {
+    let wasm = build_wasm("tests/wasm/snapshot-counter.wat")?;
+    let snapshot = NamedTempFile::new()?;
+    run_wasmtime(&[
+        "snapshot",
+        wasm.path().to_str().unwrap(),
+        "-o",
+        snapshot.path().to_str().unwrap(),
+        "--disable-cache",
+    ])?;
+    let stdout = run_wasmtime(&[
+        "run",
+        snapshot.path().to_str().unwrap(),
+        "--invoke",
+        "next",
+        "--disable-cache",
+    ])?;
+    assert_eq!(stdout, "42\n");
+    Ok(())
+}
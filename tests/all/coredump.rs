@@ -0,0 +1,210 @@
+use anyhow::Result;
+use wasmtime::*;
+
+const TRAPS: &str = r#"
+    (module $traps
+        (memory (export "memory") 1)
+        (data (i32.const 0) "hello")
+        (global (export "counter") (mut i32) (i32.const 42))
+        (func $b unreachable)
+        (func $a call $b)
+        (func (export "run")
+            (i32.store8 (i32.const 100) (i32.const 7))
+            call $a))
+"#;
+
+#[test]
+fn disabled_by_default() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(store.engine(), TRAPS)?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let run = instance.get_func("run").unwrap().get0::<()>()?;
+    let trap = run().unwrap_err();
+    assert!(trap.coredump().is_none());
+    Ok(())
+}
+
+#[test]
+fn captures_memory_globals_and_stack() -> Result<()> {
+    let store = Store::new(&Engine::new(Config::new().coredump_on_trap(true)));
+    let module = Module::new(store.engine(), TRAPS)?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let run = instance.get_func("run").unwrap().get0::<()>()?;
+    let trap = run().unwrap_err();
+    let coredump = trap.coredump().expect("core dump should be captured");
+
+    let frames: Vec<_> = coredump.frames().iter().map(|f| f.func_index()).collect();
+    assert_eq!(frames, [0, 1, 2]);
+    assert!(coredump.frames().iter().all(|f| f.instance() == 0));
+
+    let memories: Vec<_> = coredump.memories().collect();
+    assert_eq!(memories.len(), 1);
+    assert_eq!(memories[0].len(), 0x10000);
+    assert_eq!(&memories[0][..5], b"hello");
+    assert_eq!(memories[0][100], 7);
+
+    // Changes made after the trap aren't reflected in the core dump.
+    instance.get_memory("memory").unwrap().write(100, &[8])?;
+    assert_eq!(trap.coredump().unwrap().memories().next().unwrap()[100], 7);
+
+    // The serialized core dump is itself a valid wasm module.
+    let bytes = coredump.serialize("traps.wasm");
+    assert!(bytes.starts_with(b"\0asm"));
+    for section in &["core", "coremodules", "coreinstances", "corestack"] {
+        assert!(bytes
+            .windows(section.len())
+            .any(|w| w == section.as_bytes()));
+    }
+    Module::new(store.engine(), &bytes)?;
+    Ok(())
+}
+
+#[test]
+fn shared_memory_recorded_once() -> Result<()> {
+    let store = Store::new(&Engine::new(Config::new().coredump_on_trap(true)));
+    let provider = Module::new(
+        store.engine(),
+        r#"(module (memory (export "memory") 1) (global (export "g") i32 (i32.const 1)))"#,
+    )?;
+    let provider = Instance::new(&store, &provider, &[])?;
+    let user = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "memory" (memory 1))
+                (import "" "g" (global i32))
+                (func (export "run") unreachable))
+        "#,
+    )?;
+    let user = Instance::new(
+        &store,
+        &user,
+        &[
+            provider.get_memory("memory").unwrap().into(),
+            provider.get_global("g").unwrap().into(),
+        ],
+    )?;
+
+    let trap = user.get_func("run").unwrap().get0::<()>()?().unwrap_err();
+    let coredump = trap.coredump().unwrap();
+    assert_eq!(coredump.memories().len(), 1);
+    assert_eq!(coredump.frames().len(), 1);
+    assert_eq!(coredump.frames()[0].instance(), 1);
+    Module::new(store.engine(), &coredump.serialize("shared"))?;
+    Ok(())
+}
+
+#[test]
+fn frames_attributed_to_their_instance() -> Result<()> {
+    let store = Store::new(&Engine::new(Config::new().coredump_on_trap(true)));
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "" (func $f))
+                (func (export "run") call $f)
+                (func (export "trap") unreachable))
+        "#,
+    )?;
+
+    // Both instances are of the same module, and the second one's import is
+    // the first one's trapping function.
+    let nop = Func::wrap(&store, || {});
+    let first = Instance::new(&store, &module, &[nop.into()])?;
+    let trap = first.get_func("trap").unwrap();
+    let second = Instance::new(&store, &module, &[trap.into()])?;
+
+    let trap = second.get_func("run").unwrap().get0::<()>()?().unwrap_err();
+    let coredump = trap.coredump().unwrap();
+    let frames: Vec<_> = coredump
+        .frames()
+        .iter()
+        .map(|f| (f.instance(), f.func_index()))
+        .collect();
+    assert_eq!(frames, [(0, 2), (1, 1)]);
+    assert!(coredump.frames().iter().all(|f| f.locals().len() == 0));
+    Ok(())
+}
+
+#[test]
+fn locals_recorded_with_guest_debug() -> Result<()> {
+    let mut config = Config::new();
+    config.coredump_on_trap(true);
+    config.guest_debug(true);
+    let store = Store::new(&Engine::new(&config));
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (func $trap (param i32) (local i64 f32)
+                    (local.set 1 (i64.const 7))
+                    unreachable)
+                (func (export "run") (local i32)
+                    (local.set 0 (i32.const -3))
+                    (call $trap (i32.const 5))))
+        "#,
+    )?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let trap = instance.get_func("run").unwrap().get0::<()>()?().unwrap_err();
+    let coredump = trap.coredump().unwrap();
+    assert_eq!(coredump.frames().len(), 2);
+
+    let locals: Vec<_> = coredump.frames()[0].locals().map(Option::unwrap).collect();
+    assert_eq!(locals.len(), 3);
+    assert_eq!(locals[0].unwrap_i32(), 5);
+    assert_eq!(locals[1].unwrap_i64(), 7);
+    assert_eq!(locals[2].unwrap_f32(), 0.0);
+    let locals: Vec<_> = coredump.frames()[1].locals().map(Option::unwrap).collect();
+    assert_eq!(locals.len(), 1);
+    assert_eq!(locals[0].unwrap_i32(), -3);
+
+    Module::new(store.engine(), &coredump.serialize("locals"))?;
+    Ok(())
+}
+
+#[test]
+fn host_traps_and_start_functions() -> Result<()> {
+    let store = Store::new(&Engine::new(Config::new().coredump_on_trap(true)));
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "host" (func $host))
+                (memory 1)
+                (start $start)
+                (func $start call $host))
+        "#,
+    )?;
+
+    let host = Func::wrap(&store, || -> Result<(), Trap> {
+        Err(Trap::new("host error"))
+    });
+    let err = match Instance::new(&store, &module, &[host.into()]) {
+        Ok(_) => panic!("expected the start function to trap"),
+        Err(e) => e,
+    };
+    let trap = err.downcast::<Trap>()?;
+    let coredump = trap.coredump().unwrap();
+    assert_eq!(coredump.frames().len(), 1);
+    assert_eq!(coredump.frames()[0].func_index(), 1);
+    Ok(())
+}
+
+#[test]
+fn no_coredump_for_exit() -> Result<()> {
+    let store = Store::new(&Engine::new(Config::new().coredump_on_trap(true)));
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "exit" (func $exit))
+                (func (export "run") call $exit))
+        "#,
+    )?;
+    let exit = Func::wrap(&store, || -> Result<(), Trap> { Err(Trap::i32_exit(3)) });
+    let instance = Instance::new(&store, &module, &[exit.into()])?;
+    let trap = instance.get_func("run").unwrap().get0::<()>()?().unwrap_err();
+    assert_eq!(trap.i32_exit_status(), Some(3));
+    assert!(trap.coredump().is_none());
+    Ok(())
+}
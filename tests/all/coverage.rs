@@ -0,0 +1,98 @@
+use anyhow::Result;
+use wasmtime::*;
+
+const WAT: &str = r#"
+    (module
+        (func (export "pick") (param i32) (result i32)
+            local.get 0
+            if (result i32)
+                i32.const 1
+            else
+                i32.const 2
+            end))
+"#;
+
+#[test]
+fn coverage_requires_config() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(store.engine(), WAT)?;
+    let instance = Instance::new(&store, &module, &[])?;
+    assert!(instance.coverage().is_none());
+    assert!(store.coverage().is_empty());
+    Ok(())
+}
+
+#[test]
+fn coverage_counts_basic_blocks() -> Result<()> {
+    let store = Store::new(&Engine::new(Config::new().coverage(true)));
+    let module = Module::new(store.engine(), WAT)?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let pick = instance.get_func("pick").unwrap().get1::<i32, i32>()?;
+    for _ in 0..3 {
+        assert_eq!(pick(1)?, 1);
+    }
+    assert_eq!(pick(0)?, 2);
+
+    // The function's entry, the `if` arm, the `else` arm and the code after
+    // the `if`.
+    let coverage = instance.coverage().unwrap();
+    let blocks = coverage.blocks().collect::<Vec<_>>();
+    let counts = blocks.iter().map(|b| b.count()).collect::<Vec<_>>();
+    assert_eq!(counts, [4, 3, 1, 4]);
+    for pair in blocks.windows(2) {
+        assert!(pair[0].start() < pair[0].end());
+        assert_eq!(pair[0].end(), pair[1].start());
+    }
+    Ok(())
+}
+
+#[test]
+fn store_coverage_adds_up_instances() -> Result<()> {
+    let store = Store::new(&Engine::new(Config::new().coverage(true)));
+    let module = Module::new(store.engine(), WAT)?;
+    for arg in 0..2 {
+        let instance = Instance::new(&store, &module, &[])?;
+        instance.get_func("pick").unwrap().get1::<i32, i32>()?(arg)?;
+    }
+    let other = Module::new(store.engine(), r#"(module (func (export "nop")))"#)?;
+    Instance::new(&store, &other, &[])?;
+
+    let coverage = store.coverage();
+    assert_eq!(coverage.len(), 2);
+    let counts = coverage[0].blocks().map(|b| b.count()).collect::<Vec<_>>();
+    assert_eq!(counts, [2, 1, 1, 2]);
+    let counts = coverage[1].blocks().map(|b| b.count()).collect::<Vec<_>>();
+    assert_eq!(counts, [0]);
+
+    let mut merged = coverage[0].clone();
+    merged.merge(&coverage[0])?;
+    let counts = merged.blocks().map(|b| b.count()).collect::<Vec<_>>();
+    assert_eq!(counts, [4, 2, 2, 4]);
+    assert!(merged.merge(&coverage[1]).is_err());
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(target_arch = "aarch64", ignore)] // FIXME(#1642)
+fn coverage_lcov_from_dwarf() -> Result<()> {
+    let store = Store::new(&Engine::new(Config::new().coverage(true)));
+    let module = Module::from_file(store.engine(), "tests/all/debug/testsuite/fib-wasm.wasm")?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let fib = instance.get_func("fib").unwrap().get1::<i32, i32>()?;
+    fib(5)?;
+
+    let mut lcov = Vec::new();
+    instance.coverage().unwrap().write_lcov(&mut lcov)?;
+    let lcov = String::from_utf8(lcov)?;
+    assert!(lcov.starts_with("TN:\nSF:./fib-wasm.c\n"), "{}", lcov);
+    // The loop body runs five times, and the rest of the function once.
+    assert!(lcov.contains("DA:11,5\n"), "{}", lcov);
+    assert!(lcov.contains("DA:15,1\n"), "{}", lcov);
+    assert!(lcov.ends_with("end_of_record\n"), "{}", lcov);
+
+    // Without DWARF there are no lines to report coverage for.
+    let module = Module::new(store.engine(), WAT)?;
+    let instance = Instance::new(&store, &module, &[])?;
+    assert!(instance.coverage().unwrap().write_lcov(Vec::new()).is_err());
+    Ok(())
+}
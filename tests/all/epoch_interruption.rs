@@ -0,0 +1,205 @@
+use super::dummy_waker;
+use anyhow::Result;
+use std::cell::Cell;
+use std::future::Future;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+use wasmtime::*;
+
+/// Instantiates a module whose `run` export loops as many times as its argument
+/// says, calling the imported `bump` function which increments the engine's
+/// epoch on each iteration. Returns `run` along with a count of the iterations
+/// started.
+fn counting_loop(store: &Store) -> Result<(Func, Rc<Cell<u32>>)> {
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "bump" (func $bump))
+                (func (export "run") (param i32)
+                    (loop
+                        call $bump
+                        local.get 0
+                        i32.const 1
+                        i32.sub
+                        local.tee 0
+                        br_if 0)))
+        "#,
+    )?;
+    let iterations = Rc::new(Cell::new(0));
+    let bump = Func::wrap(store, {
+        let iterations = iterations.clone();
+        move |caller: Caller<'_>| {
+            iterations.set(iterations.get() + 1);
+            caller.store().engine().increment_epoch();
+        }
+    });
+    let instance = Instance::new(store, &module, &[bump.into()])?;
+    Ok((instance.get_func("run").unwrap(), iterations))
+}
+
+#[test]
+fn no_deadline_traps_immediately() -> Result<()> {
+    let store = Store::new(&Engine::new(Config::new().epoch_interruption(true)));
+    let (run, iterations) = counting_loop(&store)?;
+    let trap = run.call(&[Val::I32(10)]).unwrap_err();
+    assert!(
+        trap.to_string().contains("wasm trap: interrupt"),
+        "{}",
+        trap.to_string()
+    );
+    assert_eq!(iterations.get(), 0);
+    Ok(())
+}
+
+#[test]
+fn deadline_in_the_future() -> Result<()> {
+    let store = Store::new(&Engine::new(Config::new().epoch_interruption(true)));
+    let (run, iterations) = counting_loop(&store)?;
+    store.set_epoch_deadline(100);
+    run.call(&[Val::I32(10)])?;
+    assert_eq!(iterations.get(), 10);
+    Ok(())
+}
+
+#[test]
+fn loop_traps_at_deadline() -> Result<()> {
+    let store = Store::new(&Engine::new(Config::new().epoch_interruption(true)));
+    let (run, iterations) = counting_loop(&store)?;
+    store.set_epoch_deadline(5);
+    let trap = run.call(&[Val::I32(100)]).unwrap_err();
+    assert!(
+        trap.to_string().contains("wasm trap: interrupt"),
+        "{}",
+        trap.to_string()
+    );
+    assert_eq!(iterations.get(), 5);
+
+    // The deadline is relative to the epoch at the time it's set.
+    store.set_epoch_deadline(3);
+    assert!(run.call(&[Val::I32(100)]).is_err());
+    assert_eq!(iterations.get(), 8);
+    Ok(())
+}
+
+#[test]
+fn recursion_traps_at_deadline() -> Result<()> {
+    let store = Store::new(&Engine::new(Config::new().epoch_interruption(true)));
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "bump" (func $bump))
+                (func $f (export "recurse")
+                    call $bump
+                    call $f))
+        "#,
+    )?;
+    let bump = Func::wrap(&store, |caller: Caller<'_>| {
+        caller.store().engine().increment_epoch()
+    });
+    let instance = Instance::new(&store, &module, &[bump.into()])?;
+    let recurse = instance.get_func("recurse").unwrap().get0::<()>()?;
+    store.set_epoch_deadline(10);
+    let trap = recurse().unwrap_err();
+    assert!(
+        trap.to_string().contains("wasm trap: interrupt"),
+        "{}",
+        trap.to_string()
+    );
+    Ok(())
+}
+
+#[test]
+fn increment_epoch_from_another_thread() -> Result<()> {
+    let store = Store::new(&Engine::new(Config::new().epoch_interruption(true)));
+    let module = Module::new(store.engine(), r#"(func (export "run") (loop br 0))"#)?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let run = instance.get_func("run").unwrap().get0::<()>()?;
+    store.set_epoch_deadline(1);
+
+    let engine = store.engine().clone();
+    let thread = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        engine.increment_epoch();
+    });
+    let trap = run().unwrap_err();
+    assert!(
+        trap.to_string().contains("wasm trap: interrupt"),
+        "{}",
+        trap.to_string()
+    );
+    thread.join().unwrap();
+    Ok(())
+}
+
+#[test]
+fn callback_extends_deadline() -> Result<()> {
+    let store = Store::new(&Engine::new(Config::new().epoch_interruption(true)));
+    let (run, iterations) = counting_loop(&store)?;
+    let callbacks = Rc::new(Cell::new(0));
+    store.epoch_deadline_callback({
+        let callbacks = callbacks.clone();
+        move |_store| {
+            callbacks.set(callbacks.get() + 1);
+            if callbacks.get() < 4 {
+                Ok(2)
+            } else {
+                Err(Trap::new("too many epochs"))
+            }
+        }
+    });
+    store.set_epoch_deadline(2);
+
+    // Two iterations, three extensions of two more iterations each, and then
+    // the fourth callback gives up.
+    let trap = run.call(&[Val::I32(100)]).unwrap_err();
+    assert!(
+        trap.to_string().contains("too many epochs"),
+        "{}",
+        trap.to_string()
+    );
+    assert_eq!(callbacks.get(), 4);
+    assert_eq!(iterations.get(), 8);
+
+    // Once reconfigured to trap the callback is no longer invoked.
+    store.epoch_deadline_trap();
+    store.set_epoch_deadline(1);
+    assert!(run.call(&[Val::I32(100)]).is_err());
+    assert_eq!(callbacks.get(), 4);
+    assert_eq!(iterations.get(), 9);
+    Ok(())
+}
+
+#[test]
+fn async_yield_and_update() -> Result<()> {
+    let store = Store::new(&Engine::new(
+        Config::new().epoch_interruption(true).async_support(true),
+    ));
+    let (run, iterations) = counting_loop(&store)?;
+    store.epoch_deadline_async_yield_and_update(3);
+    store.set_epoch_deadline(3);
+
+    // Every third iteration reaches the deadline and yields once.
+    let mut future = Box::pin(run.call_async(&[Val::I32(10)]));
+    let waker = dummy_waker();
+    let mut cx = Context::from_waker(&waker);
+    let mut yields = 0;
+    let results = loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(result) => break result?,
+            Poll::Pending => yields += 1,
+        }
+    };
+    assert!(results.is_empty());
+    assert_eq!(iterations.get(), 10);
+    assert_eq!(yields, 3);
+    Ok(())
+}
+
+#[test]
+#[should_panic]
+fn async_yield_requires_async_store() {
+    Store::new(&Engine::new(Config::new().epoch_interruption(true)))
+        .epoch_deadline_async_yield_and_update(1);
+}
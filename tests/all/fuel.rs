@@ -0,0 +1,114 @@
+use anyhow::Result;
+use wasmtime::*;
+
+#[test]
+fn fuel_not_configured() -> Result<()> {
+    let store = Store::default();
+    assert!(store.add_fuel(1).is_err());
+    assert_eq!(store.fuel_consumed(), None);
+    Ok(())
+}
+
+#[test]
+fn infinite_loop_runs_out_of_fuel() -> Result<()> {
+    let store = Store::new(&Engine::new(Config::new().consume_fuel(true)));
+    let module = Module::new(store.engine(), r#"(func (export "loop") (loop br 0))"#)?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let iloop = instance.get_func("loop").unwrap().get0::<()>()?;
+    store.add_fuel(10_000)?;
+    let trap = iloop().unwrap_err();
+    assert!(
+        trap.to_string().contains("all fuel consumed"),
+        "{}",
+        trap.to_string()
+    );
+    assert!(store.fuel_consumed().unwrap() > 10_000);
+    Ok(())
+}
+
+#[test]
+fn infinite_recursion_runs_out_of_fuel() -> Result<()> {
+    let store = Store::new(&Engine::new(Config::new().consume_fuel(true)));
+    let module = Module::new(store.engine(), r#"(func $f (export "recurse") call $f)"#)?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let recurse = instance.get_func("recurse").unwrap().get0::<()>()?;
+    store.add_fuel(1_000)?;
+    let trap = recurse().unwrap_err();
+    assert!(
+        trap.to_string().contains("all fuel consumed"),
+        "{}",
+        trap.to_string()
+    );
+    Ok(())
+}
+
+#[test]
+fn no_fuel_traps_immediately() -> Result<()> {
+    let store = Store::new(&Engine::new(Config::new().consume_fuel(true)));
+    let module = Module::new(store.engine(), r#"(func (export "nop"))"#)?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let nop = instance.get_func("nop").unwrap().get0::<()>()?;
+    assert!(nop().is_err());
+    // The failed call was still charged for entering the function, so it
+    // takes two units of fuel to make a successful call now.
+    store.add_fuel(2)?;
+    nop()?;
+    assert_eq!(store.fuel_consumed(), Some(2));
+    Ok(())
+}
+
+#[test]
+fn fuel_consumed_is_deterministic() -> Result<()> {
+    let store = Store::new(&Engine::new(Config::new().consume_fuel(true)));
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (func (export "add") (result i32)
+                i32.const 1
+                i32.const 2
+                i32.add)
+        "#,
+    )?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let add = instance.get_func("add").unwrap().get0::<i32>()?;
+    store.add_fuel(10_000)?;
+    assert_eq!(store.fuel_consumed(), Some(0));
+
+    // One unit for the call itself and one for each of the three
+    // instructions.
+    assert_eq!(add()?, 3);
+    assert_eq!(store.fuel_consumed(), Some(4));
+    assert_eq!(add()?, 3);
+    assert_eq!(store.fuel_consumed(), Some(8));
+    Ok(())
+}
+
+#[test]
+fn host_can_add_fuel_during_execution() -> Result<()> {
+    let store = Store::new(&Engine::new(Config::new().consume_fuel(true)));
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (import "" "refuel" (func $refuel))
+            (func (export "run")
+                (local i32)
+                (loop
+                    call $refuel
+                    local.get 0
+                    i32.const 1
+                    i32.add
+                    local.tee 0
+                    i32.const 100
+                    i32.ne
+                    br_if 0))
+        "#,
+    )?;
+    let refuel = Func::wrap(&store, |caller: Caller<'_>| {
+        caller.store().add_fuel(10).unwrap()
+    });
+    let instance = Instance::new(&store, &module, &[refuel.into()])?;
+    let run = instance.get_func("run").unwrap().get0::<()>()?;
+    store.add_fuel(20)?;
+    run()?;
+    Ok(())
+}
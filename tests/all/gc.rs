@@ -1,6 +1,6 @@
 use super::ref_types_module;
-use std::cell::Cell;
-use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering::SeqCst};
+use std::sync::Arc;
 use wasmtime::*;
 
 #[test]
@@ -37,7 +37,7 @@ fn smoke_test_gc() -> anyhow::Result<()> {
     let instance = Instance::new(&store, &module, &[do_gc.into()])?;
     let func = instance.get_func("func").unwrap();
 
-    let inner_dropped = Rc::new(Cell::new(false));
+    let inner_dropped = Arc::new(AtomicBool::new(false));
     let r = ExternRef::new(&store, SetFlagOnDrop(inner_dropped.clone()));
     {
         let args = [Val::I32(5), Val::ExternRef(Some(r.clone()))];
@@ -55,15 +55,15 @@ fn smoke_test_gc() -> anyhow::Result<()> {
 
     // Dropping `r` should drop the inner `SetFlagOnDrop` value.
     drop(r);
-    assert!(inner_dropped.get());
+    assert!(inner_dropped.load(SeqCst));
 
     return Ok(());
 
-    struct SetFlagOnDrop(Rc<Cell<bool>>);
+    struct SetFlagOnDrop(Arc<AtomicBool>);
 
     impl Drop for SetFlagOnDrop {
         fn drop(&mut self) {
-            self.0.set(true);
+            self.0.store(true, SeqCst);
         }
     }
 }
@@ -83,7 +83,7 @@ fn wasm_dropping_refs() -> anyhow::Result<()> {
     let instance = Instance::new(&store, &module, &[])?;
     let drop_ref = instance.get_func("drop_ref").unwrap();
 
-    let num_refs_dropped = Rc::new(Cell::new(0));
+    let num_refs_dropped = Arc::new(AtomicUsize::new(0));
 
     // NB: 4096 is greater than the initial `VMExternRefActivationsTable`
     // capacity, so this will trigger at least one GC.
@@ -93,19 +93,19 @@ fn wasm_dropping_refs() -> anyhow::Result<()> {
         drop_ref.call(&args)?;
     }
 
-    assert!(num_refs_dropped.get() > 0);
+    assert!(num_refs_dropped.load(SeqCst) > 0);
 
     // And after doing a final GC, all the refs should have been dropped.
     store.gc();
-    assert_eq!(num_refs_dropped.get(), 4096);
+    assert_eq!(num_refs_dropped.load(SeqCst), 4096);
 
     return Ok(());
 
-    struct CountDrops(Rc<Cell<usize>>);
+    struct CountDrops(Arc<AtomicUsize>);
 
     impl Drop for CountDrops {
         fn drop(&mut self) {
-            self.0.set(self.0.get() + 1);
+            self.0.fetch_add(1, SeqCst);
         }
     }
 }
@@ -149,7 +149,7 @@ fn many_live_refs() -> anyhow::Result<()> {
 
     let (store, module) = ref_types_module(&wat)?;
 
-    let live_refs = Rc::new(Cell::new(0));
+    let live_refs = Arc::new(AtomicUsize::new(0));
 
     let make_ref = Func::new(
         &store,
@@ -179,7 +179,7 @@ fn many_live_refs() -> anyhow::Result<()> {
         |_caller, params, _results| {
             let r = params[0].externref().unwrap().unwrap();
             let r = r.data().downcast_ref::<CountLiveRefs>().unwrap();
-            assert!(r.live_refs.get() > 0);
+            assert!(r.live_refs.load(SeqCst) > 0);
             Ok(())
         },
     );
@@ -190,26 +190,131 @@ fn many_live_refs() -> anyhow::Result<()> {
     many_live_refs.call(&[])?;
 
     store.gc();
-    assert_eq!(live_refs.get(), 0);
+    assert_eq!(live_refs.load(SeqCst), 0);
 
     return Ok(());
 
     struct CountLiveRefs {
-        live_refs: Rc<Cell<usize>>,
+        live_refs: Arc<AtomicUsize>,
     }
 
     impl CountLiveRefs {
-        fn new(live_refs: Rc<Cell<usize>>) -> Self {
-            let live = live_refs.get();
-            live_refs.set(live + 1);
+        fn new(live_refs: Arc<AtomicUsize>) -> Self {
+            live_refs.fetch_add(1, SeqCst);
             Self { live_refs }
         }
     }
 
     impl Drop for CountLiveRefs {
         fn drop(&mut self) {
-            let live = self.live_refs.get();
-            self.live_refs.set(live - 1);
+            self.live_refs.fetch_sub(1, SeqCst);
+        }
+    }
+}
+
+#[test]
+fn refs_in_tables_and_globals_are_dropped() -> anyhow::Result<()> {
+    let (store, module) = ref_types_module(
+        r#"
+            (module
+                (table $t 4 externref)
+                (global $g (mut externref) (ref.null extern))
+
+                (func (export "table_set") (param i32 externref)
+                    (table.set $t (local.get 0) (local.get 1))
+                )
+                (func (export "table_get") (param i32) (result externref)
+                    (table.get $t (local.get 0))
+                )
+                (func (export "table_fill") (param externref)
+                    (table.fill $t (i32.const 0) (local.get 0) (i32.const 4))
+                )
+                (func (export "global_set") (param externref)
+                    (global.set $g (local.get 0))
+                )
+                (func (export "global_get") (result externref)
+                    (global.get $g)
+                )
+            )
+        "#,
+    )?;
+
+    let instance = Instance::new(&store, &module, &[])?;
+    let table_set = instance.get_func("table_set").unwrap();
+    let table_get = instance.get_func("table_get").unwrap();
+    let table_fill = instance.get_func("table_fill").unwrap();
+    let global_set = instance.get_func("global_set").unwrap();
+    let global_get = instance.get_func("global_get").unwrap();
+
+    let num_refs_dropped = Arc::new(AtomicUsize::new(0));
+    let new_ref = || {
+        Val::ExternRef(Some(ExternRef::new(
+            &store,
+            CountDrops(num_refs_dropped.clone()),
+        )))
+    };
+
+    table_set.call(&[1.into(), new_ref()])?;
+    table_fill.call(&[new_ref()])?;
+    global_set.call(&[new_ref()])?;
+
+    // The table slot that was overwritten by `table.fill` holds the last
+    // reference to the first ref, and the other two are still reachable.
+    store.gc();
+    assert_eq!(num_refs_dropped.load(SeqCst), 1);
+
+    let from_table = table_get.call(&[3.into()])?[0].unwrap_externref().unwrap();
+    assert!(from_table.data().is::<CountDrops>());
+    let from_global = global_get.call(&[])?[0].unwrap_externref().unwrap();
+    assert!(from_global.data().is::<CountDrops>());
+    drop((from_table, from_global));
+
+    table_fill.call(&[Val::ExternRef(None)])?;
+    global_set.call(&[Val::ExternRef(None)])?;
+    store.gc();
+    assert_eq!(num_refs_dropped.load(SeqCst), 3);
+
+    return Ok(());
+
+    struct CountDrops(Arc<AtomicUsize>);
+
+    impl Drop for CountDrops {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, SeqCst);
+        }
+    }
+}
+
+#[test]
+fn externref_global_is_dropped_with_store() -> anyhow::Result<()> {
+    let (store, module) = ref_types_module(
+        r#"
+            (module
+                (global (export "g") (mut externref) (ref.null extern))
+            )
+        "#,
+    )?;
+
+    let instance = Instance::new(&store, &module, &[])?;
+    let flag = Arc::new(AtomicBool::new(false));
+    let r = ExternRef::new(&store, SetFlagOnDrop(flag.clone()));
+    instance
+        .get_global("g")
+        .unwrap()
+        .set(Val::ExternRef(Some(r)))?;
+    assert!(!flag.load(SeqCst));
+
+    drop(instance);
+    drop(store);
+    assert!(flag.load(SeqCst));
+
+    return Ok(());
+
+    struct SetFlagOnDrop(Arc<AtomicBool>);
+
+    impl Drop for SetFlagOnDrop {
+        fn drop(&mut self) {
+            self.0.store(true, SeqCst);
         }
     }
 }
@@ -0,0 +1,214 @@
+use anyhow::{bail, Result};
+use std::convert::TryInto;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+use wasmtime::*;
+
+/// A minimal client for the GDB remote protocol, standing in for a debugger.
+struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    ack: bool,
+}
+
+impl Client {
+    fn connect(addr: SocketAddr) -> Result<Client> {
+        let stream = TcpStream::connect(addr)?;
+        Ok(Client {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            ack: true,
+        })
+    }
+
+    fn send(&mut self, packet: &str) -> Result<()> {
+        let sum = packet.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        write!(self.writer, "${}#{:02x}", packet, sum)?;
+        Ok(())
+    }
+
+    fn recv(&mut self) -> Result<String> {
+        let mut byte = [0];
+        loop {
+            self.reader.read_exact(&mut byte)?;
+            match byte[0] {
+                b'+' => {}
+                b'$' => break,
+                b => bail!("unexpected byte {:?}", b as char),
+            }
+        }
+        let mut packet = Vec::new();
+        self.reader.read_until(b'#', &mut packet)?;
+        packet.pop();
+        let mut checksum = [0; 2];
+        self.reader.read_exact(&mut checksum)?;
+        let sum = packet.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        assert_eq!(
+            u8::from_str_radix(std::str::from_utf8(&checksum)?, 16)?,
+            sum
+        );
+        if self.ack {
+            self.writer.write_all(b"+")?;
+        }
+        Ok(String::from_utf8(packet)?)
+    }
+
+    fn request(&mut self, packet: &str) -> Result<String> {
+        self.send(packet)?;
+        self.recv()
+    }
+
+    /// Returns the program counter reported by a stop reply.
+    fn stop_pc(reply: &str) -> u64 {
+        assert!(reply.starts_with("T05"), "not a stop reply: {}", reply);
+        let pc = reply.split(';').find(|r| r.starts_with("00:")).unwrap();
+        u64::from_le_bytes(hex(&pc[3..]).as_slice().try_into().unwrap())
+    }
+}
+
+fn hex(s: &str) -> Vec<u8> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+/// Runs `run(10)` from `wat` with a `GdbStub` attached, driving the
+/// debugger side with `client`.
+fn debug(wat: &str, client: impl FnOnce(Client) -> Result<()> + Send + 'static) -> Result<i32> {
+    let mut config = Config::new();
+    config.guest_debug(true);
+    let engine = Engine::new(&config);
+    let store = Store::new(&engine);
+    let module = Module::new(&engine, wat)?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let run = instance.get_func("run").unwrap().get1::<i32, i32>()?;
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let client = thread::spawn(move || client(Client::connect(addr)?));
+    let (stream, _) = listener.accept()?;
+    let stub = GdbStub::attach(&store, stream)?;
+
+    let result = run(10);
+    stub.report_exit(match &result {
+        Ok(_) => Some(0),
+        Err(_) => None,
+    });
+    client.join().unwrap()?;
+    Ok(result?)
+}
+
+const WAT: &str = r#"
+    (module
+        (memory 1)
+        (data (i32.const 16) "hello")
+        (global (mut i32) (i32.const 42))
+        (func $leaf (param i32) (result i32)
+            local.get 0
+            i32.const 1
+            i32.add)
+        (func (export "run") (param i32) (result i32)
+            (local i64)
+            i64.const 7
+            local.set 1
+            local.get 0
+            call $leaf
+            local.get 0
+            call $leaf
+            i32.add))
+"#;
+
+#[test]
+fn gdbstub_requires_guest_debug() -> Result<()> {
+    let store = Store::default();
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let _client = TcpStream::connect(listener.local_addr()?)?;
+    let (stream, _) = listener.accept()?;
+    assert!(GdbStub::attach(&store, stream).is_err());
+    Ok(())
+}
+
+#[test]
+fn gdbstub_steps_and_inspects_wasm() -> Result<()> {
+    let result = debug(WAT, |mut gdb| {
+        assert!(gdb
+            .request("qSupported:xmlRegisters=i386")?
+            .contains("QStartNoAckMode+"));
+        assert_eq!(gdb.request("QStartNoAckMode")?, "OK");
+        gdb.ack = false;
+
+        // Execution starts stopped at the first instruction of `run`, in the
+        // code of the first instance.
+        let start = Client::stop_pc(&gdb.request("?")?);
+        assert_eq!(start >> 62, 1);
+        assert_eq!((start >> 32) & 0x3fff_ffff, 1);
+        assert_eq!(gdb.request(&format!("m{:x},1", start))?, "42"); // i64.const
+        assert_eq!(gdb.request("qWasmLocal:0;0")?, "0a000000");
+        assert_eq!(gdb.request("qWasmLocal:0;1")?, "0000000000000000");
+        assert_eq!(gdb.request("qWasmLocal:0;2")?, "E03");
+
+        // Step past `local.set 1`.
+        let pc = Client::stop_pc(&gdb.request("s")?);
+        assert!(pc > start);
+        Client::stop_pc(&gdb.request("s")?);
+        assert_eq!(gdb.request("qWasmLocal:0;1")?, "0700000000000000");
+
+        // Step into `leaf`.
+        let call = Client::stop_pc(&gdb.request("s")?);
+        assert_eq!(gdb.request(&format!("m{:x},1", call))?, "10"); // call
+        let leaf = Client::stop_pc(&gdb.request("s")?);
+        assert!(leaf < start);
+        let stack = gdb.request("qWasmCallStack:1")?;
+        assert_eq!(
+            stack,
+            format!("{:016x}{:016x}", leaf.swap_bytes(), call.swap_bytes())
+        );
+
+        // Break at the start of `leaf` again for the second call.
+        assert_eq!(gdb.request(&format!("Z0,{:x},1", leaf))?, "OK");
+        assert_eq!(Client::stop_pc(&gdb.request("c")?), leaf);
+        assert_eq!(gdb.request("qWasmLocal:0;0")?, "0a000000");
+        assert_eq!(gdb.request("qWasmGlobal:0;0")?, "2a000000");
+        assert_eq!(gdb.request("qWasmMem:0;10;5")?, "68656c6c6f");
+        assert_eq!(
+            gdb.request(&format!("m{:x},5", (1u64 << 32) | 16))?,
+            "68656c6c6f"
+        );
+        let libraries = gdb.request("qXfer:libraries:read::0,1000")?;
+        assert!(libraries.starts_with("l<library-list>"));
+        assert!(libraries.contains(&format!("address=\"0x{:x}\"", start & !0xffff_ffff)));
+
+        // Run to completion.
+        assert_eq!(gdb.request(&format!("z0,{:x},1", leaf))?, "OK");
+        assert_eq!(gdb.request("c")?, "W00");
+        Ok(())
+    })?;
+    assert_eq!(result, 22);
+    Ok(())
+}
+
+#[test]
+fn gdbstub_kill_traps() -> Result<()> {
+    let err = debug(WAT, |mut gdb| {
+        Client::stop_pc(&gdb.request("?")?);
+        gdb.send("k")?;
+        assert_eq!(gdb.recv()?, "X09");
+        Ok(())
+    })
+    .unwrap_err();
+    assert!(err.to_string().contains("killed by debugger"), "{}", err);
+    Ok(())
+}
+
+#[test]
+fn gdbstub_detach_runs_to_completion() -> Result<()> {
+    let result = debug(WAT, |mut gdb| {
+        Client::stop_pc(&gdb.request("?")?);
+        assert_eq!(gdb.request("D")?, "OK");
+        Ok(())
+    })?;
+    assert_eq!(result, 22);
+    Ok(())
+}
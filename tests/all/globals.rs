@@ -91,3 +91,30 @@ fn use_after_drop() -> anyhow::Result<()> {
     assert_eq!(g.get().i32(), Some(101));
     Ok(())
 }
+
+#[test]
+fn reference_globals() -> anyhow::Result<()> {
+    let store = Store::default();
+
+    let g = Global::new(
+        &store,
+        GlobalType::new(ValType::ExternRef, Mutability::Var),
+        Val::ExternRef(None),
+    )?;
+    assert!(g.get().unwrap_externref().is_none());
+    g.set(Val::ExternRef(Some(ExternRef::new(&store, 42_u32))))?;
+    let r = g.get().unwrap_externref().unwrap();
+    assert_eq!(r.data().downcast_ref::<u32>(), Some(&42));
+
+    let f = Func::wrap(&store, || 5_i32);
+    let g = Global::new(
+        &store,
+        GlobalType::new(ValType::FuncRef, Mutability::Var),
+        f.into(),
+    )?;
+    let f = g.get().unwrap_funcref().unwrap().clone();
+    assert_eq!(f.call(&[])?[0].unwrap_i32(), 5);
+    g.set(Val::FuncRef(None))?;
+    assert!(g.get().unwrap_funcref().is_none());
+    Ok(())
+}
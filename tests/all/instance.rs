@@ -11,3 +11,89 @@ fn wrong_import_numbers() -> Result<()> {
     assert!(Instance::new(&store, &module, &[func.clone().into(), func.into()]).is_err());
     Ok(())
 }
+
+#[test]
+fn drop_instance() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(
+        store.engine(),
+        r#"(module (memory 1) (func (export "run")))"#,
+    )?;
+
+    for _ in 0..10 {
+        let instance = Instance::new(&store, &module, &[])?;
+        instance.get_func("run").unwrap().call(&[])?;
+        store.drop_instance(instance)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn drop_instance_with_live_export() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(store.engine(), r#"(module (func (export "run")))"#)?;
+
+    let instance = Instance::new(&store, &module, &[])?;
+    let run = instance.get_func("run").unwrap();
+    let err = store.drop_instance(instance.clone()).unwrap_err();
+    assert!(err.to_string().contains("handles to it are still alive"));
+
+    run.call(&[])?;
+    drop(run);
+    store.drop_instance(instance)?;
+    Ok(())
+}
+
+#[test]
+fn drop_instance_with_dependents() -> Result<()> {
+    let store = Store::default();
+    let exporter = Module::new(
+        store.engine(),
+        r#"(module (memory (export "m") 1) (func (export "f")))"#,
+    )?;
+    let importer = Module::new(store.engine(), r#"(module (import "" "m" (memory 1)))"#)?;
+
+    let a = Instance::new(&store, &exporter, &[])?;
+    let memory = a.get_memory("m").unwrap();
+    let b = Instance::new(&store, &importer, &[memory.into()])?;
+    let err = store.drop_instance(a.clone()).unwrap_err();
+    assert!(err.to_string().contains("other instances depend on"));
+
+    store.drop_instance(b)?;
+    store.drop_instance(a)?;
+    Ok(())
+}
+
+#[test]
+fn drop_instance_from_other_store() -> Result<()> {
+    let engine = Engine::default();
+    let module = Module::new(&engine, "(module)")?;
+    let store1 = Store::new(&engine);
+    let store2 = Store::new(&engine);
+
+    let instance = Instance::new(&store1, &module, &[])?;
+    assert!(store2.drop_instance(instance.clone()).is_err());
+    store1.drop_instance(instance)?;
+    Ok(())
+}
+
+#[test]
+fn drop_instance_releases_limits() -> Result<()> {
+    let engine = Engine::default();
+    let module = Module::new(&engine, r#"(module (memory 1) (table 1 funcref))"#)?;
+    let store = Store::new_with_limits(
+        &engine,
+        StoreLimitsBuilder::new()
+            .instances(1)
+            .memories(1)
+            .tables(1)
+            .build(),
+    );
+
+    for _ in 0..3 {
+        let instance = Instance::new(&store, &module, &[])?;
+        assert!(Instance::new(&store, &module, &[]).is_err());
+        store.drop_instance(instance)?;
+    }
+    Ok(())
+}
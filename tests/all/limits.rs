@@ -0,0 +1,340 @@
+use anyhow::Result;
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasmtime::*;
+
+#[test]
+fn test_limits() -> Result<()> {
+    let engine = Engine::default();
+    let module = Module::new(
+        &engine,
+        r#"(module (memory (export "m") 0) (table (export "t") 0 funcref))"#,
+    )?;
+
+    let store = Store::new_with_limits(
+        &engine,
+        StoreLimitsBuilder::new()
+            .memory_pages(10)
+            .table_elements(5)
+            .build(),
+    );
+
+    let instance = Instance::new(&store, &module, &[])?;
+
+    // Test instance exports and host objects hitting the limit
+    for memory in vec![
+        instance.get_memory("m").unwrap(),
+        Memory::new(&store, MemoryType::new(Limits::new(0, None)))?,
+    ] {
+        memory.grow(3)?;
+        memory.grow(5)?;
+        memory.grow(2)?;
+
+        assert_eq!(
+            memory.grow(1).map_err(|e| e.to_string()).unwrap_err(),
+            "failed to grow memory"
+        );
+    }
+
+    // Test instance exports and host objects hitting the limit
+    for table in vec![
+        instance.get_table("t").unwrap(),
+        Table::new(
+            &store,
+            TableType::new(ValType::FuncRef, Limits::new(0, None)),
+            Val::FuncRef(None),
+        )?,
+    ] {
+        table.grow(2, Val::FuncRef(None))?;
+        table.grow(1, Val::FuncRef(None))?;
+        table.grow(2, Val::FuncRef(None))?;
+
+        assert!(table.grow(1, Val::FuncRef(None)).is_err());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_limits_memory_only() -> Result<()> {
+    let engine = Engine::default();
+    let module = Module::new(
+        &engine,
+        r#"(module (memory (export "m") 0) (table (export "t") 0 funcref))"#,
+    )?;
+
+    let store = Store::new_with_limits(&engine, StoreLimitsBuilder::new().memory_pages(10).build());
+
+    let instance = Instance::new(&store, &module, &[])?;
+
+    let memory = instance.get_memory("m").unwrap();
+    memory.grow(3)?;
+    memory.grow(5)?;
+    memory.grow(2)?;
+    assert!(memory.grow(1).is_err());
+
+    let table = instance.get_table("t").unwrap();
+    table.grow(2, Val::FuncRef(None))?;
+    table.grow(1, Val::FuncRef(None))?;
+    table.grow(2, Val::FuncRef(None))?;
+    table.grow(1, Val::FuncRef(None))?;
+
+    Ok(())
+}
+
+#[test]
+fn test_initial_memory_limits_exceeded() -> Result<()> {
+    let engine = Engine::default();
+    let module = Module::new(&engine, r#"(module (memory (export "m") 11))"#)?;
+
+    let store = Store::new_with_limits(&engine, StoreLimitsBuilder::new().memory_pages(10).build());
+
+    match Instance::new(&store, &module, &[]) {
+        Ok(_) => unreachable!(),
+        Err(e) => assert_eq!(
+            e.to_string(),
+            "Insufficient resources: memory minimum size of 11 pages exceeds memory limits"
+        ),
+    }
+
+    // Host memories are held to the same limits.
+    assert!(Memory::new(&store, MemoryType::new(Limits::new(25, None))).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_initial_table_limits_exceeded() -> Result<()> {
+    let engine = Engine::default();
+    let module = Module::new(&engine, r#"(module (table (export "t") 23 funcref))"#)?;
+
+    let store =
+        Store::new_with_limits(&engine, StoreLimitsBuilder::new().table_elements(4).build());
+
+    match Instance::new(&store, &module, &[]) {
+        Ok(_) => unreachable!(),
+        Err(e) => assert_eq!(
+            e.to_string(),
+            "Insufficient resources: table minimum size of 23 elements exceeds table limits"
+        ),
+    }
+
+    assert!(Table::new(
+        &store,
+        TableType::new(ValType::FuncRef, Limits::new(99, None)),
+        Val::FuncRef(None),
+    )
+    .is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_pooling_allocator_initial_limits_exceeded() -> Result<()> {
+    let mut config = Config::new();
+    config.allocation_strategy(InstanceAllocationStrategy::Pooling {
+        instance_limits: InstanceLimits {
+            count: 1,
+            ..Default::default()
+        },
+    })?;
+
+    let engine = Engine::new(&config);
+    let module = Module::new(&engine, r#"(module (memory (export "m") 5))"#)?;
+
+    let store = Store::new_with_limits(&engine, StoreLimitsBuilder::new().memory_pages(3).build());
+
+    match Instance::new(&store, &module, &[]) {
+        Ok(_) => unreachable!(),
+        Err(e) => assert_eq!(
+            e.to_string(),
+            "Insufficient resources: memory minimum size of 5 pages exceeds memory limits"
+        ),
+    }
+
+    // The failed instantiation must not have used up the pool's only slot.
+    let module = Module::new(&engine, r#"(module (memory (export "m") 2))"#)?;
+    Instance::new(&store, &module, &[])?;
+
+    Ok(())
+}
+
+#[test]
+fn test_instance_count_limit() -> Result<()> {
+    let engine = Engine::default();
+    let module = Module::new(&engine, r#"(module (memory 1) (table 1 funcref))"#)?;
+
+    let store = Store::new_with_limits(&engine, StoreLimitsBuilder::new().instances(2).build());
+    Instance::new(&store, &module, &[])?;
+    Instance::new(&store, &module, &[])?;
+    match Instance::new(&store, &module, &[]) {
+        Ok(_) => unreachable!(),
+        Err(e) => assert_eq!(
+            e.to_string(),
+            "resource limit exceeded: instance count too high at 3"
+        ),
+    }
+
+    let store = Store::new_with_limits(&engine, StoreLimitsBuilder::new().memories(1).build());
+    Instance::new(&store, &module, &[])?;
+    match Instance::new(&store, &module, &[]) {
+        Ok(_) => unreachable!(),
+        Err(e) => assert_eq!(
+            e.to_string(),
+            "resource limit exceeded: memory count too high at 2"
+        ),
+    }
+
+    let store = Store::new_with_limits(&engine, StoreLimitsBuilder::new().tables(1).build());
+    Instance::new(&store, &module, &[])?;
+    match Instance::new(&store, &module, &[]) {
+        Ok(_) => unreachable!(),
+        Err(e) => assert_eq!(
+            e.to_string(),
+            "resource limit exceeded: table count too high at 2"
+        ),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_failed_instantiation_is_not_counted() -> Result<()> {
+    let engine = Engine::default();
+    let empty = Module::new(&engine, "(module)")?;
+
+    // Exceeding the table count doesn't count the instance either.
+    let module = Module::new(&engine, r#"(module (table 1 funcref))"#)?;
+    let store = Store::new_with_limits(
+        &engine,
+        StoreLimitsBuilder::new().instances(1).tables(0).build(),
+    );
+    assert!(Instance::new(&store, &module, &[]).is_err());
+    Instance::new(&store, &empty, &[])?;
+
+    // Neither do instances which fail to be allocated.
+    let module = Module::new(&engine, r#"(module (memory 11))"#)?;
+    let store = Store::new_with_limits(
+        &engine,
+        StoreLimitsBuilder::new()
+            .instances(1)
+            .memories(1)
+            .memory_pages(10)
+            .build(),
+    );
+    assert!(Instance::new(&store, &module, &[]).is_err());
+    let module = Module::new(&engine, r#"(module (memory 1))"#)?;
+    Instance::new(&store, &module, &[])?;
+
+    Ok(())
+}
+
+/// Tracks the memory used by both wasm and the host against one shared budget.
+struct MemoryContext {
+    host_memory_used: usize,
+    wasm_memory_used: usize,
+    memory_limit: usize,
+    limit_exceeded: bool,
+}
+
+struct HostMemoryLimiter(Rc<RefCell<MemoryContext>>);
+
+impl ResourceLimiter for HostMemoryLimiter {
+    fn memory_growing(&self, current: u32, desired: u32, maximum: Option<u32>) -> bool {
+        let mut ctx = self.0.borrow_mut();
+
+        // Check if the desired exceeds a maximum (either from Wasm or from the
+        // host)
+        if desired > maximum.unwrap_or(u32::max_value()) {
+            ctx.limit_exceeded = true;
+            return false;
+        }
+
+        assert_eq!(current as usize * 0x10000, ctx.wasm_memory_used);
+        let desired = desired as usize * 0x10000;
+
+        if desired + ctx.host_memory_used > ctx.memory_limit {
+            ctx.limit_exceeded = true;
+            return false;
+        }
+
+        ctx.wasm_memory_used = desired;
+        true
+    }
+
+    fn table_growing(&self, _current: u32, _desired: u32, _maximum: Option<u32>) -> bool {
+        true
+    }
+}
+
+#[test]
+fn test_custom_limiter() -> Result<()> {
+    let engine = Engine::default();
+
+    // 16 wasm pages is the limit for both wasm + host memory
+    let context = Rc::new(RefCell::new(MemoryContext {
+        host_memory_used: 0,
+        wasm_memory_used: 0,
+        memory_limit: 1 << 20,
+        limit_exceeded: false,
+    }));
+    let store = Store::new_with_limits(&engine, HostMemoryLimiter(context.clone()));
+
+    // This approximates a function that would "allocate" resources that the
+    // host tracks. Here this is a simple function that increments the current
+    // host memory "used".
+    let host_alloc = Func::wrap(&store, {
+        let context = context.clone();
+        move |size: u32| -> Result<(), Trap> {
+            let mut ctx = context.borrow_mut();
+            let size = size as usize;
+            if size + ctx.host_memory_used + ctx.wasm_memory_used <= ctx.memory_limit {
+                ctx.host_memory_used += size;
+                Ok(())
+            } else {
+                ctx.limit_exceeded = true;
+                Err(Trap::new("host allocation exceeded the limit"))
+            }
+        }
+    });
+
+    let module = Module::new(
+        &engine,
+        r#"(module (import "" "alloc" (func $alloc (param i32))) (memory (export "m") 0)
+            (func (export "f") (param i32) local.get 0 call $alloc))"#,
+    )?;
+
+    let instance = Instance::new(&store, &module, &[host_alloc.into()])?;
+    let memory = instance.get_memory("m").unwrap();
+    let f = instance.get_func("f").unwrap().get1::<u32, ()>()?;
+
+    // Grow the memory by 640 KiB
+    memory.grow(3)?;
+    memory.grow(5)?;
+    memory.grow(2)?;
+
+    assert!(!context.borrow().limit_exceeded);
+
+    // Grow the host "memory" by 384 KiB
+    f(0x10000)?;
+    f(3 * 0x10000)?;
+    f(2 * 0x10000)?;
+
+    // Memory is at the maximum, but the limit hasn't been exceeded
+    assert!(!context.borrow().limit_exceeded);
+
+    // Try to grow the memory again
+    assert_eq!(
+        memory.grow(1).map_err(|e| e.to_string()).unwrap_err(),
+        "failed to grow memory"
+    );
+
+    assert!(context.borrow().limit_exceeded);
+
+    // Try to grow the host "memory" again
+    context.borrow_mut().limit_exceeded = false;
+    assert!(f(1).is_err());
+    assert!(context.borrow().limit_exceeded);
+
+    Ok(())
+}
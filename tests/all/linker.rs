@@ -1,4 +1,6 @@
 use anyhow::Result;
+use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+use std::sync::Arc;
 use wasmtime::*;
 
 #[test]
@@ -50,11 +52,11 @@ fn link_twice_bad() -> Result<()> {
 
     // memories
     let ty = MemoryType::new(Limits::new(1, None));
-    let memory = Memory::new(&store, ty);
+    let memory = Memory::new(&store, ty)?;
     linker.define("", "", memory.clone())?;
     assert!(linker.define("", "", memory.clone()).is_err());
     let ty = MemoryType::new(Limits::new(2, None));
-    let memory = Memory::new(&store, ty);
+    let memory = Memory::new(&store, ty)?;
     assert!(linker.define("", "", memory.clone()).is_err());
 
     // tables
@@ -160,3 +162,120 @@ fn module_interposition() -> Result<()> {
     assert_eq!(func()?, 112);
     Ok(())
 }
+
+#[test]
+fn instance_pre() -> Result<()> {
+    let store = Store::default();
+    let mut linker = Linker::new(&store);
+    let global = Global::new(
+        &store,
+        GlobalType::new(ValType::I32, Mutability::Var),
+        Val::I32(0),
+    )?;
+    linker.define("host", "counter", global.clone())?;
+    let module = Module::new(
+        store.engine(),
+        r#"(module
+            (import "host" "counter" (global $counter (mut i32)))
+            (func $start
+                (global.set $counter (i32.add (global.get $counter) (i32.const 1))))
+            (start $start)
+        )"#,
+    )?;
+    let pre = linker.instantiate_pre(&module)?;
+    assert_eq!(pre.module().imports().len(), 1);
+
+    // Definitions made after resolution aren't picked up.
+    linker.allow_shadowing(true);
+    linker.define(
+        "host",
+        "counter",
+        Global::new(
+            &store,
+            GlobalType::new(ValType::I32, Mutability::Var),
+            Val::I32(100),
+        )?,
+    )?;
+
+    for _ in 0..3 {
+        pre.instantiate(&store)?;
+    }
+    assert_eq!(global.get().unwrap_i32(), 3);
+
+    // The global only exists within the linker's store.
+    let other = Store::new(store.engine());
+    let err = pre.instantiate(&other).err().unwrap();
+    assert!(
+        err.to_string().contains("defined in a different store"),
+        "{}",
+        err
+    );
+    Ok(())
+}
+
+#[test]
+fn instance_pre_many_stores() -> Result<()> {
+    let engine = Engine::default();
+    let linker_store = Store::new(&engine);
+    let mut linker = Linker::new(&linker_store);
+    let calls = Arc::new(AtomicUsize::new(0));
+    let host_calls = calls.clone();
+    linker.shared_func("host", "hit", move |x: i32| {
+        host_calls.fetch_add(1, SeqCst);
+        x + 1
+    })?;
+    let module = Module::new(
+        &engine,
+        r#"(module
+            (import "host" "hit" (func $hit (param i32) (result i32)))
+            (func (export "run") (param i32) (result i32)
+                (call $hit (local.get 0)))
+        )"#,
+    )?;
+    let pre = linker.instantiate_pre(&module)?;
+
+    let a = Store::new(&engine);
+    let b = Store::new(&engine);
+    let instance_a = pre.instantiate(&a)?;
+    let instance_b = pre.instantiate(&b)?;
+    assert!(Store::same(instance_a.store(), &a));
+    assert!(Store::same(instance_b.store(), &b));
+
+    let run_a = instance_a.get_func("run").unwrap().get1::<i32, i32>()?;
+    let run_b = instance_b.get_func("run").unwrap().get1::<i32, i32>()?;
+    assert_eq!(run_a(1)?, 2);
+    assert_eq!(run_b(10)?, 11);
+    assert_eq!(calls.load(SeqCst), 2);
+
+    // Stores of other engines are rejected.
+    let err = pre.instantiate(&Store::default()).err().unwrap();
+    assert!(err.to_string().contains("cross-`Engine`"), "{}", err);
+
+    // Functions defined with `func` only exist within the linker's store.
+    linker.allow_shadowing(true);
+    linker.func("host", "hit", |x: i32| x)?;
+    let pre = linker.instantiate_pre(&module)?;
+    let err = pre.instantiate(&a).err().unwrap();
+    assert!(err.to_string().contains("different store"), "{}", err);
+    pre.instantiate(&linker_store)?;
+    Ok(())
+}
+
+#[test]
+fn instance_pre_link_errors() -> Result<()> {
+    let store = Store::default();
+    let mut linker = Linker::new(&store);
+    linker.func("host", "f", |x: i32| x)?;
+
+    let module = Module::new(store.engine(), r#"(module (import "host" "g" (func)))"#)?;
+    assert!(linker.instantiate_pre(&module).is_err());
+
+    let module = Module::new(store.engine(), r#"(module (import "host" "f" (func)))"#)?;
+    let err = linker.instantiate_pre(&module).err().unwrap();
+    assert!(
+        err.to_string().contains("incompatible import type"),
+        "{}",
+        err
+    );
+    Ok(())
+}
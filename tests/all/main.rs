@@ -1,20 +1,37 @@
+mod async_functions;
+mod call_hook;
 mod cli_tests;
+mod coredump;
+mod coverage;
 mod custom_signal_handler;
 mod debug;
+mod epoch_interruption;
 mod externals;
+mod fuel;
 mod func;
 mod fuzzing;
+mod gdbstub;
 mod globals;
 mod iloop;
 mod import_calling_export;
 mod import_indexes;
 mod instance;
 mod invoke_func_via_table;
+mod limits;
 mod linker;
+mod memory;
 mod memory_creator;
+mod memory_init_cow;
+mod module_serialize;
 mod name;
+mod pooling_allocator;
+mod profiling;
+mod snapshot;
 mod stack_overflow;
+mod store_data;
+mod store_send;
 mod table;
+mod threads;
 mod traps;
 mod use_after_drop;
 mod wast;
@@ -44,3 +61,28 @@ pub(crate) fn ref_types_module(
 
     Ok((store, module))
 }
+
+/// A waker which does nothing, for polling futures to completion in a loop.
+pub(crate) fn dummy_waker() -> std::task::Waker {
+    use std::task::{RawWaker, RawWakerVTable, Waker};
+
+    return unsafe { Waker::from_raw(clone(5 as *const _)) };
+
+    unsafe fn clone(ptr: *const ()) -> RawWaker {
+        assert_eq!(ptr as usize, 5);
+        const VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake, wake_by_ref, drop);
+        RawWaker::new(ptr, &VTABLE)
+    }
+
+    unsafe fn wake(ptr: *const ()) {
+        assert_eq!(ptr as usize, 5);
+    }
+
+    unsafe fn wake_by_ref(ptr: *const ()) {
+        assert_eq!(ptr as usize, 5);
+    }
+
+    unsafe fn drop(ptr: *const ()) {
+        assert_eq!(ptr as usize, 5);
+    }
+}
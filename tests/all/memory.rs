@@ -0,0 +1,205 @@
+use anyhow::Result;
+use wasmtime::*;
+
+fn memory_instance(store: &Store) -> Result<(Instance, Memory)> {
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (memory (export "mem") 1 2)
+                (func (export "load") (param i32) (result i32)
+                    local.get 0
+                    i32.load)
+                (func (export "store") (param i32 i32)
+                    local.get 0
+                    local.get 1
+                    i32.store))
+        "#,
+    )?;
+    let instance = Instance::new(store, &module, &[])?;
+    let memory = instance.get_memory("mem").unwrap();
+    Ok((instance, memory))
+}
+
+#[test]
+fn read_write_in_bounds() -> Result<()> {
+    let store = Store::default();
+    let (_, memory) = memory_instance(&store)?;
+
+    memory.write(0x100, b"hello")?;
+    let mut buf = [0; 5];
+    memory.read(0x100, &mut buf)?;
+    assert_eq!(&buf, b"hello");
+
+    // Accesses right up to the end of memory, and empty accesses at the end,
+    // are in bounds.
+    memory.write(0xfffb, b"world")?;
+    memory.read(0xfffb, &mut buf)?;
+    assert_eq!(&buf, b"world");
+    memory.read(0x10000, &mut [])?;
+    memory.write(0x10000, &[])?;
+    Ok(())
+}
+
+#[test]
+fn read_write_out_of_bounds() -> Result<()> {
+    let store = Store::default();
+    let (_, memory) = memory_instance(&store)?;
+
+    let mut buf = [1; 8];
+    let err = memory.read(0xfffc, &mut buf).unwrap_err();
+    assert_eq!(err.offset(), 0xfffc);
+    assert_eq!(err.access_len(), 8);
+    assert_eq!(err.memory_size(), 0x10000);
+    assert_eq!(buf, [1; 8]);
+
+    assert!(memory.write(0xfffc, &buf).is_err());
+    assert!(memory.write(0x10001, &[]).is_err());
+    assert!(memory.read(usize::max_value(), &mut buf).is_err());
+    assert!(memory.read_u64(usize::max_value() - 3).is_err());
+    assert_eq!(memory.read_u32(0xfffc)?, 0);
+
+    // Growing the memory brings the previously failing access in bounds.
+    memory.grow(1)?;
+    memory.write(0xfffc, &buf)?;
+    assert_eq!(memory.read_u64(0xfffc)?, 0x0101_0101_0101_0101);
+    Ok(())
+}
+
+#[test]
+fn typed_accessors_are_little_endian() -> Result<()> {
+    let store = Store::default();
+    let (instance, memory) = memory_instance(&store)?;
+    let load = instance.get_func("load").unwrap().get1::<i32, i32>()?;
+    let store_i32 = instance.get_func("store").unwrap().get2::<i32, i32, ()>()?;
+
+    memory.write_u32(8, 0x1234_5678)?;
+    let mut bytes = [0; 4];
+    memory.read(8, &mut bytes)?;
+    assert_eq!(bytes, [0x78, 0x56, 0x34, 0x12]);
+    assert_eq!(load(8)?, 0x1234_5678);
+
+    store_i32(16, 0x0102_0304)?;
+    assert_eq!(memory.read_u32(16)?, 0x0102_0304);
+    assert_eq!(memory.read_u16(16)?, 0x0304);
+    assert_eq!(memory.read_u8(19)?, 0x01);
+
+    memory.write_u64(24, 0x1122_3344_5566_7788)?;
+    assert_eq!(load(24)?, 0x5566_7788);
+    assert_eq!(load(28)?, 0x1122_3344);
+    memory.write_u16(24, 0xabcd)?;
+    memory.write_u8(26, 0xef)?;
+    assert_eq!(memory.read_u64(24)?, 0x1122_3344_55ef_abcd);
+    Ok(())
+}
+
+#[test]
+fn data_mut_prevents_growth_and_wasm() -> Result<()> {
+    let store = Store::default();
+    let (instance, memory) = memory_instance(&store)?;
+    let load = instance.get_func("load").unwrap().get1::<i32, i32>()?;
+
+    memory.data_mut(|data| {
+        assert_eq!(data.len(), 0x10000);
+        data[0] = 42;
+
+        assert!(memory.grow(1).is_err());
+
+        let trap = load(0).unwrap_err();
+        assert!(
+            trap.to_string().contains("memory is mutably borrowed"),
+            "{}",
+            trap
+        );
+    });
+
+    // Once the borrow ends everything works as usual again.
+    assert_eq!(load(0)?, 42);
+    assert_eq!(memory.grow(1)?, 1);
+    Ok(())
+}
+
+#[test]
+fn instantiate_during_data_mut_fails() -> Result<()> {
+    let store = Store::default();
+    let (_, memory) = memory_instance(&store)?;
+    let module = Module::new(
+        store.engine(),
+        r#"(module (import "" "mem" (memory 1)) (data (i32.const 0) "hi"))"#,
+    )?;
+
+    memory.data_mut(|data| {
+        data[1] = 0;
+        let err = Instance::new(&store, &module, &[memory.clone().into()]).unwrap_err();
+        assert!(
+            err.to_string().contains("memory is mutably borrowed"),
+            "{}",
+            err
+        );
+    });
+    assert_eq!(memory.read_u8(1)?, 0);
+
+    Instance::new(&store, &module, &[memory.clone().into()])?;
+    assert_eq!(memory.read_u8(1)?, b'i');
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "memory is already mutably borrowed")]
+fn nested_data_mut_panics() {
+    let store = Store::default();
+    let (_, memory) = memory_instance(&store).unwrap();
+    let other = memory.clone();
+    memory.data_mut(|_| other.data_mut(|_| {}));
+}
+
+#[test]
+#[should_panic(expected = "memory is already mutably borrowed")]
+fn read_during_data_mut_panics() {
+    let store = Store::default();
+    let (_, memory) = memory_instance(&store).unwrap();
+    memory.data_mut(|_| memory.read_u8(0).unwrap());
+}
+
+#[test]
+fn host_functions_access_caller_memory() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "sum" (func $sum (param i32 i32) (result i32)))
+                (memory (export "memory") 1)
+                (data (i32.const 0) "\01\02\03\04")
+                (func (export "run") (param i32 i32) (result i32)
+                    local.get 0
+                    local.get 1
+                    call $sum))
+        "#,
+    )?;
+    let sum = Func::wrap(
+        &store,
+        |caller: Caller<'_>, ptr: u32, len: u32| -> Result<u32, Trap> {
+            let memory = caller.get_export("memory").unwrap().into_memory().unwrap();
+            let mut bytes = vec![0; len as usize];
+            memory.read(ptr as usize, &mut bytes)?;
+            let sum = memory.data_mut(|data| {
+                data[ptr as usize] = 0;
+                bytes.iter().map(|b| u32::from(*b)).sum()
+            });
+            Ok(sum)
+        },
+    );
+    let instance = Instance::new(&store, &module, &[sum.into()])?;
+    let run = instance.get_func("run").unwrap().get2::<i32, i32, i32>()?;
+
+    assert_eq!(run(0, 4)?, 10);
+    assert_eq!(run(0, 4)?, 9);
+    let trap = run(0xffff, 2).unwrap_err();
+    assert!(
+        trap.to_string().contains("out of bounds memory access"),
+        "{}",
+        trap
+    );
+    Ok(())
+}
@@ -0,0 +1,180 @@
+use anyhow::Result;
+use wasmtime::*;
+
+/// Runs `f` against stores whose engines do and don't use copy-on-write memory
+/// images, so both initialization paths are held to the same behavior.
+fn with_and_without_cow(mut f: impl FnMut(&Store) -> Result<()>) -> Result<()> {
+    for &cow in [true, false].iter() {
+        let mut config = Config::new();
+        config.memory_init_cow(cow);
+        f(&Store::new(&Engine::new(&config)))?;
+    }
+    Ok(())
+}
+
+const DATA: &str = r#"
+    (module
+        (memory (export "memory") 3)
+        (data (i32.const 0) "hello")
+        (data (i32.const 0x10ffe) "page boundary")
+        (data (i32.const 2) "LL"))
+"#;
+
+#[test]
+fn data_segments_are_visible() -> Result<()> {
+    with_and_without_cow(|store| {
+        let module = Module::new(store.engine(), DATA)?;
+        let instance = Instance::new(store, &module, &[])?;
+        let memory = instance.get_memory("memory").unwrap();
+
+        let mut buf = [0; 5];
+        memory.read(0, &mut buf)?;
+        assert_eq!(&buf, b"heLLo");
+        let mut buf = [0; 13];
+        memory.read(0x10ffe, &mut buf)?;
+        assert_eq!(&buf, b"page boundary");
+        assert_eq!(memory.read_u8(5)?, 0);
+        assert_eq!(memory.read_u8(0x2ffff)?, 0);
+        Ok(())
+    })
+}
+
+#[test]
+fn writes_are_private_to_each_instance() -> Result<()> {
+    with_and_without_cow(|store| {
+        let module = Module::new(store.engine(), DATA)?;
+        let first = Instance::new(store, &module, &[])?
+            .get_memory("memory")
+            .unwrap();
+        first.write(0, b"HELLO")?;
+        first.write_u8(0x20000, 1)?;
+
+        for _ in 0..2 {
+            let memory = Instance::new(store, &module, &[])?
+                .get_memory("memory")
+                .unwrap();
+            let mut buf = [0; 5];
+            memory.read(0, &mut buf)?;
+            assert_eq!(&buf, b"heLLo");
+            assert_eq!(memory.read_u8(0x20000)?, 0);
+            memory.write(0, b"world")?;
+        }
+
+        let mut buf = [0; 5];
+        first.read(0, &mut buf)?;
+        assert_eq!(&buf, b"HELLO");
+        Ok(())
+    })
+}
+
+#[test]
+fn segments_after_global_offsets_apply_in_order() -> Result<()> {
+    with_and_without_cow(|store| {
+        let module = Module::new(
+            store.engine(),
+            r#"
+                (module
+                    (import "" "offset" (global i32))
+                    (memory (export "memory") 1)
+                    (data (i32.const 0) "aaaa")
+                    (data (global.get 0) "bb")
+                    (data (i32.const 1) "c"))
+            "#,
+        )?;
+        for &(offset, expected) in [(0, b"bcaa"), (2, b"acbb")].iter() {
+            let global = Global::new(
+                store,
+                GlobalType::new(ValType::I32, Mutability::Const),
+                Val::I32(offset),
+            )?;
+            let instance = Instance::new(store, &module, &[global.into()])?;
+            let memory = instance.get_memory("memory").unwrap();
+            let mut buf = [0; 4];
+            memory.read(0, &mut buf)?;
+            assert_eq!(&buf, expected);
+        }
+        Ok(())
+    })
+}
+
+#[test]
+fn out_of_bounds_segment_fails_instantiation() -> Result<()> {
+    with_and_without_cow(|store| {
+        let module = Module::new(
+            store.engine(),
+            r#"
+                (module
+                    (memory 1)
+                    (data (i32.const 0) "in bounds")
+                    (data (i32.const 0xffff) "out of bounds"))
+            "#,
+        )?;
+        let err = Instance::new(store, &module, &[]).err().unwrap();
+        assert!(
+            format!("{:?}", err).contains("data segment does not fit"),
+            "{:?}",
+            err
+        );
+        Ok(())
+    })
+}
+
+#[test]
+fn dynamic_memory_keeps_data_when_moved() -> Result<()> {
+    let mut config = Config::new();
+    config.static_memory_maximum_size(0);
+    let store = Store::new(&Engine::new(&config));
+    let module = Module::new(store.engine(), DATA)?;
+    let memory = Instance::new(&store, &module, &[])?
+        .get_memory("memory")
+        .unwrap();
+    memory.write(0, b"H")?;
+
+    assert_eq!(memory.grow(10)?, 3);
+    let mut buf = [0; 5];
+    memory.read(0, &mut buf)?;
+    assert_eq!(&buf, b"HeLLo");
+    let mut buf = [0; 13];
+    memory.read(0x10ffe, &mut buf)?;
+    assert_eq!(&buf, b"page boundary");
+    memory.write_u8(0xcffff, 1)?;
+    Ok(())
+}
+
+#[test]
+fn deserialized_module_initializes_data() -> Result<()> {
+    with_and_without_cow(|store| {
+        let bytes = Module::new(store.engine(), DATA)?.serialize()?;
+        let module = unsafe { Module::deserialize(store.engine(), &bytes)? };
+        let memory = Instance::new(store, &module, &[])?
+            .get_memory("memory")
+            .unwrap();
+        let mut buf = [0; 5];
+        memory.read(0, &mut buf)?;
+        assert_eq!(&buf, b"heLLo");
+        Ok(())
+    })
+}
+
+#[test]
+fn pooling_allocator_copies_data() -> Result<()> {
+    let mut config = Config::new();
+    config.allocation_strategy(InstanceAllocationStrategy::Pooling {
+        instance_limits: InstanceLimits {
+            count: 2,
+            ..Default::default()
+        },
+    })?;
+    let store = Store::new(&Engine::new(&config));
+    let module = Module::new(store.engine(), DATA)?;
+    for _ in 0..2 {
+        let memory = Instance::new(&store, &module, &[])?
+            .get_memory("memory")
+            .unwrap();
+        let mut buf = [0; 5];
+        memory.read(0, &mut buf)?;
+        assert_eq!(&buf, b"heLLo");
+        memory.write(0, b"world")?;
+    }
+    Ok(())
+}
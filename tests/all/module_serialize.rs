@@ -0,0 +1,154 @@
+use anyhow::Result;
+use wasmtime::*;
+
+fn serialize(engine: &Engine, wat: &str) -> Result<Vec<u8>> {
+    let module = Module::new(engine, wat)?;
+    module.serialize()
+}
+
+fn deserialize_and_instantiate(store: &Store, buffer: &[u8]) -> Result<Instance> {
+    let module = unsafe { Module::deserialize(store.engine(), buffer)? };
+    Ok(Instance::new(store, &module, &[])?)
+}
+
+#[test]
+fn test_module_serialize_simple() -> Result<()> {
+    let buffer = serialize(
+        &Engine::default(),
+        "(module (func (export \"run\") (result i32) i32.const 42))",
+    )?;
+
+    let store = Store::default();
+    let instance = deserialize_and_instantiate(&store, &buffer)?;
+    let run = instance.get_func("run").unwrap().get0::<i32>()?;
+    assert_eq!(run()?, 42);
+    Ok(())
+}
+
+#[test]
+fn test_module_serialize_memory_and_traps() -> Result<()> {
+    let buffer = serialize(
+        &Engine::default(),
+        r#"
+            (module
+                (memory (export "memory") 1)
+                (data (i32.const 0) "\2a")
+                (func (export "load") (param i32) (result i32)
+                    local.get 0
+                    i32.load8_u))
+        "#,
+    )?;
+
+    let store = Store::default();
+    let instance = deserialize_and_instantiate(&store, &buffer)?;
+    let load = instance.get_func("load").unwrap().get1::<i32, i32>()?;
+    assert_eq!(load(0)?, 42);
+    let trap = load(0x10000).unwrap_err();
+    assert!(
+        trap.to_string().contains("out of bounds"),
+        "{}",
+        trap.to_string()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_module_serialize_keeps_name() -> Result<()> {
+    let engine = Engine::default();
+    let module = Module::new_with_name(&engine, "(module)", "foo")?;
+    let bytes = module.serialize()?;
+    let module = unsafe { Module::deserialize(&engine, &bytes)? };
+    assert_eq!(module.name(), Some("foo"));
+    Ok(())
+}
+
+#[test]
+fn test_module_serialize_deserialized() -> Result<()> {
+    let engine = Engine::default();
+    let buffer = serialize(
+        &engine,
+        "(module (func (export \"run\") (result i32) i32.const 42))",
+    )?;
+    let module = unsafe { Module::deserialize(&engine, &buffer)? };
+    assert_eq!(module.serialize()?, buffer);
+    Ok(())
+}
+
+#[test]
+fn test_module_serialize_fail() -> Result<()> {
+    let buffer = serialize(
+        &Engine::default(),
+        "(module (func (export \"run\") (result i32) i32.const 42))",
+    )?;
+
+    let mut config = Config::new();
+    config.cranelift_opt_level(OptLevel::None);
+    let store = Store::new(&Engine::new(&config));
+    match deserialize_and_instantiate(&store, &buffer) {
+        Ok(_) => panic!("expected failure"),
+        Err(e) => assert!(e
+            .to_string()
+            .starts_with("module was compiled with different code generation settings")),
+    }
+
+    let mut config = Config::new();
+    config.consume_fuel(true);
+    let store = Store::new(&Engine::new(&config));
+    match deserialize_and_instantiate(&store, &buffer) {
+        Ok(_) => panic!("expected failure"),
+        Err(e) => assert!(e
+            .to_string()
+            .starts_with("module was compiled with different tunables")),
+    }
+
+    let mut config = Config::new();
+    config.coredump_on_trap(true);
+    let store = Store::new(&Engine::new(&config));
+    match deserialize_and_instantiate(&store, &buffer) {
+        Ok(_) => panic!("expected failure"),
+        Err(e) => assert!(e
+            .to_string()
+            .starts_with("module was compiled with different tunables")),
+    }
+
+    let mut config = Config::new();
+    config.wasm_multi_value(false);
+    let store = Store::new(&Engine::new(&config));
+    match deserialize_and_instantiate(&store, &buffer) {
+        Ok(_) => panic!("expected failure"),
+        Err(e) => assert!(e
+            .to_string()
+            .starts_with("module was compiled with WebAssembly features")),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_module_deserialize_garbage() {
+    let store = Store::default();
+    assert!(deserialize_and_instantiate(&store, b"\0asm\x01\0\0\0").is_err());
+}
+
+#[test]
+fn test_module_serialize_passive_data_and_globals() -> Result<()> {
+    let engine = Engine::new(Config::new().wasm_bulk_memory(true));
+    let buffer = serialize(
+        &engine,
+        r#"
+            (module
+                (memory 1)
+                (data $d "\2a")
+                (global $g (mut i32) (i32.const 7))
+                (func (export "run") (result i32)
+                    (memory.init $d (i32.const 0) (i32.const 0) (i32.const 1))
+                    (i32.add (global.get $g) (i32.load8_u (i32.const 0)))))
+        "#,
+    )?;
+
+    let store = Store::new(&engine);
+    let instance = deserialize_and_instantiate(&store, &buffer)?;
+    let run = instance.get_func("run").unwrap().get0::<i32>()?;
+    assert_eq!(run()?, 49);
+    Ok(())
+}
@@ -0,0 +1,154 @@
+use anyhow::Result;
+use wasmtime::*;
+
+fn pooling_config(instance_limits: InstanceLimits) -> Result<Config> {
+    let mut config = Config::new();
+    config.allocation_strategy(InstanceAllocationStrategy::Pooling { instance_limits })?;
+    Ok(config)
+}
+
+fn small_limits() -> InstanceLimits {
+    InstanceLimits {
+        count: 1,
+        memories: 1,
+        tables: 1,
+        memory_pages: 2,
+        table_elements: 10,
+    }
+}
+
+#[test]
+fn successful_instantiation() -> Result<()> {
+    let engine = Engine::new(&pooling_config(small_limits())?);
+    let module = Module::new(&engine, r#"(module (memory 1) (table 10 funcref))"#)?;
+
+    // Each store frees its instance when dropped, so the single slot is
+    // reused every time.
+    for _ in 0..3 {
+        let store = Store::new(&engine);
+        Instance::new(&store, &module, &[])?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn memory_is_reset_between_instances() -> Result<()> {
+    let engine = Engine::new(&pooling_config(small_limits())?);
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (memory (export "memory") 1)
+                (func (export "set") (param i32 i32)
+                    local.get 0
+                    local.get 1
+                    i32.store))
+        "#,
+    )?;
+
+    {
+        let store = Store::new(&engine);
+        let instance = Instance::new(&store, &module, &[])?;
+        let set = instance.get_func("set").unwrap().get2::<i32, i32, ()>()?;
+        set(0, 42)?;
+        set(100, 42)?;
+    }
+
+    let store = Store::new(&engine);
+    let instance = Instance::new(&store, &module, &[])?;
+    let memory = instance.get_memory("memory").unwrap();
+    assert!(unsafe { memory.data_unchecked() }.iter().all(|b| *b == 0));
+
+    Ok(())
+}
+
+#[test]
+fn instance_count_limit() -> Result<()> {
+    let engine = Engine::new(&pooling_config(small_limits())?);
+    let module = Module::new(&engine, r#"(module)"#)?;
+    let store = Store::new(&engine);
+
+    let _instance = Instance::new(&store, &module, &[])?;
+    match Instance::new(&store, &module, &[]) {
+        Ok(_) => panic!("instantiation should fail"),
+        Err(e) => assert_eq!(
+            e.to_string(),
+            "Limit of 1 concurrent instances has been reached"
+        ),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn memory_limit() -> Result<()> {
+    let engine = Engine::new(&pooling_config(small_limits())?);
+    let store = Store::new(&engine);
+
+    let module = Module::new(&engine, r#"(module (memory 3))"#)?;
+    let err = Instance::new(&store, &module, &[]).err().unwrap();
+    assert!(
+        err.to_string().starts_with("Insufficient resources"),
+        "{}",
+        err
+    );
+
+    // Growing is limited as well, not just the initial size.
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (memory 0)
+                (func (export "grow") (param i32) (result i32)
+                    local.get 0
+                    memory.grow))
+        "#,
+    )?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let grow = instance.get_func("grow").unwrap().get1::<i32, i32>()?;
+    assert_eq!(grow(1)?, 0);
+    assert_eq!(grow(1)?, 1);
+    assert_eq!(grow(1)?, -1);
+
+    Ok(())
+}
+
+#[test]
+fn table_limit() -> Result<()> {
+    let engine = Engine::new(&pooling_config(small_limits())?);
+    let store = Store::new(&engine);
+
+    let module = Module::new(&engine, r#"(module (table 11 funcref))"#)?;
+    let err = Instance::new(&store, &module, &[]).err().unwrap();
+    assert!(
+        err.to_string().starts_with("Insufficient resources"),
+        "{}",
+        err
+    );
+
+    let module = Module::new(&engine, r#"(module (table (export "t") 8 funcref))"#)?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let table = instance.get_table("t").unwrap();
+    table.grow(2, Val::FuncRef(None))?;
+    assert_eq!(table.size(), 10);
+    assert!(table.grow(1, Val::FuncRef(None)).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn host_items_do_not_use_the_pool() -> Result<()> {
+    let engine = Engine::new(&pooling_config(small_limits())?);
+    let store = Store::new(&engine);
+
+    // Memories larger than the pool allows can still be created by the host,
+    // and they don't take up the only instance slot.
+    let memory = Memory::new(&store, MemoryType::new(Limits::new(4, None)))?;
+    assert_eq!(memory.size(), 4);
+
+    let module = Module::new(&engine, r#"(module (import "" "" (memory 1)))"#)?;
+    Instance::new(&store, &module, &[memory.into()])?;
+
+    Ok(())
+}
@@ -0,0 +1,132 @@
+use wasmtime::*;
+
+#[test]
+fn perfmap_records_functions_and_trampolines() -> anyhow::Result<()> {
+    let mut config = Config::new();
+    config.profiler(ProfilingStrategy::PerfMap)?;
+    let engine = Engine::new(&config);
+    let store = Store::new(&engine);
+
+    let wat = r#"
+        (module
+            (func $perfmap_named_function (export "run") (nop))
+        )
+    "#;
+    let module = Module::new(&engine, wat)?;
+    Instance::new(&store, &module, &[])?;
+    Func::new(
+        &store,
+        FuncType::new(Box::new([]), Box::new([])),
+        |_, _, _| Ok(()),
+    );
+
+    let map = std::fs::read_to_string(format!("/tmp/perf-{}.map", std::process::id()))?;
+    let names = map
+        .lines()
+        .map(|line| {
+            let mut parts = line.splitn(3, ' ');
+            assert!(usize::from_str_radix(parts.next().unwrap(), 16).is_ok());
+            assert!(usize::from_str_radix(parts.next().unwrap(), 16).unwrap() > 0);
+            parts.next().unwrap()
+        })
+        .collect::<Vec<_>>();
+    assert!(names.contains(&"perfmap_named_function"));
+    assert!(names.contains(&"wasm::trampoline[0]"));
+    assert!(names.contains(&"wasm::host-function-stub"));
+    assert!(names.contains(&"wasm::host-function-trampoline"));
+
+    Ok(())
+}
+
+#[test]
+fn guest_profiler_requires_epoch_interruption() {
+    let store = Store::default();
+    assert!(GuestProfiler::start(&store, std::time::Duration::from_millis(1)).is_err());
+}
+
+const PROFILED_WAT: &str = r#"
+    (module
+        (import "" "pause" (func $pause))
+        (func $leaf)
+        (func $middle
+            call $pause
+            call $leaf)
+        (func $run (export "run") (param i32)
+            (loop
+                call $middle
+                local.get 0
+                i32.const 1
+                i32.sub
+                local.tee 0
+                br_if 0)))
+"#;
+
+/// Instantiates `PROFILED_WAT` in `store`, where the host function it calls
+/// sleeps for long enough that the profiler always asks for a sample, which
+/// is then taken on entry to `leaf`.
+fn profiled_run(store: &Store) -> anyhow::Result<impl Fn(i32) -> Result<(), Trap>> {
+    let module = Module::new(store.engine(), PROFILED_WAT)?;
+    let pause = Func::wrap(store, || {
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    });
+    let instance = Instance::new(store, &module, &[pause.into()])?;
+    Ok(instance.get_func("run").unwrap().get1::<i32, ()>()?)
+}
+
+#[test]
+fn guest_profiler_records_wasm_stacks() -> anyhow::Result<()> {
+    let engine = Engine::new(Config::new().epoch_interruption(true));
+    let store = Store::new(&engine);
+    store.set_epoch_deadline(u64::MAX);
+    let run = profiled_run(&store)?;
+
+    let profiler = GuestProfiler::start(&store, std::time::Duration::from_millis(1))?;
+    run(3)?;
+    let mut profile = Vec::new();
+    profiler.finish(&mut profile)?;
+
+    let profile = String::from_utf8(profile)?;
+    assert!(profile.contains(r#""functionName":"run""#));
+    assert!(profile.contains(r#""functionName":"middle""#));
+    assert!(profile.contains(r#""functionName":"leaf""#));
+    assert!(!profile.contains(r#""samples":[]"#));
+
+    Ok(())
+}
+
+#[test]
+fn guest_profiler_leaves_epochs_alone() -> anyhow::Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+    use std::sync::Arc;
+
+    let engine = Engine::new(Config::new().epoch_interruption(true));
+    let store = Store::new(&engine);
+    let callbacks = Arc::new(AtomicUsize::new(0));
+    let counter = callbacks.clone();
+    store.epoch_deadline_callback(move |_| {
+        counter.fetch_add(1, SeqCst);
+        Ok(1)
+    });
+    store.set_epoch_deadline(1);
+    let run = profiled_run(&store)?;
+
+    // Another store of the same engine which traps as soon as the epoch
+    // advances.
+    let other = Store::new(&engine);
+    other.set_epoch_deadline(1);
+    let other_run = profiled_run(&other)?;
+
+    let profiler = GuestProfiler::start(&store, std::time::Duration::from_millis(1))?;
+    run(3)?;
+    other_run(1)?;
+    assert_eq!(callbacks.load(SeqCst), 0);
+    profiler.finish(Vec::new())?;
+
+    // The store's own deadline and callback are still in place.
+    engine.increment_epoch();
+    run(1)?;
+    assert_eq!(callbacks.load(SeqCst), 1);
+    assert!(other_run(1).is_err());
+
+    Ok(())
+}
@@ -0,0 +1,122 @@
+use anyhow::Result;
+use wasmtime::*;
+
+#[test]
+fn preinitialize_captures_state() -> Result<()> {
+    let engine = Engine::default();
+    let wasm = Module::preinitialize(
+        &engine,
+        r#"
+            (module
+                (memory (export "memory") 1)
+                (table (export "table") 3 funcref)
+                (global $starts (export "starts") (mut i32) (i32.const 0))
+                (global $big (export "big") (mut i64) (i64.const 0))
+                (func $start
+                    (global.set $starts (i32.add (global.get $starts) (i32.const 1))))
+                (start $start)
+                (func $answer (result i32) (i32.const 42))
+                (elem (i32.const 2) $answer)
+                (func (export "init")
+                    (drop (memory.grow (i32.const 1)))
+                    (i32.store (i32.const 100) (i32.const 0x01020304))
+                    (i32.store8 (i32.const 70000) (i32.const 5))
+                    (global.set $big (i64.const -12345678901)))
+            )
+        "#,
+        "init",
+    )?;
+
+    let store = Store::new(&engine);
+    let module = Module::new(&engine, &wasm)?;
+    let instance = Instance::new(&store, &module, &[])?;
+    assert!(instance.get_export("init").is_none());
+
+    // The start function already ran before the snapshot was taken.
+    assert_eq!(instance.get_global("starts").unwrap().get().i32(), Some(1));
+    assert_eq!(
+        instance.get_global("big").unwrap().get().i64(),
+        Some(-12345678901)
+    );
+
+    let memory = instance.get_memory("memory").unwrap();
+    assert_eq!(memory.size(), 2);
+    let mut bytes = [0; 4];
+    memory.read(100, &mut bytes)?;
+    assert_eq!(bytes, [4, 3, 2, 1]);
+    memory.read(70000, &mut bytes[..1])?;
+    assert_eq!(bytes[0], 5);
+
+    let table = instance.get_table("table").unwrap();
+    assert_eq!(table.size(), 3);
+    assert!(table.get(0).unwrap().unwrap_funcref().is_none());
+    let answer = table.get(2).unwrap().unwrap_funcref().cloned().unwrap();
+    assert_eq!(answer.get0::<i32>()?()?, 42);
+    Ok(())
+}
+
+#[test]
+fn preinitialize_keeps_uncalled_imports() -> Result<()> {
+    let engine = Engine::default();
+    let wasm = Module::preinitialize(
+        &engine,
+        r#"
+            (module
+                (import "" "f" (func $f (result i32)))
+                (global $x (export "x") (mut i32) (i32.const 0))
+                (func (export "init") (global.set $x (i32.const 7)))
+                (func (export "call") (result i32) (call $f))
+            )
+        "#,
+        "init",
+    )?;
+
+    let store = Store::new(&engine);
+    let module = Module::new(&engine, &wasm)?;
+    let f = Func::wrap(&store, || 3);
+    let instance = Instance::new(&store, &module, &[f.into()])?;
+    assert_eq!(instance.get_global("x").unwrap().get().i32(), Some(7));
+    assert_eq!(instance.get_func("call").unwrap().get0::<i32>()?()?, 3);
+    Ok(())
+}
+
+#[test]
+fn preinitialize_refuses_unreproducible_state() -> Result<()> {
+    let engine = Engine::default();
+
+    let err = Module::preinitialize(
+        &engine,
+        r#"
+            (module
+                (import "" "f" (func $f))
+                (func (export "init") (call $f))
+            )
+        "#,
+        "init",
+    )
+    .unwrap_err();
+    assert!(
+        format!("{:?}", err).contains("calls the import `::f`"),
+        "bad error: {:?}",
+        err
+    );
+
+    let err = Module::preinitialize(
+        &engine,
+        r#"
+            (module
+                (import "" "m" (memory 1))
+                (func (export "init"))
+            )
+        "#,
+        "init",
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("importing memory `::m`"));
+
+    let err = Module::preinitialize(&engine, "(module)", "init").unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("no exported function named `init`"));
+    Ok(())
+}
@@ -0,0 +1,116 @@
+use anyhow::Result;
+use std::cell::Cell;
+use std::rc::Rc;
+use wasmtime::*;
+
+#[derive(Default)]
+struct Context {
+    log: Vec<i32>,
+}
+
+#[test]
+fn data_is_typed() {
+    let store = Store::default();
+    assert!(store.data::<Context>().is_none());
+
+    store.set_data(Context::default());
+    store.data_mut::<Context>().unwrap().log.push(1);
+    assert_eq!(store.data::<Context>().unwrap().log, [1]);
+    assert!(store.data::<u32>().is_none());
+    assert!(store.data_mut::<u32>().is_none());
+
+    // Clones of a store share its data.
+    let clone = store.clone();
+    assert_eq!(clone.data::<Context>().unwrap().log, [1]);
+
+    // Setting new data replaces the old, whatever its type.
+    store.set_data(7u32);
+    assert!(store.data::<Context>().is_none());
+    assert_eq!(*clone.data::<u32>().unwrap(), 7);
+}
+
+#[test]
+fn host_functions_share_data() -> Result<()> {
+    let store = Store::default();
+    store.set_data(Context::default());
+
+    let mut linker = Linker::new(&store);
+    linker.func("host", "log", |caller: Caller<'_>, x: i32| {
+        caller.store().data_mut::<Context>().unwrap().log.push(x);
+    })?;
+    linker.func("host", "sum", |caller: Caller<'_>| -> i32 {
+        let store = caller.store();
+        let data = store.data::<Context>().unwrap();
+        data.log.iter().sum()
+    })?;
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "host" "log" (func $log (param i32)))
+                (import "host" "sum" (func $sum (result i32)))
+                (func (export "run") (result i32)
+                    i32.const 1
+                    call $log
+                    i32.const 2
+                    call $log
+                    call $sum))
+        "#,
+    )?;
+    let run = linker
+        .instantiate(&module)?
+        .get_func("run")
+        .unwrap()
+        .get0::<i32>()?;
+
+    assert_eq!(run()?, 3);
+    assert_eq!(run()?, 6);
+    assert_eq!(store.data::<Context>().unwrap().log, [1, 2, 1, 2]);
+    Ok(())
+}
+
+#[test]
+fn data_is_dropped_with_store() {
+    struct SetOnDrop(Rc<Cell<bool>>);
+
+    impl Drop for SetOnDrop {
+        fn drop(&mut self) {
+            self.0.set(true);
+        }
+    }
+
+    let dropped = Rc::new(Cell::new(false));
+    let store = Store::default();
+    store.set_data(SetOnDrop(dropped.clone()));
+    let clone = store.clone();
+    drop(store);
+    assert!(!dropped.get());
+    drop(clone);
+    assert!(dropped.get());
+}
+
+#[test]
+fn replaced_data_can_use_store_when_dropped() {
+    struct ReadOnDrop(Store, Rc<Cell<Option<u32>>>);
+
+    impl Drop for ReadOnDrop {
+        fn drop(&mut self) {
+            self.1.set(self.0.data::<u32>().map(|data| *data));
+        }
+    }
+
+    let seen = Rc::new(Cell::new(None));
+    let store = Store::default();
+    store.set_data(ReadOnDrop(store.clone(), seen.clone()));
+    store.set_data(7u32);
+    assert_eq!(seen.get(), Some(7));
+}
+
+#[test]
+#[should_panic(expected = "already borrowed")]
+fn data_mut_while_borrowed_panics() {
+    let store = Store::default();
+    store.set_data(Context::default());
+    let _data = store.data::<Context>().unwrap();
+    store.data_mut::<Context>();
+}
@@ -0,0 +1,118 @@
+use anyhow::Result;
+use std::sync::atomic::{AtomicBool, Ordering::SeqCst};
+use std::sync::Arc;
+use std::thread;
+use wasmtime::*;
+
+fn assert_send<T: Send>() {}
+
+#[test]
+fn send_store_is_send() {
+    assert_send::<SendStore>();
+}
+
+#[derive(Default)]
+struct Tenant {
+    calls: u32,
+}
+
+const COUNTER: &str = r#"
+    (module
+        (import "host" "hit" (func $hit (result i32)))
+        (func (export "run") (result i32)
+            call $hit))
+"#;
+
+/// Instantiates `module` into `store` and calls its `run` export once,
+/// dropping every handle into the store before returning.
+fn run(store: &Store, module: &Module) -> Result<i32> {
+    let mut linker = Linker::new(store);
+    linker.func("host", "hit", |caller: Caller<'_>| -> i32 {
+        let store = caller.store();
+        let mut tenant = store.data_mut::<Tenant>().unwrap();
+        tenant.calls += 1;
+        tenant.calls as i32
+    })?;
+    let run = linker
+        .instantiate(module)?
+        .get_func("run")
+        .unwrap()
+        .get0::<i32>()?;
+    Ok(run()?)
+}
+
+#[test]
+fn store_moves_between_threads() -> Result<()> {
+    let store = Store::default();
+    store.set_data(Tenant::default());
+    let module = Module::new(store.engine(), COUNTER)?;
+    assert_eq!(run(&store, &module)?, 1);
+
+    // The tenant data and the host function are both `Send`.
+    let store = unsafe { store.into_send() }.unwrap();
+    let store = thread::spawn({
+        let module = module.clone();
+        move || -> Result<SendStore> {
+            let store = store.into_store();
+            assert_eq!(run(&store, &module)?, 2);
+            Ok(unsafe { store.into_send() }.unwrap())
+        }
+    })
+    .join()
+    .unwrap()?
+    .into_store();
+
+    assert_eq!(run(&store, &module)?, 3);
+    assert_eq!(store.data::<Tenant>().unwrap().calls, 3);
+    Ok(())
+}
+
+#[test]
+fn store_with_live_handles_cannot_move() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(store.engine(), r#"(func (export "f"))"#)?;
+
+    let func = Func::wrap(&store, || 1);
+    let store = unsafe { store.into_send() }.unwrap_err();
+    assert_eq!(func.get0::<i32>()?()?, 1);
+    drop(func);
+
+    let instance = Instance::new(&store, &module, &[])?;
+    let store = unsafe { store.into_send() }.unwrap_err();
+    drop(instance);
+
+    let clone = store.clone();
+    let store = unsafe { store.into_send() }.unwrap_err();
+    drop(clone);
+
+    let r = ExternRef::new(&store, 1_u32);
+    let store = unsafe { store.into_send() }.unwrap_err();
+    drop(r);
+
+    assert!(unsafe { store.into_send() }.is_ok());
+    Ok(())
+}
+
+#[test]
+fn store_dropped_on_another_thread() {
+    struct SetOnDrop(Arc<AtomicBool>);
+
+    impl Drop for SetOnDrop {
+        fn drop(&mut self) {
+            self.0.store(true, SeqCst);
+        }
+    }
+
+    let dropped = Arc::new(AtomicBool::new(false));
+    let store = Store::default();
+    let flag = dropped.clone();
+    Func::wrap(&store, move || {
+        let _ = &flag;
+    });
+    store.set_data(SetOnDrop(dropped.clone()));
+
+    // The function's closure and the data are both `Send`.
+    let store = unsafe { store.into_send() }.unwrap();
+    thread::spawn(move || drop(store)).join().unwrap();
+    assert!(dropped.load(SeqCst));
+}
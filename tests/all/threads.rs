@@ -0,0 +1,216 @@
+use anyhow::Result;
+use std::thread;
+use wasmtime::*;
+
+fn threads_engine() -> Engine {
+    let mut config = Config::new();
+    config.wasm_threads(true);
+    config.wasm_bulk_memory(true);
+    Engine::new(&config)
+}
+
+const ATOMICS: &str = r#"
+    (module
+        (memory (import "" "memory") 1 2 shared)
+        (func (export "add") (param i32 i32) (result i32)
+            (i32.atomic.rmw.add (local.get 0) (local.get 1)))
+        (func (export "load") (param i32) (result i32)
+            (i32.atomic.load (local.get 0)))
+        (func (export "cmpxchg") (param i32 i64 i64) (result i64)
+            (i64.atomic.rmw.cmpxchg (local.get 0) (local.get 1) (local.get 2)))
+        (func (export "wait") (param i32 i32 i64) (result i32)
+            (i32.atomic.wait (local.get 0) (local.get 1) (local.get 2)))
+        (func (export "notify") (param i32 i32) (result i32)
+            (atomic.notify (local.get 0) (local.get 1)))
+        (func (export "size") (result i32)
+            memory.size)
+        (func (export "fill") (param i32 i32 i32)
+            (memory.fill (local.get 0) (local.get 1) (local.get 2)))
+    )
+"#;
+
+fn shared_memory(engine: &Engine) -> Result<SharedMemory> {
+    SharedMemory::new(engine, MemoryType::shared(Limits::new(1, Some(2))))
+}
+
+#[test]
+fn shared_memory_type_checks() -> Result<()> {
+    let engine = threads_engine();
+    assert!(SharedMemory::new(&engine, MemoryType::new(Limits::new(1, Some(2)))).is_err());
+    assert!(SharedMemory::new(&engine, MemoryType::shared(Limits::new(1, None))).is_err());
+
+    let memory = shared_memory(&engine)?;
+    assert!(memory.ty().is_shared());
+    assert!(memory.memory(&Store::new(&threads_engine())).is_err());
+
+    // Shared memories can only satisfy shared imports, and vice versa.
+    let store = Store::new(&engine);
+    let module = Module::new(&engine, r#"(module (memory (import "" "") 1 2))"#)?;
+    assert!(Instance::new(&store, &module, &[memory.memory(&store)?.into()]).is_err());
+    let unshared = Memory::new(&store, MemoryType::new(Limits::new(1, Some(2))))?;
+    let module = Module::new(&engine, ATOMICS)?;
+    assert!(Instance::new(&store, &module, &[unshared.into()]).is_err());
+    Ok(())
+}
+
+#[test]
+fn atomics_across_threads() -> Result<()> {
+    const THREADS: i32 = 4;
+    const ITERATIONS: i32 = 1000;
+
+    let engine = threads_engine();
+    let module = Module::new(&engine, ATOMICS)?;
+    let memory = shared_memory(&engine)?;
+
+    let threads = (0..THREADS)
+        .map(|_| {
+            let engine = engine.clone();
+            let module = module.clone();
+            let memory = memory.clone();
+            thread::spawn(move || -> Result<()> {
+                let store = Store::new(&engine);
+                let instance = Instance::new(&store, &module, &[memory.memory(&store)?.into()])?;
+                let add = instance.get_func("add").unwrap().get2::<i32, i32, i32>()?;
+                for _ in 0..ITERATIONS {
+                    add(0, 1)?;
+                }
+                Ok(())
+            })
+        })
+        .collect::<Vec<_>>();
+    for thread in threads {
+        thread.join().unwrap()?;
+    }
+
+    let store = Store::new(&engine);
+    let instance = Instance::new(&store, &module, &[memory.memory(&store)?.into()])?;
+    let load = instance.get_func("load").unwrap().get1::<i32, i32>()?;
+    assert_eq!(load(0)?, THREADS * ITERATIONS);
+
+    let cmpxchg = instance
+        .get_func("cmpxchg")
+        .unwrap()
+        .get3::<i32, i64, i64, i64>()?;
+    assert_eq!(cmpxchg(8, 1, 2)?, 0);
+    assert_eq!(cmpxchg(8, 0, 2)?, 0);
+    assert_eq!(cmpxchg(8, 0, 3)?, 2);
+    Ok(())
+}
+
+#[test]
+fn wait_and_notify() -> Result<()> {
+    let engine = threads_engine();
+    let module = Module::new(&engine, ATOMICS)?;
+    let memory = shared_memory(&engine)?;
+
+    let waiter = thread::spawn({
+        let engine = engine.clone();
+        let module = module.clone();
+        let memory = memory.clone();
+        move || -> Result<i32> {
+            let store = Store::new(&engine);
+            let instance = Instance::new(&store, &module, &[memory.memory(&store)?.into()])?;
+            let wait = instance
+                .get_func("wait")
+                .unwrap()
+                .get3::<i32, i32, i64, i32>()?;
+            Ok(wait(0, 0, -1)?)
+        }
+    });
+
+    let store = Store::new(&engine);
+    let instance = Instance::new(&store, &module, &[memory.memory(&store)?.into()])?;
+    let notify = instance
+        .get_func("notify")
+        .unwrap()
+        .get2::<i32, i32, i32>()?;
+    let wait = instance
+        .get_func("wait")
+        .unwrap()
+        .get3::<i32, i32, i64, i32>()?;
+
+    // Keep notifying until the other thread has actually parked.
+    while notify(0, 1)? == 0 {
+        thread::yield_now();
+    }
+    assert_eq!(waiter.join().unwrap()?, 0);
+    assert_eq!(notify(0, 1)?, 0);
+
+    // A value mismatch returns immediately, otherwise wait until the timeout.
+    assert_eq!(wait(0, 1, -1)?, 1);
+    assert_eq!(wait(0, 0, 1_000_000)?, 2);
+
+    let trap = wait(65536, 0, 0).unwrap_err();
+    assert!(trap.to_string().contains("out of bounds"), "{}", trap);
+    let trap = wait(2, 0, 0).unwrap_err();
+    assert!(trap.to_string().contains("misaligned"), "{}", trap);
+    Ok(())
+}
+
+#[test]
+fn wait_on_unshared_memory_traps() -> Result<()> {
+    let store = Store::new(&threads_engine());
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (memory 1)
+                (func (export "wait") (result i32)
+                    (i32.atomic.wait (i32.const 0) (i32.const 0) (i64.const -1)))
+                (func (export "notify") (result i32)
+                    (atomic.notify (i32.const 0) (i32.const 1)))
+            )
+        "#,
+    )?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let notify = instance.get_func("notify").unwrap().get0::<i32>()?;
+    assert_eq!(notify()?, 0);
+    let wait = instance.get_func("wait").unwrap().get0::<i32>()?;
+    let trap = wait().unwrap_err();
+    assert!(trap.to_string().contains("non-shared memory"), "{}", trap);
+    Ok(())
+}
+
+#[test]
+fn grow_is_visible_across_stores() -> Result<()> {
+    let engine = threads_engine();
+    let module = Module::new(&engine, ATOMICS)?;
+    let memory = shared_memory(&engine)?;
+
+    let store = Store::new(&engine);
+    let instance = Instance::new(&store, &module, &[memory.memory(&store)?.into()])?;
+    let size = instance.get_func("size").unwrap().get0::<i32>()?;
+    let notify = instance
+        .get_func("notify")
+        .unwrap()
+        .get2::<i32, i32, i32>()?;
+    let fill = instance
+        .get_func("fill")
+        .unwrap()
+        .get3::<i32, i32, i32, ()>()?;
+    let local = memory.memory(&store)?;
+    assert_eq!(size()?, 1);
+    assert!(notify(65536, 1).is_err());
+    assert!(fill(65536, 7, 16).is_err());
+    assert!(local.read_u8(65536).is_err());
+
+    let base = memory.data_ptr();
+    let other = Store::new(&engine);
+    assert_eq!(memory.memory(&other)?.grow(1)?, 1);
+    assert_eq!(memory.size(), 2);
+    assert_eq!(memory.data_size(), 2 * 65536);
+    assert_eq!(memory.data_ptr(), base);
+    assert!(memory.grow(1).is_err());
+
+    assert_eq!(size()?, 2);
+    assert_eq!(notify(65536, 1)?, 0);
+
+    // Bulk memory operations and host accessors in the first store see the
+    // new page too, even though it was added through the other store.
+    fill(65536, 7, 16)?;
+    assert_eq!(local.data_size(), 2 * 65536);
+    assert_eq!(local.read_u8(65536 + 15)?, 7);
+    local.write_u8(65536 + 16, 8)?;
+    assert_eq!(memory.memory(&other)?.read_u8(65536 + 16)?, 8);
+    Ok(())
+}
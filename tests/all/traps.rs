@@ -441,3 +441,38 @@ fn present_after_module_drop() -> Result<()> {
         assert_eq!(t.trace()[0].func_index(), 0);
     }
 }
+
+#[test]
+#[cfg_attr(target_arch = "aarch64", ignore)] // FIXME(#1642)
+fn trap_source_locations_from_dwarf() -> Result<()> {
+    // Fuel exhaustion gives us a trap in the middle of `fib`'s loop.
+    let engine = Engine::new(Config::new().consume_fuel(true));
+    let store = Store::new(&engine);
+    let module = Module::from_file(&engine, "tests/all/debug/testsuite/fib-wasm.wasm")?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let fib = instance.get_func("fib").unwrap().get1::<i32, i32>()?;
+    store.add_fuel(1_000)?;
+    let trap = fib(1_000_000).unwrap_err();
+
+    let frame = &trap.trace()[0];
+    assert_eq!(frame.func_name(), Some("fib"));
+    let symbols = frame.symbols();
+    assert_eq!(symbols.len(), 1);
+    assert_eq!(symbols[0].name(), Some("fib"));
+    assert_eq!(symbols[0].file(), Some("./fib-wasm.c"));
+    let line = symbols[0].line().unwrap();
+    assert!(8 <= line && line <= 15, "unexpected line {}", line);
+    assert!(
+        trap.to_string().contains("at ./fib-wasm.c:"),
+        "{}",
+        trap.to_string()
+    );
+
+    // Modules without DWARF have no symbols.
+    let module = Module::new(&engine, r#"(func (export "foo") unreachable)"#)?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let trap = instance.get_func("foo").unwrap().call(&[]).unwrap_err();
+    let trap = trap.downcast::<Trap>()?;
+    assert!(trap.trace()[0].symbols().is_empty());
+    Ok(())
+}
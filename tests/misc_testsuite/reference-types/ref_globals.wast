@@ -0,0 +1,37 @@
+(module
+  (global $e (mut externref) (ref.null extern))
+  (global $f (mut funcref) (ref.null func))
+  (global $g-ref funcref (ref.func $g))
+
+  (func $g (export "g") (result i32) (i32.const 7))
+
+  (func (export "get-extern") (result externref)
+    (global.get $e)
+  )
+  (func (export "set-extern") (param externref)
+    (global.set $e (local.get 0))
+  )
+
+  (func (export "func-is-null") (result i32)
+    (ref.is_null func (global.get $f))
+  )
+  (func (export "set-func")
+    (global.set $f (global.get $g-ref))
+  )
+  (func (export "call-func") (result i32)
+    (table.set $t (i32.const 0) (global.get $f))
+    (call_indirect $t (result i32) (i32.const 0))
+  )
+  (table $t 1 funcref)
+)
+
+(assert_return (invoke "get-extern") (ref.null extern))
+(assert_return (invoke "set-extern" (ref.extern 1)))
+(assert_return (invoke "get-extern") (ref.extern 1))
+(assert_return (invoke "set-extern" (ref.null extern)))
+(assert_return (invoke "get-extern") (ref.null extern))
+
+(assert_return (invoke "func-is-null") (i32.const 1))
+(assert_return (invoke "set-func"))
+(assert_return (invoke "func-is-null") (i32.const 0))
+(assert_return (invoke "call-func") (i32.const 7))
@@ -0,0 +1,53 @@
+(module
+  (table $e 4 externref)
+  (table $f 4 funcref)
+
+  (func $g (export "g") (result i32) (i32.const 42))
+
+  (func (export "get-extern") (param i32) (result externref)
+    (table.get $e (local.get 0))
+  )
+  (func (export "set-extern") (param i32 externref)
+    (table.set $e (local.get 0) (local.get 1))
+  )
+  (func (export "fill-extern") (param i32 externref i32)
+    (table.fill $e (local.get 0) (local.get 1) (local.get 2))
+  )
+
+  (func (export "func-is-null") (param i32) (result i32)
+    (ref.is_null func (table.get $f (local.get 0)))
+  )
+  (func (export "set-func") (param i32)
+    (table.set $f (local.get 0) (ref.func $g))
+  )
+  (func (export "fill-func") (param i32 i32)
+    (table.fill $f (local.get 0) (ref.func $g) (local.get 1))
+  )
+  (func (export "call-func") (param i32) (result i32)
+    (call_indirect $f (result i32) (local.get 0))
+  )
+)
+
+(assert_return (invoke "get-extern" (i32.const 0)) (ref.null extern))
+(assert_return (invoke "set-extern" (i32.const 1) (ref.extern 1)))
+(assert_return (invoke "get-extern" (i32.const 1)) (ref.extern 1))
+(assert_return (invoke "fill-extern" (i32.const 2) (ref.extern 2) (i32.const 2)))
+(assert_return (invoke "get-extern" (i32.const 1)) (ref.extern 1))
+(assert_return (invoke "get-extern" (i32.const 2)) (ref.extern 2))
+(assert_return (invoke "get-extern" (i32.const 3)) (ref.extern 2))
+(assert_return (invoke "fill-extern" (i32.const 0) (ref.null extern) (i32.const 4)))
+(assert_return (invoke "get-extern" (i32.const 2)) (ref.null extern))
+(assert_return (invoke "fill-extern" (i32.const 4) (ref.extern 3) (i32.const 0)))
+(assert_trap (invoke "get-extern" (i32.const 4)) "out of bounds")
+(assert_trap (invoke "set-extern" (i32.const 4) (ref.extern 1)) "out of bounds")
+(assert_trap (invoke "fill-extern" (i32.const 3) (ref.extern 1) (i32.const 2)) "out of bounds")
+
+(assert_return (invoke "func-is-null" (i32.const 0)) (i32.const 1))
+(assert_return (invoke "set-func" (i32.const 0)))
+(assert_return (invoke "func-is-null" (i32.const 0)) (i32.const 0))
+(assert_return (invoke "call-func" (i32.const 0)) (i32.const 42))
+(assert_return (invoke "fill-func" (i32.const 1) (i32.const 3)))
+(assert_return (invoke "call-func" (i32.const 3)) (i32.const 42))
+(assert_trap (invoke "func-is-null" (i32.const 4)) "out of bounds")
+(assert_trap (invoke "set-func" (i32.const 4)) "out of bounds")
+(assert_trap (invoke "fill-func" (i32.const 2) (i32.const 3)) "out of bounds")